@@ -7,43 +7,57 @@ use std::path::Path;
 
 
 const MIN_ARGS: usize = 1;
+// Tuples only implement `Debug`, `Eq`, `Hash` and `Default` for up to 12
+// elements, so mocks can't record calls with more args than this
 const MAX_ARGS: usize = 12;
 
 
 fn generate_matcher_macro(max_args: usize) -> String {
-    assert!(max_args >= MIN_ARGS && max_args <= MAX_ARGS);
+    assert!((MIN_ARGS..=MAX_ARGS).contains(&max_args));
 
-    let arg_nums: Vec<usize> = (MIN_ARGS..MAX_ARGS).collect();
+    let arg_nums: Vec<usize> = (MIN_ARGS..=max_args).collect();
     let macro_cases: Vec<String> = arg_nums.iter().map(
         |&i| generate_matcher_macro_case_n(i)
     ).collect();
     format!(
-        "#[macro_export]\nmacro_rules! matcher {{\n{}\n\n}}",
-        macro_cases.join("\n"))
+        "#[macro_export]\nmacro_rules! matcher {{\n{}\n{}\n}}",
+        macro_cases.join("\n"),
+        generate_too_many_args_case(
+            "$($m:expr),*", &format!("\"`matcher!` takes at most {} matchers\"", max_args)))
 }
 
 fn generate_matcher_macro_case_n(n_args: usize) -> String {
     let arg_nums: Vec<usize> = (MIN_ARGS..n_args + 1).collect();
     let case_args: Vec<String> = arg_nums.iter().map(
-        |&i| format!("$m{}:expr", i.to_string())
+        |&i| format!("$m{}:expr", i)
     ).collect();
     let match_impl_func_args: Vec<String> = arg_nums.iter().map(
-        |&i| format!("$m{}", i.to_string())
+        |&i| format!("$m{}", i)
+    ).collect();
+    let matcher_descriptions: Vec<String> = arg_nums.iter().map(
+        |&i| format!("stringify!($m{})", i)
     ).collect();
 
+    // Each arg is matched separately, so that the args that were rejected can
+    // be reported in failure messages.
     format!("
     ({}) => (
-        &|args| -> bool {{ match_impl_{}(args, ({})) }}
+        &|args| -> bool {{
+            $crate::matcher::__private_report_matches(
+                &$crate::matcher::__private_match_each_{}(args, ({})),
+                &[{}])
+        }}
     );",
         case_args.join(", "),
-        n_args.to_string(),
-        match_impl_func_args.join(", "))
+        n_args,
+        match_impl_func_args.join(", "),
+        matcher_descriptions.join(", "))
 }
 
 fn generate_match_impls(max_args: usize) -> String {
-    assert!(max_args >= MIN_ARGS && max_args <= MAX_ARGS);
+    assert!((MIN_ARGS..=MAX_ARGS).contains(&max_args));
 
-    let arg_nums: Vec<usize> = (MIN_ARGS..MAX_ARGS).collect();
+    let arg_nums: Vec<usize> = (MIN_ARGS..=max_args).collect();
     let match_impls: Vec<String> = arg_nums.iter().map(
         |&i| generate_match_impl_n(i)
     ).collect();
@@ -63,7 +77,7 @@ fn generate_match_impl_n(n_args: usize) -> String {
         8usize => "I",
         9usize => "J",
         10usize => "K",
-        11usize => "J"
+        11usize => "L"
     );
     assert!(arg_num_to_generic_type.len() == MAX_ARGS);
 
@@ -73,6 +87,14 @@ fn generate_match_impl_n(n_args: usize) -> String {
         return "
 pub fn match_impl_1<A>(arg: &A, arg_matcher: &dyn Fn(&A) -> bool) -> bool {
     arg_matcher(arg)
+}
+
+#[doc(hidden)]
+pub fn __private_match_each_1<A>(
+    arg: &A,
+    arg_matcher: &dyn Fn(&A) -> bool) -> [(bool, Option<String>); 1]
+{
+    [crate::diagnostics::with_detail(arg_matcher(arg))]
 }".to_owned();
     }
 
@@ -91,67 +113,114 @@ pub fn match_impl_1<A>(arg: &A, arg_matcher: &dyn Fn(&A) -> bool) -> bool {
     let matcher_invocations: Vec<String> = arg_number_range.iter().map(
         |&i| format!(
             "arg_matchers.{}(&args.{})",
-            i.to_string(),
-            i.to_string())
+            i,
+            i)
+    ).collect();
+    let detailed_matcher_invocations: Vec<String> = matcher_invocations.iter().map(
+        |invocation| format!("crate::diagnostics::with_detail({})", invocation)
     ).collect();
 
     format!("
+#[allow(clippy::type_complexity)]
 pub fn match_impl_{}<{}>(args: &(
         {}
     ),
     arg_matchers: (
         {}
     )) -> bool {{
-    let matches = vec!(
+    let matches = [
         {}
-    );
+    ];
     !matches.iter().any(|is_match| !is_match)
+}}
+
+#[doc(hidden)]
+#[allow(clippy::type_complexity)]
+pub fn __private_match_each_{}<{}>(args: &(
+        {}
+    ),
+    arg_matchers: (
+        {}
+    )) -> [(bool, Option<String>); {}] {{
+    [
+        {}
+    ]
 }}",
-        n_args.to_string(),
+        n_args,
+        type_param_names.join(","),
+        type_param_names.join(",\n        "),
+        matcher_params.join(",\n        "),
+        matcher_invocations.join(",\n        "),
+        n_args,
         type_param_names.join(","),
         type_param_names.join(",\n        "),
         matcher_params.join(",\n        "),
-        matcher_invocations.join(",\n        "))
+        n_args,
+        detailed_matcher_invocations.join(",\n        "))
 }
 
 fn generate_p_macro(max_args: usize) -> String {
-    assert!(max_args >= MIN_ARGS && max_args <= MAX_ARGS);
+    assert!((MIN_ARGS..=MAX_ARGS).contains(&max_args));
 
-    let arg_nums: Vec<usize> = (MIN_ARGS - 1..MAX_ARGS).collect();
+    let arg_nums: Vec<usize> = (MIN_ARGS - 1..=max_args).collect();
     let macro_cases: Vec<String> = arg_nums.iter().map(
         |&i| generate_p_macro_case_n(i)
     ).collect();
     format!(
-        "#[macro_export]\nmacro_rules! p {{\n{}\n\n}}",
-        macro_cases.join("\n"))
+        "#[macro_export]\nmacro_rules! p {{\n{}\n{}\n}}",
+        macro_cases.join("\n"),
+        generate_too_many_args_case(
+            "$func:ident, $($arg:expr),*",
+            &format!("\"`p!` takes at most {} args after the matcher function\"", max_args)))
 }
 
 fn generate_p_macro_case_n(n_args: usize) -> String {
+    // Each matcher reports its source, so that composite matchers such as
+    // `all_of` can name the clauses that caused them to fail. The built-in
+    // matchers are imported into the closure, so they can be named without
+    // importing `double::matcher::*`, while other matcher functions are
+    // found in the caller's scope.
     if n_args == 0 {
-        return "
+        "
         ($func:ident) => (
-            &|potential_match| -> bool {{ $func(potential_match) }}
+            &|potential_match| -> bool {
+                #[allow(unused_imports)]
+                use $crate::matcher::*;
+                $crate::matcher::__private_report_clause(
+                    concat!(\"p!(\", stringify!($func), \")\"),
+                    $func(potential_match))
+            }
         );".to_owned()
     } else {
         let arg_nums: Vec<usize> = (MIN_ARGS..n_args + 1).collect();
         let case_args: Vec<String> = arg_nums.iter().map(
-            |&i| format!("$arg{}:expr", i.to_string())
+            |&i| format!("$arg{}:expr", i)
         ).collect();
         let impl_func_call_args: Vec<String> = arg_nums.iter().map(
-            |&i| format!("$arg{}", i.to_string())
+            |&i| format!("$arg{}", i)
+        ).collect();
+        let description_args: Vec<String> = arg_nums.iter().map(
+            |&i| format!(", \", \", stringify!($arg{})", i)
         ).collect();
 
         format!("
         ($func:ident, {}) => (
-            &|potential_match| -> bool {{ $func(potential_match, {}) }}
+            &|potential_match| -> bool {{
+                #[allow(unused_imports)]
+                use $crate::matcher::*;
+                $crate::matcher::__private_report_clause(
+                    concat!(\"p!(\", stringify!($func){}, \")\"),
+                    $func(potential_match, {}))
+            }}
         );",
             case_args.join(", "),
+            description_args.join(""),
             impl_func_call_args.join(", "))
     }
 }
 
 fn generate_mock_func_macro(max_args: usize, use_default: bool) -> String {
-    assert!(max_args >= MIN_ARGS && max_args <= MAX_ARGS);
+    assert!((MIN_ARGS..=MAX_ARGS).contains(&max_args));
 
     let macro_name = if use_default {
         "mock_func"
@@ -159,30 +228,39 @@ fn generate_mock_func_macro(max_args: usize, use_default: bool) -> String {
         "mock_func_no_default"
     };
 
-    let arg_nums: Vec<usize> = (MIN_ARGS - 1..MAX_ARGS).collect();
+    let arg_nums: Vec<usize> = (MIN_ARGS - 1..=max_args).collect();
     let macro_cases: Vec<String> = arg_nums.iter().map(
         |&i| generate_mock_func_macro_case_n(i, use_default)
     ).collect();
     format!(
-        "#[macro_export]\nmacro_rules! {} {{\n{}\n\n}}",
+        "#[macro_export]\nmacro_rules! {} {{\n{}\n{}\n}}",
         macro_name,
-        macro_cases.join("\n"))
+        macro_cases.join("\n"),
+        generate_too_many_args_case(
+            "$mock_obj:ident, $mock_fn:ident, $retval:ty, $($arg:tt)*",
+            &format!("\"`{}!` mocks functions with at most {} args\"", macro_name, max_args)))
 }
 
 fn generate_mock_func_macro_case_n(n_args: usize, use_default: bool) -> String {
     let arg_nums: Vec<usize> = (MIN_ARGS..n_args + 1).collect();
     let case_args: Vec<String> = arg_nums.iter().map(
-        |&i| format!("$arg{}_type:ty", i.to_string())
+        |&i| format!("$arg{}_type:ty", i)
     ).collect();
     let mock_obj_arg_types: Vec<String> = arg_nums.iter().map(
-        |&i| format!("$arg{}_type", i.to_string())
+        |&i| format!("$arg{}_type", i)
     ).collect();
     let closure_args: Vec<String> = arg_nums.iter().map(
-        |&i| format!("arg{}: $arg{}_type", i.to_string(), i.to_string())
+        |&i| format!("arg{}: $arg{}_type", i, i)
     ).collect();
     let mock_obj_func_call_args: Vec<String> = arg_nums.iter().map(
-        |&i| format!("arg{}.clone()", i.to_string())
+        |&i| format!("arg{}.clone()", i)
     ).collect();
+    // The mock is called with a tuple of the args, unless there's only one
+    let mock_obj_func_call_args = if n_args == 1 {
+        mock_obj_func_call_args.join(", ")
+    } else {
+        format!("({})", mock_obj_func_call_args.join(", "))
+    };
 
     let case_retval_default_arg = if use_default {
         ""
@@ -191,11 +269,11 @@ fn generate_mock_func_macro_case_n(n_args: usize, use_default: bool) -> String {
     };
     let mock_obj_construction = if use_default {
         format!(
-            "let $mock_obj = double::Mock::<({}), $retval>::default();",
+            "let $mock_obj = $crate::Mock::<({}), $retval>::default();",
             mock_obj_arg_types.join(", "))
     } else {
         format!(
-            "let $mock_obj = double::Mock::<({}), $retval>::new($retval_default);",
+            "let $mock_obj = $crate::Mock::<({}), $retval>::new($retval_default);",
             mock_obj_arg_types.join(", "))
     };
 
@@ -208,26 +286,219 @@ fn generate_mock_func_macro_case_n(n_args: usize, use_default: bool) -> String {
         case_args.join(", "),
         mock_obj_construction,
         closure_args.join(", "),
-        mock_obj_func_call_args.join(", "))
+        mock_obj_func_call_args)
+}
+
+fn generate_mock_fn_macro(max_args: usize) -> String {
+    assert!((MIN_ARGS..=MAX_ARGS).contains(&max_args));
+
+    let arg_nums: Vec<usize> = (MIN_ARGS - 1..=max_args).collect();
+    let macro_cases: Vec<String> = arg_nums.iter().map(
+        |&i| generate_mock_fn_macro_case_n(i)
+    ).collect();
+    format!(
+        "#[macro_export]\nmacro_rules! mock_fn {{\n{}\n{}\n}}",
+        macro_cases.join("\n"),
+        generate_too_many_args_case(
+            "$mock:expr, $($arg:tt)*",
+            &format!("\"`mock_fn!` wraps mocks of functions with at most {} args\"", max_args)))
+}
+
+fn generate_mock_fn_macro_case_n(n_args: usize) -> String {
+    let arg_nums: Vec<usize> = (MIN_ARGS..n_args + 1).collect();
+    let case_args: Vec<String> = arg_nums.iter().map(
+        |&i| format!(", $arg{}_type:ty", i)
+    ).collect();
+    let closure_args: Vec<String> = arg_nums.iter().map(
+        |&i| format!("arg{}: $arg{}_type", i, i)
+    ).collect();
+    // Args are converted to the types the mock records, e.g. `&str` args to
+    // `String`s, so closures can take references that don't outlive the call
+    let mock_call_args: Vec<String> = arg_nums.iter().map(
+        |&i| format!("$crate::__private_std::convert::Into::into(arg{})", i)
+    ).collect();
+    // The mock is called with a tuple of the args, unless there's only one
+    let mock_call_args = if n_args == 1 {
+        mock_call_args.join(", ")
+    } else {
+        format!("({})", mock_call_args.join(", "))
+    };
+
+    format!("
+    ($mock:expr{}) => ({{
+        let mock = $crate::Mock::clone(&$mock);
+        move |{}| mock.call({})
+    }});",
+        case_args.join(""),
+        closure_args.join(", "),
+        mock_call_args)
+}
+
+fn generate_method_expectation_macro(max_args: usize) -> String {
+    assert!((MIN_ARGS..=MAX_ARGS).contains(&max_args));
+
+    let arg_nums: Vec<usize> = (MIN_ARGS - 1..=max_args).collect();
+    let macro_cases: Vec<String> = arg_nums.iter().map(
+        |&i| generate_method_expectation_macro_case_n(i)
+    ).collect();
+    format!(
+        "#[doc(hidden)]\n#[macro_export]\nmacro_rules! __private_mock_method_expectation {{\n{}\n{}\n}}",
+        macro_cases.join("\n"),
+        generate_too_many_args_case(
+            "$vis:tt $mock_name:ident $params:tt $bounds:tt, $method:ident, $($signature:tt)*",
+            &format!(
+                "concat!(\"cannot mock `\", stringify!($method), \"`, since mocked methods \\
+                 can have at most {} args\")",
+                max_args)))
+}
+
+// The last case of a macro, which matches invocations with more args than the
+// other cases support, so they fail with the error `message` (an expression
+// that `compile_error!` takes) rather than with a baffling parse error
+fn generate_too_many_args_case(pattern: &str, message: &str) -> String {
+    format!("
+    ({}) => (
+        compile_error!({});
+    );",
+        pattern,
+        message)
+}
+
+fn generate_method_expectation_macro_case_n(n_args: usize) -> String {
+    let arg_nums: Vec<usize> = (MIN_ARGS..n_args + 1).collect();
+    let case_args: Vec<String> = arg_nums.iter().map(
+        |&i| format!("$arg{}_type:ty", i)
+    ).collect();
+    let arg_types: Vec<String> = arg_nums.iter().map(
+        |&i| format!("$arg{}_type", i)
+    ).collect();
+    let matcher_params: Vec<String> = arg_nums.iter().map(
+        |&i| format!("arg{}_matcher: $crate::StaticPattern<$arg{}_type>", i, i)
+    ).collect();
+    let matcher_args: Vec<String> = arg_nums.iter().map(
+        |&i| format!("arg{}_matcher", i)
+    ).collect();
+
+    // Methods without arguments have nothing to match, so they get no `with`.
+    let with_fn = if n_args == 0 {
+        "".to_owned()
+    } else {
+        format!("
+                /// Only match calls whose arguments match the given
+                /// matchers, one per argument.
+                pub fn with(self, {}) -> Self {{
+                    let pattern: $crate::Pattern<({})> = $crate::__private_std::boxed::Box::new(move |args| {{
+                        $crate::matcher::match_impl_{}(args, ({}))
+                    }});
+                    Self {{
+                        expectation: self.expectation.__private_set_pattern(pattern),
+                        _params: $crate::__private_std::marker::PhantomData,
+                    }}
+                }}
+",
+            matcher_params.join(", "),
+            arg_types.join(", "),
+            n_args,
+            matcher_args.join(", "))
+    };
+
+    format!("
+    ([$($vis:tt)*] $mock_name:ident [$($param:ident),*] [$($bounds:tt)*],
+        $method:ident, ({}) -> $retval:ty) => (
+        $crate::__private_paste::paste! {{
+            impl<$($param),*> $mock_name<$($param),*>
+                where $($bounds)*
+            {{
+                /// Expect a call to the method of the same name. Returns a
+                /// typed expectation, whose `with` method takes one matcher
+                /// per argument of the method.
+                #[allow(dead_code)]
+                pub fn [<expect_ $method>](&self)
+                    -> [<$mock_name $method:camel Expectation>]<$($param),*>
+                {{
+                    [<$mock_name $method:camel Expectation>] {{
+                        expectation: self.$method.expect(&|_| true),
+                        _params: $crate::__private_std::marker::PhantomData,
+                    }}
+                }}
+            }}
+
+            /// A typed expectation on the method of the same name, created
+            /// using the mock's `expect_` method.
+            #[allow(dead_code)]
+            #[derive(Debug)]
+            $($vis)* struct [<$mock_name $method:camel Expectation>]<$($param),*>
+                where $($bounds)*
+            {{
+                expectation: $crate::Expectation<({}), $retval>,
+                // The type parameters of the mock, which the method may not use
+                _params: $crate::__private_std::marker::PhantomData<fn() -> ($($param,)*)>,
+            }}
+
+            #[allow(dead_code)]
+            impl<$($param),*> [<$mock_name $method:camel Expectation>]<$($param),*>
+                where $($bounds)*
+            {{{}
+                /// See `double::Expectation::returning`.
+                pub fn returning(self, return_value: impl Into<$retval>) -> Self {{
+                    Self {{
+                        expectation: self.expectation.returning(return_value),
+                        _params: $crate::__private_std::marker::PhantomData,
+                    }}
+                }}
+
+                /// See `double::Expectation::times`.
+                pub fn times(self, times: impl Into<$crate::Times>) -> Self {{
+                    Self {{
+                        expectation: self.expectation.times(times),
+                        _params: $crate::__private_std::marker::PhantomData,
+                    }}
+                }}
+
+                /// See `double::Expectation::in_sequence`.
+                pub fn in_sequence(self, sequence: &$crate::Sequence) -> Self {{
+                    Self {{
+                        expectation: self.expectation.in_sequence(sequence),
+                        _params: $crate::__private_std::marker::PhantomData,
+                    }}
+                }}
+
+                /// See `double::Expectation::num_matches`.
+                pub fn num_matches(&self) -> usize {{
+                    self.expectation.num_matches()
+                }}
+
+                /// See `double::Expectation::is_satisfied`.
+                pub fn is_satisfied(&self) -> bool {{
+                    self.expectation.is_satisfied()
+                }}
+            }}
+        }}
+    );",
+        case_args.join(", "),
+        arg_types.join(", "),
+        with_fn)
 }
 
 fn main() {
     let out_dir = env::var("OUT_DIR").unwrap();
 
     {
-        let file_contents = vec!(
+        let file_contents = [
             generate_matcher_macro(MAX_ARGS),
             generate_match_impls(MAX_ARGS),
-            generate_p_macro(MAX_ARGS)).join("\n\n");
+            generate_p_macro(MAX_ARGS)].join("\n\n");
         let dest_path = Path::new(&out_dir).join("matcher_generated.rs");
         let mut f = File::create(&dest_path).unwrap();
         f.write_all(file_contents.as_bytes()).unwrap();
     }
 
     {
-        let file_contents = vec!(
+        let file_contents = [
             generate_mock_func_macro(MAX_ARGS, true),
-            generate_mock_func_macro(MAX_ARGS, false)).join("\n\n");
+            generate_mock_func_macro(MAX_ARGS, false),
+            generate_mock_fn_macro(MAX_ARGS),
+            generate_method_expectation_macro(MAX_ARGS)].join("\n\n");
         let dest_path = Path::new(&out_dir).join("macros_generated.rs");
         let mut f = File::create(&dest_path).unwrap();
         f.write_all(file_contents.as_bytes()).unwrap();
@@ -0,0 +1,221 @@
+// The associated types of mocked traits are pinned to concrete types in the
+// attribute's options, e.g. `#[double::mock(type Item = u32)]`. The mock's
+// implementation of the trait defines them as such, and they are substituted
+// for `Self::Item` in the signatures of the methods that are mocked. The
+// values of associated consts are given in the same way, e.g.
+// `#[double::mock(const MAX = 10)]`.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::visit_mut::{self, VisitMut};
+use syn::{Error, Expr, Ident, ItemTrait, Result, Signature, TraitItem, Type, TypePath};
+
+use generics;
+
+// Returns the `type` items of the mock's implementation of `item`, given the
+// pinned `types`. Every associated type of the trait must be pinned.
+pub(crate) fn type_items(item: &ItemTrait, types: &[(Ident, Type)]) -> Result<Vec<TokenStream>> {
+    for (name, _) in types {
+        let declared = item.items.iter().any(|trait_item| match *trait_item {
+            TraitItem::Type(ref ty) => ty.ident == *name,
+            _ => false,
+        });
+        if !declared {
+            return Err(Error::new_spanned(
+                name, format!("`{}` has no associated type `{}`", item.ident, name)));
+        }
+    }
+
+    let mut items = Vec::new();
+    for trait_item in &item.items {
+        if let TraitItem::Type(ref ty) = *trait_item {
+            if !ty.generics.params.is_empty() {
+                return Err(Error::new_spanned(
+                    &ty.generics, "#[double::mock] does not support generic associated types"));
+            }
+            let name = &ty.ident;
+            let pinned = pinned_type(types, name).ok_or_else(|| Error::new_spanned(
+                ty,
+                format!("associated type `{0}` must be pinned, e.g. with \
+                         `#[double::mock(type {0} = u32)]`", name)))?;
+            items.push(quote!(type #name = #pinned;));
+        }
+    }
+    Ok(items)
+}
+
+// Returns the `const` items of the mock's implementation of `item`, given the
+// `consts` values. Every associated const of the trait without a default
+// value must be given one.
+pub(crate) fn const_items(item: &ItemTrait, consts: &[(Ident, Expr)]) -> Result<Vec<TokenStream>> {
+    for (name, _) in consts {
+        let declared = item.items.iter().any(|trait_item| match *trait_item {
+            TraitItem::Const(ref constant) => constant.ident == *name,
+            _ => false,
+        });
+        if !declared {
+            return Err(Error::new_spanned(
+                name, format!("`{}` has no associated const `{}`", item.ident, name)));
+        }
+    }
+
+    let mut items = Vec::new();
+    for trait_item in &item.items {
+        if let TraitItem::Const(ref constant) = *trait_item {
+            let name = &constant.ident;
+            let ty = &constant.ty;
+            match consts.iter().find(|(given, _)| given == name) {
+                Some((_, value)) => items.push(quote!(const #name: #ty = #value;)),
+                None if constant.default.is_some() => {},
+                None => return Err(Error::new_spanned(
+                    constant,
+                    format!("associated const `{0}` must be given a value, e.g. with \
+                             `#[double::mock(const {0} = 10)]`", name))),
+            }
+        }
+    }
+    Ok(items)
+}
+
+// Returns `sig` with the pinned `types` substituted for the associated types
+// it refers to, e.g. `fn next(&mut self) -> Option<u32>` for
+// `fn next(&mut self) -> Option<Self::Item>`.
+pub(crate) fn pin_types(sig: &Signature, types: &[(Ident, Type)]) -> Signature {
+    let mut sig = sig.clone();
+    PinTypes { types }.visit_signature_mut(&mut sig);
+    sig
+}
+
+fn pinned_type<'a>(types: &'a [(Ident, Type)], name: &Ident) -> Option<&'a Type> {
+    types.iter().find(|(pinned, _)| pinned == name).map(|(_, ty)| ty)
+}
+
+struct PinTypes<'a> {
+    types: &'a [(Ident, Type)],
+}
+
+impl<'a> PinTypes<'a> {
+    // Returns the type pinned for `path` if it's `Self::Name` or
+    // `<Self as Trait>::Name`.
+    fn pinned(&self, path: &TypePath) -> Option<&'a Type> {
+        let segments = &path.path.segments;
+        let name = match path.qself {
+            None if segments.len() == 2 && segments[0].ident == "Self" => &segments[1],
+            Some(ref qself) if generics::is_self(&qself.ty) && segments.len() == qself.position + 1 => {
+                &segments[qself.position]
+            },
+            _ => return None,
+        };
+        if !name.arguments.is_none() {
+            return None;
+        }
+        pinned_type(self.types, &name.ident)
+    }
+}
+
+impl<'a> VisitMut for PinTypes<'a> {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        let pinned = match *ty {
+            Type::Path(ref path) => self.pinned(path),
+            _ => None,
+        };
+        match pinned {
+            Some(pinned) => *ty = pinned.clone(),
+            None => visit_mut::visit_type_mut(self, ty),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::format_ident;
+    use syn::parse_quote;
+
+    fn types() -> Vec<(Ident, Type)> {
+        vec!((format_ident!("Item"), parse_quote!(u32)),
+             (format_ident!("Error"), parse_quote!(String)))
+    }
+
+    #[test]
+    fn const_items_define_given_values() {
+        let item: ItemTrait = parse_quote! {
+            trait Pool {
+                const MAX: usize;
+                const NAME: &'static str = "pool";
+                const TIMEOUT: u64 = 30;
+            }
+        };
+        let consts = vec!((format_ident!("MAX"), parse_quote!(4 * 2)),
+                          (format_ident!("TIMEOUT"), parse_quote!(5)));
+        let items = const_items(&item, &consts).unwrap();
+        assert_eq!(
+            quote!(const MAX: usize = 4 * 2; const TIMEOUT: u64 = 5;).to_string(),
+            quote!(#(#items)*).to_string());
+    }
+
+    #[test]
+    fn const_items_reject_missing_and_unknown_consts() {
+        let item: ItemTrait = parse_quote!(trait Pool { const MAX: usize; });
+        assert_eq!(
+            "associated const `MAX` must be given a value, e.g. with `#[double::mock(const MAX = 10)]`",
+            const_items(&item, &[]).unwrap_err().to_string());
+        assert_eq!(
+            "`Pool` has no associated const `MIN`",
+            const_items(&item, &[(format_ident!("MIN"), parse_quote!(0))])
+                .unwrap_err()
+                .to_string());
+    }
+
+    #[test]
+    fn pin_types_substitutes_associated_types() {
+        let sig: Signature = parse_quote! {
+            fn next(&mut self, hint: &Self::Item) -> Result<Option<Self::Item>, <Self as Iter>::Error>
+        };
+        let sig = pin_types(&sig, &types());
+        let expected: Signature =
+            parse_quote!(fn next(&mut self, hint: &u32) -> Result<Option<u32>, String>);
+        assert_eq!(quote!(#expected).to_string(), quote!(#sig).to_string());
+    }
+
+    #[test]
+    fn pin_types_ignores_other_paths() {
+        let sig: Signature = parse_quote!(fn get(&self, key: Self::Key) -> Other::Item);
+        let pinned = pin_types(&sig, &types());
+        assert_eq!(quote!(#sig).to_string(), quote!(#pinned).to_string());
+    }
+
+    #[test]
+    fn type_items_define_pinned_types() {
+        let item: ItemTrait = parse_quote! {
+            trait Iter {
+                type Item: Clone;
+                type Error;
+                fn next(&mut self) -> Result<Self::Item, Self::Error>;
+            }
+        };
+        let items = type_items(&item, &types()).unwrap();
+        assert_eq!(
+            quote!(type Item = u32; type Error = String;).to_string(),
+            quote!(#(#items)*).to_string());
+    }
+
+    #[test]
+    fn type_items_reject_unpinned_and_unknown_types() {
+        let item: ItemTrait = parse_quote!(trait Iter { type Item; type Error; });
+        assert_eq!(
+            "associated type `Error` must be pinned, e.g. with `#[double::mock(type Error = u32)]`",
+            type_items(&item, &types()[..1]).unwrap_err().to_string());
+
+        let item: ItemTrait = parse_quote!(trait Iter { type Item; });
+        assert_eq!(
+            "`Iter` has no associated type `Error`",
+            type_items(&item, &types()).unwrap_err().to_string());
+
+        let item: ItemTrait = parse_quote!(trait Lender { type Item<'a>; });
+        assert_eq!(
+            "#[double::mock] does not support generic associated types",
+            type_items(&item, &types()[..1]).unwrap_err().to_string());
+    }
+}
@@ -0,0 +1,104 @@
+// Checks that the args and return values of mocked methods implement the
+// traits that mocks need, generated ahead of the mock. Each trait that an arg
+// or return value doesn't implement is reported once, at its type in the
+// method's signature and naming the method, e.g. "method `frob` of a mock returns `Token`, which must
+// implement `Clone`". The mock's impls have higher-ranked bounds on the args
+// and return values (see `generics::checked_where_clause`), so the compiler
+// doesn't report the same problems again at the attribute, once for each impl.
+// The errors are pinned by the UI tests of `tests/ui`.
+//
+// Types that use the mock's or the method's type parameters, or `Self`, can't
+// be named outside of the mock's impls, so they aren't checked here, and
+// neither is `()`. Generic mocks are bounded by what they need instead.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{Ident, Type};
+
+use generics;
+use method::Method;
+
+// Generates the checks of `methods`, including that their return values
+// implement `Default` if `default` is true
+pub(crate) fn checks(params: &[&Ident], methods: &[Method], default: bool) -> TokenStream {
+    let self_type = format_ident!("Self");
+    let mut names = Vec::new();
+    let mut checks = Vec::new();
+    for method in methods {
+        let name = method.name();
+        let mut params = params.to_vec();
+        params.extend(method.type_params());
+        params.push(&self_type);
+        let checked = |ty: &&Type| !is_unit(ty) && !generics::uses_params(ty, &params);
+
+        let num_checks = checks.len();
+        checks.extend(method.stored_arg_types()
+            .filter(checked)
+            .map(|ty| check(name, "recorded_arg", ty)));
+        if let Some(returned) = Some(method.returned()).filter(checked) {
+            checks.push(check(name, "returned_value", returned));
+            if default {
+                checks.push(check(name, "default_value", returned));
+            }
+        }
+        if checks.len() > num_checks {
+            names.push(name);
+        }
+    }
+    if checks.is_empty() {
+        return quote!();
+    }
+
+    quote! {
+        const _: () = {
+            #[allow(dead_code, non_camel_case_types)]
+            mod __double_methods {
+                #(pub struct #names;)*
+            }
+
+            #(#checks)*
+        };
+    }
+}
+
+fn is_unit(ty: &Type) -> bool {
+    match ty {
+        Type::Tuple(tuple) => tuple.elems.is_empty(),
+        _ => false,
+    }
+}
+
+// Generates the call of the function of `::double::checks` named `function`,
+// which checks `ty` for the method `name`
+fn check(name: &Ident, function: &str, ty: &Type) -> TokenStream {
+    let function = format_ident!("{}", function);
+    quote_spanned! {ty.span()=>
+        let _ = ::double::checks::#function::<__double_methods::#name, #ty>;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn method(sig: syn::Signature) -> Method {
+        Method::parse(&sig, &[]).unwrap()
+    }
+
+    #[test]
+    fn types_that_use_type_parameters_or_self_are_not_checked() {
+        let param = format_ident!("T");
+        let methods = vec!(
+            method(parse_quote!(fn get(&self, key: T, index: usize) -> Option<T>)),
+            method(parse_quote!(fn merge(&self, other: Self) -> Self)));
+        let expanded = checks(&[&param], &methods, true).to_string();
+        assert!(expanded.contains(
+            &quote!(::double::checks::recorded_arg::<__double_methods::get, usize>).to_string()));
+        assert!(!expanded.contains("returned_value"), "{}", expanded);
+        assert!(!expanded.contains("default_value"), "{}", expanded);
+        assert!(!expanded.contains(&quote!(__double_methods::merge,).to_string()), "{}", expanded);
+    }
+}
@@ -0,0 +1,135 @@
+// Mocks of traits defined in other crates, whose definitions can't be seen by
+// `#[double::mock]`. The trait is restated in `double::mock_extern_trait!`,
+// named by its path, e.g. `trait std::fmt::Write { ... }`, and its mock is
+// generated from the restatement like `#[double::mock]` generates mocks from
+// definitions. The mock implements the trait at the path rather than the
+// restatement, so the compiler checks that the restated signatures are those
+// of the trait, and that none of its required methods is missing.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{braced, Attribute, ItemTrait, Meta, Path, Result, Token, TraitItem,
+          TypeParamBound, Visibility};
+
+use mock_trait;
+use options::Options;
+
+// The restated trait, e.g.
+// `#[double::mock(no_default)] pub trait std::io::Read { fn read(...) -> ...; }`
+pub(crate) struct ExternTrait {
+    options: Options,
+    path: Path,
+    // The restatement, named after the last segment of `path`
+    item: ItemTrait,
+}
+
+impl Parse for ExternTrait {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut options = Options::default();
+        let mut attrs = Vec::new();
+        for attr in input.call(Attribute::parse_outer)? {
+            if !is_mock_attr(&attr) {
+                attrs.push(attr);
+            } else if let Meta::List(_) = attr.meta {
+                options = attr.parse_args()?;
+            }
+        }
+        let vis: Visibility = input.parse()?;
+        let unsafety: Option<Token![unsafe]> = input.parse()?;
+        input.parse::<Token![trait]>()?;
+        let path: Path = input.parse()?;
+        let supertraits = if input.peek(Token![:]) {
+            input.parse::<Token![:]>()?;
+            Punctuated::<TypeParamBound, Token![+]>::parse_separated_nonempty(input)?
+        } else {
+            Punctuated::new()
+        };
+        let content;
+        braced!(content in input);
+        let mut items = Vec::<TraitItem>::new();
+        while !content.is_empty() {
+            items.push(content.parse()?);
+        }
+
+        let segment = path.segments.last().ok_or_else(|| input.error("expected a trait path"))?;
+        let name = &segment.ident;
+        let mut item: ItemTrait = syn::parse_quote! {
+            #(#attrs)*
+            #vis #unsafety trait #name {
+                #(#items)*
+            }
+        };
+        if !supertraits.is_empty() {
+            item.colon_token = Some(Default::default());
+            item.supertraits = supertraits;
+        }
+        Ok(ExternTrait { options, path, item })
+    }
+}
+
+// Returns true if `attr` is `#[double::mock(...)]` or `#[mock(...)]`, which
+// give the options of the mock
+fn is_mock_attr(attr: &Attribute) -> bool {
+    let path = attr.path();
+    path.is_ident("mock")
+        || (path.segments.len() == 2 && path.segments[0].ident == "double"
+            && path.segments[1].ident == "mock")
+}
+
+// Generates the mock of the restated trait, which implements the trait at its
+// path
+pub(crate) fn expand(restated: &ExternTrait) -> Result<TokenStream> {
+    let path = &restated.path;
+    mock_trait::mock(&restated.options, &restated.item, &quote!(#path))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    #[test]
+    fn mocks_implement_the_trait_at_the_restated_path() {
+        let restated: ExternTrait = parse_quote! {
+            #[double::mock(name = MockWriter)]
+            pub trait std::io::Write {
+                fn write(&mut self, buf: &[u8]) -> std::io::Result<usize>;
+                fn flush(&mut self) -> std::io::Result<()>;
+            }
+        };
+        let expanded = expand(&restated).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            impl std::io::Write for MockWriter
+                where for<'__double> <[u8] as ::std::borrow::ToOwned>::Owned: Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> std::io::Result<usize>: Clone + ::std::fmt::Debug,
+                      for<'__double> (): Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> std::io::Result<()>: Clone + ::std::fmt::Debug,
+            {
+                #[track_caller]
+                fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                    self.write.call(::std::borrow::ToOwned::to_owned(&*buf))
+                }
+
+                #[track_caller]
+                fn flush(&mut self) -> std::io::Result<()> {
+                    self.flush.call(())
+                }
+            }
+        }.to_string()));
+        assert!(!expanded.contains("trait Write"));
+    }
+
+    #[test]
+    fn restated_traits_keep_their_supertraits_and_type_arguments() {
+        let restated: ExternTrait = parse_quote! {
+            trait events::Handler<Click>: Send + Sync {
+                fn handle(&self, event: Click) -> bool;
+            }
+        };
+        let expanded = expand(&restated).unwrap().to_string();
+        assert!(expanded.contains(&quote!(impl events::Handler<Click> for MockHandler).to_string()));
+    }
+}
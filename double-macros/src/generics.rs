@@ -0,0 +1,136 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::visit::{self, Visit};
+use syn::{Error, GenericParam, Generics, Ident, Result, Type, TypeImplTrait, TypePath,
+          WherePredicate};
+
+use lifetimes;
+use method::Method;
+
+// Returns the type parameters of a mocked trait or `impl` block, which are the
+// type parameters of its mock. Mocks don't have lifetime parameters, since
+// they own the values they record. Const parameters are not supported.
+pub(crate) fn type_params(generics: &Generics) -> Result<Vec<&Ident>> {
+    let mut params = Vec::new();
+    for param in &generics.params {
+        match *param {
+            GenericParam::Type(ref param) => params.push(&param.ident),
+            GenericParam::Lifetime(_) => (),
+            ref other => return Err(Error::new_spanned(
+                other, "#[double::mock] does not support const parameters")),
+        }
+    }
+    Ok(params)
+}
+
+// Returns the bounds of the type parameters of a mocked trait or `impl` block,
+// both inline and in its `where` clause, which bound the mock and its impls
+// too. Bounds on lifetimes, the mock having none, and on `Self`, which are
+// met when the trait is implemented, are left out. Lifetimes are made
+// `'static`, like those of the values that the mock records.
+pub(crate) fn predicates(generics: &Generics) -> Vec<WherePredicate> {
+    let mut predicates = Vec::new();
+    for param in generics.type_params() {
+        if !param.bounds.is_empty() {
+            let ident = &param.ident;
+            let bounds = &param.bounds;
+            predicates.push(syn::parse_quote!(#ident: #bounds));
+        }
+    }
+    if let Some(ref where_clause) = generics.where_clause {
+        for predicate in &where_clause.predicates {
+            if let WherePredicate::Type(ref predicate) = *predicate {
+                if !is_self(&predicate.bounded_ty) {
+                    predicates.push(WherePredicate::Type(predicate.clone()));
+                }
+            }
+        }
+    }
+    predicates.iter().map(lifetimes::make_static_predicate).collect()
+}
+
+// The type parameters of a mock, e.g. `<T>`
+pub(crate) fn mock_type_generics(params: &[&Ident]) -> TokenStream {
+    if params.is_empty() {
+        quote!()
+    } else {
+        quote!(<#(#params),*>)
+    }
+}
+
+// The `where` clause of impls of a mock with the given type parameters,
+// `predicates` and methods, which bounds the args and return values of the
+// methods like `mock_trait!` does. Mocks without type parameters don't need
+// one.
+pub(crate) fn mock_where_clause(
+    params: &[&Ident],
+    predicates: &[WherePredicate],
+    methods: &[Method]) -> TokenStream
+{
+    if params.is_empty() || (predicates.is_empty() && methods.is_empty()) {
+        return quote!();
+    }
+    let bounds = methods.iter().map(Method::bounds);
+    quote!(where #(#bounds)* #(#predicates,)*)
+}
+
+// The `where` clause of impls of a mock whose methods were checked (see
+// `checks`), which bounds the args and return values of the methods like
+// `mock_where_clause`, but with higher-ranked bounds, even if the mock has
+// no type parameters (see `__private_mock_trait!(@checked ...)`)
+pub(crate) fn checked_where_clause(predicates: &[WherePredicate], methods: &[Method]) -> TokenStream {
+    if predicates.is_empty() && methods.is_empty() {
+        return quote!();
+    }
+    let bounds = methods.iter().map(Method::checked_bounds);
+    quote!(where #(#bounds)* #(#predicates,)*)
+}
+
+// Returns true if `ty` uses any of the type parameters `params`, e.g.
+// `Vec<T>` or `T::Output` for `T`
+pub(crate) fn uses_params(ty: &Type, params: &[&Ident]) -> bool {
+    let mut finder = ParamFinder { params, found: false };
+    finder.visit_type(ty);
+    finder.found
+}
+
+struct ParamFinder<'a> {
+    params: &'a [&'a Ident],
+    found: bool,
+}
+
+impl<'a, 'ast> Visit<'ast> for ParamFinder<'a> {
+    fn visit_type_path(&mut self, path: &'ast TypePath) {
+        if path.qself.is_none() {
+            if let Some(first) = path.path.segments.first() {
+                self.found |= self.params.iter().any(|param| first.ident == **param);
+            }
+        }
+        visit::visit_type_path(self, path);
+    }
+}
+
+// Returns true if `ty` has an `impl Trait` in it, e.g. `Vec<impl Display>`
+pub(crate) fn uses_impl_trait(ty: &Type) -> bool {
+    let mut finder = ImplTraitFinder { found: false };
+    finder.visit_type(ty);
+    finder.found
+}
+
+struct ImplTraitFinder {
+    found: bool,
+}
+
+impl<'ast> Visit<'ast> for ImplTraitFinder {
+    fn visit_type_impl_trait(&mut self, _: &'ast TypeImplTrait) {
+        self.found = true;
+    }
+}
+
+// Returns true if `ty` is `Self`
+pub(crate) fn is_self(ty: &Type) -> bool {
+    match *ty {
+        Type::Path(ref path) => path.qself.is_none() && path.path.is_ident("Self"),
+        _ => false,
+    }
+}
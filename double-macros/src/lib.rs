@@ -0,0 +1,680 @@
+//! Procedural macros for `double`. Use them through the `double` crate, with
+//! its `macros` feature, rather than depending on this crate directly.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate syn;
+
+mod associated;
+mod checks;
+mod extern_trait;
+mod generics;
+mod lifetimes;
+mod method;
+mod mock_impl;
+mod mock_trait;
+mod mocked;
+mod options;
+mod static_fns;
+mod supertraits;
+
+use proc_macro::TokenStream;
+use syn::parse_macro_input;
+
+/// Attribute that generates a mock `struct` for the `trait` or inherent `impl`
+/// block it's applied to.
+///
+/// The trait is left as it is. Alongside it, a mock named after the trait
+/// (e.g. `MockTaskManager` for `TaskManager`) is generated by `mock_trait!`,
+/// along with an implementation of the trait that calls the mock's method
+/// mocks (like `mock_method!` does). Since the mock is generated from the
+/// trait's definition, it stays in sync with the trait as methods are added
+/// or changed.
+///
+/// Use `#[double::mock(no_default)]` if one or more of the methods return a
+/// type that does not implement `Default` (see `mock_trait_no_default!`), and
+/// `#[double::mock(name = MockName)]` to give the mock a different name. To
+/// only generate the mock in tests, use `#[cfg_attr(test, double::mock)]`.
+/// The mock and its fields are as visible as the trait, unless a visibility
+/// is given, e.g. `#[double::mock(vis = pub)]` to export the mock from a
+/// crate of test helpers, or `#[double::mock(vis = pub(self))]` to keep it
+/// private.
+/// `#[double::mock(derive(Fixture), attrs(#[allow(dead_code)]))]` adds
+/// derives (e.g. of a test framework's traits) and other attributes to the
+/// mock `struct`, which always implements `Debug` and `Clone`.
+///
+/// To mock traits of the same name from different modules in one test crate,
+/// use `#[double::mock(module = mocks)]` to generate the mock in a module of
+/// its own, e.g. `mocks::MockStore`, which sees the items of the trait's
+/// module. Each module name can only be used once per module.
+///
+/// Each associated type of the trait must be pinned to a concrete type, e.g.
+/// `#[double::mock(type Item = u32)]`. The mock's implementation of the trait
+/// defines the associated type as such, and it takes the place of `Self::Item`
+/// in the mocked methods' signatures. Likewise, associated consts without a
+/// default value must be given one, e.g. `#[double::mock(const MAX = 10)]`,
+/// which can be any constant expression of the const's type.
+///
+/// Methods with a default implementation are mocked like any other, unless
+/// they are listed in `#[double::mock(passthrough(method, ...))]`. Those
+/// aren't mocked, so calls fall through to the default implementation, which
+/// is handy for helper methods defined in terms of the methods that are.
+///
+/// Since the attribute can't see the definitions of other traits, the methods
+/// of supertraits are listed in the attribute, e.g.
+/// `#[double::mock(Storage { fn get(&self, key: u32) -> String; })]`. They are
+/// mocked as part of the same mock, which implements the supertrait too.
+/// Supertraits that are implemented by hand are listed by name alone, e.g.
+/// `#[double::mock(Storage)]`, while `Clone`, `Debug`, `Default` (unless
+/// `no_default` is used), `Send`, `Sync`, `Sized` and `Unpin` don't need to be
+/// listed.
+///
+/// The methods of the trait must take `self`, `&self` or `&mut self`, or
+/// `self` in a `Box`, `Rc`, `Arc` or `Pin` (e.g. `self: Pin<&mut Self>`).
+/// Methods that take `self` consume the mock, but clones of a mock share its
+/// recorded calls, so keep a clone to verify them. `unsafe` methods and traits
+/// are implemented by `unsafe` methods and impls, while the mock's method
+/// mocks can be stubbed and verified safely.
+///
+/// Functions that don't take `self` (e.g. `fn create() -> Self` or
+/// `fn parse(s: &str) -> Result<Self, String>`) have no mock to call, so their
+/// calls are handled by a `Mock` that's registered for the current thread,
+/// using the `mock_` function generated for each of them, e.g.
+/// `MockWidget::mock_create(&mock)`. It returns a `ScopedMock` guard, and the
+/// mock handles calls until the guard is dropped, so tests running in
+/// parallel don't interfere. Calls without a registered mock panic. These
+/// functions aren't supported in traits with type parameters, or in
+/// supertraits.
+///
+/// Methods can have type parameters, as long as their return type doesn't use
+/// them. Since a mock records args of a single type, args whose type is one of
+/// the method's type parameters (e.g. `value: T` or `value: &T`), or an
+/// `impl Trait` (e.g. `value: impl Display`), are recorded as the name of
+/// their type (a `&'static str` from `std::any::type_name`), so the method
+/// can be stubbed and verified for each type it's called with.
+/// To record them as something else, e.g. as serialized bytes, give a
+/// function that's generic over the type parameters and the type it returns,
+/// e.g. `#[double::mock(project(encode = to_bytes -> Vec<u8>))]`. It's
+/// called with a reference to each of the args. Other args that use the
+/// method's type parameters or `impl Trait` (e.g. `Vec<T>`) are not
+/// supported.
+///
+/// Reference args are recorded as the owned value that `ToOwned` converts
+/// them to (e.g. `String` for `&str`), and other args as they are. To record
+/// one arg of any method as something else, e.g. an arg that doesn't
+/// implement `Clone` as its ID, give a function for the arg alone, e.g.
+/// `#[double::mock(project(send.packet = Packet::id -> u64))]`. It's called
+/// with a reference to the arg.
+///
+/// Trait objects (e.g. `shape: &dyn Shape` or `callback: Box<dyn Fn(u32)>`)
+/// can't be cloned, so they're recorded as the name of their type, e.g.
+/// `"dyn my_crate::Shape"`, unless the arg has a function of its own, e.g.
+/// `#[double::mock(project(draw.shape = Shape::name -> String))]`, which is
+/// called with a reference to the trait object.
+///
+/// Methods can return an `impl Trait` if it's an iterator or a future. The
+/// method's mock returns a `Vec` of the iterator's items, or a `MockFuture`
+/// of the future's output, which the method converts to the `impl Trait`.
+///
+/// Async methods are mocked by a mock that returns a `MockFuture` of the
+/// method's output, e.g. `MockFuture<String>` for `async fn get(&self) ->
+/// String`, so they can be stubbed with `returns_ready` and the like. The
+/// mock's async method records the call when it's first polled, and then
+/// awaits the future. Traits whose async methods are desugared by
+/// `#[async_trait]` are supported too, as long as `#[double::mock]` comes
+/// first, so it sees the `async fn`s. The mock's implementation of the trait
+/// gets the same `#[async_trait]` attribute.
+///
+/// Generic traits get a generic mock, whose impls are bounded like those of
+/// generic `mock_trait!` mocks. The bounds of the trait's type parameters,
+/// inline or in a `where` clause, bound the mock too, while the `where`
+/// clauses of methods are kept in the mock's implementation of the trait.
+///
+/// Mocks own the args and return values they record, so lifetimes are
+/// handled as follows:
+///
+/// * args that are references are recorded as owned values, converted using
+///   `ToOwned` (e.g. a `&str` is recorded as a `String` and a `&[u32]` as a
+///   `Vec<u32>`). Other args that borrow data (e.g. `Token<'a>`) are not
+///   supported.
+/// * return values that are references borrowed from `self` (e.g. `&str` in
+///   `fn name(&self) -> &str`) are returned by mocks as owned values,
+///   converted using `ToOwned` like args are. The mock keeps each value it
+///   returns, so the method can return a reference to it (see
+///   `Mock::call_ref`).
+/// * the lifetimes of other return values are made `'static`, so the mock of
+///   a method that returns `Option<&'a str>` returns `Option<&'static str>`.
+/// * lifetime parameters of traits and methods are kept in the mock's
+///   implementation of the trait, but the mock itself has none.
+///
+/// Applied to an inherent `impl` block, the attribute generates a mock with a
+/// method of the same name and signature as each of the block's methods that
+/// take `self`, calling the method mock of the same name. Associated
+/// functions that don't take `self` (e.g. constructors) are not mocked. This
+/// allows concrete types to be replaced by doubles in tests without
+/// extracting a trait for them, by importing the mock under the type's name:
+///
+/// ```
+/// # extern crate double;
+/// pub struct Mailer;
+///
+/// #[double::mock]
+/// impl Mailer {
+///     pub fn send(&self, to: String) -> bool {
+///         // talk to an SMTP server
+/// #       !to.is_empty()
+///     }
+/// }
+///
+/// // In the module that uses `Mailer`:
+/// //
+/// // #[cfg(not(test))]
+/// // use mailer::Mailer;
+/// // #[cfg(test)]
+/// // use mailer::MockMailer as Mailer;
+///
+/// # fn main() {
+/// let mailer = MockMailer::default();
+/// mailer.send.return_value(true);
+/// assert!(mailer.send("ops@example.com".to_owned()));
+/// assert!(mailer.send.called_with("ops@example.com".to_owned()));
+/// # }
+/// ```
+///
+/// Requires the `macros` feature of `double`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate double;
+/// #[double::mock]
+/// trait TaskManager {
+///     fn max_threads(&self) -> u32;
+///     fn set_max_threads(&mut self, max_threads: u32);
+/// }
+///
+/// # fn main() {
+/// let mut mock = MockTaskManager::default();
+/// mock.max_threads.return_value(42u32);
+/// assert_eq!(42, mock.max_threads());
+/// mock.set_max_threads(9001);
+/// assert!(mock.set_max_threads.called_with(9001u32));
+/// # }
+/// ```
+///
+/// Traits with return types that do not implement `Default`:
+///
+/// ```
+/// # extern crate double;
+/// #[double::mock(no_default, name = FakeStore)]
+/// pub trait UserStore {
+///     fn get_user(&self, id: i32) -> Result<String, String>;
+///     fn delete_user(&self, id: i32) -> Result<(), String>;
+/// }
+///
+/// # fn main() {
+/// let store = FakeStore::new(Err("no such user".to_owned()), Ok(()));
+/// store.get_user.return_value_for(42, Ok("Donald".to_owned()));
+/// assert_eq!(Ok("Donald".to_owned()), store.get_user(42));
+/// assert_eq!(Err("no such user".to_owned()), store.get_user(7));
+/// # }
+/// ```
+///
+/// Mocks in modules of their own:
+///
+/// ```
+/// # extern crate double;
+/// mod billing {
+///     #[double::mock(module = mocks)]
+///     pub trait Store {
+///         fn balance(&self, account: u32) -> i64;
+///     }
+/// }
+///
+/// mod inventory {
+///     #[double::mock(module = mocks)]
+///     pub trait Store {
+///         fn stock(&self, item: u32) -> u32;
+///     }
+/// }
+///
+/// use billing::mocks::MockStore as MockBillingStore;
+/// use inventory::mocks::MockStore as MockInventoryStore;
+///
+/// # fn main() {
+/// use billing::Store as _;
+/// let billing = MockBillingStore::default();
+/// billing.balance.return_value(-5i64);
+/// assert_eq!(-5, billing.balance(1));
+/// let inventory = MockInventoryStore::default();
+/// assert!(!inventory.was_used());
+/// # }
+/// ```
+///
+/// Traits and methods with lifetime parameters:
+///
+/// ```
+/// # extern crate double;
+/// #[double::mock]
+/// trait Dictionary<'input> {
+///     fn find<'a>(&self, word: &'a str) -> Option<&'a str>;
+///     fn define(&mut self, word: &'input str, meanings: &[String]);
+/// }
+///
+/// # fn main() {
+/// let mut dictionary = MockDictionary::default();
+/// dictionary.find.return_value_for("rust".to_owned(), Some("a language"));
+/// assert_eq!(Some("a language"), dictionary.find("rust"));
+///
+/// dictionary.define("mock", &["a double".to_owned()]);
+/// assert!(dictionary.define.called_with(("mock".to_owned(), vec!("a double".to_owned()))));
+/// # }
+/// ```
+///
+/// Traits with associated types and consts:
+///
+/// ```
+/// # extern crate double;
+/// #[double::mock(type Item = u32, type Key = String, const BATCH_SIZE = 16)]
+/// trait Source {
+///     type Item;
+///     type Key;
+///     const BATCH_SIZE: usize;
+///     fn next(&mut self) -> Option<Self::Item>;
+///     fn seek(&mut self, key: &Self::Key);
+/// }
+///
+/// # fn main() {
+/// let mut source = MockSource::default();
+/// source.next.return_value(Some(7));
+/// assert_eq!(Some(7), source.next());
+/// source.seek(&"b".to_owned());
+/// assert!(source.seek.called_with("b".to_owned()));
+/// assert_eq!(16, MockSource::BATCH_SIZE);
+/// # }
+/// ```
+///
+/// Traits with default methods that calls fall through to:
+///
+/// ```
+/// # extern crate double;
+/// #[double::mock(passthrough(greeting))]
+/// trait Greeter {
+///     fn name(&self) -> String;
+///     fn greeting(&self) -> String {
+///         format!("Hello, {}!", self.name())
+///     }
+/// }
+///
+/// # fn main() {
+/// let greeter = MockGreeter::default();
+/// greeter.name.return_value("Ferris".to_owned());
+/// assert_eq!("Hello, Ferris!", greeter.greeting());
+/// assert!(greeter.name.called());
+/// # }
+/// ```
+///
+/// Traits with generic methods:
+///
+/// ```
+/// # extern crate double;
+/// use std::any::type_name;
+/// use std::fmt::{Debug, Display};
+///
+/// fn debug_string<T: Debug>(value: &T) -> String {
+///     format!("{:?}", value)
+/// }
+///
+/// #[double::mock(project(log = debug_string -> String))]
+/// trait Store {
+///     fn size_of<T>(&self, value: T) -> usize;
+///     fn log<T: Debug>(&self, value: &T);
+///     fn print(&self, value: impl Display);
+/// }
+///
+/// # fn main() {
+/// let store = MockStore::default();
+/// store.size_of.return_value_for(type_name::<u64>(), 8usize);
+/// assert_eq!(8, store.size_of(7u64));
+/// assert_eq!(0, store.size_of(true));
+///
+/// store.log(&vec!(1, 2));
+/// assert!(store.log.called_with("[1, 2]".to_owned()));
+///
+/// store.print("text");
+/// assert!(store.print.called_with(type_name::<&str>()));
+/// # }
+/// ```
+///
+/// Traits with methods that return iterators or futures:
+///
+/// ```
+/// # extern crate double;
+/// use std::future::Future;
+/// use std::pin::pin;
+/// use std::task::{Context, Poll, Waker};
+///
+/// #[double::mock]
+/// trait Inbox {
+///     fn unread(&self) -> impl Iterator<Item = String>;
+///     fn fetch(&self, id: u32) -> impl Future<Output = String> + Send;
+/// }
+///
+/// # fn main() {
+/// let inbox = MockInbox::default();
+/// inbox.unread.return_value(vec!("Hi!".to_owned()));
+/// assert_eq!(vec!("Hi!".to_owned()), inbox.unread().collect::<Vec<_>>());
+///
+/// inbox.fetch.returns_ready("Hello!".to_owned());
+/// let mut cx = Context::from_waker(Waker::noop());
+/// let fetched = pin!(inbox.fetch(1)).poll(&mut cx);
+/// assert_eq!(Poll::Ready("Hello!".to_owned()), fetched);
+/// assert!(inbox.fetch.called_with(1u32));
+/// # }
+/// ```
+///
+/// Traits with async methods:
+///
+/// ```edition2018
+/// # extern crate double;
+/// use std::future::Future;
+/// use std::pin::pin;
+/// use std::task::{Context, Poll, Waker};
+///
+/// #[double::mock]
+/// trait Client {
+///     async fn get(&self, path: &str) -> String;
+/// }
+///
+/// # fn main() {
+/// let client = MockClient::default();
+/// client.get.returns_pending_then("OK".to_owned(), 1);
+/// let mut cx = Context::from_waker(Waker::noop());
+/// let mut response = pin!(client.get("/"));
+/// assert_eq!(Poll::Pending, response.as_mut().poll(&mut cx));
+/// assert!(client.get.called_with("/".to_owned()));
+/// assert_eq!(Poll::Ready("OK".to_owned()), response.as_mut().poll(&mut cx));
+/// # }
+/// ```
+///
+/// Traits with methods that return references to data owned by `self`:
+///
+/// ```
+/// # extern crate double;
+/// #[double::mock]
+/// trait Account {
+///     fn owner(&self) -> &str;
+///     fn key(&self) -> &[u8];
+/// }
+///
+/// # fn main() {
+/// let account = MockAccount::default();
+/// account.owner.return_value("Ferris".to_owned());
+/// account.key.return_value(vec!(1u8, 2));
+/// assert_eq!("Ferris", account.owner());
+/// assert_eq!(&[1, 2], account.key());
+/// # }
+/// ```
+///
+/// Traits with methods that consume `self`:
+///
+/// ```
+/// # extern crate double;
+/// #[double::mock]
+/// trait Connector {
+///     fn into_url(self, path: String) -> String;
+/// }
+///
+/// # fn main() {
+/// let connector = MockConnector::default();
+/// connector.into_url.return_value("https://example.com/".to_owned());
+/// let handle = connector.clone();
+/// assert_eq!("https://example.com/", connector.into_url("/".to_owned()));
+/// assert!(handle.into_url.called_with("/".to_owned()));
+/// # }
+/// ```
+///
+/// Traits with functions that don't take `self`:
+///
+/// ```
+/// # extern crate double;
+/// #[double::mock]
+/// trait Widget {
+///     fn create(name: String) -> Self;
+///     fn name(&self) -> String;
+/// }
+///
+/// fn build<W: Widget>() -> W {
+///     W::create("button".to_owned())
+/// }
+///
+/// # fn main() {
+/// let widget = MockWidget::default();
+/// widget.name.return_value("button".to_owned());
+/// let create = double::Mock::new(widget);
+/// let _guard = MockWidget::mock_create(&create);
+/// let built: MockWidget = build();
+/// assert_eq!("button", built.name());
+/// assert!(create.called_with("button".to_owned()));
+/// # }
+/// ```
+///
+/// Traits with methods that take `self` in a smart pointer:
+///
+/// ```
+/// # extern crate double;
+/// use std::sync::Arc;
+///
+/// #[double::mock]
+/// trait Actor {
+///     fn send(self: Arc<Self>, message: String) -> bool;
+/// }
+///
+/// # fn main() {
+/// let actor = Arc::new(MockActor::default());
+/// actor.send.return_value(true);
+/// assert!(actor.clone().send("ping".to_owned()));
+/// assert!(actor.send.called_with("ping".to_owned()));
+/// # }
+/// ```
+///
+/// Traits with supertraits:
+///
+/// ```
+/// # extern crate double;
+/// trait Storage {
+///     fn get(&self, key: u32) -> Option<String>;
+/// }
+///
+/// #[double::mock(Storage { fn get(&self, key: u32) -> Option<String>; })]
+/// trait Cache: Clone + Storage {
+///     fn hit_rate(&self) -> f64;
+/// }
+///
+/// # fn main() {
+/// let cache = MockCache::default();
+/// cache.get.return_value_for(7u32, Some("seven".to_owned()));
+/// cache.hit_rate.return_value(0.5);
+/// assert_eq!(Some("seven".to_owned()), cache.get(7));
+/// assert_eq!(0.5, cache.clone().hit_rate());
+/// # }
+/// ```
+///
+/// Generic traits:
+///
+/// ```
+/// # extern crate double;
+/// #[double::mock]
+/// trait Serializer<T> {
+///     fn serialize(&self, value: T) -> Vec<u8>;
+/// }
+///
+/// # fn main() {
+/// let serializer = MockSerializer::<(i32, bool)>::default();
+/// serializer.serialize.return_value(vec!(1, 2, 3));
+/// assert_eq!(vec!(1, 2, 3), serializer.serialize((7, true)));
+/// assert!(serializer.serialize.called_with((7, true)));
+/// # }
+/// ```
+///
+/// Generic traits with `where` clauses:
+///
+/// ```
+/// # extern crate double;
+/// trait Entity {
+///     type Id;
+/// }
+///
+/// struct User;
+///
+/// impl Entity for User {
+///     type Id = u32;
+/// }
+///
+/// #[double::mock]
+/// trait Repository<T> where T: Entity {
+///     fn delete(&self, id: T::Id) -> bool;
+///     fn size(&self) -> usize where Self: Sized;
+/// }
+///
+/// # fn main() {
+/// let repository = MockRepository::<User>::default();
+/// repository.delete.return_value(true);
+/// assert!(repository.delete(7));
+/// assert!(repository.delete.called_with(7u32));
+/// # }
+/// ```
+#[proc_macro_attribute]
+pub fn mock(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let options = parse_macro_input!(attr as options::Options);
+    let expanded = match parse_macro_input!(item as syn::Item) {
+        syn::Item::Trait(ref item) => mock_trait::expand(&options, item),
+        syn::Item::Impl(ref item) => mock_impl::expand(&options, item),
+        ref other => Err(syn::Error::new_spanned(
+            other, "#[double::mock] can only be applied to traits and `impl` blocks")),
+    };
+    expanded.unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// Generates a mock for a trait defined in another crate, from a restatement
+/// of the trait.
+///
+/// `#[double::mock]` can't see the definitions of traits from other crates,
+/// so the trait is restated instead, named by its path, e.g.
+/// `trait std::fmt::Write { ... }`. The mock is generated like
+/// `#[double::mock]` generates mocks of local traits, with the same options,
+/// which are given in a `#[double::mock(...)]` attribute on the restatement.
+/// Only the trait's required methods need to be restated, along with any of
+/// its default methods that should be mocked too.
+///
+/// The restatement isn't generated. The mock implements the trait at the
+/// path instead, using the restated signatures, so the compiler checks that
+/// they match the trait's. When an update of the dependency changes the
+/// signature of a restated method, removes it, or adds a required method,
+/// the mock fails to compile, pointing at the restatement that is out of
+/// date. The path can give the trait's type arguments, e.g.
+/// `trait events::Handler<Click>`, and supertraits are restated after it as
+/// in a trait definition.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate double;
+/// use std::fmt::{self, Write};
+///
+/// double::mock_extern_trait! {
+///     #[double::mock(no_default, name = MockWriter)]
+///     trait std::fmt::Write {
+///         fn write_str(&mut self, s: &str) -> std::fmt::Result;
+///     }
+/// }
+///
+/// fn greet(out: &mut impl Write, name: &str) -> fmt::Result {
+///     write!(out, "hello, {}", name)
+/// }
+///
+/// # fn main() {
+/// let mut writer = MockWriter::new(Ok(()));
+/// greet(&mut writer, "ferris").unwrap();
+/// assert!(writer.write_str.called_with("ferris".to_owned()));
+/// writer.write_str.return_value(Err(fmt::Error));
+/// assert!(greet(&mut writer, "ferris").is_err());
+/// # }
+/// ```
+#[proc_macro]
+pub fn mock_extern_trait(input: TokenStream) -> TokenStream {
+    let restated = parse_macro_input!(input as extern_trait::ExternTrait);
+    extern_trait::expand(&restated).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// Attribute that replaces the types it lists by their mocks in tests, so
+/// that production code can depend on concrete types while its tests use
+/// doubles.
+///
+/// The item it's applied to is kept as it is outside of tests (i.e. under
+/// `#[cfg(not(test))]`). Under `#[cfg(test)]`, it's replaced by a copy in
+/// which each use of a listed type is a use of its mock instead, named like
+/// `#[double::mock]` names mocks: `#[double::mocked(mail::Mailer)]` replaces
+/// `mail::Mailer` by `mail::MockMailer`, keeping any type arguments. Applied
+/// to a `use` item, it imports the mock under the type's name (e.g.
+/// `use mail::MockMailer as Mailer;`), so the rest of the module uses the
+/// mock too. Types are matched by their path as written in the item, and
+/// listing a type that the item doesn't use is an error. Only unit tests are
+/// built with `cfg(test)`, so integration tests and doctests see the real
+/// types.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate double;
+/// mod mail {
+///     pub struct Mailer;
+///
+///     #[double::mock]
+///     impl Mailer {
+///         pub fn send(&self, to: &str) -> bool {
+///             !to.is_empty()
+///         }
+///     }
+/// }
+///
+/// #[double::mocked(mail::Mailer)]
+/// use mail::Mailer;
+///
+/// struct Signup {
+///     mailer: Mailer,
+/// }
+///
+/// impl Signup {
+///     fn register(&self, email: &str) -> bool {
+///         self.mailer.send(email)
+///     }
+/// }
+///
+/// #[cfg(test)]
+/// mod tests {
+///     use super::*;
+///
+///     #[test]
+///     fn register_sends_mail() {
+///         let signup = Signup { mailer: Mailer::default() };
+///         signup.mailer.send.return_value(true);
+///         assert!(signup.register("ferris@example.com"));
+///         assert!(signup.mailer.send.called_with("ferris@example.com".to_owned()));
+///     }
+/// }
+///
+/// # fn main() {
+/// let signup = Signup { mailer: mail::Mailer };
+/// assert!(signup.register("ferris@example.com"));
+/// # }
+/// ```
+// The example's unit test shows the mock in use, though doctests don't run it
+#[allow(clippy::test_attr_in_doctest)]
+#[proc_macro_attribute]
+pub fn mocked(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mocked = parse_macro_input!(attr as mocked::Mocked);
+    let item = parse_macro_input!(item as syn::Item);
+    mocked::expand(&mocked, &item).unwrap_or_else(syn::Error::into_compile_error).into()
+}
@@ -0,0 +1,111 @@
+// Mocks own the args and return values they record, so types that borrow are
+// changed before they are stored in a `Mock`. Args that are references are
+// stored as owned values (see `owned`), while the lifetimes of return values
+// are made `'static` (see `make_static`).
+
+use proc_macro2::Span;
+use syn::visit::{self, Visit};
+use syn::visit_mut::{self, VisitMut};
+use syn::{Lifetime, Type, TypeReference, WherePredicate};
+
+// Returns the owned type that the referenced arg type `reference` is stored
+// as, e.g. `String` for `&str`.
+pub(crate) fn owned(reference: &TypeReference) -> Type {
+    let referenced = &reference.elem;
+    syn::parse_quote!(<#referenced as ::std::borrow::ToOwned>::Owned)
+}
+
+// Replaces every lifetime in `ty`, including elided ones, with `'static`, so
+// values of `ty` can be stored by a mock and returned for any lifetime.
+pub(crate) fn make_static(ty: &Type) -> Type {
+    let mut ty = ty.clone();
+    MakeStatic.visit_type_mut(&mut ty);
+    ty
+}
+
+// Replaces every lifetime in `predicate` with `'static`, like `make_static`,
+// unless it has higher-ranked lifetimes (e.g. `for<'a> F: Fn(&'a str)`), in
+// which case it's left as is.
+pub(crate) fn make_static_predicate(predicate: &WherePredicate) -> WherePredicate {
+    let mut predicate = predicate.clone();
+    match predicate {
+        WherePredicate::Type(ref predicate) if predicate.lifetimes.is_some() => {},
+        _ => MakeStatic.visit_where_predicate_mut(&mut predicate),
+    }
+    predicate
+}
+
+// Returns true if `ty` borrows a value, i.e. it's or contains a reference or
+// a lifetime other than `'static`.
+pub(crate) fn borrows(ty: &Type) -> bool {
+    let mut finder = BorrowFinder { found: false };
+    finder.visit_type(ty);
+    finder.found
+}
+
+struct MakeStatic;
+
+impl VisitMut for MakeStatic {
+    fn visit_lifetime_mut(&mut self, lifetime: &mut Lifetime) {
+        *lifetime = static_lifetime();
+    }
+
+    fn visit_type_reference_mut(&mut self, reference: &mut TypeReference) {
+        reference.lifetime = Some(static_lifetime());
+        visit_mut::visit_type_reference_mut(self, reference);
+    }
+}
+
+struct BorrowFinder {
+    found: bool,
+}
+
+impl<'ast> Visit<'ast> for BorrowFinder {
+    fn visit_lifetime(&mut self, lifetime: &'ast Lifetime) {
+        self.found |= lifetime.ident != "static";
+    }
+
+    fn visit_type_reference(&mut self, reference: &'ast TypeReference) {
+        self.found = true;
+        visit::visit_type_reference(self, reference);
+    }
+}
+
+fn static_lifetime() -> Lifetime {
+    Lifetime::new("'static", Span::call_site())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::quote;
+    use syn::parse_quote;
+
+    #[test]
+    fn make_static_replaces_all_lifetimes() {
+        let ty = make_static(&parse_quote!(Option<(&'a str, &[Token<'b>])>));
+        assert_eq!(
+            quote!(Option<(&'static str, &'static [Token<'static>])>).to_string(),
+            quote!(#ty).to_string());
+    }
+
+    #[test]
+    fn make_static_predicate_keeps_higher_ranked_lifetimes() {
+        let predicate = make_static_predicate(&parse_quote!(T: Parser<'a> + 'b));
+        assert_eq!(
+            quote!(T: Parser<'static> + 'static).to_string(),
+            quote!(#predicate).to_string());
+        let predicate: WherePredicate = parse_quote!(for<'a> F: Fn(&'a str));
+        let pinned = make_static_predicate(&predicate);
+        assert_eq!(quote!(#predicate).to_string(), quote!(#pinned).to_string());
+    }
+
+    #[test]
+    fn borrows_finds_references_and_lifetimes() {
+        assert!(borrows(&parse_quote!(&str)));
+        assert!(borrows(&parse_quote!(Vec<Token<'a>>)));
+        assert!(!borrows(&parse_quote!(Option<Cow<'static, str>>)));
+        assert!(!borrows(&parse_quote!(HashMap<String, u32>)));
+    }
+}
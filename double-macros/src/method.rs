@@ -0,0 +1,530 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, quote_spanned};
+use syn::{Error, FnArg, GenericArgument, GenericParam, Generics, Ident, Pat, PathArguments,
+          Receiver, Result, ReturnType, Signature, Token, Type, TypeImplTrait, TypeParamBound,
+          TypeReference, Visibility};
+
+use generics;
+use lifetimes;
+use options::Projection;
+
+// The most args a mocked method can have, since mocks record them as a tuple,
+// which only implements `Debug`, `Eq` and `Hash` for up to 12 elements
+const MAX_ARGS: usize = 12;
+
+// A mocked method
+#[derive(Clone)]
+pub(crate) struct Method {
+    name: Ident,
+    // `None` for functions without a `self` receiver, e.g. constructors
+    receiver: Option<Receiver>,
+    unsafety: Option<Token![unsafe]>,
+    asyncness: Option<Token![async]>,
+    // The method's lifetime and type parameters, and `where` clause
+    generics: Generics,
+    // Whether the method has type parameters, including the anonymous ones of
+    // `impl Trait` args
+    is_generic: bool,
+    args: Vec<Arg>,
+    output: ReturnType,
+    // The type of the values returned by the method's mock, whose lifetimes
+    // are all `'static`
+    retval: Type,
+    // `retval`, or the type of the values of the `MockFuture` it is for async
+    // methods
+    returned: Type,
+    // The method of the method's mock that's called, i.e. `call`, or
+    // `call_ref` for methods that return references to data owned by `self`
+    call: Ident,
+    // Converts the values returned by the method's mock to the method's
+    // return type, e.g. `.into_iter()` for `impl Iterator`
+    conversion: TokenStream,
+}
+
+// An arg of a mocked method
+#[derive(Clone)]
+struct Arg {
+    name: Ident,
+    ty: Type,
+    // The type of the arg as stored by the method's mock, which is owned
+    stored_ty: Type,
+    // The value of the arg as stored by the method's mock
+    stored_value: TokenStream,
+    // Whether the arg's type is an `impl Trait`, or a reference to one
+    is_impl_trait: bool,
+}
+
+impl Method {
+    // Parses the method with signature `sig`. Args with a projection of their
+    // own in `projections` are recorded using it. Other args whose type is
+    // one of the method's type parameters, or an `impl Trait`, are recorded
+    // using the projection of the whole method, or as the name of their type
+    // if there's none.
+    pub(crate) fn parse(sig: &Signature, projections: &[&Projection]) -> Result<Self> {
+        let mut params = Vec::new();
+        for param in &sig.generics.params {
+            match *param {
+                GenericParam::Type(ref param) => params.push(&param.ident),
+                GenericParam::Lifetime(_) => (),
+                ref other => return Err(Error::new_spanned(
+                    other, "#[double::mock] does not support methods with const parameters")),
+            }
+        }
+        let receiver = match sig.receiver() {
+            Some(receiver) if receiver.colon_token.is_none() || is_smart_pointer(&receiver.ty) => {
+                let mut receiver = receiver.clone();
+                // The mock isn't mutated by consuming methods, even if the
+                // mocked type is
+                if receiver.reference.is_none() {
+                    receiver.mutability = None;
+                }
+                Some(receiver)
+            },
+            None => None,
+            _ => return Err(Error::new_spanned(
+                sig,
+                "#[double::mock] only supports methods that take `self`, `&self`, \
+                 `&mut self`, or `self` in a `Box`, `Rc`, `Arc` or `Pin`")),
+        };
+
+        let mut args = Vec::new();
+        let typed_inputs = sig.inputs.iter().filter_map(|input| match *input {
+            FnArg::Typed(ref arg) => Some(arg),
+            FnArg::Receiver(_) => None,
+        });
+        for (position, arg) in typed_inputs.enumerate() {
+            let name = match *arg.pat {
+                Pat::Ident(ref pat) => pat.ident.clone(),
+                _ => format_ident!("arg{}", position),
+            };
+            let projection = projections
+                .iter()
+                .find(|projection| projection.arg.as_ref() == Some(&name))
+                .or_else(|| projections.iter().find(|projection| projection.arg.is_none()));
+            args.push(Arg::new(name, &arg.ty, &params, projection.copied())?);
+        }
+        if args.len() > MAX_ARGS {
+            return Err(Error::new_spanned(
+                &sig.inputs,
+                format!("#[double::mock] only supports methods with at most {} args", MAX_ARGS)));
+        }
+
+        // The mock's `call_ref` is called instead for methods that return
+        // references to data owned by `self`, which are returned by reference
+        // to the owned values returned by the mock
+        let mut call = format_ident!("call");
+        let (retval, conversion) = match sig.output {
+            ReturnType::Default => (syn::parse_quote!(()), quote!()),
+            ReturnType::Type(_, ref retval) if generics::uses_params(retval, &params) => {
+                return Err(Error::new_spanned(
+                    retval,
+                    "#[double::mock] does not support return types that use the method's type \
+                     parameters"));
+            },
+            ReturnType::Type(_, ref retval) => match **retval {
+                Type::ImplTrait(ref retval) => impl_trait_retval(retval)?,
+                _ => match receiver.as_ref().and_then(|receiver| borrowed_from_self(receiver, retval)) {
+                    Some(reference) if sig.asyncness.is_none() => {
+                        call = format_ident!("call_ref");
+                        (lifetimes::owned(reference), quote!())
+                    },
+                    _ => (lifetimes::make_static(retval), quote!()),
+                },
+            },
+        };
+        // Async methods await a `MockFuture` returned by their mock, after the
+        // call is recorded. `.await` is spanned like the method's `async`, so
+        // that it's parsed in the edition of the mocked method, not this
+        // crate's.
+        let returned = retval.clone();
+        let (retval, conversion) = match sig.asyncness {
+            Some(ref asyncness) => (
+                syn::parse_quote!(::double::MockFuture<#retval>),
+                quote_spanned!(asyncness.span=> .await #conversion)),
+            None => (retval, conversion),
+        };
+        Ok(Method {
+            name: sig.ident.clone(),
+            receiver,
+            unsafety: sig.unsafety,
+            asyncness: sig.asyncness,
+            generics: sig.generics.clone(),
+            is_generic: !params.is_empty() || args.iter().any(|arg| arg.is_impl_trait),
+            args,
+            output: sig.output.clone(),
+            retval,
+            returned,
+            call,
+            conversion,
+        })
+    }
+
+    pub(crate) fn name(&self) -> &Ident {
+        &self.name
+    }
+
+    // Returns true if the method is a function without a `self` receiver
+    pub(crate) fn is_static(&self) -> bool {
+        self.receiver.is_none()
+    }
+
+    // Returns true if the method has an arg called `name`
+    pub(crate) fn has_arg(&self, name: &Ident) -> bool {
+        self.args.iter().any(|arg| arg.name == *name)
+    }
+
+    // Returns true if the method has type parameters, or `impl Trait` args
+    pub(crate) fn is_generic(&self) -> bool {
+        self.is_generic
+    }
+
+    // The types of the args as stored by the method's mock
+    pub(crate) fn stored_arg_types(&self) -> impl Iterator<Item = &Type> {
+        self.args.iter().map(|arg| &arg.stored_ty)
+    }
+
+    // The type of the values returned by the method, as returned by its mock
+    // (or by the `MockFuture` it returns, for async methods)
+    pub(crate) fn returned(&self) -> &Type {
+        &self.returned
+    }
+
+    // The type parameters of the method
+    pub(crate) fn type_params(&self) -> impl Iterator<Item = &Ident> {
+        self.generics.type_params().map(|param| &param.ident)
+    }
+
+    // The method as listed in `mock_trait!`, e.g. `profit(u32, u32) -> i32`
+    pub(crate) fn mock_signature(&self) -> TokenStream {
+        let name = &self.name;
+        let arg_types = self.args.iter().map(|arg| &arg.stored_ty);
+        let retval = &self.retval;
+        quote!(#name(#(#arg_types),*) -> #retval)
+    }
+
+    // The bounds that impls of the mock need on the method's args and return
+    // value, i.e. those of `mock_trait!`
+    pub(crate) fn bounds(&self) -> TokenStream {
+        self.bounds_for(&quote!())
+    }
+
+    // The bounds of `bounds`, made higher-ranked like those of the mocks of
+    // checked methods (see `checks`)
+    pub(crate) fn checked_bounds(&self) -> TokenStream {
+        self.bounds_for(&quote!(for<'__double>))
+    }
+
+    fn bounds_for(&self, binder: &TokenStream) -> TokenStream {
+        let args = self.stored_args_type();
+        let retval = &self.retval;
+        quote! {
+            #binder #args: Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+            #binder #retval: Clone + ::std::fmt::Debug,
+        }
+    }
+
+    // The type parameters of the method's mock, e.g. `(u32, u32), i32` for
+    // `::double::Mock<(u32, u32), i32>`
+    pub(crate) fn mock_params(&self) -> TokenStream {
+        let args = self.stored_args_type();
+        let retval = &self.retval;
+        quote!(#args, #retval)
+    }
+
+    // The type of the args as stored by the method's mock, which is a tuple
+    // unless there's only one
+    fn stored_args_type(&self) -> TokenStream {
+        let arg_types: Vec<&Type> = self.args.iter().map(|arg| &arg.stored_ty).collect();
+        if arg_types.len() == 1 {
+            quote!(#(#arg_types)*)
+        } else {
+            quote!((#(#arg_types),*))
+        }
+    }
+
+    // Implementation of the method that calls the mock of the same name, like
+    // the ones generated by `mock_method!`. Borrowed args are converted to
+    // owned values first.
+    pub(crate) fn implementation(&self, vis: &Visibility) -> TokenStream {
+        let name = &self.name;
+        let call = &self.call;
+        let args = self.stored_args();
+        let conversion = &self.conversion;
+        self.definition(vis, quote!(self.#name.#call(#args)#conversion))
+    }
+
+    // Implementation of a function without a `self` receiver, which calls the
+    // mock in the thread-local `MockSlot` named `slot`, and panics with
+    // `message` if there's none
+    pub(crate) fn static_implementation(
+        &self,
+        vis: &Visibility,
+        slot: &Ident,
+        message: &str) -> TokenStream
+    {
+        let args = self.stored_args();
+        let conversion = &self.conversion;
+        self.definition(vis, quote! {
+            let mock = #slot
+                .with(::double::MockSlot::mock)
+                .unwrap_or_else(|| panic!(#message));
+            mock.call(#args)#conversion
+        })
+    }
+
+    // The method's signature, followed by `body`
+    fn definition(&self, vis: &Visibility, body: TokenStream) -> TokenStream {
+        let name = &self.name;
+        let unsafety = &self.unsafety;
+        let asyncness = &self.asyncness;
+        let generics = &self.generics;
+        let where_clause = &self.generics.where_clause;
+        let receiver = self.receiver.iter().map(|receiver| quote!(#receiver));
+        let args = self.args.iter().map(|arg| {
+            let (name, ty) = (&arg.name, &arg.ty);
+            quote!(#name: #ty)
+        });
+        let inputs = receiver.chain(args);
+        let output = &self.output;
+        quote! {
+            #[track_caller]
+            #vis #asyncness #unsafety fn #name #generics(#(#inputs),*) #output
+                #where_clause
+            {
+                #body
+            }
+        }
+    }
+
+    // The values of the args as stored by the method's mock, which are a
+    // tuple unless there's only one
+    fn stored_args(&self) -> TokenStream {
+        let stored_args: Vec<&TokenStream> =
+            self.args.iter().map(|arg| &arg.stored_value).collect();
+        if stored_args.len() == 1 {
+            quote!(#(#stored_args)*)
+        } else {
+            quote!((#(#stored_args),*))
+        }
+    }
+}
+
+// Returns the type that the method returns a reference to, if it's borrowed
+// from `self`, i.e. the reference's lifetime is elided or that of `receiver`,
+// and the referenced type owns its data (e.g. `str` in `&str`)
+fn borrowed_from_self<'a>(receiver: &Receiver, retval: &'a Type) -> Option<&'a TypeReference> {
+    let reference = match *retval {
+        Type::Reference(ref reference) if reference.mutability.is_none() => reference,
+        _ => return None,
+    };
+    let receiver_lifetime = match receiver.reference {
+        Some((_, ref lifetime)) => lifetime,
+        None => return None,
+    };
+    let borrows_self = match (&reference.lifetime, receiver_lifetime) {
+        (None, _) => true,
+        (Some(lifetime), Some(receiver_lifetime)) => lifetime == receiver_lifetime,
+        (Some(_), None) => false,
+    };
+    if borrows_self && !lifetimes::borrows(&reference.elem) {
+        Some(reference)
+    } else {
+        None
+    }
+}
+
+// Returns true if the type of a typed `self` receiver is a smart pointer to
+// `Self`, e.g. `Box<Self>` or `Pin<&mut Self>`, whose fields can be accessed
+// through it.
+fn is_smart_pointer(ty: &Type) -> bool {
+    let (pointer, pointee) = match pointer(ty) {
+        Some(pointer) => pointer,
+        None => return false,
+    };
+    match &*pointer.to_string() {
+        "Box" | "Rc" | "Arc" => generics::is_self(pointee),
+        "Pin" => match *pointee {
+            Type::Reference(ref reference) => generics::is_self(&reference.elem),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// Returns true if `ty` is a trait object behind a reference or a `Box`, `Rc`
+// or `Arc`, e.g. `&dyn Display` or `Box<dyn Fn(u32)>`
+fn is_trait_object(ty: &Type) -> bool {
+    let pointee = match *ty {
+        Type::Reference(ref reference) => &*reference.elem,
+        _ => match pointer(ty) {
+            Some((pointer, pointee)) if pointer == "Box" || pointer == "Rc" || pointer == "Arc" => {
+                pointee
+            },
+            _ => return false,
+        },
+    };
+    match *pointee {
+        Type::TraitObject(_) => true,
+        Type::Paren(ref paren) => matches!(*paren.elem, Type::TraitObject(_)),
+        _ => false,
+    }
+}
+
+// Returns the name of the pointer `ty`, e.g. `Box`, and the type it points
+// to, if `ty` is a path with a single type argument
+fn pointer(ty: &Type) -> Option<(&Ident, &Type)> {
+    let segment = match *ty {
+        Type::Path(ref path) if path.qself.is_none() => path.path.segments.last()?,
+        _ => return None,
+    };
+    match segment.arguments {
+        PathArguments::AngleBracketed(ref args) if args.args.len() == 1 => match args.args[0] {
+            GenericArgument::Type(ref pointee) => Some((&segment.ident, pointee)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Returns the type of the values returned by the mock of a method that
+// returns the `impl Trait` `ty`, and how they're converted to it. Iterators
+// are returned by mocks as a `Vec` of their items, and futures as a
+// `MockFuture`, since the types that implement them usually can't be cloned.
+fn impl_trait_retval(ty: &TypeImplTrait) -> Result<(Type, TokenStream)> {
+    for bound in &ty.bounds {
+        let segment = match *bound {
+            TypeParamBound::Trait(ref bound) => match bound.path.segments.last() {
+                Some(segment) => segment,
+                None => continue,
+            },
+            _ => continue,
+        };
+        // The associated type the trait is bound by, e.g. `Item = u32`
+        let binding = match segment.arguments {
+            PathArguments::AngleBracketed(ref args) => args.args.iter().find_map(|arg| match *arg {
+                GenericArgument::AssocType(ref binding) => Some(binding),
+                _ => None,
+            }),
+            _ => None,
+        };
+        let binding = match binding {
+            Some(binding) => binding,
+            None => continue,
+        };
+        let value = lifetimes::make_static(&binding.ty);
+        match (&*segment.ident.to_string(), &*binding.ident.to_string()) {
+            ("Iterator", "Item") | ("DoubleEndedIterator", "Item") |
+            ("ExactSizeIterator", "Item") => {
+                return Ok((syn::parse_quote!(Vec<#value>), quote!(.into_iter())));
+            },
+            ("IntoIterator", "Item") => return Ok((syn::parse_quote!(Vec<#value>), quote!())),
+            ("Future", "Output") => {
+                return Ok((syn::parse_quote!(::double::MockFuture<#value>), quote!()));
+            },
+            _ => (),
+        }
+    }
+    Err(Error::new_spanned(
+        ty,
+        "#[double::mock] only supports `impl Trait` return types that are iterators or \
+         futures (e.g. `impl Iterator<Item = u32>` or `impl Future<Output = u32>`)"))
+}
+
+impl Arg {
+    // Parses the arg `name` of type `ty`, of a method with the type parameters
+    // `params`, where `projection` is the arg's or the method's projection
+    fn new(
+        name: Ident,
+        ty: &Type,
+        params: &[&Ident],
+        projection: Option<&Projection>) -> Result<Self>
+    {
+        let (referenced, is_reference) = match *ty {
+            Type::Reference(ref reference) => (&*reference.elem, true),
+            _ => (ty, false),
+        };
+        if is_param(referenced, params) || matches!(*referenced, Type::ImplTrait(_)) {
+            return Ok(Arg::projected(name, ty, referenced, is_reference, projection));
+        }
+        if let Some(projection) = projection.filter(|projection| projection.arg.is_some()) {
+            let is_reference = is_reference || is_trait_object(ty);
+            return Ok(Arg::projected(name, ty, referenced, is_reference, Some(projection)));
+        }
+        // Trait objects can't be cloned or converted to owned values, so
+        // they're stored as the name of their type, like type parameters
+        if is_trait_object(ty) {
+            return Ok(Arg {
+                stored_ty: syn::parse_quote!(&'static str),
+                stored_value: quote!(::std::any::type_name_of_val(&*#name)),
+                name,
+                ty: ty.clone(),
+                is_impl_trait: false,
+            });
+        }
+        if generics::uses_params(ty, params) {
+            return Err(Error::new_spanned(
+                ty,
+                "#[double::mock] only supports args that use the method's type parameters if \
+                 their type is one of them (e.g. `T` or `&T`)"));
+        }
+        if generics::uses_impl_trait(ty) {
+            return Err(Error::new_spanned(
+                ty,
+                "#[double::mock] only supports `impl Trait` args if their type is one (e.g. \
+                 `impl Display` or `&impl Display`)"));
+        }
+
+        let (stored_ty, stored_value) = match *ty {
+            Type::Reference(ref reference) => {
+                (lifetimes::owned(reference), quote!(::std::borrow::ToOwned::to_owned(&*#name)))
+            },
+            _ => (ty.clone(), quote!(#name)),
+        };
+        if lifetimes::borrows(&stored_ty) {
+            return Err(Error::new_spanned(
+                ty,
+                "#[double::mock] only supports args that are owned, or references to types \
+                 that own their data (e.g. `&str` or `&[u32]`)"));
+        }
+        Ok(Arg { name, ty: ty.clone(), stored_ty, stored_value, is_impl_trait: false })
+    }
+
+    // An arg whose type is the type parameter `param` or an `impl Trait`
+    // (which is an anonymous type parameter), or a reference to it, which is
+    // stored as its projection or its type's name, or an arg of any type with
+    // a projection of its own
+    fn projected(
+        name: Ident,
+        ty: &Type,
+        param: &Type,
+        is_reference: bool,
+        projection: Option<&Projection>) -> Self
+    {
+        let value = if is_reference { quote!(&*#name) } else { quote!(&#name) };
+        let is_impl_trait = matches!(*param, Type::ImplTrait(_));
+        let (stored_ty, stored_value) = match projection {
+            Some(projection) => {
+                let function = &projection.function;
+                (projection.ty.clone(), quote!(#function(#value)))
+            },
+            // The anonymous type of an `impl Trait` can't be named
+            None if is_impl_trait => {
+                (syn::parse_quote!(&'static str), quote!(::std::any::type_name_of_val(#value)))
+            },
+            None => {
+                (syn::parse_quote!(&'static str), quote!(::std::any::type_name::<#param>()))
+            },
+        };
+        Arg { name, ty: ty.clone(), stored_ty, stored_value, is_impl_trait }
+    }
+}
+
+// Returns true if `ty` is one of the type parameters `params`
+fn is_param(ty: &Type, params: &[&Ident]) -> bool {
+    match *ty {
+        Type::Path(ref path) if path.qself.is_none() => {
+            params.iter().any(|param| path.path.is_ident(*param))
+        },
+        _ => false,
+    }
+}
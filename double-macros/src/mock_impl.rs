@@ -0,0 +1,151 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Error, ImplItem, ItemImpl, Result, Type, Visibility};
+
+use generics;
+use method::Method;
+use options::Options;
+
+// Generates the `impl` block, followed by a mock of the type it implements.
+// The mock has a method of the same name and signature as each method of the
+// `impl` block that takes `self`, so it can be used in place of the type.
+pub(crate) fn expand(options: &Options, item: &ItemImpl) -> Result<TokenStream> {
+    if let Some((_, ref path, _)) = item.trait_ {
+        return Err(Error::new_spanned(
+            path, "#[double::mock] should be applied to the trait's definition instead"));
+    }
+    if let Some((name, _)) = options.types.first() {
+        return Err(Error::new_spanned(
+            name, "#[double::mock] only pins the associated types of traits"));
+    }
+    if let Some((name, _)) = options.consts.first() {
+        return Err(Error::new_spanned(
+            name, "#[double::mock] only gives values to the associated consts of traits"));
+    }
+    if let Some(name) = options.passthrough.first() {
+        return Err(Error::new_spanned(
+            name, "#[double::mock] only passes through to the default methods of traits"));
+    }
+    if let Some(supertrait) = options.supertraits.first() {
+        return Err(Error::new_spanned(
+            &supertrait.path, "#[double::mock] only implements the supertraits of traits"));
+    }
+    let params = generics::type_params(&item.generics)?;
+    let predicates = generics::predicates(&item.generics);
+    // The type's name, which is followed by its type parameters if it has any
+    let type_name = match *item.self_ty {
+        Type::Path(ref path) if path.qself.is_none() && path.path.segments.len() == 1 => {
+            Some(&path.path.segments[0].ident)
+        },
+        _ => None,
+    };
+    let type_name = type_name.ok_or_else(|| Error::new_spanned(
+        &item.self_ty, "#[double::mock] only supports `impl` blocks of named types"))?;
+
+    // Associated functions (e.g. constructors) are not mocked
+    let mut methods = Vec::new();
+    let mut visibilities = Vec::new();
+    for impl_item in &item.items {
+        if let ImplItem::Fn(ref method) = *impl_item {
+            if method.sig.receiver().is_some() {
+                methods.push(Method::parse(&method.sig, &options.projections(&method.sig.ident))?);
+                visibilities.push(method.vis.clone());
+            }
+        }
+    }
+    options.check_projections(&methods)?;
+
+    // The mock is public if any of its methods are, or else as visible as the
+    // first of them that's visible outside its module
+    let vis = visibilities
+        .iter()
+        .find(|vis| matches!(**vis, Visibility::Public(_)))
+        .or_else(|| visibilities.iter().find(|vis| matches!(**vis, Visibility::Restricted(_))))
+        .cloned()
+        .unwrap_or(Visibility::Inherited);
+    let mock_name = options.mock_name(type_name);
+    let mock_struct = options.mock_struct(&vis, &mock_name, &params, &predicates, &methods);
+    let (impl_generics, _, _) = item.generics.split_for_impl();
+    let mock_generics = generics::mock_type_generics(&params);
+    let where_clause = generics::mock_where_clause(&params, &predicates, &methods);
+    let implementations = methods
+        .iter()
+        .zip(&visibilities)
+        .map(|(method, vis)| method.implementation(&options.visibility(vis)));
+    let mock = options.in_module(&vis, quote! {
+        #mock_struct
+
+        impl #impl_generics #mock_name #mock_generics #where_clause {
+            #(#implementations)*
+        }
+    });
+
+    Ok(quote! {
+        #item
+
+        #mock
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn expand_error(item: ItemImpl) -> String {
+        expand(&Options::default(), &item).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn mocks_methods_that_take_self() {
+        let item: ItemImpl = parse_quote! {
+            impl Mailer {
+                pub fn new() -> Self {
+                    Mailer
+                }
+
+                pub fn send(&mut self, to: String) -> bool {
+                    true
+                }
+
+                fn retries(&self) -> u32 {
+                    3
+                }
+            }
+        };
+        let expected = quote! {
+            #item
+
+            ::double::mock_trait!(pub MockMailer, send(String) -> bool, retries() -> u32);
+
+            impl MockMailer {
+                #[track_caller]
+                pub fn send(&mut self, to: String) -> bool {
+                    self.send.call(to)
+                }
+
+                #[track_caller]
+                fn retries(&self) -> u32 {
+                    self.retries.call(())
+                }
+            }
+        };
+        assert_eq!(
+            expected.to_string(),
+            expand(&Options::default(), &item).unwrap().to_string());
+    }
+
+    #[test]
+    fn rejects_unsupported_impl_blocks() {
+        assert_eq!(
+            "#[double::mock] should be applied to the trait's definition instead",
+            expand_error(parse_quote!(impl Clone for Mailer {})));
+        assert_eq!(
+            "#[double::mock] does not support const parameters",
+            expand_error(parse_quote!(impl<const N: usize> Mailer<N> {})));
+        assert_eq!(
+            "#[double::mock] only supports `impl` blocks of named types",
+            expand_error(parse_quote!(impl (u32, u32) {})));
+    }
+}
@@ -0,0 +1,818 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Attribute, Error, ItemTrait, Result, TraitItem, Visibility};
+
+use associated;
+use checks;
+use generics;
+use method::Method;
+use options::Options;
+use static_fns;
+use supertraits;
+
+// Generates the trait, followed by its mock and the mock's implementations of
+// the trait and of the supertraits whose methods are mocked with it.
+pub(crate) fn expand(options: &Options, item: &ItemTrait) -> Result<TokenStream> {
+    let trait_name = &item.ident;
+    let (_, type_generics, _) = item.generics.split_for_impl();
+    let mock = mock(options, item, &quote!(#trait_name #type_generics))?;
+    Ok(quote! {
+        #item
+
+        #mock
+    })
+}
+
+// Generates the mock of `item` and its implementations of the trait at
+// `trait_path` and of the supertraits whose methods are mocked with it
+pub(crate) fn mock(options: &Options, item: &ItemTrait, trait_path: &TokenStream) -> Result<TokenStream> {
+    let params = generics::type_params(&item.generics)?;
+    let predicates = generics::predicates(&item.generics);
+    let types = associated::type_items(item, &options.types)?;
+    let consts = associated::const_items(item, &options.consts)?;
+    check_passthrough(options, item)?;
+    let trait_name = &item.ident;
+    let mock_name = options.mock_name(trait_name);
+    let mut methods = Vec::new();
+    for trait_item in &item.items {
+        match *trait_item {
+            // Calls fall through to the default implementation
+            TraitItem::Fn(ref method) if options.passthrough.contains(&method.sig.ident) => {},
+            // Functions without a `self` receiver call the mock in a thread-local
+            // slot (see `static_fns`)
+            TraitItem::Fn(ref method) if method.sig.receiver().is_none() => {
+                if !params.is_empty() {
+                    return Err(Error::new_spanned(
+                        &method.sig,
+                        "#[double::mock] only supports functions without a `self` receiver in \
+                         traits without type parameters"));
+                }
+                let sig = associated::pin_types(&method.sig, &options.types);
+                let sig = static_fns::replace_self(&sig, &mock_name);
+                methods.push(Method::parse(&sig, &options.projections(&sig.ident))?);
+            },
+            TraitItem::Fn(ref method) => {
+                let sig = associated::pin_types(&method.sig, &options.types);
+                methods.push(Method::parse(&sig, &options.projections(&sig.ident))?);
+            },
+            TraitItem::Type(_) | TraitItem::Const(_) => {},
+            ref other => return Err(Error::new_spanned(
+                other,
+                "#[double::mock] only supports traits whose items are methods, associated \
+                 types and associated consts")),
+        }
+    }
+
+    let supertraits = supertraits::mocked(options, item)?;
+    let (functions, methods): (Vec<Method>, Vec<Method>) =
+        methods.into_iter().partition(Method::is_static);
+    // The methods of the mock, including those of the supertraits
+    let mut mock_methods = methods.clone();
+    for supertrait in &supertraits {
+        mock_methods.extend(supertrait.methods.iter().cloned());
+    }
+    options.check_projections(&[&mock_methods[..], &functions[..]].concat())?;
+
+    let checks = checks::checks(
+        &params, &[&mock_methods[..], &functions[..]].concat(), !options.no_default);
+    let unsafety = &item.unsafety;
+    let async_trait = async_trait_attrs(item);
+    let mock_struct = options.checked_mock_struct(
+        &item.vis, &mock_name, &params, &predicates, &mock_methods);
+    let (impl_generics, _, _) = item.generics.split_for_impl();
+    let mock_generics = generics::mock_type_generics(&params);
+    let where_clause = generics::checked_where_clause(&predicates, &mock_methods);
+    let implementations = methods
+        .iter()
+        .map(|method| method.implementation(&Visibility::Inherited))
+        .chain(functions
+            .iter()
+            .map(|function| static_fns::implementation(trait_name, &mock_name, function)));
+    let slots = static_fns::slots(&options.mock_vis(&item.vis), &mock_name, &functions);
+    let supertrait_impls = supertraits.iter().map(|supertrait| {
+        let path = supertrait.path;
+        let implementations = supertrait.methods
+            .iter()
+            .map(|method| method.implementation(&Visibility::Inherited));
+        quote! {
+            impl #impl_generics #path for #mock_name #mock_generics
+                #where_clause
+            {
+                #(#implementations)*
+            }
+        }
+    });
+
+    Ok(options.in_module(&item.vis, quote! {
+        #checks
+
+        #mock_struct
+
+        #slots
+
+        #(#async_trait)*
+        #unsafety impl #impl_generics #trait_path for #mock_name #mock_generics
+            #where_clause
+        {
+            #(#types)*
+            #(#consts)*
+
+            #(#implementations)*
+        }
+
+        #(#supertrait_impls)*
+    }))
+}
+
+// The `#[async_trait]` attributes of `item`, which the mock's implementation of
+// the trait needs too, so its async methods are desugared like the trait's
+fn async_trait_attrs(item: &ItemTrait) -> Vec<&Attribute> {
+    item.attrs
+        .iter()
+        .filter(|attr| {
+            attr.path().segments.last().is_some_and(|segment| segment.ident == "async_trait")
+        })
+        .collect()
+}
+
+// Checks that each method listed in the `passthrough` option is a method of
+// `item` with a default implementation
+fn check_passthrough(options: &Options, item: &ItemTrait) -> Result<()> {
+    for name in &options.passthrough {
+        let method = item.items.iter().find_map(|trait_item| match *trait_item {
+            TraitItem::Fn(ref method) if method.sig.ident == *name => Some(method),
+            _ => None,
+        });
+        match method {
+            Some(method) if method.default.is_some() => {},
+            Some(_) => return Err(Error::new_spanned(
+                name,
+                format!("method `{}` has no default implementation to pass through to", name))),
+            None => return Err(Error::new_spanned(
+                name, format!("`{}` has no method `{}`", item.ident, name))),
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::format_ident;
+    use syn::parse_quote;
+
+    fn expand_error(item: ItemTrait) -> String {
+        expand(&Options::default(), &item).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn generates_mock_and_trait_implementation() {
+        let item: ItemTrait = parse_quote! {
+            pub trait Store {
+                fn get(&self, id: u32) -> String;
+                fn put(&mut self, id: u32, value: String);
+                fn clear(&self);
+            }
+        };
+        let expected = quote! {
+            #item
+
+            const _: () = {
+                #[allow(dead_code, non_camel_case_types)]
+                mod __double_methods {
+                    pub struct get;
+                    pub struct put;
+                }
+
+                let _ = ::double::checks::recorded_arg::<__double_methods::get, u32>;
+                let _ = ::double::checks::returned_value::<__double_methods::get, String>;
+                let _ = ::double::checks::default_value::<__double_methods::get, String>;
+                let _ = ::double::checks::recorded_arg::<__double_methods::put, u32>;
+                let _ = ::double::checks::recorded_arg::<__double_methods::put, String>;
+            };
+
+            ::double::__private_mock_trait!(
+                @checked [] pub MockStore [] [],
+                get(u32) -> String, put(u32, String) -> (), clear() -> ());
+            ::double::__private_mock_trait_default_impl!(
+                @checked MockStore [] [],
+                get(u32) -> String, put(u32, String) -> (), clear() -> ());
+
+            impl Store for MockStore
+                where for<'__double> u32: Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> String: Clone + ::std::fmt::Debug,
+                      for<'__double> (u32, String): Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> (): Clone + ::std::fmt::Debug,
+                      for<'__double> (): Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> (): Clone + ::std::fmt::Debug,
+            {
+                #[track_caller]
+                fn get(&self, id: u32) -> String {
+                    self.get.call(id)
+                }
+
+                #[track_caller]
+                fn put(&mut self, id: u32, value: String) {
+                    self.put.call((id, value))
+                }
+
+                #[track_caller]
+                fn clear(&self) {
+                    self.clear.call(())
+                }
+            }
+        };
+        assert_eq!(
+            expected.to_string(),
+            expand(&Options::default(), &item).unwrap().to_string());
+    }
+
+    #[test]
+    fn consuming_methods_take_the_mock_by_value() {
+        let item: ItemTrait = parse_quote!(trait Builder { fn build(mut self) -> String; });
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            impl Builder for MockBuilder
+                where for<'__double> (): Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> String: Clone + ::std::fmt::Debug,
+            {
+                #[track_caller]
+                fn build(self) -> String {
+                    self.build.call(())
+                }
+            }
+        }.to_string()));
+    }
+
+    #[test]
+    fn smart_pointer_receivers_are_kept() {
+        let item: ItemTrait = parse_quote! {
+            trait Actor {
+                fn run(self: Arc<Self>, task: u32);
+                fn poll(self: Pin<&mut Self>) -> bool;
+            }
+        };
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            impl Actor for MockActor
+                where for<'__double> u32: Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> (): Clone + ::std::fmt::Debug,
+                      for<'__double> (): Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> bool: Clone + ::std::fmt::Debug,
+            {
+                #[track_caller]
+                fn run(self: Arc<Self>, task: u32) {
+                    self.run.call(task)
+                }
+
+                #[track_caller]
+                fn poll(self: Pin<&mut Self>) -> bool {
+                    self.poll.call(())
+                }
+            }
+        }.to_string()));
+    }
+
+    #[test]
+    fn unsafe_methods_and_traits_stay_unsafe() {
+        let item: ItemTrait = parse_quote! {
+            unsafe trait Device {
+                unsafe fn read(&self, address: u32) -> u8;
+            }
+        };
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            unsafe impl Device for MockDevice
+                where for<'__double> u32: Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> u8: Clone + ::std::fmt::Debug,
+            {
+                #[track_caller]
+                unsafe fn read(&self, address: u32) -> u8 {
+                    self.read.call(address)
+                }
+            }
+        }.to_string()));
+    }
+
+    #[test]
+    fn generic_methods_record_type_names_or_projections() {
+        let item: ItemTrait = parse_quote! {
+            trait Encoder {
+                fn encode<T: Serialize>(&self, value: &T, id: u32) -> usize;
+            }
+        };
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            ::double::__private_mock_trait!(
+                @checked [] MockEncoder [] [], encode(&'static str, u32) -> usize);
+        }.to_string()));
+        assert!(expanded.contains(&quote! {
+            fn encode<T: Serialize>(&self, value: &T, id: u32) -> usize {
+                self.encode.call((::std::any::type_name::<T>(), id))
+            }
+        }.to_string()));
+
+        let options = Options {
+            projections: vec!(parse_quote!(encode = bytes -> Vec<u8>)),
+            ..Options::default()
+        };
+        let expanded = expand(&options, &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            ::double::__private_mock_trait!(
+                @checked [] MockEncoder [] [], encode(Vec<u8>, u32) -> usize);
+        }.to_string()));
+        assert!(expanded.contains(&quote!(self.encode.call((bytes(&*value), id))).to_string()));
+
+        let options = Options {
+            projections: vec!(parse_quote!(decode = bytes -> Vec<u8>)),
+            ..Options::default()
+        };
+        assert_eq!(
+            "there is no generic method `decode` to project the args of",
+            expand(&options, &item).unwrap_err().to_string());
+    }
+
+    #[test]
+    fn args_with_projections_of_their_own_are_recorded_using_them() {
+        let item: ItemTrait = parse_quote! {
+            trait Network {
+                fn send(&self, packet: &Packet, retries: u32, body: Body);
+            }
+        };
+        let options = Options {
+            projections: vec!(
+                parse_quote!(send.packet = Packet::id -> u64),
+                parse_quote!(send.body = Body::to_vec -> Vec<u8>)),
+            ..Options::default()
+        };
+        let expanded = expand(&options, &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            ::double::__private_mock_trait!(
+                @checked [] MockNetwork [] [], send(u64, u32, Vec<u8>) -> ());
+        }.to_string()));
+        assert!(expanded.contains(
+            &quote!(self.send.call((Packet::id(&*packet), retries, Body::to_vec(&body))))
+                .to_string()));
+
+        let options = Options {
+            projections: vec!(parse_quote!(send.payload = Body::to_vec -> Vec<u8>)),
+            ..Options::default()
+        };
+        assert_eq!(
+            "method `send` has no arg `payload` to project",
+            expand(&options, &item).unwrap_err().to_string());
+    }
+
+    #[test]
+    fn trait_object_args_record_type_names_or_projections() {
+        let item: ItemTrait = parse_quote! {
+            trait Renderer {
+                fn draw(&self, shape: &dyn Shape, callback: Box<dyn Fn(u32) + Send>);
+            }
+        };
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            ::double::__private_mock_trait!(
+                @checked [] MockRenderer [] [], draw(&'static str, &'static str) -> ());
+        }.to_string()));
+        assert!(expanded.contains(&quote! {
+            self.draw.call((
+                ::std::any::type_name_of_val(&*shape),
+                ::std::any::type_name_of_val(&*callback)))
+        }.to_string()));
+
+        let options = Options {
+            projections: vec!(parse_quote!(draw.shape = Shape::name -> String)),
+            ..Options::default()
+        };
+        let expanded = expand(&options, &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            ::double::__private_mock_trait!(
+                @checked [] MockRenderer [] [], draw(String, &'static str) -> ());
+        }.to_string()));
+        assert!(expanded.contains(&quote!(Shape::name(&*shape)).to_string()));
+    }
+
+    #[test]
+    fn impl_trait_return_types_are_converted_from_the_mock() {
+        let item: ItemTrait = parse_quote! {
+            trait Inbox {
+                fn unread(&self) -> impl Iterator<Item = &'static str> + Send;
+                fn fetch(&self, id: u32) -> impl Future<Output = String>;
+            }
+        };
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            ::double::__private_mock_trait!(
+                @checked [] MockInbox [] [],
+                unread() -> Vec<&'static str>,
+                fetch(u32) -> ::double::MockFuture<String>);
+        }.to_string()));
+        assert!(expanded.contains(&quote! {
+            fn unread(&self) -> impl Iterator<Item = &'static str> + Send {
+                self.unread.call(()).into_iter()
+            }
+        }.to_string()));
+        assert!(expanded.contains(&quote! {
+            fn fetch(&self, id: u32) -> impl Future<Output = String> {
+                self.fetch.call(id)
+            }
+        }.to_string()));
+    }
+
+    #[test]
+    fn async_methods_await_mock_futures() {
+        let item: ItemTrait = parse_quote! {
+            trait Client {
+                async fn get(&self, path: &str) -> String;
+                async fn close(&self);
+            }
+        };
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            ::double::__private_mock_trait!(
+                @checked [] MockClient [] [],
+                get(<str as ::std::borrow::ToOwned>::Owned) -> ::double::MockFuture<String>,
+                close() -> ::double::MockFuture<()>);
+        }.to_string()));
+        assert!(expanded.contains(&quote! {
+            async fn get(&self, path: &str) -> String {
+                self.get.call(::std::borrow::ToOwned::to_owned(&*path)).await
+            }
+        }.to_string()));
+        assert!(expanded.contains(&quote! {
+            async fn close(&self) {
+                self.close.call(()).await
+            }
+        }.to_string()));
+
+        let item: ItemTrait = parse_quote! {
+            #[async_trait::async_trait]
+            trait Client {
+                async fn close(&self);
+            }
+        };
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(
+            &quote!(#[async_trait::async_trait] impl Client for MockClient).to_string()));
+    }
+
+    #[test]
+    fn references_borrowed_from_self_are_kept_by_the_mock() {
+        let item: ItemTrait = parse_quote! {
+            trait Account<'a> {
+                fn owner(&self) -> &str;
+                fn key<'k>(&'k self) -> &'k [u8];
+                fn label(&self) -> &'a str;
+            }
+        };
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            ::double::__private_mock_trait!(
+                @checked [] MockAccount [] [],
+                owner() -> <str as ::std::borrow::ToOwned>::Owned,
+                key() -> <[u8] as ::std::borrow::ToOwned>::Owned,
+                label() -> &'static str);
+        }.to_string()));
+        assert!(expanded.contains(&quote! {
+            fn owner(&self) -> &str {
+                self.owner.call_ref(())
+            }
+        }.to_string()));
+        assert!(expanded.contains(&quote!(self.key.call_ref(())).to_string()));
+        assert!(expanded.contains(&quote!(self.label.call(())).to_string()));
+    }
+
+    #[test]
+    fn impl_trait_args_are_anonymous_type_parameters() {
+        let item: ItemTrait = parse_quote! {
+            trait Logger {
+                fn log(&self, message: impl Display, level: &impl Debug);
+            }
+        };
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            ::double::__private_mock_trait!(
+                @checked [] MockLogger [] [], log(&'static str, &'static str) -> ());
+        }.to_string()));
+        assert!(expanded.contains(&quote! {
+            fn log(&self, message: impl Display, level: &impl Debug) {
+                self.log.call((
+                    ::std::any::type_name_of_val(&message),
+                    ::std::any::type_name_of_val(&*level)))
+            }
+        }.to_string()));
+
+        let options = Options {
+            projections: vec!(parse_quote!(log = to_string -> String)),
+            ..Options::default()
+        };
+        let expanded = expand(&options, &item).unwrap().to_string();
+        assert!(expanded.contains(
+            &quote!(self.log.call((to_string(&message), to_string(&*level)))).to_string()));
+    }
+
+    #[test]
+    fn functions_without_receivers_call_thread_local_mocks() {
+        let item: ItemTrait = parse_quote! {
+            pub trait Shape {
+                fn parse(s: String) -> Result<Self, String>;
+                fn area(&self) -> u32;
+            }
+        };
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(
+            &quote!(::double::__private_mock_trait!(
+                @checked [] pub MockShape [] [], area() -> u32);).to_string()));
+        assert!(expanded.contains(&quote! {
+            ::std::thread_local! {
+                static __DOUBLE_MOCK_SLOT_MOCKSHAPE_PARSE:
+                    ::double::MockSlot<String, Result<MockShape, String> > =
+                    const { ::double::MockSlot::new() };
+            }
+        }.to_string()));
+        assert!(expanded.contains(&quote! {
+            pub fn mock_parse(mock: &::double::Mock<String, Result<MockShape, String> >)
+                -> ::double::ScopedMock<String, Result<MockShape, String> >
+            {
+                ::double::ScopedMock::new(&__DOUBLE_MOCK_SLOT_MOCKSHAPE_PARSE, mock)
+            }
+        }.to_string()));
+        let message = "no mock handles calls to `Shape::parse` for `MockShape` on this thread \
+                       (use `MockShape::mock_parse`)";
+        assert!(expanded.contains(&quote! {
+            fn parse(s: String) -> Result<MockShape, String> {
+                let mock = __DOUBLE_MOCK_SLOT_MOCKSHAPE_PARSE
+                    .with(::double::MockSlot::mock)
+                    .unwrap_or_else(|| panic!(#message));
+                mock.call(s)
+            }
+        }.to_string()));
+    }
+
+    #[test]
+    fn generic_traits_have_generic_mocks() {
+        let item: ItemTrait = parse_quote! {
+            trait Repo<T: Send> {
+                fn get(&self, id: u32) -> Option<T>;
+                fn put(&self, item: T);
+            }
+        };
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            ::double::__private_mock_trait!(
+                @checked [] MockRepo [T] [T: Send], get(u32) -> Option<T>, put(T) -> ());
+        }.to_string()));
+        assert!(expanded.contains(&quote! {
+            impl<T: Send> Repo<T> for MockRepo<T>
+                where for<'__double> u32: Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> Option<T>: Clone + ::std::fmt::Debug,
+                      for<'__double> T: Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> (): Clone + ::std::fmt::Debug,
+                      T: Send,
+        }.to_string()));
+    }
+
+    #[test]
+    fn where_clauses_bound_the_mock() {
+        let item: ItemTrait = parse_quote! {
+            trait Repo<'a, T> where T: Entity<'a>, Self: Sized {
+                fn delete(&self, id: T::Id) -> bool where T: Clone;
+            }
+        };
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            ::double::__private_mock_trait!(
+                @checked [] MockRepo [T] [T: Entity<'static>], delete(T::Id) -> bool);
+        }.to_string()));
+        assert!(expanded.contains(&quote! {
+            impl<'a, T> Repo<'a, T> for MockRepo<T>
+                where for<'__double> T::Id: Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> bool: Clone + ::std::fmt::Debug,
+                      T: Entity<'static>,
+            {
+                #[track_caller]
+                fn delete(&self, id: T::Id) -> bool
+                    where T: Clone
+                {
+                    self.delete.call(id)
+                }
+            }
+        }.to_string()));
+    }
+
+    #[test]
+    fn associated_types_are_pinned() {
+        let item: ItemTrait = parse_quote! {
+            trait Source {
+                type Item;
+                fn next(&mut self) -> Option<Self::Item>;
+            }
+        };
+        let options = Options {
+            types: vec!((format_ident!("Item"), parse_quote!(u32))),
+            ..Options::default()
+        };
+        let expanded = expand(&options, &item).unwrap().to_string();
+        assert!(expanded.contains(
+            &quote!(::double::__private_mock_trait!(
+                @checked [] MockSource [] [], next() -> Option<u32>)).to_string()));
+        assert!(expanded.contains(&quote! {
+            impl Source for MockSource
+                where for<'__double> (): Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> Option<u32>: Clone + ::std::fmt::Debug,
+            {
+                type Item = u32;
+
+                #[track_caller]
+                fn next(&mut self) -> Option<u32> {
+                    self.next.call(())
+                }
+            }
+        }.to_string()));
+    }
+
+    #[test]
+    fn supertraits_are_mocked_with_the_trait() {
+        let item: ItemTrait = parse_quote! {
+            trait Cache: Clone + Storage {
+                fn hits(&self) -> u32;
+            }
+        };
+        let options = Options {
+            supertraits: vec!(parse_quote!(Storage { fn get(&self, key: u32) -> String; })),
+            ..Options::default()
+        };
+        let expanded = expand(&options, &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            ::double::__private_mock_trait!(
+                @checked [] MockCache [] [], hits() -> u32, get(u32) -> String);
+        }.to_string()));
+        assert!(expanded.contains(&quote! {
+            impl Storage for MockCache
+                where for<'__double> (): Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> u32: Clone + ::std::fmt::Debug,
+                      for<'__double> u32: Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                      for<'__double> String: Clone + ::std::fmt::Debug,
+            {
+                #[track_caller]
+                fn get(&self, key: u32) -> String {
+                    self.get.call(key)
+                }
+            }
+        }.to_string()));
+    }
+
+    #[test]
+    fn passthrough_methods_use_the_default_implementation() {
+        let item: ItemTrait = parse_quote! {
+            trait Greeter {
+                fn name(&self) -> String;
+                fn greeting(&self) -> String {
+                    format!("Hello, {}!", self.name())
+                }
+            }
+        };
+        let options = Options { passthrough: vec!(format_ident!("greeting")), ..Options::default() };
+        let expanded = expand(&options, &item).unwrap().to_string();
+        assert!(expanded.contains(
+            &quote!(::double::__private_mock_trait!(
+                @checked [] MockGreeter [] [], name() -> String)).to_string()));
+        assert!(!expanded.contains(&quote!(self.greeting.call).to_string()));
+
+        let options = Options { passthrough: vec!(format_ident!("name")), ..Options::default() };
+        assert_eq!(
+            "method `name` has no default implementation to pass through to",
+            expand(&options, &item).unwrap_err().to_string());
+        let options = Options { passthrough: vec!(format_ident!("age")), ..Options::default() };
+        assert_eq!(
+            "`Greeter` has no method `age`",
+            expand(&options, &item).unwrap_err().to_string());
+    }
+
+    #[test]
+    fn options_choose_macro_and_name() {
+        let item: ItemTrait = parse_quote!(trait Clock { fn now(&self) -> u64; });
+        let options = Options {
+            no_default: true,
+            name: Some(format_ident!("FakeClock")),
+            ..Options::default()
+        };
+        let expanded = expand(&options, &item).unwrap().to_string();
+        assert!(expanded.contains(
+            &quote! {
+                ::double::__private_mock_trait!(@checked [] FakeClock [] [], now() -> u64);
+                impl Clock for FakeClock
+            }.to_string()));
+        assert!(!expanded.contains(&quote!(__private_mock_trait_default_impl).to_string()));
+    }
+
+    #[test]
+    fn module_option_puts_the_mock_in_a_module_visible_to_its_parent() {
+        let item: ItemTrait = parse_quote!(trait Clock { fn now(&self) -> u64; });
+        let options = Options { module: Some(format_ident!("mocks")), ..Options::default() };
+        let expected = quote! {
+            #item
+
+            mod mocks {
+                #[allow(unused_imports)]
+                use super::*;
+
+                const _: () = {
+                    #[allow(dead_code, non_camel_case_types)]
+                    mod __double_methods {
+                        pub struct now;
+                    }
+
+                    let _ = ::double::checks::returned_value::<__double_methods::now, u64>;
+                    let _ = ::double::checks::default_value::<__double_methods::now, u64>;
+                };
+
+                ::double::__private_mock_trait!(
+                    @checked [] pub(super) MockClock [] [], now() -> u64);
+                ::double::__private_mock_trait_default_impl!(
+                    @checked MockClock [] [], now() -> u64);
+
+                impl Clock for MockClock
+                    where for<'__double> (): Clone + ::std::fmt::Debug + Eq + ::std::hash::Hash,
+                          for<'__double> u64: Clone + ::std::fmt::Debug,
+                {
+                    #[track_caller]
+                    fn now(&self) -> u64 {
+                        self.now.call(())
+                    }
+                }
+            }
+        };
+        assert_eq!(expected.to_string(), expand(&options, &item).unwrap().to_string());
+    }
+
+    #[test]
+    fn attrs_and_derives_are_added_to_the_mock() {
+        let item: ItemTrait = parse_quote!(trait Clock { fn now(&self) -> u64; });
+        let options: Options = parse_quote!(derive(Fixture), attrs(#[allow(dead_code)]));
+        let expanded = expand(&options, &item).unwrap().to_string();
+        assert!(expanded.contains(&quote! {
+            ::double::__private_mock_trait!(
+                @checked [#[derive(Fixture)] #[allow(dead_code)]] MockClock [] [], now() -> u64);
+        }.to_string()));
+    }
+
+    #[test]
+    fn mocks_have_the_trait_visibility_unless_given_one() {
+        let item: ItemTrait = parse_quote!(pub(crate) trait Clock { fn now(&self) -> u64; });
+        let expanded = expand(&Options::default(), &item).unwrap().to_string();
+        assert!(expanded.contains(
+            &quote!(::double::__private_mock_trait!(
+                @checked [] pub(crate) MockClock [] [], now() -> u64)).to_string()));
+
+        let options = Options { vis: Some(parse_quote!(pub)), ..Options::default() };
+        let expanded = expand(&options, &item).unwrap().to_string();
+        assert!(expanded.contains(
+            &quote!(::double::__private_mock_trait!(
+                @checked [] pub MockClock [] [], now() -> u64)).to_string()));
+    }
+
+    #[test]
+    fn rejects_unsupported_traits() {
+        assert_eq!(
+            "#[double::mock] does not support const parameters",
+            expand_error(parse_quote!(trait Buffer<const N: usize> { fn len(&self) -> usize; })));
+        assert_eq!(
+            "#[double::mock] only supports traits whose items are methods, associated types and \
+             associated consts",
+            expand_error(parse_quote!(trait Repo { items!(); })));
+        assert_eq!(
+            "#[double::mock] does not support return types that use the method's type parameters",
+            expand_error(parse_quote!(trait Repo { fn get<T>(&self) -> T; })));
+        assert_eq!(
+            "#[double::mock] only supports args that use the method's type parameters if their \
+             type is one of them (e.g. `T` or `&T`)",
+            expand_error(parse_quote!(trait Repo { fn put<T>(&self, items: Vec<T>); })));
+        assert_eq!(
+            "#[double::mock] only supports methods with at most 12 args",
+            expand_error(parse_quote! {
+                trait Repo {
+                    fn put(&self, a: u8, b: u8, c: u8, d: u8, e: u8, f: u8, g: u8, h: u8, i: u8,
+                           j: u8, k: u8, l: u8, m: u8);
+                }
+            }));
+        assert_eq!(
+            "#[double::mock] only supports `impl Trait` return types that are iterators or \
+             futures (e.g. `impl Iterator<Item = u32>` or `impl Future<Output = u32>`)",
+            expand_error(parse_quote!(trait Repo { fn name(&self) -> impl Display; })));
+        assert_eq!(
+            "#[double::mock] only supports `impl Trait` args if their type is one (e.g. \
+             `impl Display` or `&impl Display`)",
+            expand_error(parse_quote!(trait Repo { fn put(&self, items: Vec<impl Send>); })));
+        assert_eq!(
+            "#[double::mock] only supports functions without a `self` receiver in traits \
+             without type parameters",
+            expand_error(parse_quote!(trait Repo<T> { fn new() -> Self; })));
+        assert_eq!(
+            "#[double::mock] only supports methods that take `self`, `&self`, `&mut self`, or \
+             `self` in a `Box`, `Rc`, `Arc` or `Pin`",
+            expand_error(parse_quote!(trait Repo { fn get(self: Cell<Self>); })));
+    }
+}
@@ -0,0 +1,181 @@
+// Substitution of mocks for the types they mock in tests. The item that
+// `#[double::mocked(path::RealType)]` is applied to is kept as it is outside
+// of tests, and copied with each use of `path::RealType` replaced by
+// `path::MockRealType` (the mock generated by `#[double::mock]`) for tests.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::visit_mut::{self, VisitMut};
+use syn::{Error, Ident, Item, Path, Result, Token, TypePath, UseName, UseRename, UseTree};
+
+// The types listed in the attribute, e.g. `#[double::mocked(mail::Mailer, Clock)]`
+pub(crate) struct Mocked {
+    types: Vec<Path>,
+}
+
+impl Parse for Mocked {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let types = Punctuated::<Path, Token![,]>::parse_terminated(input)?;
+        if types.is_empty() {
+            return Err(input.error(
+                "#[double::mocked] needs the types to replace by their mocks, e.g. \
+                 `#[double::mocked(mail::Mailer)]`"));
+        }
+        Ok(Mocked { types: types.into_iter().collect() })
+    }
+}
+
+// Generates `item` for builds other than tests, followed by its copy with
+// mocks in place of the listed types for tests
+pub(crate) fn expand(mocked: &Mocked, item: &Item) -> Result<TokenStream> {
+    let mut replaced = item.clone();
+    let mut substitute = Substitute {
+        types: &mocked.types,
+        found: vec!(false; mocked.types.len()),
+    };
+    substitute.visit_item_mut(&mut replaced);
+    if let Some(position) = substitute.found.iter().position(|found| !found) {
+        let path = &mocked.types[position];
+        return Err(Error::new_spanned(
+            path,
+            format!("`{}` is not used by the item, so there is nothing to mock", quote!(#path))));
+    }
+    Ok(quote! {
+        #[cfg(not(test))]
+        #item
+
+        #[cfg(test)]
+        #replaced
+    })
+}
+
+// Name of the mock of the type named `name`
+fn mock_name(name: &Ident) -> Ident {
+    format_ident!("Mock{}", name)
+}
+
+// Returns true if the idents of `path`'s segments are those of `ty`'s, so that
+// the type arguments of a generic type are kept
+fn same_path(path: &Path, ty: &Path) -> bool {
+    path.segments.len() == ty.segments.len()
+        && path.segments.iter().zip(&ty.segments).all(|(left, right)| left.ident == right.ident)
+}
+
+struct Substitute<'a> {
+    types: &'a [Path],
+    // Whether each of `types` has been replaced
+    found: Vec<bool>,
+}
+
+impl<'a> Substitute<'a> {
+    // Returns the index of the listed type that's at `path`, if any
+    fn position(&self, path: &Path) -> Option<usize> {
+        self.types.iter().position(|ty| same_path(path, ty))
+    }
+
+    // Replaces the name `name` imported from `prefix` by its mock, imported
+    // under `alias`, e.g. `MockMailer as Mailer`
+    fn replace_use(&mut self, prefix: &[Ident], name: &Ident, alias: &Ident) -> Option<UseTree> {
+        let path: Path = {
+            let segments = prefix.iter().chain(Some(name));
+            syn::parse_quote!(#(#segments)::*)
+        };
+        let position = self.position(&path)?;
+        self.found[position] = true;
+        Some(UseTree::Rename(UseRename {
+            ident: mock_name(name),
+            as_token: Default::default(),
+            rename: alias.clone(),
+        }))
+    }
+
+    fn visit_use(&mut self, prefix: &mut Vec<Ident>, tree: &mut UseTree) {
+        let replacement = match *tree {
+            UseTree::Path(ref mut path) => {
+                prefix.push(path.ident.clone());
+                self.visit_use(prefix, &mut path.tree);
+                prefix.pop();
+                None
+            },
+            UseTree::Name(UseName { ref ident }) => self.replace_use(prefix, ident, ident),
+            UseTree::Rename(ref rename) => self.replace_use(prefix, &rename.ident, &rename.rename),
+            UseTree::Group(ref mut group) => {
+                for tree in &mut group.items {
+                    self.visit_use(prefix, tree);
+                }
+                None
+            },
+            UseTree::Glob(_) => None,
+        };
+        if let Some(replacement) = replacement {
+            *tree = replacement;
+        }
+    }
+}
+
+impl<'a> VisitMut for Substitute<'a> {
+    fn visit_type_path_mut(&mut self, ty: &mut TypePath) {
+        if ty.qself.is_none() {
+            if let Some(position) = self.position(&ty.path) {
+                self.found[position] = true;
+                let last = ty.path.segments.last_mut().unwrap();
+                last.ident = mock_name(&last.ident);
+            }
+        }
+        visit_mut::visit_type_path_mut(self, ty);
+    }
+
+    fn visit_use_tree_mut(&mut self, tree: &mut UseTree) {
+        self.visit_use(&mut Vec::new(), tree);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    #[test]
+    fn types_are_replaced_by_their_mocks_in_tests() {
+        let item: Item = parse_quote! {
+            struct Service {
+                mailer: mail::Mailer,
+                names: Vec<Name>,
+            }
+        };
+        let expected = quote! {
+            #[cfg(not(test))]
+            #item
+
+            #[cfg(test)]
+            struct Service {
+                mailer: mail::MockMailer,
+                names: Vec<MockName>,
+            }
+        };
+        let expanded = expand(&parse_quote!(mail::Mailer, Name), &item).unwrap();
+        assert_eq!(expected.to_string(), expanded.to_string());
+    }
+
+    #[test]
+    fn imports_are_replaced_by_imports_of_mocks() {
+        let item: Item = parse_quote!(use crate::mail::{Mailer, Inbox as Mailbox, Address};);
+        let expanded = expand(&parse_quote!(crate::mail::Mailer, crate::mail::Inbox), &item);
+        assert!(expanded.unwrap().to_string().contains(&quote! {
+            #[cfg(test)]
+            use crate::mail::{MockMailer as Mailer, MockInbox as Mailbox, Address};
+        }.to_string()));
+    }
+
+    #[test]
+    fn rejects_types_that_are_not_used() {
+        let item: Item = parse_quote!(struct Service { mailer: Mailer });
+        let error = expand(&parse_quote!(Clock), &item).unwrap_err();
+        assert_eq!(
+            "`Clock` is not used by the item, so there is nothing to mock",
+            error.to_string());
+    }
+}
@@ -0,0 +1,280 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{parenthesized, parse_quote, token, Attribute, Error, Expr, Ident, Path, Result, Token,
+          Type, Visibility, WherePredicate};
+
+use generics;
+use method::Method;
+use supertraits::Supertrait;
+
+// How the args of a generic method that are of one of its type parameters are
+// recorded, e.g. `encode = to_bytes -> Vec<u8>` to record them as the
+// `Vec<u8>` returned by `to_bytes(&arg)`, or how one arg of any method is,
+// e.g. `send.packet = Packet::id -> u32`
+pub(crate) struct Projection {
+    pub(crate) method: Ident,
+    pub(crate) arg: Option<Ident>,
+    pub(crate) function: Path,
+    pub(crate) ty: Type,
+}
+
+impl Parse for Projection {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let method = input.parse()?;
+        let arg = if input.peek(Token![.]) {
+            input.parse::<Token![.]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        input.parse::<Token![=]>()?;
+        let function = input.parse()?;
+        input.parse::<Token![->]>()?;
+        Ok(Projection { method, arg, function, ty: input.parse()? })
+    }
+}
+
+// Options of the attribute, e.g.
+// `#[double::mock(no_default, name = FakeStore, module = fakes, vis = pub, type Item = u32)]`
+// `#[double::mock(derive(Fixture), attrs(#[allow(dead_code)]))]` or
+// `#[double::mock(Storage { fn get(&self) -> u32; })]`
+#[derive(Default)]
+pub(crate) struct Options {
+    // Generate the mock using `mock_trait_no_default!`
+    pub(crate) no_default: bool,
+    // Name of the mock, instead of `Mock` followed by the mocked trait or type
+    pub(crate) name: Option<Ident>,
+    // Module that the mock is generated in, instead of alongside the mocked
+    // trait or type
+    pub(crate) module: Option<Ident>,
+    // Visibility of the mock and its fields, instead of the mocked item's
+    pub(crate) vis: Option<Visibility>,
+    // Extra attributes of the mock `struct`, including extra derives
+    pub(crate) attrs: Vec<Attribute>,
+    // The types that the mocked trait's associated types are pinned to
+    pub(crate) types: Vec<(Ident, Type)>,
+    // The values of the mocked trait's associated consts
+    pub(crate) consts: Vec<(Ident, Expr)>,
+    // The supertraits of the mocked trait that the mock implements
+    pub(crate) supertraits: Vec<Supertrait>,
+    // Methods of the mocked trait that aren't mocked, so that calls fall
+    // through to the trait's default implementation
+    pub(crate) passthrough: Vec<Ident>,
+    // Projections of the args of generic methods, and of single args
+    pub(crate) projections: Vec<Projection>,
+}
+
+impl Parse for Options {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut options = Options::default();
+        while !input.is_empty() {
+            if input.peek(Token![type]) {
+                input.parse::<Token![type]>()?;
+                let name: Ident = input.parse()?;
+                input.parse::<Token![=]>()?;
+                options.types.push((name, input.parse()?));
+            } else if input.peek(Token![const]) {
+                input.parse::<Token![const]>()?;
+                let name: Ident = input.parse()?;
+                input.parse::<Token![=]>()?;
+                options.consts.push((name, input.parse()?));
+            } else {
+                // Any other path is a supertrait of the mocked trait
+                let option: Path = input.parse()?;
+                if option.is_ident("no_default") {
+                    options.no_default = true;
+                } else if option.is_ident("name") && input.peek(Token![=]) {
+                    input.parse::<Token![=]>()?;
+                    options.name = Some(input.parse()?);
+                } else if option.is_ident("module") && input.peek(Token![=]) {
+                    input.parse::<Token![=]>()?;
+                    options.module = Some(input.parse()?);
+                } else if option.is_ident("vis") && input.peek(Token![=]) {
+                    input.parse::<Token![=]>()?;
+                    options.vis = Some(input.parse()?);
+                } else if option.is_ident("derive") && input.peek(token::Paren) {
+                    let content;
+                    parenthesized!(content in input);
+                    let derives = Punctuated::<Path, Token![,]>::parse_terminated(&content)?;
+                    let derives = derives.iter();
+                    options.attrs.push(parse_quote!(#[derive(#(#derives),*)]));
+                } else if option.is_ident("attrs") && input.peek(token::Paren) {
+                    let content;
+                    parenthesized!(content in input);
+                    options.attrs.extend(content.call(Attribute::parse_outer)?);
+                    if !content.is_empty() {
+                        return Err(content.error("expected attributes, e.g. `#[allow(dead_code)]`"));
+                    }
+                } else if option.is_ident("passthrough") && input.peek(token::Paren) {
+                    let content;
+                    parenthesized!(content in input);
+                    let methods = Punctuated::<Ident, Token![,]>::parse_terminated(&content)?;
+                    options.passthrough.extend(methods);
+                } else if option.is_ident("project") && input.peek(token::Paren) {
+                    let content;
+                    parenthesized!(content in input);
+                    let projections =
+                        Punctuated::<Projection, Token![,]>::parse_terminated(&content)?;
+                    options.projections.extend(projections);
+                } else if input.peek(Token![=]) {
+                    return Err(Error::new_spanned(
+                        option,
+                        "unsupported option, expected `no_default`, `name = ...`, `module = ...`, \
+                         `vis = ...`, `derive(...)`, `attrs(...)`, `passthrough(...)`, \
+                         `project(...)`, `type ... = ...`, `const ... = ...` or a supertrait"));
+                } else {
+                    options.supertraits.push(Supertrait::parse_items(option, input)?);
+                }
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(options)
+    }
+}
+
+impl Options {
+    // The projections of the args of the method `method`
+    pub(crate) fn projections(&self, method: &Ident) -> Vec<&Projection> {
+        self.projections.iter().filter(|projection| projection.method == *method).collect()
+    }
+
+    // Checks that each projection is of one of the generic `methods`, or of
+    // an arg of one of the `methods`
+    pub(crate) fn check_projections(&self, methods: &[Method]) -> Result<()> {
+        for projection in &self.projections {
+            let name = &projection.method;
+            let method = methods.iter().find(|method| method.name() == name);
+            match projection.arg {
+                Some(ref arg) => match method {
+                    Some(method) if method.has_arg(arg) => (),
+                    Some(_) => return Err(Error::new_spanned(
+                        arg, format!("method `{}` has no arg `{}` to project", name, arg))),
+                    None => return Err(Error::new_spanned(
+                        name, format!("there is no method `{}` to project the args of", name))),
+                },
+                None if method.is_some_and(Method::is_generic) => (),
+                None => return Err(Error::new_spanned(
+                    name, format!("there is no generic method `{}` to project the args of", name))),
+            }
+        }
+        Ok(())
+    }
+
+    // Name of the mock of `mocked`, e.g. a trait or struct
+    pub(crate) fn mock_name(&self, mocked: &Ident) -> Ident {
+        self.name.clone().unwrap_or_else(|| format_ident!("Mock{}", mocked))
+    }
+
+    // Visibility of the mock, given that of the mocked item
+    pub(crate) fn mock_vis(&self, mocked: &Visibility) -> Visibility {
+        self.visibility(self.vis.as_ref().unwrap_or(mocked))
+    }
+
+    // Visibility of an item of the mock that's as visible as `vis` would make
+    // it alongside the mocked item. Items in the mock's own module must be
+    // visible to its parent, so private items are `pub(super)` there.
+    pub(crate) fn visibility(&self, vis: &Visibility) -> Visibility {
+        if self.module.is_none() {
+            return vis.clone();
+        }
+        match *vis {
+            Visibility::Inherited => parse_quote!(pub(super)),
+            Visibility::Restricted(ref restricted) if restricted.in_token.is_none() => {
+                if restricted.path.is_ident("self") {
+                    parse_quote!(pub(super))
+                } else if restricted.path.is_ident("super") {
+                    parse_quote!(pub(in super::super))
+                } else {
+                    vis.clone()
+                }
+            },
+            _ => vis.clone(),
+        }
+    }
+
+    // Puts `mock` in the module given by the `module` option, if any, which is
+    // as visible as the mock would be alongside the mocked item
+    pub(crate) fn in_module(&self, mocked: &Visibility, mock: TokenStream) -> TokenStream {
+        match self.module {
+            Some(ref module) => {
+                let vis = self.vis.as_ref().unwrap_or(mocked);
+                quote! {
+                    #vis mod #module {
+                        #[allow(unused_imports)]
+                        use super::*;
+
+                        #mock
+                    }
+                }
+            },
+            None => mock,
+        }
+    }
+
+    // Generates the mock `struct`, with a method mock for each of `methods`
+    // and the type parameters `params`, bounded by `predicates`. The `struct`
+    // and its fields have the visibility `vis`, unless the `vis` option is
+    // given.
+    pub(crate) fn mock_struct(
+        &self,
+        vis: &Visibility,
+        name: &Ident,
+        params: &[&Ident],
+        predicates: &[WherePredicate],
+        methods: &[Method]) -> TokenStream
+    {
+        let vis = self.mock_vis(vis);
+        let mock_macro = if self.no_default {
+            quote!(mock_trait_no_default)
+        } else {
+            quote!(mock_trait)
+        };
+        // Only generic mocks have a `where` clause
+        let where_clause = if params.is_empty() || predicates.is_empty() {
+            quote!()
+        } else {
+            quote!(where #(#predicates),*)
+        };
+        let attrs = &self.attrs;
+        let params = generics::mock_type_generics(params);
+        let signatures = methods.iter().map(Method::mock_signature);
+        quote! {
+            ::double::#mock_macro!(
+                #(#attrs)* #vis #name #params #(, #signatures)* #where_clause);
+        }
+    }
+
+    // Generates the mock `struct` like `mock_struct`, for methods that were
+    // checked (see `checks`), so its impls are bounded like those of
+    // `generics::checked_where_clause`
+    pub(crate) fn checked_mock_struct(
+        &self,
+        vis: &Visibility,
+        name: &Ident,
+        params: &[&Ident],
+        predicates: &[WherePredicate],
+        methods: &[Method]) -> TokenStream
+    {
+        let vis = self.mock_vis(vis);
+        let attrs = &self.attrs;
+        let signatures: Vec<TokenStream> = methods.iter().map(Method::mock_signature).collect();
+        let default_impl = if self.no_default {
+            quote!()
+        } else {
+            quote! {
+                ::double::__private_mock_trait_default_impl!(
+                    @checked #name [#(#params),*] [#(#predicates),*] #(, #signatures)*);
+            }
+        };
+        quote! {
+            ::double::__private_mock_trait!(
+                @checked [#(#attrs)*] #vis #name [#(#params),*] [#(#predicates),*]
+                #(, #signatures)*);
+            #default_impl
+        }
+    }
+}
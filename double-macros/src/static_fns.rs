@@ -0,0 +1,92 @@
+// Functions of a mocked trait that don't take `self` (e.g. `fn create() -> Self`)
+// have no mock to call, so each one is routed through a thread-local
+// `::double::MockSlot` instead. A test fills the slot by passing a `Mock` to the
+// function's `mock_` function, which the mock gets an inherent implementation
+// of, e.g. `MockFactory::mock_create`.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::visit_mut::{self, VisitMut};
+use syn::{Ident, Signature, Type, Visibility};
+
+use generics;
+use method::Method;
+
+// Returns `sig` with `mock_name` substituted for `Self`, since the slots that
+// calls are routed through are declared outside of the mock's `impl` blocks
+pub(crate) fn replace_self(sig: &Signature, mock_name: &Ident) -> Signature {
+    let mut sig = sig.clone();
+    ReplaceSelf { mock_name }.visit_signature_mut(&mut sig);
+    sig
+}
+
+// The thread-local slot that calls to `function` are routed through
+fn slot_name(mock_name: &Ident, function: &Method) -> Ident {
+    format_ident!(
+        "__DOUBLE_MOCK_SLOT_{}_{}",
+        mock_name.to_string().to_uppercase(),
+        function.name().to_string().to_uppercase())
+}
+
+// The implementation of `function` in the mock's implementation of the trait
+pub(crate) fn implementation(trait_name: &Ident, mock_name: &Ident, function: &Method) -> TokenStream {
+    let message = format!(
+        "no mock handles calls to `{}::{}` for `{}` on this thread (use `{}::mock_{}`)",
+        trait_name, function.name(), mock_name, mock_name, function.name());
+    function.static_implementation(
+        &Visibility::Inherited, &slot_name(mock_name, function), &message)
+}
+
+// Generates the slots of `functions`, and the mock's `mock_` functions, which
+// fill them while the guards they return are alive
+pub(crate) fn slots(vis: &Visibility, mock_name: &Ident, functions: &[Method]) -> TokenStream {
+    if functions.is_empty() {
+        return quote!();
+    }
+    let slots = functions.iter().map(|function| {
+        let slot = slot_name(mock_name, function);
+        let params = function.mock_params();
+        quote! {
+            ::std::thread_local! {
+                static #slot: ::double::MockSlot<#params> = const { ::double::MockSlot::new() };
+            }
+        }
+    });
+    let mock_fns = functions.iter().map(|function| {
+        let name = format_ident!("mock_{}", function.name());
+        let slot = slot_name(mock_name, function);
+        let params = function.mock_params();
+        let doc = format!(
+            "Routes the calls to `{}` made on the current thread to `mock`, until the \
+             returned guard is dropped.",
+            function.name());
+        quote! {
+            #[doc = #doc]
+            #vis fn #name(mock: &::double::Mock<#params>) -> ::double::ScopedMock<#params> {
+                ::double::ScopedMock::new(&#slot, mock)
+            }
+        }
+    });
+    quote! {
+        #(#slots)*
+
+        impl #mock_name {
+            #(#mock_fns)*
+        }
+    }
+}
+
+struct ReplaceSelf<'a> {
+    mock_name: &'a Ident,
+}
+
+impl<'a> VisitMut for ReplaceSelf<'a> {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        if generics::is_self(ty) {
+            let mock_name = self.mock_name;
+            *ty = syn::parse_quote!(#mock_name);
+        } else {
+            visit_mut::visit_type_mut(self, ty);
+        }
+    }
+}
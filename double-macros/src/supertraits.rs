@@ -0,0 +1,198 @@
+// Supertraits of mocked traits. The methods of those that are listed with
+// their methods in the attribute's options, e.g.
+// `#[double::mock(Storage { fn get(&self, key: u32) -> String; })]`, are
+// mocked as part of the same mock, which implements them alongside the
+// mocked trait. Supertraits that are listed by name alone, e.g.
+// `#[double::mock(Storage)]`, are implemented by hand. The rest must be
+// traits that every mock implements, e.g. `Clone` and `Debug`.
+
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::{braced, token, Error, ItemTrait, Path, Result, TraitBoundModifier, TraitItem,
+          TypeParamBound};
+
+use method::Method;
+use options::Options;
+
+// A supertrait listed in the attribute's options
+pub(crate) struct Supertrait {
+    pub(crate) path: Path,
+    // The supertrait's methods, or `None` if it's implemented by hand
+    pub(crate) items: Option<Vec<TraitItem>>,
+}
+
+// A supertrait that the mock implements, with the methods that are mocked
+pub(crate) struct Mocked<'a> {
+    pub(crate) path: &'a Path,
+    pub(crate) methods: Vec<Method>,
+}
+
+// Traits that every mock implements, or that are implemented automatically.
+// Mocks implement `Default` unless the `no_default` option is used.
+const IMPLEMENTED: &[&str] = &["Clone", "Debug", "Send", "Sized", "Sync", "Unpin"];
+
+impl Supertrait {
+    // Parses the methods that follow the supertrait's `path`, if any
+    pub(crate) fn parse_items(path: Path, input: ParseStream) -> Result<Self> {
+        if !input.peek(token::Brace) {
+            return Ok(Supertrait { path, items: None });
+        }
+        let content;
+        braced!(content in input);
+        let mut items = Vec::new();
+        while !content.is_empty() {
+            items.push(content.parse()?);
+        }
+        Ok(Supertrait { path, items: Some(items) })
+    }
+}
+
+impl Parse for Supertrait {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let path = input.parse()?;
+        Supertrait::parse_items(path, input)
+    }
+}
+
+// Returns the supertraits of `item` whose methods are mocked with it, after
+// checking that the mock implements every supertrait of `item`.
+pub(crate) fn mocked<'a>(options: &'a Options, item: &ItemTrait) -> Result<Vec<Mocked<'a>>> {
+    for supertrait in &options.supertraits {
+        let declared = item.supertraits.iter().any(|bound| match *bound {
+            TypeParamBound::Trait(ref bound) => same_path(&bound.path, &supertrait.path),
+            _ => false,
+        });
+        if !declared {
+            let path = &supertrait.path;
+            return Err(Error::new_spanned(
+                path, format!("`{}` has no supertrait `{}`", item.ident, quote!(#path))));
+        }
+    }
+
+    for bound in &item.supertraits {
+        let bound = match *bound {
+            TypeParamBound::Trait(ref bound) => bound,
+            _ => continue,
+        };
+        if let TraitBoundModifier::Maybe(_) = bound.modifier {
+            continue;
+        }
+        let listed = options.supertraits
+            .iter()
+            .any(|supertrait| same_path(&bound.path, &supertrait.path));
+        if !listed && !implemented(options, &bound.path) {
+            let path = &bound.path;
+            return Err(Error::new_spanned(
+                path,
+                format!("supertrait `{0}` must be listed in #[double::mock], with its methods \
+                         to mock them with the trait's (e.g. `#[double::mock({0} {{ fn get(&self) \
+                         -> u32; }})]`) or alone if it's implemented by hand \
+                         (e.g. `#[double::mock({0})]`)",
+                        quote!(#path))));
+        }
+    }
+
+    let mut mocked = Vec::new();
+    for supertrait in &options.supertraits {
+        let items = match supertrait.items {
+            Some(ref items) => items,
+            None => continue,
+        };
+        let methods = items
+            .iter()
+            .map(|trait_item| match *trait_item {
+                TraitItem::Fn(ref method) if method.sig.receiver().is_none() => Err(Error::new_spanned(
+                    &method.sig,
+                    "#[double::mock] only supports supertrait methods that take `self`")),
+                TraitItem::Fn(ref method) => {
+                    Method::parse(&method.sig, &options.projections(&method.sig.ident))
+                },
+                ref other => Err(Error::new_spanned(
+                    other, "#[double::mock] only supports supertraits whose items are all methods")),
+            })
+            .collect::<Result<Vec<Method>>>()?;
+        mocked.push(Mocked { path: &supertrait.path, methods });
+    }
+    Ok(mocked)
+}
+
+// Returns true if `path` is a trait that the mock implements regardless of
+// the supertraits listed in `options`
+fn implemented(options: &Options, path: &Path) -> bool {
+    let name = match path.segments.last() {
+        Some(segment) if segment.arguments.is_none() => segment.ident.to_string(),
+        _ => return false,
+    };
+    IMPLEMENTED.contains(&&*name) || (name == "Default" && !options.no_default)
+}
+
+fn same_path(left: &Path, right: &Path) -> bool {
+    quote!(#left).to_string() == quote!(#right).to_string()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn options(supertraits: Vec<Supertrait>) -> Options {
+        Options { supertraits, ..Options::default() }
+    }
+
+    fn mocked_error(options: &Options, item: ItemTrait) -> String {
+        match mocked(options, &item) {
+            Ok(_) => panic!("expected an error"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn listed_supertraits_are_mocked() {
+        let item: ItemTrait = parse_quote!(trait Cache: Clone + Storage + Logger + 'static {});
+        let options = options(vec!(
+            parse_quote!(Storage { fn get(&self, key: u32) -> String; fn clear(&self); }),
+            parse_quote!(Logger)));
+        let mocked = mocked(&options, &item).unwrap();
+        assert_eq!(1, mocked.len());
+        let path = mocked[0].path;
+        assert_eq!(quote!(Storage).to_string(), quote!(#path).to_string());
+        let signatures: Vec<String> = mocked[0].methods
+            .iter()
+            .map(|method| method.mock_signature().to_string())
+            .collect();
+        assert_eq!(
+            vec!(quote!(get(u32) -> String).to_string(), quote!(clear() -> ()).to_string()),
+            signatures);
+    }
+
+    #[test]
+    fn default_is_only_implemented_with_default() {
+        let item: ItemTrait = parse_quote!(trait Cache: Default {});
+        assert!(mocked(&options(vec!()), &item).is_ok());
+        let options = Options { no_default: true, ..Options::default() };
+        assert!(mocked_error(&options, item).starts_with("supertrait `Default` must be listed"));
+    }
+
+    #[test]
+    fn rejects_unlisted_and_unknown_supertraits() {
+        assert_eq!(
+            "supertrait `Storage` must be listed in #[double::mock], with its methods to mock \
+             them with the trait's (e.g. `#[double::mock(Storage { fn get(&self) -> u32; })]`) \
+             or alone if it's implemented by hand (e.g. `#[double::mock(Storage)]`)",
+            mocked_error(&options(vec!()), parse_quote!(trait Cache: Storage {})));
+        assert_eq!(
+            "`Cache` has no supertrait `Storage`",
+            mocked_error(&options(vec!(parse_quote!(Storage))), parse_quote!(trait Cache {})));
+        assert_eq!(
+            "#[double::mock] only supports supertraits whose items are all methods",
+            mocked_error(
+                &options(vec!(parse_quote!(Storage { type Key; }))),
+                parse_quote!(trait Cache: Storage {})));
+        assert_eq!(
+            "#[double::mock] only supports supertrait methods that take `self`",
+            mocked_error(
+                &options(vec!(parse_quote!(Storage { fn open() -> Self; }))),
+                parse_quote!(trait Cache: Storage {})));
+    }
+}
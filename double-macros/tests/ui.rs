@@ -0,0 +1,10 @@
+// The errors of mocks whose args or return values can't be recorded or
+// returned, which are reported at the method's signature
+
+extern crate trybuild;
+
+#[test]
+fn ui() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,11 @@
+extern crate double;
+
+#[derive(Debug)]
+pub struct Token(u32);
+
+#[double::mock]
+pub trait Parser {
+    fn parse(&self, token: Token, depth: u32) -> u32;
+}
+
+fn main() {}
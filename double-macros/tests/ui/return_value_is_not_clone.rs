@@ -0,0 +1,12 @@
+extern crate double;
+
+#[derive(Debug, Default)]
+pub struct Token(u32);
+
+#[double::mock]
+pub trait Lexer {
+    fn next(&self, pos: u32) -> Token;
+    fn peek(&self) -> u32;
+}
+
+fn main() {}
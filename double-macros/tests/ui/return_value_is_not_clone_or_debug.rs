@@ -0,0 +1,10 @@
+extern crate double;
+
+pub struct Token(u32);
+
+#[double::mock(no_default)]
+pub trait Lexer {
+    fn next(&self) -> Token;
+}
+
+fn main() {}
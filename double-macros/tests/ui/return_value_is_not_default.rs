@@ -0,0 +1,11 @@
+extern crate double;
+
+#[derive(Clone, Debug)]
+pub struct Token(u32);
+
+#[double::mock]
+pub trait Lexer {
+    fn next(&self, pos: u32) -> Token;
+}
+
+fn main() {}
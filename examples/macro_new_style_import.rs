@@ -3,6 +3,8 @@
 // macros.rs uses the legacy #[macro_use] approach. This one imports the macros
 // being used directly.
 
+#![allow(dead_code)]
+
 extern crate double;
 
 use double::mock_method;
@@ -1,8 +1,6 @@
 #[macro_use]
 extern crate double;
 
-use double::matcher::*;
-
 pub trait ProfitForecaster {
     fn profit_at(&self, timestamp: i32) -> f64;
     fn write_report_for(&self, timestamp: i32, dry_run: bool);
@@ -6,9 +6,9 @@ trait Dependency: Clone {
     fn profit(&self, revenue: u32, costs: u32) -> i32;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 struct MockDependency {
-    pub profit: Mock<(u32, u32), (i32)>,
+    pub profit: Mock<(u32, u32), i32>,
 }
 
 impl Dependency for MockDependency {
@@ -17,12 +17,6 @@ impl Dependency for MockDependency {
     }
 }
 
-impl Default for MockDependency {
-    fn default() -> Self {
-        MockDependency { profit: Mock::default() }
-    }
-}
-
 fn main() {
     // Test individual return values
     let mock = MockDependency::default();
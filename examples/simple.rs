@@ -24,7 +24,7 @@ impl<T: Dependency> Consumer<T> {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 struct MockDependency {
     pub greet: Mock<String, ()>,
 }
@@ -35,12 +35,6 @@ impl Dependency for MockDependency {
     }
 }
 
-impl Default for MockDependency {
-    fn default() -> Self {
-        MockDependency { greet: Mock::default() }
-    }
-}
-
 fn main() {
     let mock = MockDependency::default();
     let consumer = Consumer::new(&mock);
@@ -14,7 +14,7 @@ trait FileSystem: Clone {
 
 fn copy_to_all<FS: FileSystem, P: AsRef<Path>>(fs: &FS, from: P, to: &[P]) -> Vec<io::Result<()>> {
     to.iter()
-        .map(|path| fs.copy(&from, &path))
+        .map(|path| fs.copy(&from, path))
         .collect::<Vec<io::Result<()>>>()
 }
 
@@ -47,15 +47,11 @@ struct CloneableError<'a> {
     pub description: &'a str,
 }
 
-impl<'a> Error for CloneableError<'a> {
-    fn description(&self) -> &str {
-        self.description
-    }
-}
+impl<'a> Error for CloneableError<'a> {}
 
 impl<'a> Display for CloneableError<'a> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "{}", self.description())
+        write!(f, "{}", self.description)
     }
 }
 
@@ -0,0 +1,88 @@
+// Checks that the macros of `double` expand in `#![no_std]` crates, which can't
+// name anything through `::std`. To check them without `double`'s `std`
+// feature, build this crate on its own, e.g. for a target without `std`:
+//
+//     cargo build -p double-no-std-check --target thumbv7em-none-eabihf
+//
+// Building the whole workspace turns `std` on, but still checks the paths the
+// macros generate.
+
+#![no_std]
+
+#[macro_use]
+extern crate double;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+use double::{Mock, Snapshot, Verify};
+
+pub trait Sensor {
+    fn read(&self, channel: u8) -> u16;
+    fn calibrate(&mut self, offset: i16, scale: u16);
+}
+
+mock_trait!(
+    pub MockSensor,
+    read(u8) -> u16,
+    calibrate(i16, u16) -> ());
+
+impl Sensor for MockSensor {
+    mock_method!(read(&self, channel: u8) -> u16);
+    mock_method!(calibrate(&mut self, offset: i16, scale: u16));
+}
+
+mock_trait_no_default!(
+    pub MockNamer,
+    name(u32) -> String);
+
+pub trait Log {
+    fn write(&self, message: &str);
+}
+
+spy_trait!(pub SpyLog: Log {
+    fn write(&self, message: &str as String);
+});
+
+pub struct NullLog;
+
+impl Log for NullLog {
+    fn write(&self, _message: &str) {}
+}
+
+fake_trait!(pub FakeSensor: Sensor {
+    fn read(&self, channel: u8) -> u16;
+    fn calibrate(&mut self, offset: i16, scale: u16);
+});
+
+pub fn check_mock_trait() -> bool {
+    let mut sensor = MockSensor::default();
+    sensor.read.return_value_for(0, 10u16);
+    sensor.expect_calibrate().with(p!(lt, 0), p!(any)).times(1);
+    sensor.calibrate(-2, 100);
+
+    expect_called!(sensor.read, times: 0);
+    let namer = MockNamer::new(String::from("sensor"));
+    sensor.read(0) == 10
+        && sensor.read.called_with_pattern(matcher!(p!(le, 1)))
+        && sensor.read.has_calls_exactly_in_order(vec!(0))
+        && sensor.verify()
+        && !sensor.rendered_calls("sensor").is_empty()
+        && namer.name.num_calls() == 0
+}
+
+pub fn check_mock_fns() -> u16 {
+    mock_func!(mock, read, u16, u8, u8);
+    mock.return_value(3u16);
+    let sum = Mock::<(u8, u8), u16>::default();
+    let add = mock_fn!(sum, u8, u8);
+    read(1, 2) + add(3, 4)
+}
+
+pub fn check_spy_and_fake() -> bool {
+    let log = SpyLog::new(NullLog);
+    log.write("started");
+    let sensor = FakeSensor::new();
+    sensor.read.push(7);
+    log.write.called_with(String::from("started")) && log.verify() && sensor.read(0) == 7
+}
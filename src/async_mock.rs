@@ -0,0 +1,190 @@
+use std::fmt;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::ops::Deref;
+
+use crate::future::{Gate, MockFuture};
+use crate::mock::Mock;
+use crate::shared::Shared;
+
+/// A `Mock` for async methods.
+///
+/// `AsyncMock::call` records the call and returns a future that resolves to
+/// the value the underlying `Mock` returns for the call, so async methods can
+/// be configured and verified like any other mock. The future can be made to
+/// return `Poll::Pending` a number of times first, or until a `Gate` is
+/// opened, to test code that must cope with futures that aren't immediately
+/// ready.
+///
+/// `AsyncMock` dereferences to the underlying `Mock`, so all of its methods
+/// for configuring behaviour, setting expectations and checking calls can be
+/// used directly. Clones of an `AsyncMock` share its state.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use std::future::Future;
+/// use std::pin::Pin;
+/// use std::task::{Context, Poll, Waker};
+/// use double::AsyncMock;
+/// use double::matcher::*;
+///
+/// # fn main() {
+/// let fetch = AsyncMock::<&str, Vec<u8>>::default();
+/// fetch.return_value_for("a.txt", vec!(1, 2));
+/// fetch.expect(p!(eq, "a.txt"));
+/// fetch.set_pending_polls(1);
+///
+/// let mut future = fetch.call("a.txt");
+/// let mut cx = Context::from_waker(Waker::noop());
+/// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Pending);
+/// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(vec!(1, 2)));
+/// assert!(fetch.verify());
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AsyncMock<C, R>
+    where C: Clone + Eq + Hash,
+          R: Clone
+{
+    mock: Mock<C, R>,
+    pending_polls: Shared<usize>,
+    gate: Shared<Option<Gate>>,
+}
+
+impl<C, R> AsyncMock<C, R>
+    where C: Clone + Eq + Hash,
+          R: Clone
+{
+    /// Creates a new `AsyncMock` whose calls resolve to `return_value`. See
+    /// `Mock::new`.
+    pub fn new<T: Into<R>>(return_value: T) -> Self {
+        AsyncMock::from_mock(Mock::new(return_value))
+    }
+
+    /// Wraps an existing `Mock`. Clones of `mock` share its state with the
+    /// `AsyncMock`.
+    pub fn from_mock(mock: Mock<C, R>) -> Self {
+        AsyncMock { mock, pending_polls: Shared::new(0), gate: Shared::new(None) }
+    }
+
+    /// Record a call with `args`, returning a future that resolves to the
+    /// value the underlying `Mock` returns for them. See `Mock::call`.
+    ///
+    /// The call is recorded, and its expectations matched, immediately
+    /// rather than when the future is first polled.
+    #[track_caller]
+    pub fn call(&self, args: C) -> MockFuture<R> {
+        let return_value = self.mock.call(args);
+        match *self.gate.borrow() {
+            Some(ref gate) => MockFuture::until_opened(return_value, gate),
+            None => MockFuture::pending_then(return_value, *self.pending_polls.borrow()),
+        }
+    }
+
+    /// Make the futures returned by subsequent calls return `Poll::Pending`
+    /// for their first `num_polls` polls.
+    pub fn set_pending_polls(&self, num_polls: usize) {
+        *self.pending_polls.borrow_mut() = num_polls;
+    }
+
+    /// Make the futures returned by subsequent calls return `Poll::Pending`
+    /// until `gate` is opened, instead of for the number of polls set by
+    /// `set_pending_polls`.
+    pub fn hold_until_opened(&self, gate: &Gate) {
+        *self.gate.borrow_mut() = Some(gate.clone());
+    }
+
+    /// Returns the underlying `Mock`.
+    pub fn mock(&self) -> &Mock<C, R> {
+        &self.mock
+    }
+}
+
+impl<C, R> Default for AsyncMock<C, R>
+    where C: Clone + Eq + Hash,
+          R: Clone + Default
+{
+    /// Use `R::default()` as the initial return value.
+    fn default() -> Self {
+        AsyncMock::from_mock(Mock::default())
+    }
+}
+
+impl<C, R> Deref for AsyncMock<C, R>
+    where C: Clone + Eq + Hash,
+          R: Clone
+{
+    type Target = Mock<C, R>;
+
+    fn deref(&self) -> &Mock<C, R> {
+        &self.mock
+    }
+}
+
+impl<C, R> Debug for AsyncMock<C, R>
+    where C: Clone + Debug + Eq + Hash,
+          R: Clone + Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncMock")
+            .field("pending_polls", &self.pending_polls)
+            .field("gate", &self.gate)
+            .field("mock", &self.mock)
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    #[test]
+    fn calls_are_recorded_before_future_is_polled() {
+        let mock = AsyncMock::<i32, i32>::new(5);
+        mock.use_closure(Box::new(|x| x + 1));
+        let mut future = mock.call(1);
+        assert!(mock.called_with(1));
+
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(Poll::Ready(2), Pin::new(&mut future).poll(&mut cx));
+    }
+
+    #[test]
+    fn pending_polls_apply_to_later_calls() {
+        let mock = AsyncMock::<(), i32>::new(5);
+        let mut ready = mock.call(());
+        mock.set_pending_polls(2);
+        let mut pending = mock.call(());
+
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(Poll::Ready(5), Pin::new(&mut ready).poll(&mut cx));
+        assert_eq!(Poll::Pending, Pin::new(&mut pending).poll(&mut cx));
+        assert_eq!(Poll::Pending, Pin::new(&mut pending).poll(&mut cx));
+        assert_eq!(Poll::Ready(5), Pin::new(&mut pending).poll(&mut cx));
+    }
+
+    #[test]
+    fn held_calls_resolve_once_the_gate_is_opened() {
+        let mock = AsyncMock::<(), i32>::new(5);
+        let gate = Gate::new();
+        mock.hold_until_opened(&gate);
+        let mut held = mock.call(());
+        let cancelled = mock.call(());
+
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(Poll::Pending, Pin::new(&mut held).poll(&mut cx));
+        assert_eq!(Poll::Pending, Pin::new(&mut cancelled.clone()).poll(&mut cx));
+        assert_eq!(1, gate.num_cancelled());
+        drop(cancelled);
+        gate.open();
+        assert_eq!(Poll::Ready(5), Pin::new(&mut held).poll(&mut cx));
+        assert_eq!(1, gate.num_cancelled());
+        assert_eq!(2, mock.num_calls());
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use crate::expectation::Times;
+use crate::mock::Mock;
+use crate::policy::MockPolicy;
+use crate::shared::{ArgsFormatter, Closure, Pattern, StaticPattern};
+
+type FnMap<C, R> = HashMap<C, fn(C) -> R>;
+type ClosureMap<C, R> = HashMap<C, Closure<C, R>>;
+
+/// Declares the entire behaviour of a `Mock` in a single expression.
+///
+/// Created using `Mock::builder`. Each method configures the behaviour of the
+/// same name on `Mock`, and `build` constructs a mock with all of it applied.
+/// This is convenient in fixture functions that return ready-to-use mocks.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use double::{Mock, MockPolicy};
+/// use double::matcher::*;
+///
+/// # fn main() {
+/// fn inventory() -> Mock<(&'static str, u32), bool> {
+///     Mock::builder()
+///         .default_return(false)
+///         .return_value_for(("widget", 1), true)
+///         .stub(matcher!(p!(eq, "gadget"), p!(lt, 10)), true)
+///         .strict()
+///         .build()
+/// }
+///
+/// let mock = inventory();
+/// assert!(mock.call(("widget", 1)));
+/// assert!(mock.call(("gadget", 5)));
+/// assert!(!mock.call(("gadget", 50)));
+/// assert_eq!(mock.policy(), MockPolicy::Strict);
+/// # }
+/// ```
+pub struct MockBuilder<C, R>
+    where C: Clone + Eq + Hash,
+          R: Clone
+{
+    default_return: Option<R>,
+    return_values: Option<Vec<R>>,
+    return_values_for: HashMap<C, R>,
+    default_fn: Option<fn(C) -> R>,
+    fns_for: FnMap<C, R>,
+    default_closure: Option<Closure<C, R>>,
+    closures_for: ClosureMap<C, R>,
+    stubs: Vec<(Pattern<C>, R)>,
+    policy: Option<MockPolicy>,
+    call_log_capacity: Option<usize>,
+    args_formatter: Option<ArgsFormatter<C>>,
+}
+
+impl<C, R> MockBuilder<C, R>
+    where C: Clone + Eq + Hash,
+          R: Clone
+{
+    pub(crate) fn new() -> Self {
+        MockBuilder {
+            default_return: None,
+            return_values: None,
+            return_values_for: HashMap::new(),
+            default_fn: None,
+            fns_for: HashMap::new(),
+            default_closure: None,
+            closures_for: HashMap::new(),
+            stubs: vec!(),
+            policy: None,
+            call_log_capacity: None,
+            args_formatter: None,
+        }
+    }
+
+    /// Return `value` by default. See `Mock::return_value`.
+    pub fn default_return<T: Into<R>>(mut self, value: T) -> Self {
+        self.default_return = Some(value.into());
+        self
+    }
+
+    /// Return `values`, one per call, by default. See `Mock::return_values`.
+    pub fn return_values<T: Into<R>>(mut self, values: Vec<T>) -> Self {
+        self.return_values = Some(values.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Return `return_value` when called with `args`. See
+    /// `Mock::return_value_for`.
+    pub fn return_value_for<S: Into<C>, T: Into<R>>(mut self, args: S, return_value: T) -> Self {
+        self.return_values_for.insert(args.into(), return_value.into());
+        self
+    }
+
+    /// Call `default_fn` by default. See `Mock::use_fn`.
+    pub fn use_fn(mut self, default_fn: fn(C) -> R) -> Self {
+        self.default_fn = Some(default_fn);
+        self
+    }
+
+    /// Call `function` when called with `args`. See `Mock::use_fn_for`.
+    pub fn use_fn_for<T: Into<C>>(mut self, args: T, function: fn(C) -> R) -> Self {
+        self.fns_for.insert(args.into(), function);
+        self
+    }
+
+    /// Call `default_fn` by default. See `Mock::use_closure`.
+    pub fn use_closure(mut self, default_fn: Closure<C, R>) -> Self {
+        self.default_closure = Some(default_fn);
+        self
+    }
+
+    /// Call `function` when called with `args`. See `Mock::use_closure_for`.
+    pub fn use_closure_for<T: Into<C>>(mut self, args: T, function: Closure<C, R>) -> Self {
+        self.closures_for.insert(args.into(), function);
+        self
+    }
+
+    /// Return `return_value` when called with args that match `pattern`.
+    ///
+    /// A stub is an expectation (see `Mock::expect`) that may be matched any
+    /// number of times, so it takes precedence over all other behaviour and
+    /// never makes `Mock::verify` fail.
+    pub fn stub<T: Into<R>>(mut self, pattern: StaticPattern<C>, return_value: T) -> Self {
+        self.stubs.push((Box::new(move |args| pattern(args)), return_value.into()));
+        self
+    }
+
+    /// Use `policy` to handle unexpected calls, regardless of the policy
+    /// active when the mock is built. See `Mock::set_policy`.
+    pub fn policy(mut self, policy: MockPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Panic on unexpected calls. Shorthand for
+    /// `policy(MockPolicy::Strict)`.
+    pub fn strict(self) -> Self {
+        self.policy(MockPolicy::Strict)
+    }
+
+    /// Only keep the `capacity` most recent calls. See
+    /// `Mock::set_call_log_capacity`.
+    pub fn call_log_capacity(mut self, capacity: usize) -> Self {
+        self.call_log_capacity = Some(capacity);
+        self
+    }
+
+    /// Format args in failure messages using `formatter`. See
+    /// `Mock::format_args_with`.
+    pub fn format_args_with(mut self, formatter: ArgsFormatter<C>) -> Self {
+        self.args_formatter = Some(formatter);
+        self
+    }
+}
+
+impl<C, R> MockBuilder<C, R>
+    where C: Clone + Eq + Hash,
+          R: Clone
+{
+    /// Construct the `Mock`, which returns the value given to
+    /// `default_return` by default. `R` doesn't need to implement `Default`,
+    /// so this also builds mocks that return e.g. a `Result`.
+    ///
+    /// Like any other `Mock`, it registers with the active mock registry, if
+    /// there is one. See `double::register_mocks`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no default return value was given. Use `build_or_default`
+    /// to fall back to `R::default()` instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<&str, Result<u32, String>>::builder()
+    ///     .default_return(Err("not found".to_owned()))
+    ///     .return_value_for("one", Ok(1))
+    ///     .build();
+    ///
+    /// assert_eq!(mock.call("one"), Ok(1));
+    /// assert_eq!(mock.call("two"), Err("not found".to_owned()));
+    /// ```
+    pub fn build(mut self) -> Mock<C, R> {
+        let value = self.default_return.take().expect(
+            "`MockBuilder::build` needs a default return value, given using `default_return`, \
+             or use `build_or_default` to return `R::default()`");
+        let mock = Mock::new(value);
+        mock.configure_default_stub();
+        self.apply(mock)
+    }
+
+    // Configures everything but the default return value on `mock`.
+    fn apply(self, mock: Mock<C, R>) -> Mock<C, R> {
+        if let Some(values) = self.return_values {
+            mock.return_values(values);
+        }
+        for (args, return_value) in self.return_values_for {
+            mock.return_value_for(args, return_value);
+        }
+        if let Some(default_fn) = self.default_fn {
+            mock.use_fn(default_fn);
+        }
+        for (args, function) in self.fns_for {
+            mock.use_fn_for(args, function);
+        }
+        if let Some(default_closure) = self.default_closure {
+            mock.use_closure(default_closure);
+        }
+        for (args, function) in self.closures_for {
+            mock.use_closure_for(args, function);
+        }
+        for (pattern, return_value) in self.stubs {
+            mock.expect_closure(pattern).times(Times::any()).returning(return_value);
+        }
+        if let Some(policy) = self.policy {
+            mock.set_policy(policy);
+        }
+        if let Some(capacity) = self.call_log_capacity {
+            mock.set_call_log_capacity(Some(capacity));
+        }
+        if let Some(formatter) = self.args_formatter {
+            mock.format_args_with(formatter);
+        }
+        mock
+    }
+}
+
+impl<C, R> MockBuilder<C, R>
+    where C: Clone + Eq + Hash,
+          R: Clone + Default
+{
+    /// Construct the `Mock` like `build`, but if no default return value was
+    /// given, the mock is constructed using `Mock::default`.
+    pub fn build_or_default(self) -> Mock<C, R> {
+        if self.default_return.is_some() {
+            self.build()
+        } else {
+            self.apply(Mock::default())
+        }
+    }
+}
+
+impl<C, R> fmt::Debug for MockBuilder<C, R>
+    where C: Clone + Eq + Hash,
+          R: Clone
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MockBuilder").finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_applies_all_behaviour() {
+        let mock = MockBuilder::<i32, i32>::new()
+            .default_return(1)
+            .return_value_for(2, 20)
+            .use_closure_for(3, Box::new(|x| x * 100))
+            .stub(&|&x| x > 10, 7)
+            .call_log_capacity(2)
+            .build();
+
+        assert_eq!(1, mock.call(0));
+        assert_eq!(20, mock.call(2));
+        assert_eq!(300, mock.call(3));
+        assert_eq!(7, mock.call(11));
+        assert_eq!(7, mock.call(12));
+        assert!(mock.verify());
+        assert_eq!(vec!(11, 12), mock.calls());
+    }
+
+    #[test]
+    fn default_return_is_configured_behaviour() {
+        // A strict mock would panic if the default return value didn't count
+        let mock = MockBuilder::<i32, i32>::new().default_return(5).strict().build();
+        assert_eq!(MockPolicy::Strict, mock.policy());
+        assert_eq!(5, mock.call(1));
+    }
+
+    #[test]
+    fn build_does_not_need_a_default_for_the_return_type() {
+        let mock = MockBuilder::<i32, Result<i32, String>>::new()
+            .default_return(Err("no".to_owned()))
+            .return_value_for(1, Ok(10))
+            .strict()
+            .build();
+        assert_eq!(Ok(10), mock.call(1));
+        assert_eq!(Err("no".to_owned()), mock.call(2));
+        assert!(mock.verify());
+    }
+
+    #[test]
+    #[should_panic(expected = "needs a default return value")]
+    fn build_panics_without_a_default_return_value() {
+        MockBuilder::<i32, Result<i32, String>>::new().return_value_for(1, Ok(10)).build();
+    }
+
+    #[test]
+    fn build_or_default_falls_back_to_the_default_of_the_return_type() {
+        let mock = MockBuilder::<i32, i32>::new().return_value_for(1, 10).build_or_default();
+        assert_eq!(10, mock.call(1));
+        assert_eq!(0, mock.call(2));
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Debug;
+use std::panic::Location;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use crate::call_record::{self, CallRecord};
+use crate::shared::{AtomicBool, AtomicUsize, Ordering, Shared, SharedPtr};
+
+// With the `sync` feature, each thread records calls in one of several shards,
+// so threads calling the same mock rarely contend for the same lock. Threads
+// are given shards in turn, so the first `NUM_SHARDS` threads each have a
+// shard of their own. Calls are put back in order using their sequence
+// numbers, which are shared by all mocks, when the log is read, so the log
+// agrees with the order used to verify calls across mocks.
+//
+// `loom` runs the threads of a model on one thread, so they would all share a
+// shard anyway, and a single shard keeps the model small.
+#[cfg(any(not(feature = "sync"), loom))]
+const NUM_SHARDS: usize = 1;
+#[cfg(all(feature = "sync", not(loom)))]
+const NUM_SHARDS: usize = 16;
+
+// Capacity of a log that keeps every call
+const UNBOUNDED: usize = usize::MAX;
+
+// The calls recorded by a `Mock`. If a capacity is set, only the most recent
+// calls are kept and older ones are dropped (and counted). Calls can also be
+// counted without being recorded at all.
+//
+// Recording a call only locks the current thread's shard. Dropped calls are removed from a shard
+// the next time a call is recorded in it, and are ignored when reading the log
+// until then.
+pub(crate) struct CallLog<C, R> {
+    shards: Vec<Shared<VecDeque<CallRecord<C, R>>>>,
+    // Shared with the mock registry
+    num_calls: SharedPtr<AtomicUsize>,
+    // Calls with a lower index have been dropped
+    first_retained: AtomicUsize,
+    capacity: AtomicUsize,
+    recording: AtomicBool,
+    #[cfg(feature = "std")]
+    recording_durations: AtomicBool,
+}
+
+impl<C, R> CallLog<C, R> {
+    pub(crate) fn new() -> Self {
+        CallLog {
+            shards: (0..NUM_SHARDS).map(|_| Shared::new(VecDeque::new())).collect(),
+            num_calls: SharedPtr::new(AtomicUsize::new(0)),
+            first_retained: AtomicUsize::new(0),
+            capacity: AtomicUsize::new(UNBOUNDED),
+            recording: AtomicBool::new(true),
+            #[cfg(feature = "std")]
+            recording_durations: AtomicBool::new(false),
+        }
+    }
+
+    // Records a call made from `location` and returns its index, along with
+    // the indices of the calls dropped to make room for it.
+    pub(crate) fn push(
+        &self,
+        args: C,
+        location: &'static Location<'static>) -> (usize, Vec<usize>)
+    {
+        let (index, sequence_number) = self.number_call();
+        let record = CallRecord::new(index, sequence_number, args, location);
+        let dropped = self.drop_beyond_capacity(index + 1);
+        let first_retained = self.first_retained.load(Ordering::SeqCst);
+        let mut shard = self.shards[current_shard()].borrow_mut();
+        while shard.front().is_some_and(|record| record.index() < first_retained) {
+            shard.pop_front();
+        }
+        shard.push_back(record);
+        (index, dropped)
+    }
+
+    // Gives a call its index and sequence number, so that the calls to the
+    // mock are in the same order by both. The sequence number is taken after
+    // the index is read and kept only if the index is still free once it has
+    // been taken, so a call given the next index takes its sequence number
+    // later. Sequence numbers taken by calls that lose the race are skipped.
+    fn number_call(&self) -> (usize, usize) {
+        let mut index = self.num_calls.load(Ordering::SeqCst);
+        loop {
+            let sequence_number = call_record::next_sequence_number();
+            match self.num_calls.compare_exchange(
+                index, index + 1, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => return (index, sequence_number),
+                Err(current) => index = current,
+            }
+        }
+    }
+
+    // Counts a call without recording it and returns its index.
+    pub(crate) fn count(&self) -> usize {
+        self.num_calls.fetch_add(1, Ordering::SeqCst)
+    }
+
+    // Sets the capacity and returns the indices of the calls dropped because
+    // of it.
+    pub(crate) fn set_capacity(&self, capacity: Option<usize>) -> Vec<usize> {
+        self.capacity.store(capacity.unwrap_or(UNBOUNDED), Ordering::SeqCst);
+        let dropped = self.drop_beyond_capacity(self.num_calls());
+        let first_retained = self.first_retained.load(Ordering::SeqCst);
+        for shard in &self.shards {
+            shard.borrow_mut().retain(|record| record.index() >= first_retained);
+        }
+        dropped
+    }
+
+    pub(crate) fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn set_recording(&self, recording: bool) {
+        self.recording.store(recording, Ordering::SeqCst);
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn is_recording_durations(&self) -> bool {
+        self.recording_durations.load(Ordering::SeqCst)
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn set_recording_durations(&self, recording_durations: bool) {
+        self.recording_durations.store(recording_durations, Ordering::SeqCst);
+    }
+
+    // Records when the stub that handled the call with the given index
+    // started running, and how long it ran for.
+    #[cfg(feature = "std")]
+    pub(crate) fn record_stub_run(&self, index: usize, started: Instant, duration: Duration) {
+        let mut shard = self.shards[current_shard()].borrow_mut();
+        if let Some(record) = shard.iter_mut().rev().find(|record| record.index() == index) {
+            record.set_stub_run(started, duration);
+        }
+    }
+
+    pub(crate) fn num_calls(&self) -> usize {
+        self.num_calls.load(Ordering::SeqCst)
+    }
+
+    // Returns the counter of calls, which counts calls even after they are
+    // dropped from the log. Used by the mock registry.
+    #[cfg(feature = "std")]
+    pub(crate) fn call_counter(&self) -> SharedPtr<AtomicUsize> {
+        self.num_calls.clone()
+    }
+
+    pub(crate) fn num_dropped(&self) -> usize {
+        self.first_retained.load(Ordering::SeqCst)
+    }
+
+    // Calls `f` with the records of all calls that have not been dropped, in
+    // order from first to last. Every shard stays borrowed while `f` runs, so
+    // `f` mustn't run code that might use the mock, e.g. the patterns of
+    // tests. Those are given a copy of the log instead.
+    fn with_records<T, F>(&self, f: F) -> T
+        where F: FnOnce(&[&CallRecord<C, R>]) -> T
+    {
+        let shards: Vec<_> = self.shards.iter().map(|shard| shard.borrow()).collect();
+        let first_retained = self.first_retained.load(Ordering::SeqCst);
+        let mut records: Vec<&CallRecord<C, R>> = shards
+            .iter()
+            .flat_map(|shard| shard.iter())
+            .filter(|record| record.index() >= first_retained)
+            .collect();
+        if NUM_SHARDS > 1 {
+            records.sort_by_key(|record| record.sequence_number());
+        }
+        f(&records)
+    }
+
+    pub(crate) fn clear(&self) {
+        for shard in &self.shards {
+            shard.borrow_mut().clear();
+        }
+        self.num_calls.store(0, Ordering::SeqCst);
+        self.first_retained.store(0, Ordering::SeqCst);
+    }
+
+    fn drop_beyond_capacity(&self, num_calls: usize) -> Vec<usize> {
+        let capacity = self.capacity.load(Ordering::SeqCst);
+        if capacity == UNBOUNDED {
+            return vec!();
+        }
+        let first_retained = num_calls.saturating_sub(capacity);
+        let previous = self.first_retained.fetch_max(first_retained, Ordering::SeqCst);
+        (previous..first_retained).collect()
+    }
+}
+
+impl<C: Clone, R> CallLog<C, R> {
+    // Returns the index and args of each call that has not been dropped, in
+    // order from first to last, for callers whose return type isn't `Clone`.
+    pub(crate) fn indexed_args(&self) -> Vec<(usize, C)> {
+        self.with_records(|records| {
+            records.iter().map(|record| (record.index(), record.args().clone())).collect()
+        })
+    }
+}
+
+impl<C: Clone, R: Clone> CallLog<C, R> {
+    // Records the value returned by the call with the given index. The value
+    // is only cloned if the log records calls.
+    pub(crate) fn complete(&self, index: usize, return_value: &R) {
+        if !self.is_recording() {
+            return;
+        }
+        let mut shard = self.shards[current_shard()].borrow_mut();
+        if let Some(record) = shard.iter_mut().rev().find(|record| record.index() == index) {
+            record.complete(return_value.clone());
+        }
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<CallRecord<C, R>> {
+        self.with_records(|records| {
+            records
+                .iter()
+                .find(|record| record.index() == index)
+                .map(|record| (*record).clone())
+        })
+    }
+
+    pub(crate) fn last(&self) -> Option<CallRecord<C, R>> {
+        self.with_records(|records| records.last().map(|record| (*record).clone()))
+    }
+
+    pub(crate) fn records(&self) -> Vec<CallRecord<C, R>> {
+        self.with_records(|records| records.iter().map(|record| (*record).clone()).collect())
+    }
+}
+
+// Lists each retained call as `#<index> called with <args>, returned <value>`
+impl<C: Debug, R: Debug> Debug for CallLog<C, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.with_records(|records| {
+            f.debug_list()
+                .entries(records.iter().map(|record| Summary(record)))
+                .finish()
+        })
+    }
+}
+
+struct Summary<'a, C, R>(&'a CallRecord<C, R>);
+
+impl<'a, C: Debug, R: Debug> Debug for Summary<'a, C, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{} {}", self.0.index(), self.0)
+    }
+}
+
+#[cfg(any(not(feature = "sync"), loom))]
+fn current_shard() -> usize {
+    0
+}
+
+#[cfg(all(feature = "sync", not(loom)))]
+fn current_shard() -> usize {
+    use std::sync::atomic;
+
+    static NEXT_THREAD: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
+
+    thread_local! {
+        static SHARD: usize = NEXT_THREAD.fetch_add(1, Ordering::Relaxed) % NUM_SHARDS;
+    }
+    SHARD.with(|shard| *shard)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_oldest_calls_beyond_capacity() {
+        let log = CallLog::<i32, ()>::new();
+        assert_eq!((0, vec!()), log.push(10, Location::caller()));
+        assert_eq!((1, vec!()), log.push(11, Location::caller()));
+        assert_eq!(vec!(0), log.set_capacity(Some(1)));
+        assert_eq!((2, vec!(1)), log.push(12, Location::caller()));
+
+        assert_eq!(3, log.num_calls());
+        assert_eq!(2, log.num_dropped());
+        assert_eq!(None, log.get(1));
+        assert_eq!(Some(12), log.get(2).map(CallRecord::into_args));
+
+        log.clear();
+        assert_eq!(0, log.num_calls());
+        assert_eq!((0, vec!()), log.push(13, Location::caller()));
+    }
+
+    #[test]
+    fn records_are_ordered_by_index() {
+        let log = CallLog::<i32, i32>::new();
+        for i in 0..10 {
+            let (index, _) = log.push(i, Location::caller());
+            log.complete(index, &(i * 2));
+        }
+        log.with_records(|records| {
+            let indices: Vec<usize> = records.iter().map(|record| record.index()).collect();
+            assert_eq!((0..10).collect::<Vec<usize>>(), indices);
+            assert_eq!(Some(&18), records[9].return_value());
+        });
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn calls_from_many_threads_are_read_in_sequence_order() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let log = Arc::new(CallLog::<usize, ()>::new());
+        let threads: Vec<_> = (0..NUM_SHARDS * 2)
+            .map(|i| {
+                let log = log.clone();
+                thread::spawn(move || {
+                    for j in 0..100 {
+                        log.push(i * 100 + j, Location::caller());
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        log.with_records(|records| {
+            assert_eq!(NUM_SHARDS * 200, records.len());
+            assert!(records
+                .windows(2)
+                .all(|pair| pair[0].sequence_number() < pair[1].sequence_number()));
+            let mut indices: Vec<usize> = records.iter().map(|record| record.index()).collect();
+            indices.sort();
+            assert_eq!((0..NUM_SHARDS * 200).collect::<Vec<usize>>(), indices);
+        });
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn indices_of_calls_from_many_threads_are_in_sequence_order() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let log = Arc::new(CallLog::<(), ()>::new());
+        let threads: Vec<_> = (0..NUM_SHARDS * 2)
+            .map(|_| {
+                let log = log.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        log.push((), Location::caller());
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        log.with_records(|records| {
+            let indices: Vec<usize> = records.iter().map(|record| record.index()).collect();
+            assert_eq!((0..NUM_SHARDS * 2000).collect::<Vec<usize>>(), indices);
+        });
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn recording_a_call_only_locks_the_shard_of_the_calling_thread() {
+        use std::sync::Arc;
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        let log = Arc::new(CallLog::<(), ()>::new());
+        let (shard_sender, shard_receiver) = mpsc::channel();
+        let (go_sender, go_receiver) = mpsc::channel();
+        let (done_sender, done_receiver) = mpsc::channel();
+        let caller = {
+            let log = log.clone();
+            thread::spawn(move || {
+                shard_sender.send(current_shard()).unwrap();
+                go_receiver.recv().unwrap();
+                done_sender.send(log.push((), Location::caller())).unwrap();
+            })
+        };
+
+        let own_shard = shard_receiver.recv().unwrap();
+        let other_shards: Vec<_> = log
+            .shards
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != own_shard)
+            .map(|(_, shard)| shard.borrow_mut())
+            .collect();
+        go_sender.send(()).unwrap();
+        let recorded = done_receiver.recv_timeout(Duration::from_secs(10));
+        drop(other_shards);
+        caller.join().unwrap();
+        assert_eq!(Ok((0, vec!())), recorded);
+    }
+
+    #[test]
+    fn debug_summarises_calls() {
+        let log = CallLog::<i32, &str>::new();
+        let (index, _) = log.push(1, Location::caller());
+        log.complete(index, &"one");
+        log.push(2, Location::caller());
+        assert_eq!(
+            "[#0 called with 1, returned \"one\", #1 called with 2, did not return]",
+            format!("{:?}", log));
+    }
+}
@@ -0,0 +1,425 @@
+#[cfg(feature = "call-backtraces")]
+use std::backtrace::Backtrace;
+use std::fmt;
+use std::fmt::Debug;
+use std::panic::Location;
+#[cfg(feature = "call-backtraces")]
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+use std::vec;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+// Shared by all mocks, so calls to different mocks can be ordered.
+static NEXT_SEQUENCE_NUMBER: AtomicUsize = AtomicUsize::new(0);
+
+/// A single call recorded by a `Mock`.
+///
+/// Call records are returned by `Mock::records` and `Mock::calls_matching`.
+/// If both the args and return type implement `Debug`, the record's `Display`
+/// implementation describes the whole interaction, e.g.
+/// `called with (1, 2), returned 3`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CallRecord<C, R> {
+    index: usize,
+    sequence_number: usize,
+    args: C,
+    return_value: Option<R>,
+    #[cfg(feature = "std")]
+    timestamp: Instant,
+    // When the stub that handled the call started running, and how long it
+    // ran for, if the mock records durations
+    #[cfg(feature = "std")]
+    stub_run: Option<(Instant, Duration)>,
+    location: &'static Location<'static>,
+    #[cfg(feature = "call-backtraces")]
+    backtrace: CallBacktrace,
+}
+
+// Returns the sequence number of a new call. See `CallRecord::sequence_number`.
+pub(crate) fn next_sequence_number() -> usize {
+    NEXT_SEQUENCE_NUMBER.fetch_add(1, Ordering::SeqCst)
+}
+
+impl<C, R> CallRecord<C, R> {
+    pub(crate) fn new(
+        index: usize,
+        sequence_number: usize,
+        args: C,
+        location: &'static Location<'static>) -> Self
+    {
+        CallRecord {
+            index,
+            sequence_number,
+            args,
+            return_value: None,
+            #[cfg(feature = "std")]
+            timestamp: Instant::now(),
+            #[cfg(feature = "std")]
+            stub_run: None,
+            location,
+            #[cfg(feature = "call-backtraces")]
+            backtrace: CallBacktrace(Arc::new(Backtrace::force_capture())),
+        }
+    }
+
+    pub(crate) fn complete(&mut self, return_value: R) {
+        self.return_value = Some(return_value);
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn set_stub_run(&mut self, started: Instant, duration: Duration) {
+        self.stub_run = Some((started, duration));
+    }
+
+    /// Returns the position of the call in the mock's call history, starting
+    /// from zero.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns a number that orders the call among the calls made to *all*
+    /// mocks. A call with a lower sequence number happened before a call with
+    /// a higher one, even if the calls were made to different mocks. Sequence
+    /// numbers increase, but not always by one.
+    pub fn sequence_number(&self) -> usize {
+        self.sequence_number
+    }
+
+    /// Returns the arguments the mock was called with.
+    pub fn args(&self) -> &C {
+        &self.args
+    }
+
+    /// Returns the value the call returned, or `None` if the call has not
+    /// returned yet (e.g. because the mock's closure panicked or is still
+    /// running).
+    pub fn return_value(&self) -> Option<&R> {
+        self.return_value.as_ref()
+    }
+
+    /// Returns the (monotonic) time at which the call was made. Requires the
+    /// `std` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i32, ()>::default();
+    /// mock.call(1);
+    /// thread::sleep(Duration::from_millis(10));
+    /// mock.call(2);
+    ///
+    /// let records: Vec<_> = mock.records().collect();
+    /// let gap = records[1].timestamp() - records[0].timestamp();
+    /// assert!(gap >= Duration::from_millis(10));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn timestamp(&self) -> Instant {
+        self.timestamp
+    }
+
+    /// Returns how long the closure or function that handled the call (see
+    /// `Mock::use_closure` and `Mock::use_fn`) ran for. Durations are only
+    /// recorded once `Mock::set_recording_durations` is called, so this is
+    /// `None` until then, as well as for calls that haven't returned yet and
+    /// calls handled by a return value. Requires the `std` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i32, ()>::default();
+    /// mock.set_recording_durations(true);
+    /// mock.use_closure(Box::new(|_| thread::sleep(Duration::from_millis(10))));
+    /// mock.call(1);
+    ///
+    /// let record = mock.records().next().unwrap();
+    /// assert!(record.duration().unwrap() >= Duration::from_millis(10));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn duration(&self) -> Option<Duration> {
+        self.stub_run.map(|(_, duration)| duration)
+    }
+
+    /// Returns the source location the mock was called from.
+    ///
+    /// `Mock::call` is annotated with `#[track_caller]`, as are the methods
+    /// generated by `mock_method!`, so this is the location of the call in
+    /// the code under test rather than inside `double`. Calls made through
+    /// the closures returned by `Mock::as_fn` and `Mock::as_fn_mut` are
+    /// recorded as coming from `double` itself, since closures can't track
+    /// their caller.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i32, ()>::default();
+    /// mock.call(1);
+    ///
+    /// let record = mock.records().next().unwrap();
+    /// assert_eq!(record.location().file(), file!());
+    /// assert_eq!(record.location().line(), line!() - 4);
+    /// ```
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    /// Returns the backtrace of the call, captured when it was made. Unlike
+    /// `CallRecord::location`, this shows the entire chain of calls that led
+    /// to the mock being called, which helps to find out why a mock was called
+    /// more often than expected.
+    ///
+    /// The backtrace is captured regardless of the `RUST_BACKTRACE`
+    /// environment variable. Capturing a backtrace for every call is slow, so
+    /// this requires the `call-backtraces` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::backtrace::BacktraceStatus;
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i32, ()>::default();
+    /// mock.call(1);
+    ///
+    /// let record = mock.records().next().unwrap();
+    /// assert_eq!(record.backtrace().status(), BacktraceStatus::Captured);
+    /// println!("mock called from:\n{}", record.backtrace());
+    /// ```
+    #[cfg(feature = "call-backtraces")]
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace.0
+    }
+
+    /// Returns true if the closures or functions that handled this call and
+    /// `other` ran at the same time. Calls without a duration (see
+    /// `CallRecord::duration`) are treated as running from when they were
+    /// made until now, so both mocks should record durations. Requires the
+    /// `std` feature.
+    #[cfg(feature = "std")]
+    pub fn overlaps<D, S>(&self, other: &CallRecord<D, S>) -> bool {
+        let now = Instant::now();
+        let (start, end) = self.span(now);
+        let (other_start, other_end) = other.span(now);
+        start < other_end && other_start < end
+    }
+
+    #[cfg(feature = "std")]
+    fn span(&self, now: Instant) -> (Instant, Instant) {
+        match self.stub_run {
+            Some((started, duration)) => (started, started + duration),
+            None => (self.timestamp, now),
+        }
+    }
+
+    /// Consumes the record, returning the arguments the mock was called with.
+    pub fn into_args(self) -> C {
+        self.args
+    }
+}
+
+impl<C: Debug, R: Debug> fmt::Display for CallRecord<C, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "called with {:?}", self.args)?;
+        match self.return_value {
+            Some(ref return_value) => write!(f, ", returned {:?}", return_value),
+            None => write!(f, ", did not return"),
+        }
+    }
+}
+
+// A backtrace shared by the clones of a `CallRecord`. Backtraces can't be
+// compared, so clones are equal to each other but not to other backtraces.
+#[cfg(feature = "call-backtraces")]
+#[derive(Clone)]
+struct CallBacktrace(Arc<Backtrace>);
+
+#[cfg(feature = "call-backtraces")]
+impl PartialEq for CallBacktrace {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[cfg(feature = "call-backtraces")]
+impl Eq for CallBacktrace {}
+
+// Backtraces are long, so they are left out of the record's `Debug` output.
+#[cfg(feature = "call-backtraces")]
+impl Debug for CallBacktrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Backtrace { .. }")
+    }
+}
+
+/// An iterator over `CallRecord`s, ordered from first call to last.
+///
+/// Standard iterator adaptors such as `filter` and `map` can be used to
+/// refine the query further. `args`, `return_values`, `durations`, `indices`
+/// and `intervals` are shorthands for the most common projections.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use double::Mock;
+/// use double::matcher::*;
+///
+/// # fn main() {
+/// let mock = Mock::<(i32, &str), ()>::default();
+/// mock.call((1, "one"));
+/// mock.call((20, "twenty"));
+/// mock.call((30, "thirty"));
+///
+/// let large = mock.calls_matching(matcher!(p!(gt, 10), p!(any)));
+/// assert_eq!(large.indices(), vec!(1, 2));
+///
+/// let names: Vec<&str> = mock.records()
+///     .filter(|record| record.index() != 1)
+///     .map(|record| record.args().1)
+///     .collect();
+/// assert_eq!(names, vec!("one", "thirty"));
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct CallRecords<C, R> {
+    records: vec::IntoIter<CallRecord<C, R>>,
+}
+
+impl<C, R> CallRecords<C, R> {
+    pub(crate) fn new(records: Vec<CallRecord<C, R>>) -> Self {
+        CallRecords { records: records.into_iter() }
+    }
+
+    /// Returns the arguments of the remaining calls.
+    pub fn args(self) -> Vec<C> {
+        self.map(CallRecord::into_args).collect()
+    }
+
+    /// Returns the return values of the remaining calls. Calls that have not
+    /// returned are skipped.
+    pub fn return_values(self) -> Vec<R> {
+        self.filter_map(|record| record.return_value).collect()
+    }
+
+    /// Returns the durations of the remaining calls. Calls without a duration
+    /// are skipped (see `CallRecord::duration`). Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn durations(self) -> Vec<Duration> {
+        self.filter_map(|record| record.duration()).collect()
+    }
+
+    /// Returns the indices of the remaining calls.
+    pub fn indices(self) -> Vec<usize> {
+        self.map(|record| record.index()).collect()
+    }
+
+    /// Returns the time elapsed between each consecutive pair of the
+    /// remaining calls. Useful for testing retry and backoff logic. Requires
+    /// the `std` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i32, ()>::default();
+    /// for attempt in 0..3 {
+    ///     mock.call(attempt);
+    ///     thread::sleep(Duration::from_millis(5));
+    /// }
+    ///
+    /// let intervals = mock.records().intervals();
+    /// assert_eq!(intervals.len(), 2);
+    /// assert!(intervals.iter().all(|gap| *gap >= Duration::from_millis(5)));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn intervals(self) -> Vec<Duration> {
+        let timestamps: Vec<Instant> = self.map(|record| record.timestamp()).collect();
+        timestamps
+            .windows(2)
+            .map(|pair| pair[1].duration_since(pair[0]))
+            .collect()
+    }
+}
+
+impl<C, R> Iterator for CallRecords<C, R> {
+    type Item = CallRecord<C, R>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.records.size_hint()
+    }
+}
+
+impl<C, R> DoubleEndedIterator for CallRecords<C, R> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.records.next_back()
+    }
+}
+
+impl<C, R> ExactSizeIterator for CallRecords<C, R> {}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "call-backtraces")]
+    use std::ptr;
+    use std::thread;
+
+    #[test]
+    fn display_describes_interaction() {
+        let mut record = CallRecord::new(0, 0, (1, "two"), Location::caller());
+        assert_eq!("called with (1, \"two\"), did not return", record.to_string());
+        record.complete(3);
+        assert_eq!("called with (1, \"two\"), returned 3", record.to_string());
+    }
+
+    #[test]
+    fn overlaps() {
+        let pause = || thread::sleep(Duration::from_millis(1));
+        let mut first = CallRecord::<i32, ()>::new(0, 0, 1, Location::caller());
+        pause();
+        let mut second = CallRecord::<i32, ()>::new(1, 1, 2, Location::caller());
+        pause();
+        assert!(first.overlaps(&second));
+
+        first.set_stub_run(first.timestamp(), first.timestamp().elapsed());
+        pause();
+        let third = CallRecord::<i32, ()>::new(2, 2, 3, Location::caller());
+        pause();
+        assert!(!first.overlaps(&third));
+
+        second.set_stub_run(second.timestamp(), second.timestamp().elapsed());
+        assert!(first.overlaps(&second));
+        assert!(!third.overlaps(&first));
+    }
+
+    #[cfg(feature = "call-backtraces")]
+    #[test]
+    fn backtrace_is_shared_by_clones() {
+        let record = CallRecord::<i32, ()>::new(0, 0, 1, Location::caller());
+        let clone = record.clone();
+        assert!(ptr::eq(record.backtrace(), clone.backtrace()));
+        assert_eq!(record, clone);
+        assert!(record.backtrace().to_string().contains("backtrace_is_shared_by_clones"));
+        assert!(format!("{:?}", record).contains("backtrace: Backtrace { .. }"));
+    }
+}
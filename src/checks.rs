@@ -0,0 +1,67 @@
+// Checks of the types that `#[double::mock]` mocks record and return, which
+// it generates for each method ahead of the mock, spanned like the method's
+// types. `Method` is a type named after the method, so that the errors name
+// it, e.g. "method `frob` of a mock returns `Token`, which must implement
+// `Clone`". The mock's impls are bounded so that they don't fail too (see
+// `__private_mock_trait!(@checked ...)`), so these are the only errors.
+//
+// Each trait that a type must implement has a check of its own, so a type
+// that implements none of them is reported once for each, rather than with
+// the same message for each.
+
+use std::fmt::Debug;
+use std::hash::Hash;
+
+// Declares the check `$check`, which types implementing `$bound` pass
+macro_rules! check {
+    ($check:ident: $bound:ident, $message:literal, $label:literal, $note:literal) => (
+        #[diagnostic::on_unimplemented(message = $message, label = $label, note = $note)]
+        pub trait $check<Method> {}
+
+        impl<T: $bound, Method> $check<Method> for T {}
+    );
+}
+
+check!(RecordedClone: Clone,
+       "method `{Method}` of a mock takes `{Self}`, which must implement `Clone`",
+       "recorded by the mock",
+       "mocks record a clone of each arg, so calls can be compared to the expected args");
+check!(RecordedDebug: Debug,
+       "method `{Method}` of a mock takes `{Self}`, which must implement `Debug`",
+       "recorded by the mock",
+       "mocks print the args of the calls they record, e.g. when expectations fail");
+check!(RecordedEq: Eq,
+       "method `{Method}` of a mock takes `{Self}`, which must implement `Eq`",
+       "recorded by the mock",
+       "mocks compare the args of the calls they record to the expected args");
+check!(RecordedHash: Hash,
+       "method `{Method}` of a mock takes `{Self}`, which must implement `Hash`",
+       "recorded by the mock",
+       "mocks look up the behaviour for each call by its args");
+check!(ReturnedClone: Clone,
+       "method `{Method}` of a mock returns `{Self}`, which must implement `Clone`",
+       "returned by the mock",
+       "mocks return a clone of the value they're configured with from each call; values \
+        that can't be cloned can be returned from a custom method body that builds them, \
+        see `mock_method!`");
+check!(ReturnedDebug: Debug,
+       "method `{Method}` of a mock returns `{Self}`, which must implement `Debug`",
+       "returned by the mock",
+       "mocks print the value each call they record returned, e.g. when expectations fail");
+check!(DefaultValue: Default,
+       "method `{Method}` of a mock returns `{Self}`, which must implement `Default` for the \
+        mock to implement `Default`",
+       "returned by the mock",
+       "use `#[double::mock(no_default)]` to construct the mock using `new` instead, which \
+        takes the value that each method returns");
+
+pub fn recorded_arg<Method, T>()
+    where T: RecordedClone<Method> + RecordedDebug<Method> + RecordedEq<Method>
+        + RecordedHash<Method>
+{}
+
+pub fn returned_value<Method, T>()
+    where T: ReturnedClone<Method> + ReturnedDebug<Method>
+{}
+
+pub fn default_value<Method, T: DefaultValue<Method>>() {}
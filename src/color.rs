@@ -0,0 +1,85 @@
+#[cfg(feature = "std")]
+use std::env;
+#[cfg(feature = "std")]
+use std::io::{self, IsTerminal};
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// Styles the parts of failure messages that are worth telling apart: expected
+// values in green, actual values in red, and the mismatching args in bold.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Palette {
+    enabled: bool,
+}
+
+impl Palette {
+    // Colours are used if failures are printed to a terminal, unless the
+    // `NO_COLOR` environment variable is set (see https://no-color.org).
+    #[cfg(feature = "std")]
+    pub(crate) fn detect() -> Self {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        Palette { enabled: !no_color && io::stdout().is_terminal() }
+    }
+
+    // Without `std` there's no terminal to print failures to
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn detect() -> Self {
+        Palette::plain()
+    }
+
+    pub(crate) fn plain() -> Self {
+        Palette { enabled: false }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn ansi() -> Self {
+        Palette { enabled: true }
+    }
+
+    pub(crate) fn expected(&self, text: &str) -> String {
+        self.paint(GREEN, text)
+    }
+
+    pub(crate) fn actual(&self, text: &str) -> String {
+        self.paint(RED, text)
+    }
+
+    pub(crate) fn highlight(&self, text: &str) -> String {
+        self.paint(BOLD, text)
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.enabled {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_owned()
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_palette_leaves_text_alone() {
+        let palette = Palette::plain();
+        assert_eq!("3", palette.expected("3"));
+        assert_eq!("4", palette.actual("4"));
+        assert_eq!("arg 1", palette.highlight("arg 1"));
+    }
+
+    #[test]
+    fn ansi_palette_wraps_text_in_styles() {
+        let palette = Palette::ansi();
+        assert_eq!("\x1b[32m3\x1b[0m", palette.expected("3"));
+        assert_eq!("\x1b[31m4\x1b[0m", palette.actual("4"));
+        assert_eq!("\x1b[1marg 1\x1b[0m", palette.highlight("arg 1"));
+    }
+}
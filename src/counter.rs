@@ -0,0 +1,36 @@
+use std::fmt;
+use crate::shared::{AtomicUsize, Ordering, SharedPtr};
+
+/// Counts the calls of a mock whose args match a pattern, without recording
+/// the calls. See `Mock::count_calls_with`.
+///
+/// `CallCounter` is a handle. Clones of it share its count.
+#[derive(Clone)]
+pub struct CallCounter {
+    count: SharedPtr<AtomicUsize>,
+}
+
+impl CallCounter {
+    pub(crate) fn new() -> Self {
+        CallCounter { count: SharedPtr::new(AtomicUsize::new(0)) }
+    }
+
+    pub(crate) fn increment(&self) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn reset(&self) {
+        self.count.store(0, Ordering::Relaxed);
+    }
+
+    /// Returns the number of matching calls that have been counted.
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for CallCounter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("CallCounter").field(&self.count()).finish()
+    }
+}
@@ -0,0 +1,453 @@
+#[cfg(feature = "std")]
+use std::cell::RefCell;
+use std::fmt;
+use std::fmt::Debug;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use crate::call_record::CallRecord;
+use crate::color::Palette;
+
+// Args longer than this (when formatted using `Debug`) are diffed rather than
+// printed inline.
+const MAX_INLINE_LEN: usize = 60;
+
+#[cfg(feature = "std")]
+thread_local! {
+    // Set while a mismatch is being explained. Matchers report what they
+    // rejected here.
+    static EXPLANATION: RefCell<Option<Explanation>> = const { RefCell::new(None) };
+}
+
+#[derive(Default)]
+struct Explanation {
+    // Reported by patterns built using `matcher!`
+    rejections: Vec<Rejection>,
+    // Source of the most recently evaluated `p!` matcher
+    last_clause: Option<&'static str>,
+    // Why the most recently evaluated composite matcher (e.g. `all_of`) failed
+    detail: Option<String>,
+}
+
+#[cfg(feature = "std")]
+fn with_explanation<T, F: FnOnce(&mut Explanation) -> T>(f: F) -> Option<T> {
+    EXPLANATION.with(|explanation| explanation.borrow_mut().as_mut().map(f))
+}
+
+// Without `std` there are no thread locals to report to, so mismatches are
+// never explained and failure messages don't say which args were rejected
+#[cfg(not(feature = "std"))]
+fn with_explanation<T, F: FnOnce(&mut Explanation) -> T>(_: F) -> Option<T> {
+    None
+}
+
+// Evaluates `pattern` while explaining a mismatch, returning what its matchers
+// reported
+#[cfg(feature = "std")]
+fn explain<F: FnOnce() -> bool>(pattern: F) -> (bool, Option<Explanation>) {
+    EXPLANATION.with(|explanation| *explanation.borrow_mut() = Some(Explanation::default()));
+    let matched = pattern();
+    (matched, EXPLANATION.with(|explanation| explanation.borrow_mut().take()))
+}
+
+#[cfg(not(feature = "std"))]
+fn explain<F: FnOnce() -> bool>(pattern: F) -> (bool, Option<Explanation>) {
+    (pattern(), None)
+}
+
+// A clause of a composite matcher, after it has been evaluated.
+pub(crate) struct Clause {
+    pub(crate) matched: bool,
+    source: Option<&'static str>,
+    detail: Option<String>,
+}
+
+impl Clause {
+    // Describes the clause at `index` (starting from zero).
+    pub(crate) fn describe(&self, index: usize) -> String {
+        let mut description = format!("clause #{}", index + 1);
+        if let Some(source) = self.source {
+            description += &format!(" ({})", source);
+        }
+        if let Some(ref detail) = self.detail {
+            description += &format!(" [{}]", detail);
+        }
+        description
+    }
+}
+
+// An arg that caused a call not to match, and why.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Rejection {
+    position: usize,
+    reason: String,
+    // The arg that was expected instead, if it is known
+    expected: Option<String>,
+}
+
+impl Rejection {
+    // Describes the rejection, highlighting the arg and the expected value.
+    pub(crate) fn describe(&self, palette: Palette) -> String {
+        let mut description = format!(
+            "{} {}",
+            palette.highlight(&format!("arg {}", self.position)),
+            self.reason);
+        if let Some(ref expected) = self.expected {
+            description += &format!(" {}", palette.expected(expected));
+        }
+        description
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.describe(Palette::plain()))
+    }
+}
+
+pub(crate) fn explaining() -> bool {
+    with_explanation(|_| ()).is_some()
+}
+
+// Called by patterns built using `matcher!` with the result of each arg's
+// matcher (see `with_detail`), and the source of the matchers.
+pub(crate) fn report_matches(matches: &[(bool, Option<String>)], matchers: &[&'static str]) {
+    with_explanation(|explanation| {
+        explanation.rejections = matches
+            .iter()
+            .zip(matchers)
+            .enumerate()
+            .filter(|&(_, (&(matched, _), _))| !matched)
+            .map(|(position, ((_, detail), matcher))| Rejection {
+                position,
+                reason: match detail {
+                    Some(detail) => format!("was rejected by {}: {}", matcher, detail),
+                    None => format!("was rejected by {}", matcher),
+                },
+                expected: None,
+            })
+            .collect();
+    });
+}
+
+// Called by `p!` matchers with their source, after they have been evaluated.
+pub(crate) fn report_clause(source: &'static str) {
+    with_explanation(|explanation| explanation.last_clause = Some(source));
+}
+
+// Records why a composite matcher failed. `detail` is only called while a
+// mismatch is being explained.
+pub(crate) fn report_detail<F: FnOnce() -> String>(detail: F) {
+    with_explanation(|explanation| explanation.detail = Some(detail()));
+}
+
+// Pairs the result of a matcher with the reason any composite matcher in it
+// failed, so the reason isn't attributed to a different matcher.
+pub(crate) fn with_detail(matched: bool) -> (bool, Option<String>) {
+    let detail = with_explanation(|explanation| explanation.detail.take()).and_then(|detail| detail);
+    (matched, if matched { None } else { detail })
+}
+
+// Evaluates a clause of a composite matcher.
+pub(crate) fn evaluate_clause<F: FnOnce() -> bool>(clause: F) -> Clause {
+    with_explanation(|explanation| {
+        explanation.last_clause = None;
+        explanation.detail = None;
+    });
+    let matched = clause();
+    let explained = with_explanation(|explanation| {
+        (explanation.last_clause.take(), explanation.detail.take())
+    });
+    let (source, detail) = explained.unwrap_or((None, None));
+    Clause { matched, source, detail }
+}
+
+// Returns the args that `pattern` rejected, or `None` if it matches `args`.
+// Only patterns built using `matcher!` say which args they rejected, so the
+// result is empty for other patterns.
+pub(crate) fn rejected_by_pattern<C>(
+    pattern: &dyn Fn(&C) -> bool,
+    args: &C) -> Option<Vec<Rejection>>
+{
+    let (matched, explanation) = explain(|| pattern(args));
+    if matched {
+        None
+    } else {
+        Some(explanation.map(|explanation| explanation.rejections).unwrap_or_default())
+    }
+}
+
+// Returns the args that differ from `expected`, or `None` if there are none.
+// Args are compared using their `Debug` representation, since the fields of
+// arbitrary tuples can't be accessed generically.
+pub(crate) fn rejected_by_args<C: Debug + PartialEq>(
+    expected: &C,
+    args: &C) -> Option<Vec<Rejection>>
+{
+    if args == expected {
+        return None;
+    }
+    let expected = format!("{:?}", expected);
+    let actual = format!("{:?}", args);
+    let rejections = debug_fields(&expected)
+        .into_iter()
+        .zip(debug_fields(&actual))
+        .enumerate()
+        .filter(|&(_, (expected, actual))| expected != actual)
+        .map(|(position, (expected, _))| {
+            if expected.len() > MAX_INLINE_LEN {
+                Rejection { position, reason: "differs".to_owned(), expected: None }
+            } else {
+                Rejection {
+                    position,
+                    reason: "should have been".to_owned(),
+                    expected: Some(expected.to_owned()),
+                }
+            }
+        })
+        .collect();
+    Some(rejections)
+}
+
+// Returns the call that came closest to matching, along with the args it was
+// rejected for. The closest call is the one with the fewest rejected args.
+// Calls whose rejected args are unknown come last. Ties go to the most recent
+// call.
+pub(crate) fn closest_call<'a, C, R, F>(
+    records: &'a [CallRecord<C, R>],
+    rejected: F) -> Option<(&'a CallRecord<C, R>, Vec<Rejection>)>
+    where F: Fn(&C) -> Option<Vec<Rejection>>
+{
+    let rank = |rejections: &Vec<Rejection>| {
+        if rejections.is_empty() { usize::MAX } else { rejections.len() }
+    };
+    let mut closest: Option<(&'a CallRecord<C, R>, Vec<Rejection>)> = None;
+    for record in records {
+        if let Some(rejections) = rejected(record.args()) {
+            if closest.as_ref().is_none_or(|(_, closest)| rank(&rejections) <= rank(closest)) {
+                closest = Some((record, rejections));
+            }
+        }
+    }
+    closest
+}
+
+// Describes the closest call returned by `closest_call`, including its
+// formatted args if they can be formatted.
+pub(crate) fn describe_closest_call<C, R>(
+    record: &CallRecord<C, R>,
+    args: Option<&str>,
+    rejections: &[Rejection],
+    palette: Palette) -> String
+{
+    let mut description = format!(
+        "{} call was #{} from {}",
+        if rejections.is_empty() { "Most recent" } else { "Closest" },
+        record.index(),
+        record.location());
+    if let Some(args) = args {
+        description += &format!(" with args {}", palette.actual(args));
+    }
+    if !rejections.is_empty() {
+        let rejections: Vec<String> = rejections
+            .iter()
+            .map(|rejection| rejection.describe(palette))
+            .collect();
+        description += &format!(": {}", rejections.join(", "));
+    }
+    description
+}
+
+// Formats `args` using `Debug`, unless they're too long to read inline.
+pub(crate) fn inline_debug<C: Debug>(args: &C) -> Option<String> {
+    let debug = format!("{:?}", args);
+    if debug.len() > MAX_INLINE_LEN { None } else { Some(debug) }
+}
+
+// Returns a line-by-line diff of the pretty-printed `Debug` representations
+// of `expected` and `actual`, if either is too long to compare inline. Lines
+// only in `expected` are prefixed with `-`, and lines only in `actual` are
+// prefixed with `+`, and coloured as expected and actual values respectively.
+pub(crate) fn diff<C: Debug>(expected: &C, actual: &C, palette: Palette) -> Option<String> {
+    if inline_debug(expected).is_some() && inline_debug(actual).is_some() {
+        return None;
+    }
+    let expected = format!("{:#?}", expected);
+    let actual = format!("{:#?}", actual);
+    let lines: Vec<String> = diff_lines(
+        &expected.lines().collect::<Vec<_>>(),
+        &actual.lines().collect::<Vec<_>>())
+        .into_iter()
+        .map(|line| if line.starts_with('-') {
+            palette.expected(&line)
+        } else if line.starts_with('+') {
+            palette.actual(&line)
+        } else {
+            line
+        })
+        .collect();
+    Some(format!("Diff of expected (-) and actual (+) args:\n{}", lines.join("\n")))
+}
+
+// Diffs two sequences of lines using their longest common subsequence.
+fn diff_lines(expected: &[&str], actual: &[&str]) -> Vec<String> {
+    // common[i][j] is the length of the longest common subsequence of
+    // expected[i..] and actual[j..]
+    let mut common = vec!(vec!(0; actual.len() + 1); expected.len() + 1);
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = vec!();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            lines.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j == actual.len() ||
+            (i < expected.len() && common[i + 1][j] >= common[i][j + 1])
+        {
+            lines.push(format!("- {}", expected[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", actual[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+// Splits the `Debug` representation of a tuple into the representations of
+// its fields. Anything else is treated as a single field.
+fn debug_fields(debug: &str) -> Vec<&str> {
+    let inner = match debug.strip_prefix('(').and_then(|rest| rest.strip_suffix(')')) {
+        Some(inner) => inner,
+        None => return vec!(debug),
+    };
+    let mut fields = vec!();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                fields.push(inner[start..i].trim());
+                start = i + 1;
+            },
+            _ => (),
+        }
+    }
+    let last = inner[start..].trim();
+    if !last.is_empty() {
+        fields.push(last);
+    }
+    fields
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::Location;
+
+    #[test]
+    fn debug_fields_splits_tuples_only() {
+        assert_eq!(vec!("1", "\"a, b\"", "[2, 3]"), debug_fields("(1, \"a, b\", [2, 3])"));
+        assert_eq!(vec!("5"), debug_fields("(5,)"));
+        assert_eq!(Vec::<&str>::new(), debug_fields("()"));
+        assert_eq!(vec!("Point(1, 2)"), debug_fields("Point(1, 2)"));
+    }
+
+    #[test]
+    fn rejected_by_args_reports_differing_fields() {
+        assert_eq!(None, rejected_by_args(&(1, "a"), &(1, "a")));
+        let rejections = rejected_by_args(&(1, "a"), &(1, "b")).unwrap();
+        assert_eq!("arg 1 should have been \"a\"", rejections[0].to_string());
+        assert_eq!(1, rejections.len());
+    }
+
+    #[test]
+    fn diff_marks_changed_lines() {
+        let expected = vec!("unchanged".repeat(5), "removed".repeat(5));
+        let actual = vec!("unchanged".repeat(5), "added".repeat(5));
+        let lines = [
+            "Diff of expected (-) and actual (+) args:".to_owned(),
+            "  [".to_owned(),
+            format!("      {:?},", expected[0]),
+            format!("-     {:?},", expected[1]),
+            format!("+     {:?},", actual[1]),
+            "  ]".to_owned()];
+        assert_eq!(lines.join("\n"), diff(&expected, &actual, Palette::plain()).unwrap());
+        assert_eq!(None, diff(&vec!(1), &vec!(2), Palette::plain()));
+    }
+
+    #[test]
+    fn colours_expected_and_actual_values() {
+        let rejections = rejected_by_args(&(1, "a"), &(1, "b")).unwrap();
+        assert_eq!(
+            "\x1b[1marg 1\x1b[0m should have been \x1b[32m\"a\"\x1b[0m",
+            rejections[0].describe(Palette::ansi()));
+
+        let closest = CallRecord::<(i32, &str), ()>::new(0, 0, (1, "b"), Location::caller());
+        let description = describe_closest_call(
+            &closest, Some("(1, \"b\")"), &rejections, Palette::ansi());
+        assert!(description.contains("with args \x1b[31m(1, \"b\")\x1b[0m"));
+
+        let expected = vec!("removed".repeat(10));
+        let actual = vec!("added".repeat(10));
+        let diff = diff(&expected, &actual, Palette::ansi()).unwrap();
+        assert!(diff.contains(&format!("\x1b[32m-     {:?},\x1b[0m", expected[0])));
+        assert!(diff.contains(&format!("\x1b[31m+     {:?},\x1b[0m", actual[0])));
+    }
+
+    #[test]
+    fn closest_call_has_fewest_rejected_args() {
+        let first = CallRecord::<(i32, i32, i32), ()>::new(0, 0, (9, 9, 3), Location::caller());
+        let second = CallRecord::new(1, 1, (1, 9, 3), Location::caller());
+        let third = CallRecord::new(2, 2, (9, 2, 9), Location::caller());
+        let records = [first, second, third];
+
+        let (closest, rejections) = closest_call(
+            &records,
+            |args| rejected_by_args(&(1, 2, 3), args)).unwrap();
+        assert_eq!(1, closest.index());
+        assert_eq!(
+            format!(
+                "Closest call was #1 from {} with args (1, 9, 3): arg 1 should have been 2",
+                closest.location()),
+            describe_closest_call(closest, Some("(1, 9, 3)"), &rejections, Palette::plain()));
+
+        let (closest, rejections) = closest_call(
+            &records,
+            |args| rejected_by_pattern(&|args: &(i32, i32, i32)| args.0 == 5, args)).unwrap();
+        assert_eq!(2, closest.index());
+        assert!(describe_closest_call(closest, None, &rejections, Palette::plain())
+            .starts_with("Most recent call"));
+    }
+
+    #[test]
+    fn rejected_by_pattern_is_empty_for_opaque_patterns() {
+        assert_eq!(None, rejected_by_pattern(&|&x: &i32| x > 0, &1));
+        assert_eq!(Some(vec!()), rejected_by_pattern(&|&x: &i32| x > 0, &-1));
+    }
+}
@@ -0,0 +1,432 @@
+use std::fmt;
+use std::ops::{Bound, Range, RangeBounds, RangeFrom, RangeFull, RangeInclusive, RangeTo,
+               RangeToInclusive};
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use crate::sequence::Sequence;
+use crate::shared::{Pattern, Shared, SharedPtr};
+
+/// The number of times an `Expectation` must be matched by calls for it to be
+/// satisfied.
+///
+/// `usize` values convert into `Times`, meaning "exactly that many times".
+/// Ranges of `usize` (e.g. `2..=5`, `..3`, `1..`) also convert into `Times`.
+///
+/// # Examples
+///
+/// ```
+/// use double::Times;
+///
+/// assert!(Times::exactly(2).contains(2));
+/// assert!(!Times::exactly(2).contains(3));
+/// assert!(Times::at_least(1).contains(100));
+/// assert!(Times::at_most(1).contains(0));
+/// assert!(Times::between(2, 4).contains(3));
+/// assert_eq!(Times::from(3), Times::exactly(3));
+/// assert_eq!(Times::from(2..=5), Times::between(2, 5));
+/// assert_eq!(Times::from(..3), Times::at_most(2));
+/// assert_eq!(Times::from(1..), Times::at_least(1));
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Times {
+    min: usize,
+    max: Option<usize>,
+}
+
+impl Times {
+    /// Exactly `n` times.
+    pub fn exactly(n: usize) -> Self {
+        Times { min: n, max: Some(n) }
+    }
+
+    /// `n` or more times.
+    pub fn at_least(n: usize) -> Self {
+        Times { min: n, max: None }
+    }
+
+    /// `n` or fewer times.
+    pub fn at_most(n: usize) -> Self {
+        Times { min: 0, max: Some(n) }
+    }
+
+    /// Between `min` and `max` times (inclusive).
+    pub fn between(min: usize, max: usize) -> Self {
+        assert!(min <= max, "invalid range: {} > {}", min, max);
+        Times { min, max: Some(max) }
+    }
+
+    /// Zero times.
+    pub fn never() -> Self {
+        Times::exactly(0)
+    }
+
+    /// Any number of times, including zero.
+    pub fn any() -> Self {
+        Times::at_least(0)
+    }
+
+    /// Any number of times within `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is empty.
+    pub fn from_range<B: RangeBounds<usize>>(range: B) -> Self {
+        let min = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let max = match range.end_bound() {
+            Bound::Included(&n) => Some(n),
+            Bound::Excluded(&n) => {
+                assert!(n > min, "empty range of call counts");
+                Some(n - 1)
+            },
+            Bound::Unbounded => None,
+        };
+        if let Some(max) = max {
+            assert!(min <= max, "empty range of call counts");
+        }
+        Times { min, max }
+    }
+
+    /// Returns true if `n` calls satisfy this cardinality.
+    pub fn contains(&self, n: usize) -> bool {
+        n >= self.min && self.max.is_none_or(|max| n <= max)
+    }
+
+    /// Returns true if one more call would exceed this cardinality.
+    pub fn is_saturated_by(&self, n: usize) -> bool {
+        self.max.is_some_and(|max| n >= max)
+    }
+}
+
+impl From<usize> for Times {
+    fn from(n: usize) -> Self {
+        Times::exactly(n)
+    }
+}
+
+macro_rules! impl_times_from_range {
+    ($($range:ty),*) => (
+        $(
+            impl From<$range> for Times {
+                fn from(range: $range) -> Self {
+                    Times::from_range(range)
+                }
+            }
+        )*
+    );
+}
+
+impl_times_from_range!(
+    Range<usize>,
+    RangeInclusive<usize>,
+    RangeFrom<usize>,
+    RangeTo<usize>,
+    RangeToInclusive<usize>,
+    RangeFull);
+
+impl fmt::Display for Times {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn plural(n: usize) -> &'static str {
+            if n == 1 { "time" } else { "times" }
+        }
+        match self.max {
+            Some(max) if max == self.min => write!(f, "exactly {} {}", max, plural(max)),
+            Some(max) if self.min == 0 => write!(f, "at most {} {}", max, plural(max)),
+            Some(max) => write!(f, "between {} and {} times", self.min, max),
+            None => write!(f, "at least {} {}", self.min, plural(self.min)),
+        }
+    }
+}
+
+/// An up-front expectation on the calls a `Mock` will receive.
+///
+/// Created using `Mock::expect` or `Mock::expect_closure`. By default, an
+/// expectation must be matched exactly once. Use `times` to change this.
+/// Expectations are checked using `Mock::verify`.
+///
+/// `Expectation` is a handle. Clones of it refer to the same expectation, so
+/// it can be configured after it has been registered with the mock.
+pub struct Expectation<C, R> {
+    state: Shared<ExpectationState<C, R>>,
+}
+
+struct ExpectationState<C, R> {
+    // Taken out of the state to be run, so it can use the mock
+    pattern: SharedPtr<Pattern<C>>,
+    return_value: Option<R>,
+    // The sequence the expectation joined, and its position in it
+    sequence: Option<(Sequence, usize)>,
+    status: StatusRef,
+}
+
+pub(crate) type StatusRef = Shared<ExpectationStatus>;
+
+// The part of an expectation's state that does not depend on the mock's
+// argument and return types, so it can be shared with `Sequence`s and the
+// mock registry.
+#[derive(Debug)]
+pub(crate) struct ExpectationStatus {
+    times: Times,
+    num_matches: usize,
+    matched_out_of_sequence: bool,
+}
+
+impl ExpectationStatus {
+    pub(crate) fn is_satisfied(&self) -> bool {
+        self.times.contains(self.num_matches)
+    }
+
+    pub(crate) fn is_verified(&self) -> bool {
+        self.is_satisfied() && !self.matched_out_of_sequence
+    }
+
+    pub(crate) fn needs_more_matches(&self) -> bool {
+        self.num_matches < self.times.min
+    }
+
+    pub(crate) fn describe_failure(&self) -> String {
+        if self.is_satisfied() {
+            return "was called out of sequence".to_owned();
+        }
+        format!(
+            "expected to be called {}, but was called {} {}{}",
+            self.times,
+            self.num_matches,
+            if self.num_matches == 1 { "time" } else { "times" },
+            if self.matched_out_of_sequence { " and out of sequence" } else { "" })
+    }
+}
+
+impl<C, R: Clone> Expectation<C, R> {
+    pub(crate) fn new(pattern: Pattern<C>) -> Self {
+        Expectation {
+            state: Shared::new(ExpectationState {
+                pattern: SharedPtr::new(pattern),
+                return_value: None,
+                sequence: None,
+                status: Shared::new(ExpectationStatus {
+                    times: Times::exactly(1),
+                    num_matches: 0,
+                    matched_out_of_sequence: false,
+                }),
+            }),
+        }
+    }
+
+    /// Set how many times the expectation must be matched.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use double::{Mock, Times};
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<(i32, i32), i32>::default();
+    /// mock.expect(matcher!(p!(eq, 3), p!(any))).times(2);
+    /// mock.expect(matcher!(p!(eq, 4), p!(any))).times(Times::at_least(1));
+    ///
+    /// mock.call((3, 0));
+    /// mock.call((4, 0));
+    /// assert!(!mock.verify());
+    ///
+    /// mock.call((3, 1));
+    /// assert!(mock.verify());
+    /// # }
+    /// ```
+    pub fn times<T: Into<Times>>(self, times: T) -> Self {
+        self.status().borrow_mut().times = times.into();
+        self
+    }
+
+    /// Return `return_value` from all calls that match the expectation.
+    ///
+    /// This takes precedence over all other behaviour configured on the mock.
+    /// If no return value is set, calls that match the expectation use the
+    /// mock's other configured behaviour.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<(i32, i32), i32>::default();
+    /// mock.expect(matcher!(p!(eq, 3), p!(any))).times(2).returning(7);
+    ///
+    /// assert_eq!(mock.call((3, 1)), 7);
+    /// assert_eq!(mock.call((3, 2)), 7);
+    /// assert_eq!(mock.call((4, 2)), 0);
+    /// assert!(mock.verify());
+    /// # }
+    /// ```
+    pub fn returning<T: Into<R>>(self, return_value: T) -> Self {
+        self.state.borrow_mut().return_value = Some(return_value.into());
+        self
+    }
+
+    /// Add the expectation to the end of `sequence`. Calls that match the
+    /// expectation before all earlier expectations in the sequence are
+    /// satisfied, or after a later expectation in the sequence was matched,
+    /// are out of order. See `Sequence`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use double::{Mock, Sequence};
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<&str, ()>::default();
+    /// let seq = Sequence::new();
+    /// mock.expect(p!(eq, "open")).in_sequence(&seq);
+    /// mock.expect(p!(eq, "close")).in_sequence(&seq);
+    ///
+    /// mock.call("close");
+    /// mock.call("open");
+    /// assert!(!mock.verify());
+    /// # }
+    /// ```
+    pub fn in_sequence(self, sequence: &Sequence) -> Self {
+        let status = self.status();
+        let position = sequence.add_step(Box::new(move || status.borrow().is_satisfied()));
+        self.state.borrow_mut().sequence = Some((sequence.clone(), position));
+        self
+    }
+
+    /// Returns the number of calls that matched the expectation so far.
+    pub fn num_matches(&self) -> usize {
+        self.status().borrow().num_matches
+    }
+
+    /// Returns true if the expectation's cardinality is met.
+    pub fn is_satisfied(&self) -> bool {
+        self.status().borrow().is_satisfied()
+    }
+
+    // Used by the typed expectations generated by `mock_trait!`, which start
+    // out matching any args.
+    #[doc(hidden)]
+    pub fn __private_set_pattern(self, pattern: Pattern<C>) -> Self {
+        self.state.borrow_mut().pattern = SharedPtr::new(pattern);
+        self
+    }
+
+    pub(crate) fn matches(&self, args: &C) -> bool {
+        let pattern = self.state.borrow().pattern.clone();
+        pattern(args)
+    }
+
+    pub(crate) fn is_saturated(&self) -> bool {
+        let status = self.status();
+        let status = status.borrow();
+        status.times.is_saturated_by(status.num_matches)
+    }
+
+    pub(crate) fn is_verified(&self) -> bool {
+        self.status().borrow().is_verified()
+    }
+
+    pub(crate) fn needs_more_matches(&self) -> bool {
+        self.status().borrow().needs_more_matches()
+    }
+
+    pub(crate) fn status(&self) -> StatusRef {
+        self.state.borrow().status.clone()
+    }
+
+    pub(crate) fn record_match(&self) -> Option<R> {
+        // The sequence checks the other expectations in it, so this
+        // expectation's state must not be borrowed while it does.
+        let sequence = self.state.borrow().sequence.clone();
+        let in_order = sequence.is_none_or(|(sequence, position)| sequence.record_match(position));
+        let state = self.state.borrow();
+        let mut status = state.status.borrow_mut();
+        status.num_matches += 1;
+        status.matched_out_of_sequence |= !in_order;
+        state.return_value.clone()
+    }
+
+    pub(crate) fn describe_failure(&self) -> String {
+        self.status().borrow().describe_failure()
+    }
+}
+
+impl<C, R> Clone for Expectation<C, R> {
+    fn clone(&self) -> Self {
+        Expectation { state: self.state.clone() }
+    }
+}
+
+impl<C, R> fmt::Debug for Expectation<C, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.borrow();
+        let status = state.status.borrow();
+        f.debug_struct("Expectation")
+            .field("times", &status.times)
+            .field("num_matches", &status.num_matches)
+            .field("satisfied", &status.is_satisfied())
+            .field("in_sequence", &state.sequence.is_some())
+            .field("matched_out_of_sequence", &status.matched_out_of_sequence)
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_contains() {
+        assert!(Times::never().contains(0));
+        assert!(!Times::never().contains(1));
+        assert!(Times::any().contains(0));
+        assert!(Times::any().contains(1000));
+        assert!(!Times::between(2, 4).contains(1));
+        assert!(Times::between(2, 4).contains(2));
+        assert!(Times::between(2, 4).contains(4));
+        assert!(!Times::between(2, 4).contains(5));
+    }
+
+    #[test]
+    fn times_from_range() {
+        assert_eq!(Times::between(2, 4), Times::from(2..5));
+        assert_eq!(Times::between(2, 5), Times::from(2..=5));
+        assert_eq!(Times::at_least(3), Times::from(3..));
+        assert_eq!(Times::at_most(2), Times::from(..3));
+        assert_eq!(Times::at_most(3), Times::from(..=3));
+        assert_eq!(Times::any(), Times::from(..));
+        assert_eq!(Times::exactly(0), Times::from(..1));
+    }
+
+    #[test]
+    #[should_panic(expected = "empty range of call counts")]
+    fn times_from_empty_range() {
+        let _ = Times::from(3..3);
+    }
+
+    #[test]
+    fn times_saturation() {
+        assert!(Times::exactly(2).is_saturated_by(2));
+        assert!(!Times::exactly(2).is_saturated_by(1));
+        assert!(!Times::at_least(2).is_saturated_by(100));
+    }
+
+    #[test]
+    fn times_display() {
+        assert_eq!("exactly 1 time", Times::exactly(1).to_string());
+        assert_eq!("exactly 3 times", Times::exactly(3).to_string());
+        assert_eq!("at least 1 time", Times::at_least(1).to_string());
+        assert_eq!("at most 2 times", Times::at_most(2).to_string());
+        assert_eq!("between 2 and 5 times", Times::between(2, 5).to_string());
+    }
+}
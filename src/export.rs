@@ -0,0 +1,194 @@
+extern crate serde;
+extern crate serde_json;
+
+use std::hash::Hash;
+use self::serde::ser::{Serialize, SerializeStruct, Serializer};
+use self::serde_json::{Map, Value};
+
+use crate::async_mock::AsyncMock;
+use crate::call_record::CallRecord;
+use crate::mock::Mock;
+
+/// Serialized as an object with the call's `index`, `sequence_number`,
+/// `args`, `return_value` (`null` if the call has not returned), `location`
+/// (as `file:line:column`) and `duration_ns` (`null` if the call has no
+/// duration, see `CallRecord::duration`).
+///
+/// Requires the `serde` feature.
+impl<C: Serialize, R: Serialize> Serialize for CallRecord<C, R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut record = serializer.serialize_struct("CallRecord", 6)?;
+        record.serialize_field("index", &self.index())?;
+        record.serialize_field("sequence_number", &self.sequence_number())?;
+        record.serialize_field("args", self.args())?;
+        record.serialize_field("return_value", &self.return_value())?;
+        record.serialize_field("location", &self.location().to_string())?;
+        let duration_ns = self.duration().map(|duration| duration.as_nanos() as u64);
+        record.serialize_field("duration_ns", &duration_ns)?;
+        record.end()
+    }
+}
+
+/// Implemented by objects whose recorded calls can be exported as JSON.
+///
+/// This is implemented by `Mock` and `AsyncMock`, so mocks with different
+/// argument and return types can be exported together using
+/// `double::export_calls_json`.
+///
+/// Requires the `serde` feature.
+pub trait ExportCalls {
+    /// Returns the recorded calls as a JSON array, with one object per call
+    /// (see the `Serialize` implementation of `CallRecord`).
+    fn calls_json_value(&self) -> Value;
+}
+
+impl<C, R> ExportCalls for Mock<C, R>
+    where C: Clone + Eq + Hash + Serialize,
+          R: Clone + Serialize
+{
+    fn calls_json_value(&self) -> Value {
+        let records: Vec<CallRecord<C, R>> = self.records().collect();
+        serde_json::to_value(records)
+            .expect("args and return values of recorded calls could not be serialized")
+    }
+}
+
+impl<C, R> ExportCalls for AsyncMock<C, R>
+    where C: Clone + Eq + Hash + Serialize,
+          R: Clone + Serialize
+{
+    fn calls_json_value(&self) -> Value {
+        self.mock().calls_json_value()
+    }
+}
+
+impl<C, R> Mock<C, R>
+    where C: Clone + Eq + Hash + Serialize,
+          R: Clone + Serialize
+{
+    /// Returns the mock's interaction history as pretty-printed JSON: an
+    /// array with one object per call, in order from first to last (see the
+    /// `Serialize` implementation of `CallRecord`).
+    ///
+    /// Requires the `serde` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an arg or return value can't be serialized to JSON (e.g. a
+    /// map with non-string keys).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate double;
+    /// # extern crate serde_json;
+    /// use double::Mock;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<(i32, String), bool>::new(true);
+    /// mock.call((1, "one".to_owned()));
+    ///
+    /// let calls: serde_json::Value = serde_json::from_str(&mock.calls_json()).unwrap();
+    /// assert_eq!(calls[0]["index"], 0);
+    /// assert_eq!(calls[0]["args"], serde_json::json!([1, "one"]));
+    /// assert_eq!(calls[0]["return_value"], true);
+    /// assert!(calls[0]["location"].as_str().unwrap().starts_with(file!()));
+    /// # }
+    /// ```
+    pub fn calls_json(&self) -> String {
+        to_pretty_json(&self.calls_json_value())
+    }
+}
+
+/// Returns the interaction history of each of the given named `mocks` as
+/// pretty-printed JSON: an object that maps each name to the mock's calls
+/// (see `Mock::calls_json`).
+///
+/// This is intended for test failures in CI, where the interaction dump can
+/// be written to a file and attached to the run for offline debugging. Since
+/// calls to different mocks share one sequence, the `sequence_number` of
+/// each call can be used to interleave the histories.
+///
+/// Requires the `serde` feature.
+///
+/// # Panics
+///
+/// Panics if an arg or return value can't be serialized to JSON.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate double;
+/// # extern crate serde_json;
+/// use double::Mock;
+///
+/// # fn main() {
+/// let reader = Mock::<i32, ()>::default();
+/// let writer = Mock::<String, ()>::default();
+/// reader.call(1);
+/// writer.call("data".to_owned());
+///
+/// let json = double::export_calls_json(&[("reader", &reader), ("writer", &writer)]);
+/// let calls: serde_json::Value = serde_json::from_str(&json).unwrap();
+/// assert_eq!(calls["reader"][0]["args"], 1);
+/// assert_eq!(calls["writer"][0]["args"], "data");
+/// assert!(calls["reader"][0]["sequence_number"].as_u64()
+///     < calls["writer"][0]["sequence_number"].as_u64());
+/// # }
+/// ```
+pub fn export_calls_json(mocks: &[(&str, &dyn ExportCalls)]) -> String {
+    let mut calls = Map::new();
+    for &(name, mock) in mocks {
+        calls.insert(name.to_owned(), mock.calls_json_value());
+    }
+    to_pretty_json(&Value::Object(calls))
+}
+
+fn to_pretty_json(value: &Value) -> String {
+    serde_json::to_string_pretty(value).expect("a JSON value always serializes")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use self::serde_json::json;
+
+    #[test]
+    fn calls_json_records_every_call() {
+        let mock = Mock::<(i32, i32), i32>::new(3);
+        mock.call((1, 2));
+        mock.set_recording_durations(true);
+        mock.use_closure(Box::new(|_| 7));
+        mock.call((3, 4));
+
+        let calls: Value = serde_json::from_str(&mock.calls_json()).unwrap();
+        let calls = calls.as_array().unwrap();
+        assert_eq!(2, calls.len());
+        assert_eq!(json!([1, 2]), calls[0]["args"]);
+        assert_eq!(json!(3), calls[0]["return_value"]);
+        assert_eq!(json!(1), calls[1]["index"]);
+        assert_eq!(json!([3, 4]), calls[1]["args"]);
+        assert_eq!(json!(7), calls[1]["return_value"]);
+        assert!(calls[0]["duration_ns"].is_null());
+        assert!(calls[1]["duration_ns"].is_u64());
+        assert!(calls[0]["sequence_number"].as_u64() < calls[1]["sequence_number"].as_u64());
+    }
+
+    #[test]
+    fn calls_json_of_mock_without_calls_is_empty_array() {
+        let mock = Mock::<i32, ()>::default();
+        assert_eq!("[]", mock.calls_json());
+        assert_eq!("{}", export_calls_json(&[]));
+    }
+
+    #[test]
+    fn export_calls_json_includes_async_mocks() {
+        let fetch = AsyncMock::<u32, Vec<u8>>::new(vec!(1, 2));
+        drop(fetch.call(5));
+
+        let calls: Value = serde_json::from_str(&export_calls_json(&[("fetch", &fetch)])).unwrap();
+        assert_eq!(json!(5), calls["fetch"][0]["args"]);
+        assert_eq!(json!([1, 2]), calls["fetch"][0]["return_value"]);
+    }
+}
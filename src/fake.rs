@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+use std::fmt;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use crate::shared::Shared;
+
+/// A queue of canned responses for one method of a fake generated using
+/// `fake_trait!`. Each call of the method pops the response at the front of
+/// the queue. Unlike a `Mock`, it doesn't record args or match them against
+/// expectations, so the responses don't need to implement `Clone`, and the
+/// args don't need to implement anything.
+///
+/// `Responses` is a handle. Clones of it refer to the same queue.
+///
+/// # Examples
+///
+/// ```
+/// use double::Responses;
+///
+/// let responses = Responses::named("Clock::now");
+/// responses.push(10);
+/// responses.push_all(vec!(20, 30));
+///
+/// assert_eq!(10, responses.next());
+/// assert_eq!(20, responses.next());
+/// assert_eq!(1, responses.remaining());
+/// ```
+///
+/// ```should_panic
+/// use double::Responses;
+///
+/// let responses = Responses::<u64>::named("Clock::now");
+/// // panics: "`Clock::now` has no canned responses left (it was called 1 time
+/// // and 0 responses were pushed)"
+/// responses.next();
+/// ```
+pub struct Responses<R> {
+    state: Shared<ResponsesState<R>>,
+}
+
+struct ResponsesState<R> {
+    name: &'static str,
+    queue: VecDeque<R>,
+    num_pushed: usize,
+    num_calls: usize,
+}
+
+impl<R> Responses<R> {
+    /// Creates an empty queue of responses for the method called `name`,
+    /// which is named when a call panics because there are none left.
+    pub fn named(name: &'static str) -> Self {
+        Responses {
+            state: Shared::new(ResponsesState {
+                name,
+                queue: VecDeque::new(),
+                num_pushed: 0,
+                num_calls: 0,
+            }),
+        }
+    }
+
+    /// Adds `response` to the back of the queue.
+    pub fn push(&self, response: R) {
+        let mut state = self.state.borrow_mut();
+        state.queue.push_back(response);
+        state.num_pushed += 1;
+    }
+
+    /// Adds `responses` to the back of the queue, in order.
+    pub fn push_all(&self, responses: Vec<R>) {
+        for response in responses {
+            self.push(response);
+        }
+    }
+
+    /// Pops the response at the front of the queue.
+    ///
+    /// # Panics
+    ///
+    /// If the queue is empty, naming the method and how often it was called.
+    #[track_caller]
+    pub fn next(&self) -> R {
+        let mut state = self.state.borrow_mut();
+        state.num_calls += 1;
+        match state.queue.pop_front() {
+            Some(response) => response,
+            None => panic!(
+                "`{}` has no canned responses left (it was called {} {} and {} {} pushed)",
+                state.name,
+                state.num_calls,
+                if state.num_calls == 1 { "time" } else { "times" },
+                state.num_pushed,
+                if state.num_pushed == 1 { "response was" } else { "responses were" }),
+        }
+    }
+
+    /// Pops the response at the front of the queue, or returns the default
+    /// value of `R` if it is empty. Used for fake methods that return `()`,
+    /// which don't need responses.
+    pub fn next_or_default(&self) -> R
+        where R: Default
+    {
+        let mut state = self.state.borrow_mut();
+        state.num_calls += 1;
+        state.queue.pop_front().unwrap_or_default()
+    }
+
+    /// Returns the number of responses that haven't been popped yet.
+    pub fn remaining(&self) -> usize {
+        self.state.borrow().queue.len()
+    }
+
+    /// Returns the number of times the method has been called.
+    pub fn num_calls(&self) -> usize {
+        self.state.borrow().num_calls
+    }
+}
+
+impl<R> Clone for Responses<R> {
+    fn clone(&self) -> Self {
+        Responses { state: self.state.clone() }
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for Responses<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.borrow();
+        f.debug_struct("Responses")
+            .field("name", &state.name)
+            .field("queue", &state.queue)
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn responses_are_popped_in_the_order_they_were_pushed() {
+        let responses = Responses::named("Store::get");
+        responses.push("first".to_owned());
+        responses.push_all(vec!("second".to_owned(), "third".to_owned()));
+        let clone = responses.clone();
+
+        assert_eq!("first", responses.next());
+        assert_eq!("second", clone.next());
+        assert_eq!(1, responses.remaining());
+        assert_eq!(2, responses.num_calls());
+    }
+
+    #[test]
+    fn empty_queues_panic_naming_the_method() {
+        let responses = Responses::<i32>::named("Store::get");
+        responses.push(1);
+        responses.next();
+        let message = panic::catch_unwind(AssertUnwindSafe(|| responses.next()))
+            .unwrap_err()
+            .downcast::<String>()
+            .unwrap();
+        assert_eq!(
+            "`Store::get` has no canned responses left (it was called 2 times and 1 response \
+             was pushed)",
+            *message);
+    }
+
+    #[test]
+    fn unit_responses_default_when_the_queue_is_empty() {
+        let responses = Responses::<()>::named("Store::put");
+        responses.next_or_default();
+        assert_eq!(1, responses.num_calls());
+    }
+}
@@ -0,0 +1,726 @@
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::call_log::CallLog;
+use crate::shared::{Pattern, Shared, SharedPtr};
+
+/// A scripted future, for mocking async collaborators.
+///
+/// `MockFuture` implements `Clone`, so it can be used as the return type of
+/// a `Mock`. Every call to the mock returns a fresh clone of the configured
+/// future, which resolves according to its script. See `Mock::returns_ready`,
+/// `Mock::returns_pending_then`, `Mock::returns_err_after`,
+/// `Mock::returns_when_opened` and `Mock::returns_never`. Stubs that need to
+/// await something themselves can return any future, using
+/// `Mock::use_async_closure`.
+///
+/// Mocked async methods that must return a boxed future can use
+/// `MockFuture::boxed` or `MockFuture::boxed_local`.
+///
+/// # Examples
+///
+/// ```
+/// use std::future::Future;
+/// use std::pin::Pin;
+/// use std::task::{Context, Poll, Waker};
+/// use double::MockFuture;
+///
+/// let mut future = MockFuture::pending_then(42, 2);
+/// let mut cx = Context::from_waker(Waker::noop());
+///
+/// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Pending);
+/// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Pending);
+/// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(42));
+/// ```
+#[derive(Clone, Debug)]
+pub struct MockFuture<T> {
+    value: Option<T>,
+    script: Script<T>,
+}
+
+#[derive(Debug)]
+enum Script<T> {
+    Ready,
+    PendingFor { num_polls: usize },
+    // `timer` wakes the future at its deadline, from its first pending poll
+    After { delay: Duration, first_polled: Option<Instant>, timer: Option<Timer> },
+    // `waiting` is true from the future's first pending poll until it
+    // completes, while it's counted by the gate's `num_waiting`
+    UntilOpened { gate: Gate, waiting: bool },
+    Never,
+    // Resolves to the output of another future, instead of `value`
+    Awaiting(SharedFuture<T>),
+}
+
+// A future shared by the clones of a `MockFuture`, since the future itself
+// can't be cloned. Mocks keep a clone of each future they return in their
+// call log, but only the future returned from the call is ever polled.
+struct SharedFuture<T> {
+    // `None` once the future has completed
+    future: Arc<Mutex<Option<BoxFuture<T>>>>,
+}
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+impl<T> SharedFuture<T> {
+    fn poll(&self, cx: &mut Context) -> Poll<T> {
+        let mut future = self.future.lock().unwrap_or_else(PoisonError::into_inner);
+        let output = match *future {
+            Some(ref mut future) => future.as_mut().poll(cx),
+            None => panic!("MockFuture polled after it completed"),
+        };
+        if output.is_ready() {
+            *future = None;
+        }
+        output
+    }
+}
+
+impl<T> Clone for SharedFuture<T> {
+    fn clone(&self) -> Self {
+        SharedFuture { future: self.future.clone() }
+    }
+}
+
+impl<T> fmt::Debug for SharedFuture<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedFuture")
+    }
+}
+
+// Wakes the waker of the latest poll of a future once its deadline has
+// passed, from a single background thread however often the future is polled
+#[derive(Debug)]
+pub(crate) struct Timer {
+    state: Arc<Mutex<TimerState>>,
+}
+
+#[derive(Debug)]
+struct TimerState {
+    waker: Waker,
+    fired: bool,
+}
+
+impl Timer {
+    pub(crate) fn start(deadline: Instant, waker: &Waker) -> Self {
+        let state = Arc::new(Mutex::new(TimerState { waker: waker.clone(), fired: false }));
+        let timer_state = state.clone();
+        thread::spawn(move || {
+            let now = Instant::now();
+            if now < deadline {
+                thread::sleep(deadline - now);
+            }
+            let mut state = timer_state.lock().unwrap_or_else(PoisonError::into_inner);
+            state.fired = true;
+            state.waker.wake_by_ref();
+        });
+        Timer { state }
+    }
+
+    // Wakes `waker` instead of the waker of an earlier poll, or right away if
+    // the timer fired since the deadline was checked
+    pub(crate) fn update(&self, waker: &Waker) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.fired {
+            waker.wake_by_ref();
+        } else if !state.waker.will_wake(waker) {
+            state.waker = waker.clone();
+        }
+    }
+}
+
+// Clones of a future that's waiting for a gate aren't waiting until they're
+// polled themselves, and clones of a delayed future start their own timer
+impl<T> Clone for Script<T> {
+    fn clone(&self) -> Self {
+        match *self {
+            Script::Ready => Script::Ready,
+            Script::PendingFor { num_polls } => Script::PendingFor { num_polls },
+            Script::After { delay, first_polled, .. } => {
+                Script::After { delay, first_polled, timer: None }
+            },
+            Script::UntilOpened { ref gate, .. } => {
+                Script::UntilOpened { gate: gate.clone(), waiting: false }
+            },
+            Script::Never => Script::Never,
+            Script::Awaiting(ref future) => Script::Awaiting(future.clone()),
+        }
+    }
+}
+
+impl<T> MockFuture<T> {
+    /// A future that resolves to `value` the first time it is polled.
+    pub fn ready(value: T) -> Self {
+        MockFuture { value: Some(value), script: Script::Ready }
+    }
+
+    /// A future that returns `Poll::Pending` for the first `num_polls` polls
+    /// and then resolves to `value`. The waker is woken after each pending
+    /// poll, so executors keep polling it.
+    pub fn pending_then(value: T, num_polls: usize) -> Self {
+        MockFuture { value: Some(value), script: Script::PendingFor { num_polls } }
+    }
+
+    /// A future that resolves to `value` once `delay` has passed since it
+    /// was first polled. A background thread wakes the waker when the delay
+    /// has passed, so this works with any executor.
+    pub fn after(value: T, delay: Duration) -> Self {
+        MockFuture {
+            value: Some(value),
+            script: Script::After { delay, first_polled: None, timer: None },
+        }
+    }
+
+    /// A future that returns `Poll::Pending` until `gate` is opened, and
+    /// then resolves to `value`. Opening the gate wakes the futures that
+    /// are waiting for it, so tests decide exactly when they resolve, e.g.
+    /// to check which branch of a `select!` wins or what happens when the
+    /// future is cancelled by being dropped. See `Gate`.
+    pub fn until_opened(value: T, gate: &Gate) -> Self {
+        MockFuture {
+            value: Some(value),
+            script: Script::UntilOpened { gate: gate.clone(), waiting: false },
+        }
+    }
+
+    /// A future that never resolves, and never wakes its waker, e.g. to
+    /// test timeouts.
+    pub fn never() -> Self {
+        MockFuture { value: None, script: Script::Never }
+    }
+
+    /// A future that resolves to the output of `future`, e.g. the future of
+    /// an `async` block that awaits a channel or a timer. The clones of the
+    /// `MockFuture` share `future`, so only one of them can be awaited.
+    pub fn from_future<F>(future: F) -> Self
+        where F: Future<Output = T> + Send + 'static
+    {
+        MockFuture {
+            value: None,
+            script: Script::Awaiting(SharedFuture {
+                future: Arc::new(Mutex::new(Some(Box::pin(future)))),
+            }),
+        }
+    }
+}
+
+impl<T: Send + 'static> MockFuture<T> {
+    /// Box the future, for mocked methods that return
+    /// `Pin<Box<dyn Future<Output = T> + Send>>`.
+    pub fn boxed(self) -> Pin<Box<dyn Future<Output = T> + Send>> {
+        Box::pin(self)
+    }
+}
+
+impl<T: 'static> MockFuture<T> {
+    /// Box the future, for mocked methods that return
+    /// `Pin<Box<dyn Future<Output = T>>>`.
+    pub fn boxed_local(self) -> Pin<Box<dyn Future<Output = T>>> {
+        Box::pin(self)
+    }
+}
+
+impl<T: Default> Default for MockFuture<T> {
+    /// A future that resolves to `T::default()` the first time it is polled.
+    fn default() -> Self {
+        MockFuture::ready(T::default())
+    }
+}
+
+// The value is never pinned, so the future can be moved even if it isn't
+impl<T> Unpin for MockFuture<T> {}
+
+impl<T> Future for MockFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let future = self.get_mut();
+        match future.script {
+            Script::Ready => (),
+            Script::PendingFor { ref mut num_polls } => {
+                if *num_polls > 0 {
+                    *num_polls -= 1;
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+            },
+            Script::After { delay, ref mut first_polled, ref mut timer } => {
+                let now = Instant::now();
+                let deadline = *first_polled.get_or_insert(now) + delay;
+                if now < deadline {
+                    match *timer {
+                        Some(ref timer) => timer.update(cx.waker()),
+                        None => *timer = Some(Timer::start(deadline, cx.waker())),
+                    }
+                    return Poll::Pending;
+                }
+            },
+            Script::UntilOpened { ref gate, ref mut waiting } => {
+                let mut state = gate.lock();
+                if !state.pass() {
+                    if !*waiting {
+                        *waiting = true;
+                        state.num_waiting += 1;
+                        gate.changed.notify_all();
+                    }
+                    if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                        state.wakers.push(cx.waker().clone());
+                    }
+                    return Poll::Pending;
+                }
+                if *waiting {
+                    *waiting = false;
+                    state.num_waiting -= 1;
+                }
+            },
+            Script::Never => return Poll::Pending,
+            Script::Awaiting(ref future) => return future.poll(cx),
+        }
+        Poll::Ready(future.value.take().expect("MockFuture polled after it completed"))
+    }
+}
+
+// Futures that are dropped while they wait for a gate have been cancelled
+impl<T> Drop for MockFuture<T> {
+    fn drop(&mut self) {
+        if let Script::UntilOpened { ref gate, waiting: true } = self.script {
+            let mut state = gate.lock();
+            state.num_waiting -= 1;
+            state.num_cancelled += 1;
+        }
+    }
+}
+
+/// A gate that holds back the futures made by `MockFuture::until_opened`,
+/// and the calls of mocks that block until it's opened (see `Mock::gate`),
+/// until the test opens it. The test can also let them through one at a
+/// time using `release_one`, to test specific interleavings.
+///
+/// `Gate` is a handle. Clones of it refer to the same gate, and it can be
+/// opened from any thread. It counts the futures and threads that are
+/// waiting for it, i.e. futures that have been polled but haven't completed,
+/// and the futures that were cancelled by being dropped while they waited.
+///
+/// # Examples
+///
+/// ```
+/// use std::future::Future;
+/// use std::pin::Pin;
+/// use std::task::{Context, Poll, Waker};
+/// use double::{Gate, MockFuture};
+///
+/// let gate = Gate::new();
+/// let mut first = MockFuture::until_opened("first", &gate);
+/// let mut second = MockFuture::until_opened("second", &gate);
+/// let mut cx = Context::from_waker(Waker::noop());
+///
+/// assert_eq!(Pin::new(&mut first).poll(&mut cx), Poll::Pending);
+/// assert_eq!(Pin::new(&mut second).poll(&mut cx), Poll::Pending);
+/// assert_eq!(2, gate.num_waiting());
+///
+/// // e.g. the other branch of a `select!` won
+/// drop(second);
+/// assert_eq!(1, gate.num_cancelled());
+///
+/// gate.open();
+/// assert_eq!(Pin::new(&mut first).poll(&mut cx), Poll::Ready("first"));
+/// assert_eq!(0, gate.num_waiting());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Gate {
+    state: Arc<Mutex<GateState>>,
+    // Notified when the gate lets waiting threads through, or when the
+    // number of futures and threads that are waiting for it goes up
+    changed: Arc<Condvar>,
+}
+
+#[derive(Debug, Default)]
+struct GateState {
+    open: bool,
+    // The number of futures or threads that `release_one` let through, but
+    // haven't passed yet
+    permits: usize,
+    // The wakers of the futures that are waiting for the gate
+    wakers: Vec<Waker>,
+    num_waiting: usize,
+    num_cancelled: usize,
+}
+
+impl GateState {
+    // Returns true if a future or thread can pass the gate, using up one of
+    // the permits if the gate is closed
+    fn pass(&mut self) -> bool {
+        if self.open {
+            return true;
+        }
+        if self.permits > 0 {
+            self.permits -= 1;
+            return true;
+        }
+        false
+    }
+}
+
+impl Gate {
+    /// Creates a closed gate.
+    pub fn new() -> Self {
+        Gate::default()
+    }
+
+    /// Opens the gate, waking the futures that are waiting for it. They,
+    /// and any futures of the gate that are polled while it's open,
+    /// resolve.
+    pub fn open(&self) {
+        let wakers = {
+            let mut state = self.lock();
+            state.open = true;
+            mem::take(&mut state.wakers)
+        };
+        self.changed.notify_all();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Lets one of the futures or threads that are waiting for the gate
+    /// through, while keeping it closed. If none are waiting, the next one
+    /// to reach the gate passes it.
+    pub fn release_one(&self) {
+        // All of the waiting futures are woken, since it isn't known which of
+        // them will be polled first. The others wait again.
+        let wakers = {
+            let mut state = self.lock();
+            state.permits += 1;
+            mem::take(&mut state.wakers)
+        };
+        self.changed.notify_all();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Blocks the current thread until the gate is opened, or `release_one`
+    /// lets it through. The thread counts as waiting for the gate meanwhile.
+    /// Stubs can call this to hold the code under test at a specific point.
+    pub fn wait(&self) {
+        let mut state = self.lock();
+        if state.pass() {
+            return;
+        }
+        state.num_waiting += 1;
+        self.changed.notify_all();
+        while !state.pass() {
+            state = self.changed.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+        state.num_waiting -= 1;
+    }
+
+    /// Blocks the current thread until at least `n` futures or threads are
+    /// waiting for the gate, or `timeout` has elapsed. Returns true if they
+    /// are. Use this to make sure the code under test has reached the gate
+    /// before inspecting it and letting it through.
+    pub fn wait_for_waiting(&self, n: usize, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.lock();
+        while state.num_waiting < n {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self.changed
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        true
+    }
+
+    /// Closes the gate again, so its futures that haven't resolved yet wait
+    /// until it's reopened.
+    pub fn close(&self) {
+        self.lock().open = false;
+    }
+
+    /// Returns true if the gate is open.
+    pub fn is_open(&self) -> bool {
+        self.lock().open
+    }
+
+    /// Returns the number of futures and threads that are waiting for the
+    /// gate.
+    pub fn num_waiting(&self) -> usize {
+        self.lock().num_waiting
+    }
+
+    /// Returns the number of futures that were dropped while they were
+    /// waiting for the gate.
+    pub fn num_cancelled(&self) -> usize {
+        self.lock().num_cancelled
+    }
+
+    // A panic while the lock is held must not make the gate unusable, so
+    // poisoning is ignored
+    fn lock(&self) -> MutexGuard<'_, GateState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A future that resolves to the args of the next call of a mock that
+/// matches a pattern. See `Mock::await_call`.
+pub struct CallFuture<C, R> {
+    calls: SharedPtr<CallLog<C, R>>,
+    // Shared with the mock, which wakes them when it's called
+    wakers: Shared<Vec<Waker>>,
+    pattern: Pattern<C>,
+    // Calls with a lower index were made before the future was created
+    first_call: usize,
+}
+
+impl<C, R> CallFuture<C, R> {
+    pub(crate) fn new(
+        calls: SharedPtr<CallLog<C, R>>,
+        wakers: Shared<Vec<Waker>>,
+        pattern: Pattern<C>) -> Self
+    {
+        let first_call = calls.num_calls();
+        CallFuture { calls, wakers, pattern, first_call }
+    }
+}
+
+impl<C, R> Unpin for CallFuture<C, R> {}
+
+impl<C: Clone, R> Future for CallFuture<C, R> {
+    type Output = C;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<C> {
+        // The waker is registered before the log is searched, so a call made
+        // meanwhile wakes it. Nothing is locked while the pattern runs, so it
+        // can use the mock.
+        {
+            let mut wakers = self.wakers.borrow_mut();
+            if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+        }
+        let call = self.calls
+            .indexed_args()
+            .into_iter()
+            .find(|&(index, ref args)| index >= self.first_call && (self.pattern)(args));
+        match call {
+            Some((_, args)) => Poll::Ready(args),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<C, R> fmt::Debug for CallFuture<C, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CallFuture")
+            .field("first_call", &self.first_call)
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::task::{Wake, Waker};
+    use crate::mock::Mock;
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    // Returns the future's output and the number of times it was polled
+    fn block_on<F: Future + Unpin>(mut future: F) -> (F::Output, usize) {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut num_polls = 0;
+        loop {
+            num_polls += 1;
+            if let Poll::Ready(output) = Pin::new(&mut future).poll(&mut cx) {
+                return (output, num_polls);
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn clones_are_polled_independently() {
+        let future = MockFuture::pending_then("done", 3);
+        assert_eq!(("done", 4), block_on(future.clone()));
+        assert_eq!(("done", 4), block_on(future));
+    }
+
+    #[test]
+    fn after_resolves_once_delay_has_passed() {
+        let start = Instant::now();
+        let (output, _) = block_on(MockFuture::after(7, Duration::from_millis(20)));
+        assert_eq!(7, output);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn after_wakes_the_latest_waker_once_however_often_it_is_polled() {
+        let first = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let latest = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let mut future = MockFuture::after(7, Duration::from_millis(20));
+        let waker = Waker::from(first.clone());
+        assert_eq!(Poll::Pending, Pin::new(&mut future).poll(&mut Context::from_waker(&waker)));
+        let waker = Waker::from(latest.clone());
+        for _ in 0..100 {
+            assert_eq!(Poll::Pending, Pin::new(&mut future).poll(&mut Context::from_waker(&waker)));
+        }
+        while latest.0.load(Ordering::SeqCst) == 0 {
+            thread::yield_now();
+        }
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(0, first.0.load(Ordering::SeqCst));
+        assert_eq!(1, latest.0.load(Ordering::SeqCst));
+        assert_eq!(Poll::Ready(7), Pin::new(&mut future).poll(&mut Context::from_waker(&waker)));
+    }
+
+    #[test]
+    fn awaited_patterns_can_call_the_mock_they_are_matched_by() {
+        let mock = Mock::<i32, ()>::default();
+        let inner = mock.clone();
+        let mut call = mock.await_call(Box::leak(Box::new(move |&x: &i32| {
+            inner.call(0);
+            x > 0
+        })));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(Poll::Pending, Pin::new(&mut call).poll(&mut cx));
+        mock.call(2);
+        assert_eq!(Poll::Ready(2), Pin::new(&mut call).poll(&mut cx));
+    }
+
+    #[test]
+    fn opening_a_gate_wakes_the_futures_waiting_for_it() {
+        let gate = Gate::new();
+        let future = MockFuture::until_opened(3, &gate);
+        let opener = {
+            let gate = gate.clone();
+            thread::spawn(move || {
+                while gate.num_waiting() == 0 {
+                    thread::yield_now();
+                }
+                gate.open();
+            })
+        };
+        assert_eq!(3, block_on(future.clone()).0);
+        opener.join().unwrap();
+        assert_eq!((3, 1), block_on(future));
+        assert_eq!(0, gate.num_waiting());
+        assert_eq!(0, gate.num_cancelled());
+    }
+
+    #[test]
+    fn released_threads_pass_a_closed_gate_one_at_a_time() {
+        let gate = Gate::new();
+        let (passed, passes) = mpsc::channel();
+        let threads: Vec<_> = (0..2)
+            .map(|i| {
+                let gate = gate.clone();
+                let passed = passed.clone();
+                thread::spawn(move || {
+                    gate.wait();
+                    passed.send(i).unwrap();
+                })
+            })
+            .collect();
+
+        assert!(gate.wait_for_waiting(2, Duration::from_secs(5)));
+        gate.release_one();
+        assert!(passes.recv_timeout(Duration::from_secs(5)).is_ok());
+        assert!(passes.recv_timeout(Duration::from_millis(20)).is_err());
+        assert_eq!(1, gate.num_waiting());
+        assert!(!gate.is_open());
+
+        gate.release_one();
+        assert!(passes.recv_timeout(Duration::from_secs(5)).is_ok());
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(0, gate.num_waiting());
+    }
+
+    #[test]
+    fn never_stays_pending() {
+        let mut future = MockFuture::<u32>::never();
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(Poll::Pending, Pin::new(&mut future).poll(&mut cx));
+        assert_eq!(Poll::Pending, Pin::new(&mut future).poll(&mut cx));
+    }
+
+    #[test]
+    fn calls_wake_the_tasks_awaiting_them() {
+        struct Woken(AtomicUsize);
+
+        impl Wake for Woken {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let mock = Mock::<&str, ()>::default();
+        let woken = Arc::new(Woken(AtomicUsize::new(0)));
+        let waker = Waker::from(woken.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut call = mock.await_call(&|&path| path == "/health");
+
+        assert_eq!(Poll::Pending, Pin::new(&mut call).poll(&mut cx));
+        assert_eq!(Poll::Pending, Pin::new(&mut call).poll(&mut cx));
+        mock.call("/");
+        assert_eq!(1, woken.0.load(Ordering::SeqCst));
+        assert_eq!(Poll::Pending, Pin::new(&mut call).poll(&mut cx));
+        mock.call("/health");
+        assert_eq!(2, woken.0.load(Ordering::SeqCst));
+        assert_eq!(Poll::Ready("/health"), Pin::new(&mut call).poll(&mut cx));
+    }
+
+    #[test]
+    fn futures_made_from_other_futures_resolve_to_their_output() {
+        let gate = Gate::new();
+        let future = MockFuture::from_future(MockFuture::until_opened("opened", &gate));
+        let kept = future.clone();
+        let opener = {
+            let gate = gate.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                gate.open();
+            })
+        };
+
+        assert_eq!("opened", block_on(future).0);
+        opener.join().unwrap();
+        assert_eq!(0, gate.num_waiting());
+        drop(kept);
+        assert_eq!(0, gate.num_cancelled());
+    }
+
+    #[test]
+    fn boxed_future_resolves() {
+        assert_eq!((1, 1), block_on(MockFuture::ready(1).boxed()));
+    }
+}
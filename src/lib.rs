@@ -45,9 +45,144 @@
 //!     test_doubling_a_sheets_profit();
 //! }
 //! ```
+//!
+//! The macros refer to the items they use through `$crate`, so they can be
+//! imported by name like any other item instead of with `#[macro_use]`, e.g.
+//! with `use double::{mock_trait, mock_method, matcher, p};` on the 2018 and
+//! later editions. `p!` finds the built-in matchers of `double::matcher` by
+//! itself, so they don't need to be imported either:
+//!
+//! ```
+//! # extern crate double;
+//! use double::{matcher, mock_method, mock_trait, p};
+//!
+//! trait Thermostat {
+//!     fn set(&self, room: &'static str, celsius: i32);
+//! }
+//!
+//! mock_trait!(MockThermostat, set(&'static str, i32) -> ());
+//! impl Thermostat for MockThermostat {
+//!     mock_method!(set(&self, room: &'static str, celsius: i32));
+//! }
+//!
+//! # fn main() {
+//! let thermostat = MockThermostat::default();
+//! thermostat.set("kitchen", 21);
+//! assert!(thermostat.set.called_with_pattern(
+//!     matcher!(p!(eq, "kitchen"), p!(between_exc, 18, 24))));
+//! # }
+//! ```
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(feature = "std"))]
+#[macro_use]
+extern crate alloc;
+
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+compile_error!("`double` needs either its `std` feature (the default) or its `alloc` feature");
+
+#[cfg(not(feature = "std"))]
+#[macro_use]
+#[path = "no_std.rs"]
+mod std;
+
+// Used by `mock_trait!` to generate the names of typed expectations
+#[doc(hidden)]
+pub extern crate paste as __private_paste;
+
+// The parts of `std` that the macros name, since crates without `std` can't
+// name them through `::std`. With `std`, these are the modules of `std`, and
+// without it, those of `core` and `alloc`.
+#[doc(hidden)]
+pub mod __private_std {
+    pub use std::{borrow, boxed, convert, fmt, format, hash, marker, string, vec};
+    #[cfg(feature = "std")]
+    pub use std::thread_local;
+}
+
+#[cfg(feature = "macros")]
+extern crate double_macros;
+
+// Mocks can only be shared by the threads of a `loom` model if they're
+// `Send + Sync`
+#[cfg(all(loom, not(feature = "sync")))]
+compile_error!("building `double` with `--cfg loom` requires its `sync` feature");
 
+#[cfg(feature = "std")]
+pub use crate::async_mock::AsyncMock;
+pub use crate::builder::MockBuilder;
+pub use crate::call_record::{CallRecord, CallRecords};
+pub use crate::counter::CallCounter;
+pub use crate::expectation::{Expectation, Times};
+#[cfg(feature = "serde")]
+pub use crate::export::{export_calls_json, ExportCalls};
+pub use crate::fake::Responses;
+#[cfg(feature = "std")]
+pub use crate::future::{CallFuture, Gate, MockFuture};
 pub use crate::mock::Mock;
+#[cfg(feature = "macros")]
+pub use double_macros::{mock, mock_extern_trait, mocked};
+#[cfg(feature = "std")]
+pub use crate::mock_fn_once::MockFnOnce;
+#[cfg(feature = "std")]
+pub use crate::mockable::{MockSlot, ScopedMock};
+pub use crate::policy::{active_policy, MockPolicy, UnusedStubs};
+#[cfg(feature = "std")]
+pub use crate::policy::{set_policy, PolicyGuard};
+#[cfg(feature = "std")]
+pub use crate::registry::{register_mocks, report, verify_all, RegistryGuard};
+pub use crate::sequence::Sequence;
+pub use crate::snapshot::{snapshot, Snapshot};
+pub use crate::shared::{ArgsFormatter, Closure, Pattern, PatternFn, StaticPattern};
+#[cfg(feature = "futures")]
+pub use crate::sink::MockSink;
+#[cfg(feature = "futures")]
+pub use crate::stream::MockStream;
+pub use crate::verify::{assert_called_before, verify_no_more_interactions, Verify};
+#[cfg(feature = "std")]
+pub use crate::verify::VerifyGuard;
 
+#[cfg(feature = "std")]
+pub mod async_mock;
+pub mod builder;
+mod call_log;
+pub mod call_record;
+// Used by `#[double::mock]` to report the types that mocks can't record or return
+#[doc(hidden)]
+pub mod checks;
+mod color;
+pub mod counter;
+mod diagnostics;
+pub mod expectation;
+#[cfg(feature = "serde")]
+pub mod export;
+pub mod fake;
+#[cfg(feature = "std")]
+pub mod future;
 pub mod macros;
 pub mod matcher;
 pub mod mock;
+#[cfg(feature = "std")]
+pub mod mock_fn_once;
+#[cfg(feature = "std")]
+pub mod mockable;
+pub mod policy;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(any(feature = "futures", feature = "tokio"))]
+mod script;
+pub mod sequence;
+mod shared;
+#[cfg(feature = "futures")]
+pub mod sink;
+pub mod snapshot;
+#[cfg(feature = "std")]
+pub mod std_mocks;
+#[cfg(feature = "futures")]
+pub mod stream;
+#[cfg(feature = "tokio")]
+pub mod tokio_mocks;
+#[cfg(feature = "tracing")]
+mod trace;
+pub mod verify;
@@ -3,13 +3,106 @@
 // clients, but since that's not possible, we at least make it explicit that
 // these are intended to be private by prepending the macro names with
 // "__private".
+#[macro_export]
+macro_rules! __private_named_mock {
+    ($mock_name:ident, $method:ident, $mock:expr) => ({
+        let mock = $mock;
+        mock.set_name(concat!(stringify!($mock_name), "::", stringify!($method)));
+        mock
+    });
+}
+
+// Generic mocks are supported by listing the type parameters of the mock in
+// `[$($param),*]`, which is empty for mocks that aren't generic, followed by
+// the predicates of its `where` clause in `[$($clause)*]`. Every impl of the
+// mock is bounded by those predicates and by what `Mock` requires of the args
+// and return value of each method, which `__private_mock_trait` works out
+// once and passes to the macros of the impls as `[$($bound)*]`.
+#[macro_export]
+macro_rules! __private_mock_trait_struct {
+    // `$attr` are the extra attributes of the mock, e.g. `[#[allow(dead_code)]]`,
+    // and `$vis` is the visibility of the mock and its fields, e.g.
+    // `[pub(crate)]`, or `[]` if it's private
+    ([$($attr:tt)*] [$vis:vis] $mock_name:ident [$($param:ident),*] [$($bound:tt)*]
+        $(, $method:ident($($arg_type:ty),*) -> $retval:ty)*) => (
+        $($attr)*
+        $vis struct $mock_name<$($param),*>
+            where $($bound)*
+        {
+            $(
+                $vis $method: $crate::Mock<(($($arg_type),*)), $retval>
+            ),*
+        }
+    );
+}
+
+// `Clone` and `Debug` are implemented by hand, since deriving them would
+// require the mock's type parameters to implement them too. Clones share the
+// method mocks of the original.
+#[macro_export]
+macro_rules! __private_mock_trait_clone_and_debug_impls {
+    ($mock_name:ident [$($param:ident),*] [$($bound:tt)*]
+        $(, $method:ident($($arg_type:ty),*) -> $retval:ty)*) => (
+        impl<$($param),*> Clone for $mock_name<$($param),*>
+            where $($bound)*
+        {
+            fn clone(&self) -> Self {
+                Self {
+                    $( $method: self.$method.clone() ),*
+                }
+            }
+        }
+
+        impl<$($param),*> $crate::__private_std::fmt::Debug for $mock_name<$($param),*>
+            where $($bound)*
+        {
+            fn fmt(&self, f: &mut $crate::__private_std::fmt::Formatter)
+                -> $crate::__private_std::fmt::Result
+            {
+                f.debug_struct(stringify!($mock_name))
+                    $( .field(stringify!($method), &self.$method) )*
+                    .finish()
+            }
+        }
+    );
+}
+
 #[macro_export]
 macro_rules! __private_mock_trait_default_impl {
-    ($mock_name:ident $(, $method:ident)*) => (
-         impl Default for $mock_name {
+    ($mock_name:ident [$($param:ident),*] [$($clause:tt)*]
+        $(, $method:ident($($arg_type:ty),*) -> $retval:ty)*) => (
+        $crate::__private_mock_trait_default_impl!(
+            @bounded $mock_name [$($param),*]
+            [$(
+                (($($arg_type),*)):
+                    Clone + $crate::__private_std::fmt::Debug + Eq + $crate::__private_std::hash::Hash,
+                $retval: Clone + $crate::__private_std::fmt::Debug + Default,
+            )* $($clause)*]
+            $(, $method($($arg_type),*) -> $retval)*);
+    );
+
+    // See `__private_mock_trait!(@checked ...)`
+    (@checked $mock_name:ident [$($param:ident),*] [$($clause:tt)*]
+        $(, $method:ident($($arg_type:ty),*) -> $retval:ty)*) => (
+        $crate::__private_mock_trait_default_impl!(
+            @bounded $mock_name [$($param),*]
+            [$(
+                for<'__double> (($($arg_type),*)):
+                    Clone + $crate::__private_std::fmt::Debug + Eq + $crate::__private_std::hash::Hash,
+                for<'__double> $retval: Clone + $crate::__private_std::fmt::Debug + Default,
+            )* $($clause)*]
+            $(, $method($($arg_type),*) -> $retval)*);
+    );
+
+    (@bounded $mock_name:ident [$($param:ident),*] [$($bound:tt)*]
+        $(, $method:ident($($arg_type:ty),*) -> $retval:ty)*) => (
+        impl<$($param),*> Default for $mock_name<$($param),*>
+            where $($bound)*
+        {
             fn default() -> Self {
                 Self {
-                    $( $method: double::Mock::default() ),*
+                    $( $method: $crate::__private_named_mock!(
+                        $mock_name, $method, $crate::Mock::default()) ),*
                 }
             }
         }
@@ -18,18 +111,161 @@ macro_rules! __private_mock_trait_default_impl {
 
 #[macro_export]
 macro_rules! __private_mock_trait_new_impl {
-    ($mock_name:ident $(, $method:ident: $retval: ty)*) => (
-        impl $mock_name {
+    ($mock_name:ident [$($param:ident),*] [$($bound:tt)*]
+        $(, $method:ident($($arg_type:ty),*) -> $retval:ty)*) => (
+        impl<$($param),*> $mock_name<$($param),*>
+            where $($bound)*
+        {
             #[allow(dead_code)]
             pub fn new( $($method: $retval),* ) -> Self {
                 Self {
-                    $( $method: double::Mock::new($method) ),*
+                    $( $method: $crate::__private_named_mock!(
+                        $mock_name, $method, $crate::Mock::new($method)) ),*
                 }
             }
         }
     );
 }
 
+#[macro_export]
+macro_rules! __private_mock_trait_usage_impl {
+    ($mock_name:ident [$($param:ident),*] [$($bound:tt)*]
+        $(, $method:ident($($arg_type:ty),*) -> $retval:ty)*) => (
+        impl<$($param),*> $mock_name<$($param),*>
+            where $($bound)*
+        {
+            /// Returns the total number of calls made to all of the mock's
+            /// methods.
+            #[allow(dead_code)]
+            pub fn total_calls(&self) -> usize {
+                0 $( + self.$method.num_calls() )*
+            }
+
+            /// Returns true if any of the mock's methods have been called.
+            #[allow(dead_code)]
+            pub fn was_used(&self) -> bool {
+                self.total_calls() > 0
+            }
+        }
+    );
+}
+
+#[macro_export]
+macro_rules! __private_mock_trait_expectations_impl {
+    // `$vis` is the visibility of the mock, e.g. `[pub(crate)]`, `$params` lists
+    // the mock's type parameters and `$bounds` the bounds of its impls
+    ($vis:tt $mock_name:ident $params:tt $bounds:tt
+        $(, $method:ident($($arg_type:ty),*) -> $retval:ty)*) => (
+        $(
+            $crate::__private_mock_method_expectation!(
+                $vis $mock_name $params $bounds, $method, ($($arg_type),*) -> $retval);
+        )*
+    );
+}
+
+#[macro_export]
+macro_rules! __private_mock_trait_verify_impl {
+    ($mock_name:ident [$($param:ident),*] [$($bound:tt)*]
+        $(, $method:ident($($arg_type:ty),*) -> $retval:ty)*) => (
+        impl<$($param),*> $crate::Verify for $mock_name<$($param),*>
+            where $($bound)*
+        {
+            fn verify(&self) -> bool {
+                let results: &[bool] = &[ $( self.$method.verify() ),* ];
+                results.iter().all(|verified| *verified)
+            }
+
+            fn checkpoint(&self) -> bool {
+                let results: &[bool] = &[ $( self.$method.checkpoint() ),* ];
+                results.iter().all(|verified| *verified)
+            }
+
+            fn verify_no_more_interactions(&self) -> bool {
+                let results: &[bool] = &[
+                    $( self.$method.verify_no_more_interactions() ),* ];
+                results.iter().all(|verified| *verified)
+            }
+        }
+    );
+}
+
+#[macro_export]
+macro_rules! __private_mock_trait_snapshot_impl {
+    ($mock_name:ident [$($param:ident),*] [$($bound:tt)*]
+        $(, $method:ident($($arg_type:ty),*) -> $retval:ty)*) => (
+        impl<$($param),*> $crate::Snapshot for $mock_name<$($param),*>
+            where $($bound)*
+        {
+            fn rendered_calls(&self, name: &str)
+                -> $crate::__private_std::vec::Vec<(usize, $crate::__private_std::string::String)>
+            {
+                let mut calls = $crate::__private_std::vec::Vec::new();
+                $(
+                    calls.extend(self.$method.rendered_calls(
+                        &$crate::__private_std::format!("{}.{}", name, stringify!($method))));
+                )*
+                calls
+            }
+        }
+    );
+}
+
+// Generates everything that `mock_trait_no_default!` does
+#[macro_export]
+macro_rules! __private_mock_trait {
+    // `$attr` are the extra attributes of the mock, and `$vis` is the
+    // visibility of the mock and its fields, e.g. `[pub(crate)]`, or `[]` if
+    // it's private
+    ([$($attr:tt)*] [$($vis:tt)*] $mock_name:ident [$($param:ident),*] [$($clause:tt)*]
+        $(, $method:ident($($arg_type:ty),*) -> $retval:ty)*) => (
+        $crate::__private_mock_trait!(
+            @bounded [$($attr)*] [$($vis)*] $mock_name [$($param),*]
+            [$(
+                (($($arg_type),*)):
+                    Clone + $crate::__private_std::fmt::Debug + Eq + $crate::__private_std::hash::Hash,
+                $retval: Clone + $crate::__private_std::fmt::Debug,
+            )* $($clause)*]
+            $(, $method($($arg_type),*) -> $retval)*);
+    );
+
+    // Used by `#[double::mock]`, which checks the args and return values of
+    // each method itself, and reports those that don't implement what the
+    // mock needs at the method's signature (see `double::checks`). The bounds
+    // are higher-ranked, so the compiler doesn't reject the mock's impls
+    // outright when a bound doesn't hold, which would report the same problem
+    // again at each impl.
+    (@checked [$($attr:tt)*] $vis:vis $mock_name:ident [$($param:ident),*]
+        [$($clause:tt)*] $(, $method:ident($($arg_type:ty),*) -> $retval:ty)*) => (
+        $crate::__private_mock_trait!(
+            @bounded [$($attr)*] [$vis] $mock_name [$($param),*]
+            [$(
+                for<'__double> (($($arg_type),*)):
+                    Clone + $crate::__private_std::fmt::Debug + Eq + $crate::__private_std::hash::Hash,
+                for<'__double> $retval: Clone + $crate::__private_std::fmt::Debug,
+            )* $($clause)*]
+            $(, $method($($arg_type),*) -> $retval)*);
+    );
+
+    (@bounded [$($attr:tt)*] [$($vis:tt)*] $mock_name:ident [$($param:ident),*]
+        [$($bound:tt)*] $(, $method:ident($($arg_type:ty),*) -> $retval:ty)*) => (
+        $crate::__private_mock_trait_struct!(
+            [$($attr)*] [$($vis)*] $mock_name [$($param),*] [$($bound)*]
+            $(, $method($($arg_type),*) -> $retval)*);
+        $crate::__private_mock_trait_clone_and_debug_impls!(
+            $mock_name [$($param),*] [$($bound)*] $(, $method($($arg_type),*) -> $retval)*);
+        $crate::__private_mock_trait_new_impl!(
+            $mock_name [$($param),*] [$($bound)*] $(, $method($($arg_type),*) -> $retval)*);
+        $crate::__private_mock_trait_verify_impl!(
+            $mock_name [$($param),*] [$($bound)*] $(, $method($($arg_type),*) -> $retval)*);
+        $crate::__private_mock_trait_usage_impl!(
+            $mock_name [$($param),*] [$($bound)*] $(, $method($($arg_type),*) -> $retval)*);
+        $crate::__private_mock_trait_snapshot_impl!(
+            $mock_name [$($param),*] [$($bound)*] $(, $method($($arg_type),*) -> $retval)*);
+        $crate::__private_mock_trait_expectations_impl!(
+            [$($vis)*] $mock_name [$($param),*] [$($param: 'static,)* $($bound)*]
+            $(, $method($($arg_type),*) -> $retval)*);
+    );
+}
 
 include!(concat!(env!("OUT_DIR"), "/macros_generated.rs"));
 
@@ -44,7 +280,11 @@ include!(concat!(env!("OUT_DIR"), "/macros_generated.rs"));
 /// This macro generates a `struct` that implements the traits `Clone`, `Debug`
 /// and `Default`. Create instances of the mock object by calling the
 /// `struct`'s `default()` method, or specify custom default return values for
-/// each mocked method using `new()`.
+/// each mocked method using `new()`. Clones share the method mocks of the
+/// original, so fixtures can hand out clones of a mock and verify the calls
+/// made through any of them. `Clone` and `Debug` are implemented whatever the
+/// mock's type parameters are, while `Default` requires the return type of
+/// each method to implement `Default`.
 ///
 /// The `struct` has a field for each method of the `trait`, which manages
 /// their respective method's behaviour and call expectations. For example, if
@@ -83,6 +323,21 @@ include!(concat!(env!("OUT_DIR"), "/macros_generated.rs"));
 /// }
 /// ```
 ///
+/// The generated `struct` also implements `double::Verify`, which verifies
+/// (or checkpoints) the expectations of all of its method mocks. Its
+/// `total_calls()` and `was_used()` methods aggregate the calls made to all
+/// of its method mocks. It implements `double::Snapshot` too, so its calls
+/// can be rendered with `double::snapshot`. Each method mock is named after
+/// the mock and the method, e.g. `MockTaskManager::max_threads` (see
+/// `double::Mock::set_name`).
+///
+/// For each method, the `struct` also has an `expect_<method>()` method,
+/// which sets an expectation on the method's mock (see `double::Mock::expect`)
+/// and returns a typed handle to it. The handle's `with()` takes one matcher
+/// per argument of the method, so passing the wrong number of matchers, or
+/// matchers of the wrong type, is a compile error. The handle is named after
+/// the mock and the method, e.g. `MockTaskManagerSetMaxThreadsExpectation`.
+///
 /// Note that just defining this macro is not enough. This macro is used to
 /// generate the necessary boilerplate, but the generated struct *does not*
 /// implement the desired `trait`. To do that, use `double`'s `mock_method`
@@ -110,32 +365,152 @@ include!(concat!(env!("OUT_DIR"), "/macros_generated.rs"));
 /// assert_eq!(42, mock.max_threads.call(()));
 /// mock.set_max_threads.call(9001u32);
 /// assert!(mock.set_max_threads.called_with(9001u32));
+/// assert_eq!(2, mock.total_calls());
+///
+/// let untouched = MockTaskManager::default();
+/// assert!(!untouched.was_used());
+/// # }
+/// ```
+///
+/// Typed expectations:
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use double::Verify;
+/// use double::matcher::*;
+///
+/// mock_trait!(
+///     MockStack,
+///     push(i32, String) -> (),
+///     pop(()) -> Option<i32>
+/// );
+///
+/// # fn main() {
+/// let mock = MockStack::default();
+/// mock.expect_push().with(p!(gt, 0), p!(any)).times(2);
+/// mock.expect_pop().returning(Some(7));
+///
+/// mock.push.call((1, "one".to_owned()));
+/// mock.push.call((2, "two".to_owned()));
+/// assert_eq!(Some(7), mock.pop.call(()));
+/// assert!(mock.verify());
+/// # }
+/// ```
+///
+/// The `struct` and its fields are private, unless the mock's name is preceded
+/// by a visibility, e.g. `pub` for mocks exported by a crate of test helpers,
+/// or `pub(crate)` for mocks shared by the crate's tests. Attributes before
+/// the visibility are added to the `struct`, e.g. extra derives or lints to
+/// allow (`Debug` and `Clone` are always implemented):
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// mod fixtures {
+///     mock_trait!(
+///         #[allow(dead_code)]
+///         pub(crate) MockClock,
+///         now(()) -> u64,
+///         sleep(u64) -> ()
+///     );
+/// }
+///
+/// # fn main() {
+/// let clock = fixtures::MockClock::default();
+/// clock.now.return_value(42u64);
+/// assert_eq!(42, clock.now.call(()));
+/// # }
+/// ```
+///
+/// Generic traits are mocked by a generic `struct`. List its type parameters
+/// after its name, e.g. `MockRepository<T>`. Its impls require the args and
+/// return values of each method to implement the traits that `double::Mock`
+/// requires of them (`Clone`, `Debug`, `Eq` and `Hash` for args, and `Clone`
+/// and `Debug` for return values), so the type parameters don't need bounds
+/// of their own. Implementations of the mocked trait need the same bounds.
+/// Typed expectations also require the type parameters to be `'static`:
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use std::fmt::Debug;
+/// use std::hash::Hash;
+/// use double::matcher::*;
+///
+/// trait Repository<T> {
+///     fn get(&self, id: u32) -> Option<T>;
+///     fn put(&mut self, id: u32, item: T);
+/// }
+///
+/// mock_trait!(
+///     MockRepository<T>,
+///     get(u32) -> Option<T>,
+///     put(u32, T) -> ()
+/// );
+///
+/// impl<T: Clone + Debug + Eq + Hash> Repository<T> for MockRepository<T> {
+///     mock_method!(get(&self, id: u32) -> Option<T>);
+///     mock_method!(put(&mut self, id: u32, item: T));
+/// }
+///
+/// # fn main() {
+/// let mut mock = MockRepository::<String>::default();
+/// mock.get.return_value_for(1u32, Some("one".to_owned()));
+/// mock.put(2, "two".to_owned());
+/// assert_eq!(Some("one".to_owned()), mock.get(1));
+/// assert!(mock.put.called_with((2, "two".to_owned())));
+/// mock.expect_get().with(p!(eq, 3)).returning(None);
+/// # }
+/// ```
+///
+/// Bounds that the type parameters do need, e.g. when a method's args use
+/// their associated types, go in a `where` clause after the methods. It
+/// bounds the `struct` and all of its impls:
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// trait Entity {
+///     type Id;
+/// }
+///
+/// struct User;
+///
+/// impl Entity for User {
+///     type Id = u32;
+/// }
+///
+/// mock_trait!(
+///     MockRepository<T>,
+///     delete(T::Id) -> bool
+///     where T: Entity
+/// );
+///
+/// # fn main() {
+/// let mock = MockRepository::<User>::default();
+/// mock.delete.return_value(true);
+/// assert!(mock.delete.call(7));
+/// // `User` isn't `Clone`, but the mock is
+/// let shared = mock.clone();
+/// assert!(shared.delete.called_with(7u32));
 /// # }
 /// ```
 #[macro_export]
 macro_rules! mock_trait {
-    ($mock_name:ident $(, $method:ident($($arg_type:ty),* ) -> $retval:ty )* ) => (
-        #[derive(Debug, Clone)]
-        struct $mock_name {
-            $(
-                $method: double::Mock<(($($arg_type),*)), $retval>
-            ),*
-        }
-
-        $crate::__private_mock_trait_new_impl!($mock_name $(, $method: $retval)*);
-        $crate::__private_mock_trait_default_impl!($mock_name $(, $method)*);
+    ($(#[$attr:meta])* $vis:vis $mock_name:ident
+        $(, $method:ident($($arg_type:ty),* ) -> $retval:ty )* ) => (
+        $crate::__private_mock_trait!(
+            [$(#[$attr])*] [$vis] $mock_name [] [] $(, $method($($arg_type),*) -> $retval)*);
+        $crate::__private_mock_trait_default_impl!(
+            $mock_name [] [] $(, $method($($arg_type),*) -> $retval)*);
     );
 
-    (pub $mock_name:ident $(, $method:ident($($arg_type:ty),* ) -> $retval:ty )* ) => (
-        #[derive(Debug, Clone)]
-        pub struct $mock_name {
-            $(
-                $method: double::Mock<(($($arg_type),*)), $retval>
-            ),*
-        }
-
-        $crate::__private_mock_trait_new_impl!($mock_name $(, $method: $retval)*);
-        $crate::__private_mock_trait_default_impl!($mock_name $(, $method)*);
+    ($(#[$attr:meta])* $vis:vis $mock_name:ident<$($param:ident),+>
+        $(, $method:ident($($arg_type:ty),* ) -> $retval:ty )*
+        $(where $($clause:tt)*)? ) => (
+        $crate::__private_mock_trait!(
+            [$(#[$attr])*] [$vis] $mock_name [$($param),*] [$($($clause)*)?]
+            $(, $method($($arg_type),*) -> $retval)*);
+        $crate::__private_mock_trait_default_impl!(
+            $mock_name [$($param),*] [$($($clause)*)?]
+            $(, $method($($arg_type),*) -> $retval)*);
     );
 }
 
@@ -188,6 +563,12 @@ macro_rules! mock_trait {
 /// }
 /// ```
 ///
+/// Like the `struct` generated by `mock_trait!`, it also implements
+/// `double::Verify` and provides `total_calls()` and `was_used()`, and it's
+/// generic if type parameters are listed after its name, optionally followed
+/// by a `where` clause after the methods. The mock's name can be preceded by
+/// attributes and a visibility in the same way too.
+///
 /// Note that just defining this macro is not enough. This macro is used to
 /// generate the necessary boilerplate, but the generated struct *does not*
 /// implement the desired `trait`. To do that, use `double`'s `mock_method`
@@ -218,26 +599,18 @@ macro_rules! mock_trait {
 /// ```
 #[macro_export]
 macro_rules! mock_trait_no_default {
-    ($mock_name:ident $(, $method:ident($($arg_type:ty),* ) -> $retval:ty )* ) => (
-        #[derive(Debug, Clone)]
-        struct $mock_name {
-            $(
-                $method: double::Mock<(($($arg_type),*)), $retval>
-            ),*
-        }
-
-        $crate::__private_mock_trait_new_impl!($mock_name $(, $method: $retval)*);
+    ($(#[$attr:meta])* $vis:vis $mock_name:ident
+        $(, $method:ident($($arg_type:ty),* ) -> $retval:ty )* ) => (
+        $crate::__private_mock_trait!(
+            [$(#[$attr])*] [$vis] $mock_name [] [] $(, $method($($arg_type),*) -> $retval)*);
     );
 
-    (pub $mock_name:ident $(, $method:ident($($arg_type:ty),* ) -> $retval:ty )* ) => (
-        #[derive(Debug, Clone)]
-        pub struct $mock_name {
-            $(
-                $method: double::Mock<(($($arg_type),*)), $retval>
-            ),*
-        }
-
-        $crate::__private_mock_trait_new_impl!($mock_name $(, $method: $retval)*);
+    ($(#[$attr:meta])* $vis:vis $mock_name:ident<$($param:ident),+>
+        $(, $method:ident($($arg_type:ty),* ) -> $retval:ty )*
+        $(where $($clause:tt)*)? ) => (
+        $crate::__private_mock_trait!(
+            [$(#[$attr])*] [$vis] $mock_name [$($param),*] [$($($clause)*)?]
+            $(, $method($($arg_type),*) -> $retval)*);
     );
 }
 
@@ -278,15 +651,17 @@ macro_rules! mock_trait_no_default {
 /// # }
 /// ```
 ///
-/// There are many different variants of `mock_method`. In total there are 12
-/// variants. 8 variants provides a combination of the following:
+/// There are many different variants of `mock_method`. In total there are 24
+/// variants. 16 variants provides a combination of the following:
 ///
-/// 1. const method (`&self`) **or** mutable method (`&mut self`)
+/// 1. const method (`&self`), mutable method (`&mut self`), consuming method
+///    (`self`) **or** method with a typed receiver (e.g. `self: Rc<Self>`)
 /// 2. return value (`fn foo(&self) -> bool`) **or** no return value (`fn foo(&self)`)
 /// 3. automatically generated method body **or** custom method body
 ///
-/// (1) allows both constant and mutable methods tobe mocked, like in the
-/// `MockTaskManager` example above.
+/// (1) allows constant, mutable and consuming methods, and methods that take
+/// `self` in a smart pointer, to be mocked, like in the `MockTaskManager`
+/// example above and in the sections below.
 ///
 /// (2) is for convenience. It means one doesn't have to specify `-> ()`
 /// explicitly for mocked methods that don't return values. This can also be
@@ -341,14 +716,230 @@ macro_rules! mock_trait_no_default {
 /// The name of the underlying mock object is always the same as the mocked
 /// method's name.
 ///
-/// `&str` parameters are common. It can be inconvenient haven't to manually
-/// specify the body each time they appear. There are plans to add a macro to
-/// generate a body that calls `to_owned()` automatically.
-/// (TODO: implement the macro)
+/// `&str` parameters are common. It can be inconvenient to have to manually
+/// specify the body each time they appear, so the generated body can convert
+/// reference args itself. Follow the arg's type by `as` and the type that the
+/// mock stores, e.g. `text: &str as String` or `bytes: &[u8] as Vec<u8>`, to
+/// record the owned value that `ToOwned` converts the arg to. To record an
+/// arg as something else, e.g. an arg that doesn't implement `Clone` as its
+/// ID, follow its type by `=>` and a function or closure that's called with a
+/// reference to the arg, e.g. `request: Request => Request::id`, or
+/// `shape: &dyn Shape => |shape| shape.name()` for a trait object, which
+/// can't be cloned either:
+///
+/// ```
+/// # #[macro_use] extern crate double;
+///
+/// // doesn't implement `Clone`
+/// pub struct Request {
+///     id: u32,
+/// }
+///
+/// impl Request {
+///     fn id(&self) -> u32 {
+///         self.id
+///     }
+/// }
+///
+/// trait Handler {
+///     fn name(&self) -> String;
+/// }
+///
+/// trait Server {
+///     fn route(&self, path: &str, request: Request) -> usize;
+///     fn register(&mut self, handler: Box<dyn Handler>);
+/// }
+///
+/// mock_trait!(
+///     MockServer,
+///     route(String, u32) -> usize,
+///     register(String) -> ()
+/// );
+///
+/// impl Server for MockServer {
+///     mock_method!(route(&self, path: &str as String, request: Request => Request::id)
+///         -> usize);
+///     mock_method!(register(&mut self, handler: Box<dyn Handler> => |handler| handler.name()));
+/// }
+///
+/// struct Users;
+///
+/// impl Handler for Users {
+///     fn name(&self) -> String {
+///         "users".to_owned()
+///     }
+/// }
+///
+/// # fn main() {
+/// let mut server = MockServer::default();
+/// server.route("/users", Request { id: 7 });
+/// assert!(server.route.called_with(("/users".to_owned(), 7)));
+/// server.register(Box::new(Users));
+/// assert!(server.register.called_with("users".to_owned()));
+/// # }
+/// ```
+///
+/// ### Mutable Receivers
+///
+/// Methods that take `&mut self`, like those of writers and builders, are
+/// mocked the same way as those that take `&self`. Each `Mock` records calls
+/// and returns values through interior state, so mutable access isn't needed
+/// for either. A mock that was lent to the code under test as `&mut` can be
+/// stubbed and verified as soon as the borrow ends:
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// trait Writer {
+///     fn write(&mut self, bytes: Vec<u8>) -> usize;
+///     fn flush(&mut self);
+/// }
+///
+/// mock_trait!(
+///     MockWriter,
+///     write(Vec<u8>) -> usize,
+///     flush() -> ()
+/// );
+///
+/// impl Writer for MockWriter {
+///     mock_method!(write(&mut self, bytes: Vec<u8>) -> usize);
+///     mock_method!(flush(&mut self));
+/// }
+///
+/// fn greet<W: Writer>(writer: &mut W) {
+///     let written = writer.write(b"hello".to_vec());
+///     if written > 0 {
+///         writer.flush();
+///     }
+/// }
+///
+/// # fn main() {
+/// let mut writer = MockWriter::default();
+/// writer.write.return_value(5usize);
+/// greet(&mut writer);
+/// assert!(writer.write.called_with(b"hello".to_vec()));
+/// assert!(writer.flush.called());
+/// # }
+/// ```
+///
+/// ### Consuming Receivers
+///
+/// Methods that take `self` by value, e.g. `fn into_connection(self) -> Conn`,
+/// consume the mock. Clones of a mock share its method mocks' behaviour and
+/// recorded calls though, so keep a clone as a handle to verify the calls
+/// after the mock has been consumed:
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// trait Builder {
+///     fn port(&mut self, port: u16);
+///     fn build(self) -> String;
+/// }
+///
+/// mock_trait!(
+///     MockBuilder,
+///     port(u16) -> (),
+///     build() -> String
+/// );
+///
+/// impl Builder for MockBuilder {
+///     mock_method!(port(&mut self, port: u16));
+///     mock_method!(build(self) -> String);
+/// }
+///
+/// fn connect<B: Builder>(mut builder: B) -> String {
+///     builder.port(8080);
+///     builder.build()
+/// }
+///
+/// # fn main() {
+/// let builder = MockBuilder::default();
+/// builder.build.return_value("localhost:8080".to_owned());
+/// let handle = builder.clone();
+///
+/// assert_eq!("localhost:8080", connect(builder));
+/// assert!(handle.port.called_with(8080u16));
+/// assert!(handle.build.called());
+/// # }
+/// ```
+///
+/// ### Smart Pointer Receivers
+///
+/// Methods can also take `self` in a smart pointer, such as `self: Rc<Self>`,
+/// `self: Arc<Self>`, `self: Box<Self>` or `self: Pin<&mut Self>`, as is
+/// common for actors and futures. Any receiver type that dereferences to the
+/// mock works, since the method mock is called through it:
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use std::pin::Pin;
+/// use std::rc::Rc;
+///
+/// trait Actor {
+///     fn send(self: Rc<Self>, message: String);
+///     fn poll(self: Pin<&mut Self>) -> bool;
+/// }
+///
+/// mock_trait!(
+///     MockActor,
+///     send(String) -> (),
+///     poll() -> bool
+/// );
+///
+/// impl Actor for MockActor {
+///     mock_method!(send(self: Rc<Self>, message: String));
+///     mock_method!(poll(self: Pin<&mut Self>) -> bool);
+/// }
+///
+/// # fn main() {
+/// let mut actor = MockActor::default();
+/// actor.poll.return_value(true);
+/// assert!(Pin::new(&mut actor).poll());
+///
+/// let actor = Rc::new(actor);
+/// actor.clone().send("ping".to_owned());
+/// assert!(actor.send.called_with("ping".to_owned()));
+/// # }
+/// ```
+///
+/// ### Unsafe Methods
+///
+/// Prefix any variant with `unsafe` to mock an `unsafe fn`, such as the
+/// methods of traits that wrap FFI calls. Only the generated method's
+/// signature is `unsafe`. Its mock records calls and returns values like any
+/// other, so stubbing and verifying it is safe:
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// trait Device {
+///     unsafe fn write_register(&mut self, address: u32, value: u8);
+///     unsafe fn read_register(&self, address: u32) -> u8;
+/// }
+///
+/// mock_trait!(
+///     MockDevice,
+///     write_register(u32, u8) -> (),
+///     read_register(u32) -> u8
+/// );
+///
+/// impl Device for MockDevice {
+///     mock_method!(unsafe write_register(&mut self, address: u32, value: u8));
+///     mock_method!(unsafe read_register(&self, address: u32) -> u8);
+/// }
+///
+/// # fn main() {
+/// let mut device = MockDevice::default();
+/// device.read_register.return_value(0x2a);
+/// unsafe {
+///     device.write_register(0x10, 7);
+///     assert_eq!(0x2a, device.read_register(0x10));
+/// }
+/// assert!(device.write_register.called_with((0x10, 7)));
+/// # }
+/// ```
 ///
 /// ### Type Parameters
 ///
-/// There are an additional 4 variants to handle method type parameters
+/// There are an additional 8 variants to handle method type parameters
 /// (e.g. `fn foo<T: Eq>(&self, a: &T)`). These variants allow one to generate
 /// mock methods which take some generic type parameters.
 ///
@@ -412,91 +1003,1169 @@ macro_rules! mock_trait_no_default {
 /// are all still handled by `double`. Arguably, reimplenting those features is
 /// more cumbersome than the small amount of boilerplate required to mock
 /// methods with type arguments.
+///
+/// ### Lifetime Parameters
+///
+/// The type parameter variants also handle methods with lifetime parameters
+/// (e.g. `fn find<'a>(&self, word: &'a str) -> Option<&'a str>`). Since mocks
+/// own the args they record, borrowed args are stored as owned values (e.g.
+/// `String` for `&'a str`). Return values are stored with `'static` lifetimes
+/// instead, which can be returned for any shorter lifetime. Traits with
+/// lifetime parameters are implemented for all of them, since the mock itself
+/// doesn't borrow anything:
+///
+/// ```
+/// # #[macro_use] extern crate double;
+///
+/// trait Dictionary<'input> {
+///     fn define(&mut self, word: &'input str);
+///     fn find<'a>(&self, word: &'a str) -> Option<&'a str>;
+/// }
+///
+/// mock_trait!(
+///     MockDictionary,
+///     define(String) -> (),
+///     find(String) -> Option<&'static str>
+/// );
+///
+/// impl<'input> Dictionary<'input> for MockDictionary {
+///     mock_method!(define(&mut self, word: &'input str), self, {
+///         self.define.call(word.to_owned())
+///     });
+///     mock_method!(find<('a)>(&self, word: &'a str) -> Option<&'a str>, self, {
+///         self.find.call(word.to_owned())
+///     });
+/// }
+///
+/// # fn main() {
+/// let mock = MockDictionary::default();
+/// mock.find.return_value_for("rust".to_owned(), Some("a language"));
+/// assert_eq!(Some("a language"), mock.find("rust"));
+/// # }
+/// ```
+///
+/// `#[double::mock]` (with the `macros` feature) generates these bodies
+/// automatically.
 #[macro_export]
 macro_rules! mock_method {
+    (unsafe $($method:tt)*) => (
+        $crate::__private_mock_method!([unsafe] $($method)*);
+    );
+
+    ($($method:tt)*) => (
+        $crate::__private_mock_method!([] $($method)*);
+    );
+}
+
+// Generates the method for `mock_method!`. `$unsafety` is `[unsafe]` or `[]`.
+#[macro_export]
+macro_rules! __private_mock_method {
 
     // immutable, no return value, no type parameter, no body
-    ( $method:ident(&self $(,$arg_name:ident: $arg_type:ty)*)) => (
-        fn $method(&self $(,$arg_name: $arg_type)*) {
-            self.$method.call(($($arg_name.clone()),*))
+    ([$($unsafety:tt)*]
+        $method:ident(&self
+            $(,$arg_name:ident: $arg_type:ty $(as $stored:ty)? $(=> $project:expr)?)*)) => (
+        #[track_caller]
+        $($unsafety)* fn $method(&self $(,$arg_name: $arg_type)*) {
+            self.$method.call(($($crate::__private_mock_method_arg!(
+                $arg_name $(as $stored)? $(=> $project)?)),*))
         }
     );
 
     // immutable, no return value, no type parameter, body
-    ( $method:ident(&self $(,$arg_name:ident: $arg_type:ty)*), $sel:ident, $body:tt ) => (
-        fn $method(&$sel $(,$arg_name: $arg_type)*) $body
+    ([$($unsafety:tt)*]
+        $method:ident(&self $(,$arg_name:ident: $arg_type:ty)*), $sel:ident, $body:tt ) => (
+        $($unsafety)* fn $method(&$sel $(,$arg_name: $arg_type)*) $body
     );
 
     // immutable, no return value, type parameter, no body
     // not provided, since type parameters need a custom body 99% of the time
 
     // immutable, no return value, type parameter, body
-    ( $method:ident<($($type_params: tt)*)>(&self $(,$arg_name:ident: $arg_type:ty)*),
+    ([$($unsafety:tt)*]
+        $method:ident<($($type_params: tt)*)>(&self $(,$arg_name:ident: $arg_type:ty)*),
         $sel:ident, $body:tt) => (
-            fn $method<$($type_params)*>(&$sel $(,$arg_name: $arg_type)*) $body
+            $($unsafety)* fn $method<$($type_params)*>(&$sel $(,$arg_name: $arg_type)*) $body
     );
 
     // immutable, return value, no type parameter, no body
-    ( $method:ident(&self $(,$arg_name:ident: $arg_type:ty)*) -> $retval:ty ) => (
-        fn $method(&self $(,$arg_name: $arg_type)*) -> $retval {
-            self.$method.call(($($arg_name.clone()),*))
+    ([$($unsafety:tt)*]
+        $method:ident(&self
+            $(,$arg_name:ident: $arg_type:ty $(as $stored:ty)? $(=> $project:expr)?)*)
+            -> $retval:ty ) => (
+        #[track_caller]
+        $($unsafety)* fn $method(&self $(,$arg_name: $arg_type)*) -> $retval {
+            self.$method.call(($($crate::__private_mock_method_arg!(
+                $arg_name $(as $stored)? $(=> $project)?)),*))
         }
     );
 
     // immutable, return value, no type parameter, body
-    ( $method:ident(&self $(,$arg_name:ident: $arg_type:ty)*) -> $retval:ty, $sel:ident, $body:tt ) => (
-        fn $method(&$sel $(,$arg_name: $arg_type)*) -> $retval $body
+    ([$($unsafety:tt)*]
+        $method:ident(&self $(,$arg_name:ident: $arg_type:ty)*) -> $retval:ty, $sel:ident, $body:tt ) => (
+        $($unsafety)* fn $method(&$sel $(,$arg_name: $arg_type)*) -> $retval $body
     );
 
     // immutable, return value, type parameter, no body
     // not provided, since type parameters need a custom body 99% of the time
 
     // immutable, return value, type parameter, body
-    ( $method:ident<($($type_params: tt)*)>(&self $(,$arg_name:ident: $arg_type:ty)*)
+    ([$($unsafety:tt)*]
+        $method:ident<($($type_params: tt)*)>(&self $(,$arg_name:ident: $arg_type:ty)*)
         -> $retval:ty, $sel:ident, $body:tt ) => (
-            fn $method<$($type_params)*>(&$sel $(,$arg_name: $arg_type)*) -> $retval $body
+            $($unsafety)* fn $method<$($type_params)*>(&$sel $(,$arg_name: $arg_type)*)
+                -> $retval $body
     );
 
     // mutable, no return value, no type parameter, no body
-    ( $method:ident(&mut self $(,$arg_name:ident: $arg_type:ty)*)) => (
-        fn $method(&mut self $(,$arg_name: $arg_type)*) {
-            self.$method.call(($($arg_name.clone()),*))
+    ([$($unsafety:tt)*]
+        $method:ident(&mut self
+            $(,$arg_name:ident: $arg_type:ty $(as $stored:ty)? $(=> $project:expr)?)*)) => (
+        #[track_caller]
+        $($unsafety)* fn $method(&mut self $(,$arg_name: $arg_type)*) {
+            self.$method.call(($($crate::__private_mock_method_arg!(
+                $arg_name $(as $stored)? $(=> $project)?)),*))
         }
     );
 
     // mutable, no return value, no type parameter, body
-    ( $method:ident(&mut self $(,$arg_name:ident: $arg_type:ty)*), $sel:ident, $body:tt ) => (
-        fn $method(&mut $sel $(,$arg_name: $arg_type)*) $body
+    ([$($unsafety:tt)*]
+        $method:ident(&mut self $(,$arg_name:ident: $arg_type:ty)*), $sel:ident, $body:tt ) => (
+        $($unsafety)* fn $method(&mut $sel $(,$arg_name: $arg_type)*) $body
     );
 
     // mutable, no return value, type parameter, no body
     // not provided, since type parameters need a custom body 99% of the time
 
     // mutable, no return value, type parameter, body
-    ( $method:ident<($($type_params: tt)*)>(&mut self $(,$arg_name:ident: $arg_type:ty)*),
+    ([$($unsafety:tt)*]
+        $method:ident<($($type_params: tt)*)>(&mut self $(,$arg_name:ident: $arg_type:ty)*),
         $sel:ident, $body:tt) => (
-            fn $method<$($type_params)*>(&mut $sel $(,$arg_name: $arg_type)*) $body
+            $($unsafety)* fn $method<$($type_params)*>(&mut $sel $(,$arg_name: $arg_type)*) $body
     );
 
     // mutable, return value, no type parameter, no body
-    ( $method:ident(&mut self $(,$arg_name:ident: $arg_type:ty)*) -> $retval:ty ) => (
-        fn $method(&mut self $(,$arg_name: $arg_type)*) -> $retval {
-            self.$method.call(($($arg_name.clone()),*))
+    ([$($unsafety:tt)*]
+        $method:ident(&mut self
+            $(,$arg_name:ident: $arg_type:ty $(as $stored:ty)? $(=> $project:expr)?)*)
+            -> $retval:ty ) => (
+        #[track_caller]
+        $($unsafety)* fn $method(&mut self $(,$arg_name: $arg_type)*) -> $retval {
+            self.$method.call(($($crate::__private_mock_method_arg!(
+                $arg_name $(as $stored)? $(=> $project)?)),*))
         }
     );
 
     // mutable, return value, no type parameter, body
-    ( $method:ident(&mut self $(,$arg_name:ident: $arg_type:ty)*) -> $retval:ty, $sel:ident, $body:tt ) => (
-        fn $method(&mut $sel $(,$arg_name: $arg_type)*) -> $retval $body
+    ([$($unsafety:tt)*]
+        $method:ident(&mut self $(,$arg_name:ident: $arg_type:ty)*) -> $retval:ty, $sel:ident, $body:tt ) => (
+        $($unsafety)* fn $method(&mut $sel $(,$arg_name: $arg_type)*) -> $retval $body
     );
 
     // mutable, return value, type parameter, no body
     // not provided, since type parameters need a custom body 99% of the time
 
     // mutable, return value, type parameter, body
-    ( $method:ident<($($type_params: tt)*)>(&mut self $(,$arg_name:ident: $arg_type:ty)*)
+    ([$($unsafety:tt)*]
+        $method:ident<($($type_params: tt)*)>(&mut self $(,$arg_name:ident: $arg_type:ty)*)
         -> $retval:ty, $sel:ident, $body:tt ) => (
-            fn $method<$($type_params)*>(&mut $sel $(,$arg_name: $arg_type)*) -> $retval $body
+            $($unsafety)* fn $method<$($type_params)*>(&mut $sel $(,$arg_name: $arg_type)*)
+                -> $retval $body
     );
 
+    // consuming, no return value, no type parameter, no body
+    ([$($unsafety:tt)*]
+        $method:ident(self
+            $(,$arg_name:ident: $arg_type:ty $(as $stored:ty)? $(=> $project:expr)?)*)) => (
+        #[track_caller]
+        $($unsafety)* fn $method(self $(,$arg_name: $arg_type)*) {
+            self.$method.call(($($crate::__private_mock_method_arg!(
+                $arg_name $(as $stored)? $(=> $project)?)),*))
+        }
+    );
+
+    // consuming, no return value, no type parameter, body
+    ([$($unsafety:tt)*]
+        $method:ident(self $(,$arg_name:ident: $arg_type:ty)*), $sel:ident, $body:tt ) => (
+        $($unsafety)* fn $method($sel $(,$arg_name: $arg_type)*) $body
+    );
+
+    // consuming, no return value, type parameter, no body
+    // not provided, since type parameters need a custom body 99% of the time
+
+    // consuming, no return value, type parameter, body
+    ([$($unsafety:tt)*]
+        $method:ident<($($type_params: tt)*)>(self $(,$arg_name:ident: $arg_type:ty)*),
+        $sel:ident, $body:tt) => (
+            $($unsafety)* fn $method<$($type_params)*>($sel $(,$arg_name: $arg_type)*) $body
+    );
+
+    // consuming, return value, no type parameter, no body
+    ([$($unsafety:tt)*]
+        $method:ident(self
+            $(,$arg_name:ident: $arg_type:ty $(as $stored:ty)? $(=> $project:expr)?)*)
+            -> $retval:ty ) => (
+        #[track_caller]
+        $($unsafety)* fn $method(self $(,$arg_name: $arg_type)*) -> $retval {
+            self.$method.call(($($crate::__private_mock_method_arg!(
+                $arg_name $(as $stored)? $(=> $project)?)),*))
+        }
+    );
+
+    // consuming, return value, no type parameter, body
+    ([$($unsafety:tt)*]
+        $method:ident(self $(,$arg_name:ident: $arg_type:ty)*) -> $retval:ty, $sel:ident, $body:tt ) => (
+        $($unsafety)* fn $method($sel $(,$arg_name: $arg_type)*) -> $retval $body
+    );
+
+    // consuming, return value, type parameter, no body
+    // not provided, since type parameters need a custom body 99% of the time
+
+    // consuming, return value, type parameter, body
+    ([$($unsafety:tt)*]
+        $method:ident<($($type_params: tt)*)>(self $(,$arg_name:ident: $arg_type:ty)*)
+        -> $retval:ty, $sel:ident, $body:tt ) => (
+            $($unsafety)* fn $method<$($type_params)*>($sel $(,$arg_name: $arg_type)*)
+                -> $retval $body
+    );
+
+    // typed receiver, no return value, no type parameter, no body
+    ([$($unsafety:tt)*]
+        $method:ident(self: $self_type:ty
+            $(,$arg_name:ident: $arg_type:ty $(as $stored:ty)? $(=> $project:expr)?)*)) => (
+        #[track_caller]
+        $($unsafety)* fn $method(self: $self_type $(,$arg_name: $arg_type)*) {
+            self.$method.call(($($crate::__private_mock_method_arg!(
+                $arg_name $(as $stored)? $(=> $project)?)),*))
+        }
+    );
+
+    // typed receiver, no return value, no type parameter, body
+    ([$($unsafety:tt)*]
+        $method:ident(self: $self_type:ty $(,$arg_name:ident: $arg_type:ty)*),
+        $sel:ident, $body:tt ) => (
+        $($unsafety)* fn $method($sel: $self_type $(,$arg_name: $arg_type)*) $body
+    );
+
+    // typed receiver, no return value, type parameter, no body
+    // not provided, since type parameters need a custom body 99% of the time
+
+    // typed receiver, no return value, type parameter, body
+    ([$($unsafety:tt)*]
+        $method:ident<($($type_params: tt)*)>(self: $self_type:ty
+        $(,$arg_name:ident: $arg_type:ty)*), $sel:ident, $body:tt) => (
+            $($unsafety)* fn $method<$($type_params)*>($sel: $self_type $(,$arg_name: $arg_type)*)
+                $body
+    );
+
+    // typed receiver, return value, no type parameter, no body
+    ([$($unsafety:tt)*]
+        $method:ident(self: $self_type:ty
+            $(,$arg_name:ident: $arg_type:ty $(as $stored:ty)? $(=> $project:expr)?)*)
+            -> $retval:ty ) => (
+        #[track_caller]
+        $($unsafety)* fn $method(self: $self_type $(,$arg_name: $arg_type)*) -> $retval {
+            self.$method.call(($($crate::__private_mock_method_arg!(
+                $arg_name $(as $stored)? $(=> $project)?)),*))
+        }
+    );
+
+    // typed receiver, return value, no type parameter, body
+    ([$($unsafety:tt)*]
+        $method:ident(self: $self_type:ty $(,$arg_name:ident: $arg_type:ty)*) -> $retval:ty,
+        $sel:ident, $body:tt ) => (
+        $($unsafety)* fn $method($sel: $self_type $(,$arg_name: $arg_type)*) -> $retval $body
+    );
+
+    // typed receiver, return value, type parameter, no body
+    // not provided, since type parameters need a custom body 99% of the time
+
+    // typed receiver, return value, type parameter, body
+    ([$($unsafety:tt)*]
+        $method:ident<($($type_params: tt)*)>(self: $self_type:ty
+        $(,$arg_name:ident: $arg_type:ty)*) -> $retval:ty, $sel:ident, $body:tt ) => (
+            $($unsafety)* fn $method<$($type_params)*>($sel: $self_type $(,$arg_name: $arg_type)*)
+                -> $retval $body
+    );
+
+}
+
+// Generates the value of the arg `$arg_name` that's recorded by the mock of a
+// method generated by `mock_method!`: a clone of it, the owned value that
+// `ToOwned` converts it to, or its projection
+#[macro_export]
+macro_rules! __private_mock_method_arg {
+    ($arg_name:ident) => ($arg_name.clone());
+    ($arg_name:ident as $stored:ty) => ($crate::__private_std::borrow::ToOwned::to_owned(&*$arg_name));
+    // A function, rather than a call of `$project`, so that the types of the
+    // params of closures are inferred
+    ($arg_name:ident => $project:expr) => ({
+        fn project<A: ?Sized, S>(arg: &A, project: impl FnOnce(&A) -> S) -> S {
+            project(arg)
+        }
+        project(&$arg_name, $project)
+    });
+}
+
+
+/// Macro that defines free functions whose calls can be routed to a `Mock`
+/// within a scope, for code that calls free functions (e.g. to get the time
+/// or a random number) rather than methods of a `trait` that can be mocked.
+///
+/// Each function is defined as written, along with a function named `mock_`
+/// followed by the function's name. That takes a `Mock` of the function's
+/// args and return value, and returns a `ScopedMock` guard. While the guard
+/// is alive, calls to the function on the current thread are handled by the
+/// mock instead of the function's body, so tests running in parallel on
+/// other threads are unaffected. Dropping the guard restores what handled
+/// calls before it was created, so guards can be nested.
+///
+/// As with `mock_trait!`, the mock's args are a tuple of the function's arg
+/// types, or the arg's type if it only has one. They must be `'static`, so
+/// functions that borrow their args can't be made mockable.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use double::Mock;
+///
+/// mockable_fn! {
+///     pub fn now() -> u64 {
+///         std::time::SystemTime::now()
+///             .duration_since(std::time::UNIX_EPOCH)
+///             .unwrap()
+///             .as_secs()
+///     }
+///
+///     fn roll_die(sides: u32) -> u32 {
+///         sides
+///     }
+/// }
+///
+/// fn is_expired(expiry: u64) -> bool {
+///     now() >= expiry
+/// }
+///
+/// # fn main() {
+/// let clock = Mock::new(100u64);
+/// {
+///     let _guard = mock_now(&clock);
+///     assert!(is_expired(100));
+///     assert!(!is_expired(101));
+/// }
+/// assert!(is_expired(101));
+/// assert_eq!(2, clock.num_calls());
+///
+/// let die = Mock::new(4u32);
+/// let _guard = mock_roll_die(&die);
+/// assert_eq!(4, roll_die(6));
+/// assert!(die.called_with(6u32));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! mockable_fn {
+    () => ();
+
+    ($(#[$attr:meta])* $vis:vis fn $name:ident($($arg_name:ident: $arg_type:ty),* $(,)?)
+        -> $retval:ty $body:block $($rest:tt)*) => (
+        $crate::__private_mockable_fn!(
+            [$(#[$attr])* #[track_caller]] [$vis] []
+            $name($($arg_name: $arg_type),*) -> $retval $body);
+        $crate::mockable_fn!($($rest)*);
+    );
+
+    ($(#[$attr:meta])* $vis:vis fn $name:ident($($arg_name:ident: $arg_type:ty),* $(,)?)
+        $body:block $($rest:tt)*) => (
+        $crate::__private_mockable_fn!(
+            [$(#[$attr])* #[track_caller]] [$vis] []
+            $name($($arg_name: $arg_type),*) -> () $body);
+        $crate::mockable_fn!($($rest)*);
+    );
+}
+
+// The type of the args recorded by a mock of a function with the arg types
+// `$arg_type`, which is a tuple unless there's only one
+#[macro_export]
+macro_rules! __private_args_type {
+    ($arg_type:ty) => ($arg_type);
+    ($($arg_type:ty),*) => (($($arg_type),*));
+}
+
+// Generates a single function for `mockable_fn!` and `mock_extern_fn!`, with
+// the thread-local slot its calls are routed through and the `mock_` function
+// that fills the slot. `$qualifiers` go before `fn` (e.g. `unsafe extern "C"`)
+// and `$body` handles calls while the slot is empty.
+#[macro_export]
+macro_rules! __private_mockable_fn {
+    ([$($attr:tt)*] [$vis:vis] [$($qualifiers:tt)*]
+        $name:ident($($arg_name:ident: $arg_type:ty),*) -> $retval:ty $body:block) => (
+        $crate::__private_paste::paste! {
+            $crate::__private_std::thread_local! {
+                #[allow(non_upper_case_globals)]
+                static [<__DOUBLE_MOCK_SLOT_ $name:upper>]:
+                    $crate::MockSlot<$crate::__private_args_type!($($arg_type),*), $retval> =
+                    const { $crate::MockSlot::new() };
+            }
+
+            $($attr)*
+            $vis $($qualifiers)* fn $name($($arg_name: $arg_type),*) -> $retval {
+                let mock = [<__DOUBLE_MOCK_SLOT_ $name:upper>].with($crate::MockSlot::mock);
+                match mock {
+                    Some(mock) => mock.call(($($arg_name),*)),
+                    None => $body,
+                }
+            }
+
+            /// Routes calls to the function of the same name (without `mock_`)
+            /// on the current thread to `mock`, until the returned guard is
+            /// dropped.
+            #[allow(dead_code)]
+            $vis fn [<mock_ $name>](
+                mock: &$crate::Mock<$crate::__private_args_type!($($arg_type),*), $retval>)
+                -> $crate::ScopedMock<$crate::__private_args_type!($($arg_type),*), $retval>
+            {
+                $crate::ScopedMock::new(&[<__DOUBLE_MOCK_SLOT_ $name:upper>], mock)
+            }
+        }
+    );
+}
+
+/// Macro that generates doubles of `extern "C"` functions, for testing code
+/// that passes callbacks to C libraries through function pointers or tables
+/// of them.
+///
+/// Each signature is turned into an `unsafe extern "C"` function, a shim
+/// whose pointer can be handed to C code, along with a function named
+/// `mock_` followed by the function's name, like those of `mockable_fn!`.
+/// That takes a `Mock` of the function's args and return value, which records
+/// and stubs the shim's calls on the current thread, safely, until the guard
+/// it returns is dropped.
+///
+/// Since there's no real implementation to fall back on, calling a shim
+/// while no mock handles its calls on the current thread panics. Panics
+/// can't unwind out of `extern "C"` functions, so this aborts the process.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use double::Mock;
+///
+/// // A table of callbacks, as declared by bindings to a C library
+/// #[repr(C)]
+/// struct Callbacks {
+///     on_data: unsafe extern "C" fn(data: *const u8, len: usize) -> i32,
+///     on_close: unsafe extern "C" fn(),
+/// }
+///
+/// mock_extern_fn! {
+///     fn on_data(data: *const u8, len: usize) -> i32;
+///     fn on_close();
+/// }
+///
+/// # fn main() {
+/// let on_data_mock = Mock::new(0i32);
+/// let on_close_mock = Mock::<(), ()>::default();
+/// let _on_data = mock_on_data(&on_data_mock);
+/// let _on_close = mock_on_close(&on_close_mock);
+///
+/// let callbacks = Callbacks { on_data, on_close };
+/// let data = [1u8, 2, 3];
+/// unsafe {
+///     assert_eq!(0, (callbacks.on_data)(data.as_ptr(), data.len()));
+///     (callbacks.on_close)();
+/// }
+///
+/// assert!(on_data_mock.called_with((data.as_ptr(), 3usize)));
+/// assert!(on_close_mock.called());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! mock_extern_fn {
+    () => ();
+
+    ($(#[$attr:meta])* $vis:vis fn $name:ident($($arg_name:ident: $arg_type:ty),* $(,)?)
+        -> $retval:ty; $($rest:tt)*) => (
+        $crate::__private_mock_extern_fn!(
+            [$(#[$attr])*] [$vis] $name($($arg_name: $arg_type),*) -> $retval);
+        $crate::mock_extern_fn!($($rest)*);
+    );
+
+    ($(#[$attr:meta])* $vis:vis fn $name:ident($($arg_name:ident: $arg_type:ty),* $(,)?);
+        $($rest:tt)*) => (
+        $crate::__private_mock_extern_fn!(
+            [$(#[$attr])*] [$vis] $name($($arg_name: $arg_type),*) -> ());
+        $crate::mock_extern_fn!($($rest)*);
+    );
+}
+
+// Generates a single shim for `mock_extern_fn!`
+#[macro_export]
+macro_rules! __private_mock_extern_fn {
+    ([$($attr:tt)*] [$vis:vis] $name:ident($($arg_name:ident: $arg_type:ty),*)
+        -> $retval:ty) => (
+        $crate::__private_mockable_fn!(
+            [$($attr)* #[allow(clippy::missing_safety_doc)]] [$vis] [unsafe extern "C"]
+            $name($($arg_name: $arg_type),*) -> $retval {
+                panic!(concat!("no mock handles calls to `", stringify!($name), "` on this thread"))
+            });
+    );
+}
+
+/// Macro that generates a fake implementation of a trait, whose methods
+/// return canned responses rather than being driven by matchers like a mock's.
+///
+/// The fake gets a `Responses` field for each method, named after it, and
+/// each call of the method pops the response at the front of that queue. A
+/// call panics if the queue is empty, naming the method. Methods that return
+/// `()` don't need responses. Args are ignored, and not recorded, so use a
+/// mock instead when the test needs to check how the trait was used.
+///
+/// The trait's methods are restated in the macro, like in the trait's
+/// definition. Only methods that take `&self` or `&mut self` are supported.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// trait Inventory {
+///     fn stock(&self, item: &str) -> u32;
+///     fn reserve(&mut self, item: &str, count: u32) -> Result<(), String>;
+///     fn log(&self, message: &str);
+/// }
+///
+/// fake_trait!(FakeInventory: Inventory {
+///     fn stock(&self, item: &str) -> u32;
+///     fn reserve(&mut self, item: &str, count: u32) -> Result<(), String>;
+///     fn log(&self, message: &str);
+/// });
+///
+/// fn order<I: Inventory>(inventory: &mut I, item: &str, count: u32) -> Result<(), String> {
+///     if inventory.stock(item) < count {
+///         inventory.log("out of stock");
+///         return Err("not enough stock".to_owned());
+///     }
+///     inventory.reserve(item, count)
+/// }
+///
+/// # fn main() {
+/// let mut inventory = FakeInventory::new();
+/// inventory.stock.push_all(vec!(10, 1));
+/// inventory.reserve.push(Ok(()));
+///
+/// assert_eq!(Ok(()), order(&mut inventory, "widget", 5));
+/// assert_eq!(Err("not enough stock".to_owned()), order(&mut inventory, "widget", 5));
+/// # }
+/// ```
+///
+/// ```should_panic
+/// # #[macro_use] extern crate double;
+/// trait Clock {
+///     fn now(&self) -> u64;
+/// }
+///
+/// fake_trait!(FakeClock: Clock {
+///     fn now(&self) -> u64;
+/// });
+///
+/// # fn main() {
+/// let clock = FakeClock::new();
+/// clock.now.push(100);
+/// clock.now();
+/// // panics: "`FakeClock::now` has no canned responses left (it was called 2
+/// // times and 1 response was pushed)"
+/// clock.now();
+/// # }
+/// ```
+#[macro_export]
+macro_rules! fake_trait {
+    ($(#[$attr:meta])* $vis:vis $name:ident: $trait_name:path {
+        $(fn $method:ident $params:tt $(-> $retval:ty)?;)*
+    }) => (
+        $(#[$attr])*
+        #[derive(Clone)]
+        $vis struct $name {
+            $(pub $method: $crate::Responses<$crate::__private_fake_retval!($($retval)?)>,)*
+        }
+
+        impl $name {
+            /// Creates a fake whose methods have no canned responses.
+            pub fn new() -> Self {
+                $name {
+                    $($method: $crate::Responses::named(
+                        concat!(stringify!($name), "::", stringify!($method))),)*
+                }
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                $name::new()
+            }
+        }
+
+        impl $trait_name for $name {
+            $($crate::__private_fake_method!($method $params $(-> $retval)?);)*
+        }
+    );
+}
+
+// The type of the responses of a fake method, which is `()` for methods
+// without a return type
+#[macro_export]
+macro_rules! __private_fake_retval {
+    () => (());
+    ($retval:ty) => ($retval);
+}
+
+// Generates a method of a fake, which pops its response
+#[macro_export]
+macro_rules! __private_fake_method {
+    ($method:ident(&self $(, $arg_name:ident: $arg_type:ty)* $(,)?)) => (
+        fn $method(&self $(, _: $arg_type)*) {
+            self.$method.next_or_default()
+        }
+    );
+
+    ($method:ident(&mut self $(, $arg_name:ident: $arg_type:ty)* $(,)?)) => (
+        fn $method(&mut self $(, _: $arg_type)*) {
+            self.$method.next_or_default()
+        }
+    );
+
+    ($method:ident(&self $(, $arg_name:ident: $arg_type:ty)* $(,)?) -> $retval:ty) => (
+        #[track_caller]
+        fn $method(&self $(, _: $arg_type)*) -> $retval {
+            self.$method.next()
+        }
+    );
+
+    ($method:ident(&mut self $(, $arg_name:ident: $arg_type:ty)* $(,)?) -> $retval:ty) => (
+        #[track_caller]
+        fn $method(&mut self $(, _: $arg_type)*) -> $retval {
+            self.$method.next()
+        }
+    );
+}
+
+/// Macro that generates a spy, which wraps a real implementation of a trait,
+/// delegating every call to it while recording the args and return values of
+/// the calls.
+///
+/// The spy gets a `Mock` field for each method, named after it, like the
+/// mocks generated by `mock_trait!`, so the calls can be checked using the
+/// same methods, expectations and macros, e.g. `called_with` or
+/// `expect_called!`. The calls always return what the real implementation
+/// returns, whatever behaviour is configured on the mocks.
+///
+/// The trait's methods are restated in the macro, like in the trait's
+/// definition. Args are recorded by cloning them, so reference args must
+/// either be `'static`, or be followed by `as` and the owned type that
+/// `ToOwned` converts them to, e.g. `key: &str as String`. Only methods that
+/// take `&self` or `&mut self` are supported.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use std::collections::HashMap;
+///
+/// trait Store {
+///     fn get(&self, key: &str) -> Option<i32>;
+///     fn put(&mut self, key: &str, value: i32);
+/// }
+///
+/// struct MemoryStore(HashMap<String, i32>);
+///
+/// impl Store for MemoryStore {
+///     fn get(&self, key: &str) -> Option<i32> {
+///         self.0.get(key).cloned()
+///     }
+///
+///     fn put(&mut self, key: &str, value: i32) {
+///         self.0.insert(key.to_owned(), value);
+///     }
+/// }
+///
+/// spy_trait!(SpyStore: Store {
+///     fn get(&self, key: &str as String) -> Option<i32>;
+///     fn put(&mut self, key: &str as String, value: i32);
+/// });
+///
+/// fn increment<S: Store>(store: &mut S, key: &str) {
+///     let value = store.get(key).unwrap_or(0);
+///     store.put(key, value + 1);
+/// }
+///
+/// # fn main() {
+/// let mut store = SpyStore::new(MemoryStore(HashMap::new()));
+/// increment(&mut store, "visits");
+/// increment(&mut store, "visits");
+///
+/// assert_eq!(Some(2), store.real().get("visits"));
+/// assert!(store.get.has_calls_exactly(vec!("visits".to_owned(), "visits".to_owned())));
+/// assert_eq!(vec!(None, Some(1)), store.get.records().return_values());
+/// assert!(store.put.called_with(("visits".to_owned(), 2)));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! spy_trait {
+    ($(#[$attr:meta])* $vis:vis $name:ident: $trait_name:path {
+        $(fn $method:ident $params:tt $(-> $retval:ty)?;)*
+    }) => (
+        $(#[$attr])*
+        $vis struct $name<T> {
+            real: T,
+            $(pub $method: $crate::Mock<
+                $crate::__private_spy_args_type!($params),
+                $crate::__private_fake_retval!($($retval)?)>,)*
+        }
+
+        impl<T> $name<T> {
+            /// Creates a spy that delegates calls to `real`.
+            pub fn new(real: T) -> Self {
+                $name {
+                    real,
+                    $($method: $crate::__private_named_mock!(
+                        $name, $method, $crate::Mock::__private_for_spy()),)*
+                }
+            }
+
+            /// Returns the real implementation that calls are delegated to.
+            #[allow(dead_code)]
+            pub fn real(&self) -> &T {
+                &self.real
+            }
+
+            /// Returns the real implementation, dropping the spy.
+            #[allow(dead_code)]
+            pub fn into_real(self) -> T {
+                self.real
+            }
+        }
+
+        impl<T: $trait_name> $trait_name for $name<T> {
+            $($crate::__private_spy_method!($trait_name, $method $params $(-> $retval)?);)*
+        }
+
+        impl<T> $crate::Verify for $name<T> {
+            fn verify(&self) -> bool {
+                let results: &[bool] = &[ $( self.$method.verify() ),* ];
+                results.iter().all(|verified| *verified)
+            }
+
+            fn checkpoint(&self) -> bool {
+                let results: &[bool] = &[ $( self.$method.checkpoint() ),* ];
+                results.iter().all(|verified| *verified)
+            }
+
+            fn verify_no_more_interactions(&self) -> bool {
+                let results: &[bool] = &[
+                    $( self.$method.verify_no_more_interactions() ),* ];
+                results.iter().all(|verified| *verified)
+            }
+        }
+    );
+}
+
+// The type of the args that a spy method records
+#[macro_export]
+macro_rules! __private_spy_args_type {
+    ((&self $(, $arg_name:ident: $arg_type:ty $(as $recorded:ty)?)* $(,)?)) => (
+        $crate::__private_args_type!($($crate::__private_spy_arg_type!($arg_type $(as $recorded)?)),*)
+    );
+
+    ((&mut self $(, $arg_name:ident: $arg_type:ty $(as $recorded:ty)?)* $(,)?)) => (
+        $crate::__private_args_type!($($crate::__private_spy_arg_type!($arg_type $(as $recorded)?)),*)
+    );
+}
+
+#[macro_export]
+macro_rules! __private_spy_arg_type {
+    ($arg_type:ty) => ($arg_type);
+    ($arg_type:ty as $recorded:ty) => ($recorded);
+}
+
+#[macro_export]
+macro_rules! __private_spy_arg {
+    ($arg_name:ident) => ($arg_name.clone());
+    ($arg_name:ident as $recorded:ty) => ($crate::__private_std::borrow::ToOwned::to_owned(&*$arg_name));
+}
+
+// Generates a method of a spy, which records the call and delegates it to the
+// real implementation
+#[macro_export]
+macro_rules! __private_spy_method {
+    ($trait_name:path, $method:ident(&self $(, $arg_name:ident: $arg_type:ty $(as $recorded:ty)?)* $(,)?)
+        $(-> $retval:ty)?) => (
+        #[track_caller]
+        fn $method(&self $(, $arg_name: $arg_type)*) $(-> $retval)? {
+            let real = &self.real;
+            self.$method.__private_spy(
+                ($($crate::__private_spy_arg!($arg_name $(as $recorded)?)),*),
+                move || <T as $trait_name>::$method(real $(, $arg_name)*))
+        }
+    );
+
+    ($trait_name:path, $method:ident(&mut self $(, $arg_name:ident: $arg_type:ty $(as $recorded:ty)?)* $(,)?)
+        $(-> $retval:ty)?) => (
+        #[track_caller]
+        fn $method(&mut self $(, $arg_name: $arg_type)*) $(-> $retval)? {
+            let real = &mut self.real;
+            self.$method.__private_spy(
+                ($($crate::__private_spy_arg!($arg_name $(as $recorded)?)),*),
+                move || <T as $trait_name>::$method(real $(, $arg_name)*))
+        }
+    );
+}
+
+/// Macro that asserts that a method mock (or any `Mock`) was called, with a
+/// panic message that names the mock and lists its recorded calls.
+///
+/// By default, the mock must have been called at least once. `times:` gives
+/// the number of calls instead, as anything that converts to a `Times`, e.g.
+/// `2`, `1..=3` or `Times::at_least(2)`. `with:` only counts the calls whose
+/// args match the given matchers, one per arg, each written as a call of a
+/// matcher function without the arg it checks, e.g. `eq(3)` for `p!(eq, 3)`,
+/// or as its name alone, e.g. `any` for `p!(any)`. `_` is short for `any`,
+/// and any other expression, e.g. `"user-1"` or `-1`, matches args equal to
+/// it, like `eq("user-1")`. Variables and calls of constructors such as
+/// `Some(3)` look like matchers, so they need an explicit `eq`. The calls it
+/// counts are verified, as far as `verify_no_more_interactions` is concerned.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// mock_trait!(MockQueue, push(i32, i32) -> ());
+///
+/// # fn main() {
+/// let queue = MockQueue::default();
+/// queue.push.call((3, 10));
+/// queue.push.call((3, 20));
+/// queue.push.call((4, 30));
+///
+/// expect_called!(queue.push);
+/// expect_called!(queue.push, times: 3);
+/// expect_called!(queue.push, with: (eq(4), gt(25)));
+/// expect_called!(queue.push, times: 2, with: (eq(3), any));
+/// expect_called!(queue.push, times: 2, with: (3, _));
+/// expect_called!(queue.push, with: (4, 30));
+/// expect_called!(queue.push, times: 0, with: (any, between_inc(40, 50)));
+/// # }
+/// ```
+///
+/// ```should_panic
+/// # #[macro_use] extern crate double;
+/// mock_trait!(MockQueue, push(i32, i32) -> ());
+///
+/// # fn main() {
+/// let queue = MockQueue::default();
+/// queue.push.call((3, 10));
+/// queue.push.call((4, 30));
+///
+/// // panics: "expected `queue.push` to be called exactly 2 times with args
+/// // matching (eq(3), any), but it was called 1 time
+/// // Recorded calls: [(3, 10), (4, 30)]"
+/// expect_called!(queue.push, times: 2, with: (eq(3), any));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! expect_called {
+    ($mock:expr $(,)?) => (
+        $mock.__private_expect_called(
+            stringify!($mock), None, &|_| true, $crate::Times::at_least(1))
+    );
+
+    ($mock:expr, times: $times:expr $(,)?) => (
+        $mock.__private_expect_called(
+            stringify!($mock),
+            None,
+            &|_| true,
+            $crate::Times::from($times))
+    );
+
+    ($mock:expr, with: $matchers:tt $(,)?) => (
+        $mock.__private_expect_called(
+            stringify!($mock),
+            Some(stringify!($matchers)),
+            $crate::__private_expect_called_matcher!($matchers),
+            $crate::Times::at_least(1))
+    );
+
+    ($mock:expr, times: $times:expr, with: $matchers:tt $(,)?) => (
+        $mock.__private_expect_called(
+            stringify!($mock),
+            Some(stringify!($matchers)),
+            $crate::__private_expect_called_matcher!($matchers),
+            $crate::Times::from($times))
+    );
+
+    ($mock:expr, with: $matchers:tt, times: $times:expr $(,)?) => (
+        $crate::expect_called!($mock, times: $times, with: $matchers)
+    );
+}
+
+// Turns the matchers of `expect_called!`, e.g. `(eq(3), _, "user-1")`, into the
+// pattern that `matcher!` builds from `p!(eq, 3), p!(any), p!(eq, "user-1")`.
+// The matchers are munched one at a time into `[...]` groups, since whether
+// each one is a matcher or a value to wrap in `eq` depends on its tokens.
+#[macro_export]
+macro_rules! __private_expect_called_matcher {
+    (($($matchers:tt)+)) => (
+        $crate::__private_expect_called_matcher!(@munch [] $($matchers)+)
+    );
+
+    (@munch [$([$($done:tt)*])+]) => (
+        $crate::matcher!($($($done)*),+)
+    );
+
+    (@munch [$($done:tt)*] _ $(, $($rest:tt)*)?) => (
+        $crate::__private_expect_called_matcher!(
+            @munch [$($done)* [$crate::p!(any)]] $($($rest)*)?)
+    );
+
+    // Before the matcher names, since `true` and `false` are idents too
+    (@munch [$($done:tt)*] $value:literal $(, $($rest:tt)*)?) => (
+        $crate::__private_expect_called_matcher!(
+            @munch [$($done)* [$crate::p!(eq, $value)]] $($($rest)*)?)
+    );
+
+    (@munch [$($done:tt)*] $func:ident $(, $($rest:tt)*)?) => (
+        $crate::__private_expect_called_matcher!(
+            @munch [$($done)* [$crate::p!($func)]] $($($rest)*)?)
+    );
+
+    (@munch [$($done:tt)*] $func:ident($($arg:expr),*) $(, $($rest:tt)*)?) => (
+        $crate::__private_expect_called_matcher!(
+            @munch [$($done)* [$crate::p!($func $(, $arg)*)]] $($($rest)*)?)
+    );
+
+    (@munch [$($done:tt)*] $value:expr $(, $($rest:tt)*)?) => (
+        $crate::__private_expect_called_matcher!(
+            @munch [$($done)* [$crate::p!(eq, $value)]] $($($rest)*)?)
+    );
+}
+
+
+/// Stubs mocks in the style of a spec's "given" step. Each stub is written as
+/// a call of the mock with matchers in place of its args, like those of
+/// `expect_called!`, which may be plain values, followed by `=>` and the value
+/// returned by the calls they match. Stubs are separated by `;`. A call
+/// without matchers stubs the mock's return value for all args.
+///
+/// `given!(repo.load("key") => Some(42))` is short for
+/// `repo.load.when_args(...).returning(Some(42))`, so the matchers' args are
+/// evaluated for every call. They must be constants or `Copy`.
+///
+/// Only available with the `bdd` feature.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// mock_trait!(MockRepo, load(&'static str) -> Option<i32>, count() -> i32);
+///
+/// # fn main() {
+/// let repo = MockRepo::default();
+/// let first = "first";
+/// given!(
+///     repo.load("key") => Some(42);
+///     repo.load(eq(first)) => Some(1);
+///     repo.count() => 3);
+///
+/// assert_eq!(Some(42), repo.load.call("key"));
+/// assert_eq!(Some(1), repo.load.call("first"));
+/// assert_eq!(None, repo.load.call("missing"));
+/// assert_eq!(3, repo.count.call(()));
+/// # }
+/// ```
+#[cfg(feature = "bdd")]
+#[macro_export]
+macro_rules! given {
+    () => ();
+
+    ($($mock:ident).+ () => $ret:expr $(; $($rest:tt)*)?) => (
+        $($mock).+.return_value($ret);
+        $crate::given!($($($rest)*)?);
+    );
+
+    ($($mock:ident).+ ($($matchers:tt)*) => $ret:expr $(; $($rest:tt)*)?) => (
+        $($mock).+.when_args(
+            move |args| ($crate::__private_expect_called_matcher!(($($matchers)*)))(args)
+        ).returning($ret);
+        $crate::given!($($($rest)*)?);
+    );
+}
+
+/// Verifies how a mock was called in the style of a spec's "then" step, e.g.
+/// `then!(repo.save called once)`. Compiles to `expect_called!`, so it panics
+/// with the mock's recorded calls if they don't match.
+///
+/// The mock may be followed by matchers for the args of the calls to count,
+/// like those of `expect_called!`, e.g. `then!(repo.save(eq(1), any) called
+/// twice)`. The number of calls is one of:
+///
+/// | Written as | Calls |
+/// | --- | --- |
+/// | `called` | at least one |
+/// | `called once`, `called twice` | exactly one, exactly two |
+/// | `called 3 times` | exactly 3 |
+/// | `called at least 3 times` | 3 or more |
+/// | `called at most 3 times` | 3 or fewer |
+/// | `never called` | none |
+///
+/// Only available with the `bdd` feature.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// mock_trait!(MockRepo, save(i32, &'static str) -> (), flush() -> ());
+///
+/// # fn main() {
+/// let repo = MockRepo::default();
+/// repo.save.call((1, "one"));
+/// repo.save.call((2, "two"));
+///
+/// then!(repo.save called twice);
+/// then!(repo.save(eq(1), _) called once);
+/// then!(repo.save(gt(1), any) called at most 1 times);
+/// then!(repo.save(2, "two") called once);
+/// then!(repo.flush never called);
+/// # }
+/// ```
+///
+/// ```should_panic
+/// # #[macro_use] extern crate double;
+/// mock_trait!(MockRepo, save(i32, &'static str) -> ());
+///
+/// # fn main() {
+/// let repo = MockRepo::default();
+/// repo.save.call((1, "one"));
+///
+/// // panics: "expected `repo.save` to be called exactly 2 times, but it was
+/// // called 1 time
+/// // Recorded calls: [(1, \"one\")]"
+/// then!(repo.save called twice);
+/// # }
+/// ```
+#[cfg(feature = "bdd")]
+#[macro_export]
+macro_rules! then {
+    ($($mock:ident).+ $(($($matchers:tt)*))? never called) => (
+        $crate::__private_then!(($($mock).+) ($($($matchers)*)?) $crate::Times::never())
+    );
+
+    ($($mock:ident).+ $(($($matchers:tt)*))? called) => (
+        $crate::__private_then!(($($mock).+) ($($($matchers)*)?) $crate::Times::at_least(1))
+    );
+
+    ($($mock:ident).+ $(($($matchers:tt)*))? called once) => (
+        $crate::__private_then!(($($mock).+) ($($($matchers)*)?) $crate::Times::exactly(1))
+    );
+
+    ($($mock:ident).+ $(($($matchers:tt)*))? called twice) => (
+        $crate::__private_then!(($($mock).+) ($($($matchers)*)?) $crate::Times::exactly(2))
+    );
+
+    ($($mock:ident).+ $(($($matchers:tt)*))? called at least $n:tt times) => (
+        $crate::__private_then!(($($mock).+) ($($($matchers)*)?) $crate::Times::at_least($n))
+    );
+
+    ($($mock:ident).+ $(($($matchers:tt)*))? called at most $n:tt times) => (
+        $crate::__private_then!(($($mock).+) ($($($matchers)*)?) $crate::Times::at_most($n))
+    );
+
+    ($($mock:ident).+ $(($($matchers:tt)*))? called $n:tt times) => (
+        $crate::__private_then!(($($mock).+) ($($($matchers)*)?) $crate::Times::exactly($n))
+    );
+}
+
+// Passes the mock, matchers and number of calls parsed by `then!` on to
+// `expect_called!`
+#[cfg(feature = "bdd")]
+#[macro_export]
+macro_rules! __private_then {
+    (($mock:expr) () $times:expr) => (
+        $crate::expect_called!($mock, times: $times)
+    );
+
+    (($mock:expr) $matchers:tt $times:expr) => (
+        $crate::expect_called!($mock, times: $times, with: $matchers)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn mock_func_mocks_functions_with_up_to_twelve_args() {
+        mock_func!(mock, mock_fn, u32, u8, u8);
+        mock.return_value(3u32);
+        assert_eq!(3, mock_fn(1, 2));
+        assert!(mock.called_with((1, 2)));
+
+        mock_func!(wide_mock, wide_mock_fn, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8);
+        wide_mock_fn(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12);
+        assert!(wide_mock.called_with((1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12)));
+    }
+
+    fn fold_with<F: Fn(u32, &str) -> u32>(words: &[&str], step: F) -> u32 {
+        words.iter().fold(0, |total, word| step(total, word))
+    }
+
+    fn run<F: FnMut()>(mut task: F) {
+        task();
+    }
+
+    #[test]
+    fn mock_fn_wraps_mocks_in_closures_satisfying_fn_bounds() {
+        let step = crate::Mock::<(u32, String), u32>::default();
+        step.use_closure(Box::new(|(total, word)| total + word.len() as u32));
+        assert_eq!(7, fold_with(&["mock", "fn!"], mock_fn!(step, u32, &str)));
+        assert!(step.has_calls_exactly_in_order(vec!(
+            (0u32, "mock".to_owned()), (4u32, "fn!".to_owned()))));
+
+        let task = crate::Mock::<(), ()>::default();
+        run(mock_fn!(task));
+        assert!(task.called());
+    }
+
+    mockable_fn! {
+        fn add(a: u32, b: u32) -> u32 {
+            a + b
+        }
+
+        fn tick() {}
+    }
+
+    #[test]
+    fn mockable_fns_call_their_mocks_while_guarded() {
+        let adder = crate::Mock::new(0u32);
+        let ticker = crate::Mock::<(), ()>::default();
+        {
+            let _adder = mock_add(&adder);
+            let _ticker = mock_tick(&ticker);
+            assert_eq!(0, add(1, 2));
+            tick();
+        }
+        assert_eq!(3, add(1, 2));
+        tick();
+        assert!(adder.has_calls_exactly(vec!((1u32, 2u32))));
+        assert_eq!(1, ticker.num_calls());
+    }
+
+    mock_extern_fn! {
+        fn on_event(code: i32) -> i32;
+    }
+
+    #[test]
+    fn extern_fn_shims_call_their_mocks() {
+        let on_event_mock = crate::Mock::new(7i32);
+        let _guard = mock_on_event(&on_event_mock);
+        let callback: unsafe extern "C" fn(i32) -> i32 = on_event;
+        assert_eq!(7, unsafe { callback(3) });
+        assert!(on_event_mock.called_with(3i32));
+    }
 }
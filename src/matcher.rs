@@ -1,12 +1,41 @@
+#[cfg(feature = "std")]
 extern crate float_cmp;
 
+#[cfg(feature = "std")]
 use std::f32;
+#[cfg(feature = "std")]
 use std::f64;
+use std::fmt::Debug;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+#[cfg(feature = "std")]
 use self::float_cmp::ApproxEqUlps;
+use crate::diagnostics;
 
 
 include!(concat!(env!("OUT_DIR"), "/matcher_generated.rs"));
 
+// Used by `matcher!`. Returns true if every arg matched, and reports the args
+// that didn't so that failure messages can name them.
+#[doc(hidden)]
+pub fn __private_report_matches(
+    matches: &[(bool, Option<String>)],
+    matchers: &[&'static str]) -> bool
+{
+    if diagnostics::explaining() {
+        diagnostics::report_matches(matches, matchers);
+    }
+    matches.iter().all(|&(matched, _)| matched)
+}
+
+// Used by `p!`. Returns `matched`, and reports the source of the matcher so
+// that composite matchers can name their clauses.
+#[doc(hidden)]
+pub fn __private_report_clause(source: &'static str, matched: bool) -> bool {
+    diagnostics::report_clause(source);
+    matched
+}
+
 
 // ============================================================================
 // * Comparison Matchers
@@ -89,8 +118,12 @@ pub fn is_err<T, U>(arg: &Result<T, U>, matcher: &dyn Fn(&U) -> bool) -> bool {
 // * Float Matchers
 // ============================================================================
 
+// The float matchers compare using `float-cmp`, so they require the `std`
+// feature.
+
 /// Matcher that matches if `arg` is equal to `target_val`. This uses
 /// approximate floating point equality, as defined by the `float-cmp` crate.
+#[cfg(feature = "std")]
 pub fn f32_eq(arg: &f32, target_val: f32) -> bool {
     if target_val.is_nan() && arg.is_nan() {
         false
@@ -101,6 +134,7 @@ pub fn f32_eq(arg: &f32, target_val: f32) -> bool {
 
 /// Matcher that matches if `arg` is equal to `target_val`. This uses
 /// approximate floating point equality, as defined by the `float-cmp` crate.
+#[cfg(feature = "std")]
 pub fn f64_eq(arg: &f64, target_val: f64) -> bool {
     if target_val.is_nan() && arg.is_nan() {
         false
@@ -114,6 +148,7 @@ pub fn f64_eq(arg: &f64, target_val: f64) -> bool {
 ///
 /// Unlike `f32_eq`, this matcher returns `true` if both the actual `arg` and
 /// the `target_val` are NaN.
+#[cfg(feature = "std")]
 pub fn nan_sensitive_f32_eq(arg: &f32, target_val: f32) -> bool {
     if target_val.is_nan() && arg.is_nan() {
         true
@@ -127,6 +162,7 @@ pub fn nan_sensitive_f32_eq(arg: &f32, target_val: f32) -> bool {
 ///
 /// Unlike `f64_eq`, this matcher returns `true` if both the actual `arg` and
 /// the `target_val` are NaN.
+#[cfg(feature = "std")]
 pub fn nan_sensitive_f64_eq(arg: &f64, target_val: f64) -> bool {
     if target_val.is_nan() && arg.is_nan() {
         true
@@ -186,9 +222,16 @@ pub fn not<T>(arg: &T, matcher: &dyn Fn(&T) -> bool) -> bool {
 /// Matcher that matches if `arg` matches *all* of the specified `matchers`. If
 /// at least one of `matchers` doesn't match with `arg`, this matcher doesn't
 /// match.
-pub fn all_of<T>(arg: &T, matchers: Vec<&dyn Fn(&T) -> bool>) -> bool {
-    for matcher in matchers {
-        if !matcher(arg) {
+///
+/// When a check or expectation using this matcher fails, the failure message
+/// names the first clause that rejected the arg, and the arg, e.g. `all_of
+/// failed: clause #2 (p!(le, 10)) rejected value 15`.
+pub fn all_of<T: Debug>(arg: &T, matchers: Vec<&dyn Fn(&T) -> bool>) -> bool {
+    for (index, matcher) in matchers.into_iter().enumerate() {
+        let clause = diagnostics::evaluate_clause(|| matcher(arg));
+        if !clause.matched {
+            diagnostics::report_detail(
+                || format!("all_of failed: {} rejected value {:?}", clause.describe(index), arg));
             return false
         }
     }
@@ -197,15 +240,35 @@ pub fn all_of<T>(arg: &T, matchers: Vec<&dyn Fn(&T) -> bool>) -> bool {
 
 /// Matcher that matches if `arg` matches *any* of the specified `matchers`. If
 /// none of the `matchers` match with `arg`, this matcher doesn't match.
-pub fn any_of<T>(arg: &T, matchers: Vec<&dyn Fn(&T) -> bool>) -> bool {
+pub fn any_of<T: Debug>(arg: &T, matchers: Vec<&dyn Fn(&T) -> bool>) -> bool {
+    let num_matchers = matchers.len();
     for matcher in matchers {
-        if matcher(arg) {
+        if diagnostics::evaluate_clause(|| matcher(arg)).matched {
             return true
         }
     }
+    diagnostics::report_detail(
+        || format!("any_of failed: none of its {} clauses matched value {:?}", num_matchers, arg));
     false
 }
 
+/// Matcher that matches if `arg` matches *none* of the specified `matchers`.
+/// If at least one of `matchers` matches `arg`, this matcher doesn't match.
+///
+/// When a check or expectation using this matcher fails, the failure message
+/// names the first clause that unexpectedly matched the arg, and the arg.
+pub fn none_of<T: Debug>(arg: &T, matchers: Vec<&dyn Fn(&T) -> bool>) -> bool {
+    for (index, matcher) in matchers.into_iter().enumerate() {
+        let clause = diagnostics::evaluate_clause(|| matcher(arg));
+        if clause.matched {
+            diagnostics::report_detail(
+                || format!("none_of failed: {} matched value {:?}", clause.describe(index), arg));
+            return false
+        }
+    }
+    true
+}
+
 
 // ============================================================================
 // * Unit Tests
@@ -214,6 +277,30 @@ pub fn any_of<T>(arg: &T, matchers: Vec<&dyn Fn(&T) -> bool>) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::diagnostics;
+
+    #[test]
+    fn matcher_reports_rejected_args() {
+        let pattern: &dyn Fn(&(i32, i32, i32)) -> bool = matcher!(p!(eq, 1), p!(lt, 5), p!(any));
+        let rejections: Vec<String> = diagnostics::rejected_by_pattern(pattern, &(2, 7, 0))
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            vec!("arg 0 was rejected by p!(eq, 1)", "arg 1 was rejected by p!(lt, 5)"),
+            rejections);
+        assert_eq!(None, diagnostics::rejected_by_pattern(pattern, &(1, 4, 0)));
+    }
+
+    #[test]
+    fn matcher_matches_up_to_twelve_args() {
+        type Args = (u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8);
+        let pattern: &dyn Fn(&Args) -> bool = matcher!(
+            p!(eq, 1), &any, &any, &any, &any, &any, &any, &any, &any, &any, &any, p!(eq, 12));
+        assert!(pattern(&(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12)));
+        assert!(!pattern(&(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 11)));
+    }
 
     #[test]
     fn any_matcher() {
@@ -497,6 +584,50 @@ mod tests {
         assert!(!matcher(&15));
     }
 
+    #[test]
+    fn none_of_matcher() {
+        let matcher = p!(none_of, vec!(
+            p!(eq, 26),
+            p!(gt, 40)
+        ));
+        assert!(matcher(&0));
+        assert!(!matcher(&26));
+        assert!(!matcher(&42));
+    }
+
+    #[test]
+    fn composite_matchers_report_failing_clause() {
+        let pattern: &dyn Fn(&(i32, i32)) -> bool = matcher!(
+            p!(all_of, vec!(p!(ge, 0), p!(le, 10))),
+            p!(none_of, vec!(p!(eq, 1), p!(any_of, vec!(p!(eq, 2), p!(eq, 3))))));
+        let rejections: Vec<String> = diagnostics::rejected_by_pattern(pattern, &(15, 3))
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            vec!(
+                "arg 0 was rejected by p!(all_of, vec!(p!(ge, 0), p!(le, 10))): \
+                 all_of failed: clause #2 (p!(le, 10)) rejected value 15",
+                "arg 1 was rejected by \
+                 p!(none_of, vec!(p!(eq, 1), p!(any_of, vec!(p!(eq, 2), p!(eq, 3))))): \
+                 none_of failed: clause #2 (p!(any_of, vec!(p!(eq, 2), p!(eq, 3)))) \
+                 matched value 3"),
+            rejections);
+
+        let pattern: &dyn Fn(&i32) -> bool = matcher!(p!(any_of, vec!(p!(eq, 2), p!(eq, 3))));
+        let rejections: Vec<String> = diagnostics::rejected_by_pattern(pattern, &4)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            vec!(
+                "arg 0 was rejected by p!(any_of, vec!(p!(eq, 2), p!(eq, 3))): \
+                 any_of failed: none of its 2 clauses matched value 4"),
+            rejections);
+    }
+
     #[test]
     fn any_of_matcher() {
         let matcher = p!(any_of, vec!(
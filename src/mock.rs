@@ -1,21 +1,61 @@
-extern crate lazysort;
-
-use std::cell::RefCell;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::any::type_name;
 use std::fmt;
 use std::fmt::{Debug, Formatter};
+#[cfg(feature = "std")]
+use std::future::Future;
 use std::hash::Hash;
 use std::iter::FromIterator;
-use std::rc::Rc;
-use self::lazysort::SortedBy;
-
-type Ref<T> = Rc<RefCell<T>>;
-type OptionalRef<T> = Rc<RefCell<Option<T>>>;
+#[cfg(feature = "std")]
+use std::mem;
+use std::panic::Location;
+#[cfg(feature = "std")]
+use std::task::Waker;
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+use crate::builder::MockBuilder;
+use crate::call_log::CallLog;
+use crate::call_record::{CallRecord, CallRecords};
+use crate::color::Palette;
+use crate::counter::CallCounter;
+use crate::diagnostics::{self, Rejection};
+use crate::expectation::{Expectation, StatusRef, Times};
+#[cfg(feature = "std")]
+use crate::future::{CallFuture, Gate, MockFuture};
+use crate::policy::{self, MockPolicy, UnusedStubs};
+#[cfg(feature = "std")]
+use crate::registry::{self, RegisteredMock};
+use crate::shared::{ArgsFormatter, AtomicBool, Closure, Ordering, Pattern, PatternFn, Shared,
+                    SharedPtr, StaticPattern};
+#[cfg(feature = "tracing")]
+use crate::trace;
+
+type Ref<T> = Shared<T>;
+type OptionalRef<T> = Shared<Option<T>>;
+type FnMap<C, R> = HashMap<C, Stub<fn(C) -> R>>;
+type ClosureMap<C, R> = HashMap<C, Stub<SharedPtr<Closure<C, R>>>>;
+type Counters<C> = Vec<(SharedPtr<Pattern<C>>, CallCounter)>;
+
+// How often the call log is checked while waiting for calls
+#[cfg(feature = "std")]
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
 
 /// Used for tracking function call arguments and specifying a predetermined
 /// return value or mock function.
 ///
+/// By default, `Mock` can only be used on a single thread. Enable the `sync`
+/// feature to make it `Send + Sync` (as long as its argument and return types
+/// are), so it can be shared with other threads. Clones of a `Mock` share its
+/// configured behaviour and recorded calls.
+///
+/// Mocks are re-entrant. The closures and patterns configured on a mock can
+/// call the mock again, or reconfigure it, e.g. to mock callback-heavy APIs.
+///
 /// See the crate documentation for more substantial examples, including some
 /// that demonstrate how to use `Mock` for methods that have multiple arguments
 /// as well as methods with argument or return types that do not implement
@@ -25,16 +65,53 @@ pub struct Mock<C, R>
     where C: Clone + Eq + Hash,
           R: Clone
 {
+    // Restored by `reset`. Only the mocks of spies have none, since spies
+    // return what the real implementation returns.
+    initial_return_value: OptionalRef<R>,
     // Ordered from lowest precedence to highest
-    default_return_value: Ref<R>,
+    default_return_value: OptionalRef<R>,
     return_value_sequence: Ref<Vec<R>>,
     default_fn: OptionalRef<fn(C) -> R>,
-    default_closure: OptionalRef<Box<dyn Fn(C) -> R>>,
-    return_values: Ref<HashMap<C, R>>,
-    fns: Ref<HashMap<C, fn(C) -> R>>,
-    closures: Ref<HashMap<C, Box<dyn Fn(C) -> R>>>,
-
-    calls: Ref<Vec<C>>,
+    // Closures, patterns and formatters are shared pointers, so they can be
+    // taken out of the mock to be run, and can then call and reconfigure the
+    // mock themselves
+    default_closure: OptionalRef<SharedPtr<Closure<C, R>>>,
+    return_values: Ref<HashMap<C, Stub<R>>>,
+    fns: Ref<FnMap<C, R>>,
+    closures: Ref<ClosureMap<C, R>>,
+    expectations: Ref<Vec<Expectation<C, R>>>,
+    // Shared with the mock registry, which can't depend on `C` and `R`
+    expectation_statuses: Ref<Vec<StatusRef>>,
+    // Set once any default behaviour is configured after construction
+    default_configured: Ref<bool>,
+    policy: Ref<MockPolicy>,
+    // Used instead of `Debug` to format args in failure messages
+    args_formatter: OptionalRef<SharedPtr<ArgsFormatter<C>>>,
+    name: OptionalRef<String>,
+    // Formats args using `Debug`, outside of blocks that require it. Set by
+    // the methods that need it, such as `set_name`, which require `Debug`.
+    debug_args: OptionalRef<fn(&C) -> String>,
+    unused_stubs: Ref<UnusedStubs>,
+    // Whether the default behaviour handled a call, if one was configured.
+    // Set by calls without taking the lock for writing.
+    default_stub_used: OptionalRef<AtomicBool>,
+
+    calls: SharedPtr<CallLog<C, R>>,
+    // The wakers of the futures returned by `await_call`, woken by each call
+    #[cfg(feature = "std")]
+    call_wakers: Ref<Vec<Waker>>,
+    // Blocks calls, after they're recorded, until it's opened
+    #[cfg(feature = "std")]
+    gate: OptionalRef<Gate>,
+    // Counted for every call, whether or not the call log records calls
+    // Replaced rather than modified when a counter is added, so calls only
+    // need to clone the pointer
+    counters: Ref<SharedPtr<Counters<C>>>,
+    // Indices of calls matched by a successful verification or expectation
+    verified_calls: Ref<HashSet<usize>>,
+    // The values returned by `call_ref`, which are borrowed until the last
+    // clone of the mock is dropped, so they're never removed
+    kept_return_values: Ref<Vec<Box<R>>>,
 }
 
 impl<C, R> Mock<C, R>
@@ -42,21 +119,86 @@ impl<C, R> Mock<C, R>
           R: Clone
 {
     /// Creates a new `Mock` that will return `return_value`.
+    ///
+    /// The `Mock` uses the policy that is active on the current thread. See
+    /// `double::set_policy`. If a mock registry is active on the current
+    /// thread, the `Mock` registers with it. See `double::register_mocks`.
     pub fn new<T: Into<R>>(return_value: T) -> Self {
-        Mock {
-            default_return_value: Ref::new(RefCell::new(return_value.into())),
-            return_value_sequence: Ref::new(RefCell::new(Vec::new())),
-            default_fn: OptionalRef::new(RefCell::new(None)),
-            default_closure: OptionalRef::new(RefCell::new(None)),
-            return_values: Ref::new(RefCell::new(HashMap::new())),
-            fns: Ref::new(RefCell::new(HashMap::new())),
-            closures: Ref::new(RefCell::new(HashMap::new())),
-            calls: Ref::new(RefCell::new(vec![])),
-        }
+        Mock::with_return_value(Some(return_value.into()))
+    }
+
+    // Creates the mock that records the calls of a `spy_trait!` spy, which
+    // doesn't need a return value.
+    #[doc(hidden)]
+    pub fn __private_for_spy() -> Self {
+        Mock::with_return_value(None)
+    }
+
+    fn with_return_value(return_value: Option<R>) -> Self {
+        let mock = Mock {
+            initial_return_value: OptionalRef::new(return_value.clone()),
+            default_return_value: OptionalRef::new(return_value),
+            return_value_sequence: Ref::new(Vec::new()),
+            default_fn: OptionalRef::new(None),
+            default_closure: OptionalRef::new(None),
+            return_values: Ref::new(HashMap::new()),
+            fns: Ref::new(HashMap::new()),
+            closures: Ref::new(HashMap::new()),
+            expectations: Ref::new(vec![]),
+            expectation_statuses: Ref::new(vec![]),
+            default_configured: Ref::new(false),
+            policy: Ref::new(policy::active_policy()),
+            args_formatter: OptionalRef::new(None),
+            name: OptionalRef::new(None),
+            debug_args: OptionalRef::new(None),
+            unused_stubs: Ref::new(UnusedStubs::Ignore),
+            default_stub_used: OptionalRef::new(None),
+            calls: SharedPtr::new(CallLog::new()),
+            #[cfg(feature = "std")]
+            call_wakers: Ref::new(Vec::new()),
+            #[cfg(feature = "std")]
+            gate: OptionalRef::new(None),
+            counters: Ref::new(SharedPtr::new(Vec::new())),
+            verified_calls: Ref::new(HashSet::new()),
+            kept_return_values: Ref::new(Vec::new()),
+        };
+        #[cfg(feature = "std")]
+        registry::register(RegisteredMock {
+            description: mock.describe(),
+            name: mock.name.clone(),
+            expectations: mock.expectation_statuses.clone(),
+            num_calls: mock.calls.call_counter(),
+            verified_calls: mock.verified_calls.clone(),
+        });
+        mock
+    }
+
+    /// Returns a `MockBuilder`, which declares the entire behaviour of a new
+    /// `Mock` in a single expression.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<&str, i32>::builder()
+    ///     .default_return(-1)
+    ///     .return_value_for("one", 1)
+    ///     .return_value_for("two", 2)
+    ///     .build();
+    ///
+    /// assert_eq!(mock.call("two"), 2);
+    /// assert_eq!(mock.call("three"), -1);
+    /// ```
+    pub fn builder() -> MockBuilder<C, R> {
+        MockBuilder::new()
     }
 
     /// Use the `Mock` to return a value, keeping track of the arguments used.
     ///
+    /// If the arguments match an expectation (see `Mock::expect`) that has a
+    /// return value, that value is returned.
+    ///
     /// If specific behaviour has been configured for a specific set of
     /// arguments, this will return (in this order of precedence):
     ///     1. the return value returned by the configured closure
@@ -69,6 +211,10 @@ impl<C, R> Mock<C, R>
     ///     3. next return value in default sequence (if sequence is not empty)
     ///     4. the default return value (always configured)
     ///
+    /// If the call matches no expectation and no configured behaviour, it is
+    /// handled according to the `Mock`'s `MockPolicy`. `Strict` mocks panic on
+    /// such calls.
+    ///
     /// # Examples
     ///
     /// ```
@@ -103,28 +249,127 @@ impl<C, R> Mock<C, R>
     /// mock.use_closure_for("  banana  ", Box::new(|x| x.trim_left()));
     /// assert_eq!(mock.call("  banana  "), "banana  ");
     /// ```
+    #[track_caller]
     pub fn call(&self, args: C) -> R {
-        self.calls.borrow_mut().push(args.clone());
-
-        if let Some(ref closure) = self.closures.borrow().get(&args) {
-            return closure(args)
-        } else if let Some(ref function) = self.fns.borrow().get(&args) {
-            return function(args)
-        } else if let Some(return_value) = self.return_values.borrow().get(&args) {
-            return return_value.clone()
-        } else if let Some(ref default_fn) = *self.default_fn.borrow() {
-            return default_fn(args);
-        } else if let Some(ref default_closure) = *self.default_closure.borrow() {
-            return default_closure(args);
-        } else {
-            // If there are no return values in the value sequence left, fall
-            // back to the configured default value.
-            let ref mut sequence = *self.return_value_sequence.borrow_mut();
-            match sequence.pop() {
-                Some(return_value) => return_value,
-                None => self.default_return_value.borrow().clone()
-            }
+        let location = Location::caller();
+        let call_index = self.record_call(&args, location);
+
+        self.trace_call(call_index, &args, location);
+        #[cfg(feature = "std")]
+        self.wait_for_gate();
+        let return_value = self.respond(call_index, args, location);
+        self.calls.complete(call_index, &return_value);
+        return_value
+    }
+
+    /// Call the mock like `call`, but return a reference to the return value,
+    /// which the mock keeps until it's dropped. Use this to mock methods that
+    /// return references to data owned by `self`, such as
+    /// `fn name(&self) -> &str`, using a mock that returns the owned data
+    /// (e.g. a `String`).
+    ///
+    /// Each call keeps its return value until the last clone of the mock is
+    /// dropped, since the references returned by earlier calls may still be
+    /// in use. `reset` and `reset_calls` don't free them either, so a mock
+    /// that lives for a whole test and is called in a loop uses more memory
+    /// with every call.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// struct MockUser {
+    ///     name: Mock<(), String>,
+    /// }
+    ///
+    /// impl MockUser {
+    ///     fn name(&self) -> &str {
+    ///         self.name.call_ref(())
+    ///     }
+    /// }
+    ///
+    /// let user = MockUser { name: Mock::new("Ferris".to_owned()) };
+    /// assert_eq!(user.name(), "Ferris");
+    /// assert!(user.name.called());
+    /// ```
+    #[track_caller]
+    pub fn call_ref(&self, args: C) -> &R {
+        let return_value = Box::new(self.call(args));
+        let mut kept = self.kept_return_values.borrow_mut();
+        kept.push(return_value);
+        let value: *const R = &**kept.last().unwrap();
+        // SAFETY: the value is boxed, so it isn't moved when more values are
+        // kept, and it's never removed or mutated, so it lives as long as the
+        // last clone of the mock, which outlives `self`.
+        unsafe { &*value }
+    }
+
+    // Records a call of a `spy_trait!` spy, whose return value comes from
+    // `delegate` (the call of the real implementation) rather than the mock's
+    // behaviours. The call still counts as a match of the first expectation
+    // it matches, so expectations can be verified as usual.
+    #[doc(hidden)]
+    #[track_caller]
+    pub fn __private_spy<F: FnOnce() -> R>(&self, args: C, delegate: F) -> R {
+        let location = Location::caller();
+        let call_index = self.record_call(&args, location);
+
+        self.trace_call(call_index, &args, location);
+        #[cfg(feature = "std")]
+        self.wait_for_gate();
+        if let Some(expectation) = self.match_expectation(&args) {
+            self.verified_calls.borrow_mut().insert(call_index);
+            expectation.record_match();
         }
+        let return_value = delegate();
+        self.calls.complete(call_index, &return_value);
+        return_value
+    }
+
+    /// Returns a closure that calls the `Mock`, for code under test that
+    /// takes an `Fn` callback. The closure owns a clone of the mock, so the
+    /// calls it makes can be checked using the original.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// fn apply_all<F: Fn(i32) -> i32>(values: &[i32], callback: F) -> Vec<i32> {
+    ///     values.iter().map(|&value| callback(value)).collect()
+    /// }
+    ///
+    /// let mock = Mock::<i32, i32>::default();
+    /// mock.use_closure(Box::new(|x| x * 10));
+    ///
+    /// assert_eq!(apply_all(&[1, 2], mock.as_fn()), vec!(10, 20));
+    /// assert!(mock.has_calls_exactly_in_order(vec!(1, 2)));
+    /// ```
+    pub fn as_fn(&self) -> impl Fn(C) -> R {
+        let mock = self.clone();
+        move |args| mock.call(args)
+    }
+
+    /// Returns a closure that calls the `Mock`, for code under test that
+    /// takes an `FnMut` callback. See `Mock::as_fn`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// fn for_each_word<F: FnMut(&'static str)>(text: &'static str, mut callback: F) {
+    ///     text.split(' ').for_each(|word| callback(word));
+    /// }
+    ///
+    /// let mock = Mock::<&str, ()>::default();
+    /// for_each_word("hello mock world", mock.as_fn_mut());
+    ///
+    /// assert_eq!(mock.calls(), vec!("hello", "mock", "world"));
+    /// ```
+    pub fn as_fn_mut(&self) -> impl FnMut(C) -> R {
+        self.as_fn()
     }
 
     /// Override the default return value.
@@ -140,7 +385,15 @@ impl<C, R> Mock<C, R>
     /// assert_eq!(mock.call("something"), "new value");
     /// ```
     pub fn return_value<T: Into<R>>(&self, value: T) {
-        *self.default_return_value.borrow_mut() = value.into();
+        self.configure_default_stub();
+        *self.default_return_value.borrow_mut() = Some(value.into());
+    }
+
+    // Records that a default behaviour was configured after construction, so
+    // calls it handles aren't unexpected and it's reported if it's never used.
+    pub(crate) fn configure_default_stub(&self) {
+        *self.default_configured.borrow_mut() = true;
+        *self.default_stub_used.borrow_mut() = Some(AtomicBool::new(false));
     }
 
     /// Provide a sequence of default return values. The specified are returned
@@ -160,6 +413,7 @@ impl<C, R> Mock<C, R>
     /// assert_eq!(mock.call("farewell"), "default");
     /// ```
     pub fn return_values<T: Into<R>>(&self, values: Vec<T>) {
+        *self.default_configured.borrow_mut() = true;
         // Reverse so efficient back pop() can be used to extract  the next
         // value in the sequence
         *self.return_value_sequence.borrow_mut() = values
@@ -186,7 +440,7 @@ impl<C, R> Mock<C, R>
     pub fn return_value_for<S: Into<C>, T: Into<R>>(&self, args: S, return_value: T) {
         self.return_values.borrow_mut().insert(
             args.into(),
-            return_value.into());
+            Stub::new(return_value.into()));
     }
 
     /// Specify a function to determine the `Mock`'s return value based on
@@ -226,6 +480,7 @@ impl<C, R> Mock<C, R>
     /// assert_eq!(mock.call((1, 2, 3,)), 6);
     /// ```
     pub fn use_fn(&self, default_fn: fn(C) -> R) {
+        self.configure_default_stub();
         *self.default_closure.borrow_mut() = None;
         *self.default_fn.borrow_mut() = Some(default_fn)
     }
@@ -270,7 +525,7 @@ impl<C, R> Mock<C, R>
     /// assert_eq!(mock.call((1, 2, 3)), 6);
     /// ```
     pub fn use_fn_for<T: Into<C>>(&self, args: T, function: fn(C) -> R) {
-        self.fns.borrow_mut().insert(args.into(), function);
+        self.fns.borrow_mut().insert(args.into(), Stub::new(function));
     }
 
     /// Specify a closure to determine the `Mock`'s return value based on
@@ -303,9 +558,10 @@ impl<C, R> Mock<C, R>
     /// assert_eq!(mock.call((1, 1, 1)), 3);
     /// assert_eq!(mock.call((1, 2, 3,)), 6);
     /// ```
-    pub fn use_closure(&self, default_fn: Box<dyn Fn(C) -> R>) {
+    pub fn use_closure(&self, default_fn: Closure<C, R>) {
+        self.configure_default_stub();
         *self.default_fn.borrow_mut() = None;
-        *self.default_closure.borrow_mut() = Some(default_fn)
+        *self.default_closure.borrow_mut() = Some(SharedPtr::new(default_fn))
     }
 
     /// Specify a closure to determine the `Mock`'s return value based on
@@ -341,8 +597,8 @@ impl<C, R> Mock<C, R>
     /// assert_eq!(mock.call((1, 1, 1)), 42);
     /// assert_eq!(mock.call((1, 2, 3)), 6);
     /// ```
-    pub fn use_closure_for<T: Into<C>>(&self, args: T, function: Box<dyn Fn(C) -> R>) {
-        self.closures.borrow_mut().insert(args.into(), function);
+    pub fn use_closure_for<T: Into<C>>(&self, args: T, function: Closure<C, R>) {
+        self.closures.borrow_mut().insert(args.into(), Stub::new(SharedPtr::new(function)));
     }
 
     /// Returns true if `Mock::call` has been called.
@@ -357,7 +613,7 @@ impl<C, R> Mock<C, R>
     /// assert!(mock.called());
     /// ```
     pub fn called(&self) -> bool {
-        !self.calls.borrow().is_empty()
+        self.num_calls() > 0
     }
 
     /// Returns the number of times `Mock::call` has been called.
@@ -376,11 +632,156 @@ impl<C, R> Mock<C, R>
     /// assert_eq!(mock.num_calls(), 2);
     /// ```
     pub fn num_calls(&self) -> usize {
-        self.calls.borrow().len()
+        self.calls.num_calls()
+    }
+
+    /// Returns the number of times `Mock::call` has been called. Equivalent to
+    /// `Mock::num_calls`.
+    pub fn call_count(&self) -> usize {
+        self.num_calls()
+    }
+
+    /// Assert that `Mock::call` has been called exactly `n` times.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a message containing the actual number of calls if the
+    /// `Mock` was not called exactly `n` times.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i64, ()>::default();
+    /// mock.call(1);
+    /// mock.call(2);
+    ///
+    /// mock.called_exactly(2);
+    /// ```
+    ///
+    /// ```should_panic
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i64, ()>::default();
+    /// mock.call(1);
+    ///
+    /// // panics: "expected Mock to be called exactly 2 times, but it was
+    /// // called 1 time"
+    /// mock.called_exactly(2);
+    /// ```
+    pub fn called_exactly(&self, n: usize) {
+        self.assert_called(Times::exactly(n))
+    }
+
+    /// Assert that `Mock::call` has been called `n` or more times.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a message containing the actual number of calls if the
+    /// `Mock` was called fewer than `n` times.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i64, ()>::default();
+    /// mock.call(1);
+    /// mock.call(2);
+    ///
+    /// mock.called_at_least(1);
+    /// mock.called_at_least(2);
+    /// ```
+    pub fn called_at_least(&self, n: usize) {
+        self.assert_called(Times::at_least(n))
+    }
+
+    /// Assert that `Mock::call` has been called `n` or fewer times.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a message containing the actual number of calls if the
+    /// `Mock` was called more than `n` times.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i64, ()>::default();
+    /// mock.call(1);
+    ///
+    /// mock.called_at_most(1);
+    /// mock.called_at_most(5);
+    /// ```
+    pub fn called_at_most(&self, n: usize) {
+        self.assert_called(Times::at_most(n))
+    }
+
+    /// Assert that the number of times `Mock::call` has been called is within
+    /// `times`. `times` can be a `usize`, any range of `usize` or a `Times`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a message containing the actual number of calls if the
+    /// number of calls is outside `times`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::{Mock, Times};
+    ///
+    /// let mock = Mock::<i64, ()>::default();
+    /// mock.call(1);
+    /// mock.call(2);
+    /// mock.call(3);
+    ///
+    /// mock.called_times(3);
+    /// mock.called_times(2..=5);
+    /// mock.called_times(..4);
+    /// mock.called_times(1..);
+    /// mock.called_times(Times::at_least(3));
+    /// ```
+    ///
+    /// ```should_panic
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i64, ()>::default();
+    /// mock.call(1);
+    ///
+    /// // panics: "expected Mock to be called between 2 and 5 times, but it
+    /// // was called 1 time"
+    /// mock.called_times(2..=5);
+    /// ```
+    pub fn called_times<T: Into<Times>>(&self, times: T) {
+        self.assert_called(times.into())
+    }
+
+    /// Assert that `Mock::call` has never been called.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a message containing the actual number of calls if the
+    /// `Mock` was called.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i64, ()>::default();
+    /// mock.never_called();
+    /// ```
+    pub fn never_called(&self) {
+        self.assert_called(Times::never())
     }
 
     /// Returns the arguments to `Mock::call` in order from first to last.
     ///
+    /// Only calls that are still in the call log are returned (see
+    /// `Mock::set_call_log_capacity`).
+    ///
     /// # Examples
     ///
     /// ```
@@ -395,10 +796,12 @@ impl<C, R> Mock<C, R>
     /// assert_eq!(mock.calls().as_slice(), ["first", "second", "third"]);
     /// ```
     pub fn calls(&self) -> Vec<C> {
-        self.calls.borrow().clone()
+        self.calls.indexed_args().into_iter().map(|(_, args)| args).collect()
     }
 
-    /// Reset the call history for the `Mock`.
+    /// Returns the arguments of the `n`th call to `Mock::call` (starting from
+    /// zero), or `None` if there are `n` or fewer calls or the call has been
+    /// dropped from the call log (see `Mock::set_call_log_capacity`).
     ///
     /// # Examples
     ///
@@ -410,170 +813,1297 @@ impl<C, R> Mock<C, R>
     /// mock.call("first");
     /// mock.call("second");
     ///
-    /// assert!(mock.called());
-    /// assert_eq!(mock.num_calls(), 2);
-    /// assert!(mock.called_with("first"));
-    /// assert!(mock.called_with("second"));
-    ///
-    /// mock.reset_calls();
-    ///
-    /// assert!(!mock.called());
-    /// assert_eq!(mock.num_calls(), 0);
-    /// assert!(!mock.called_with("first"));
-    /// assert!(!mock.called_with("second"));
+    /// assert_eq!(mock.nth_call(1), Some("second"));
+    /// assert_eq!(mock.nth_call(2), None);
     /// ```
-    pub fn reset_calls(&self) {
-        self.calls.borrow_mut().clear()
+    pub fn nth_call(&self, n: usize) -> Option<C> {
+        self.calls.get(n).map(CallRecord::into_args)
     }
-}
 
-impl<C, R> Default for Mock<C, R>
-    where C: Clone + Eq + Hash,
-          R: Clone + Default
-{
-    /// Use `R::default()` as the initial return value.
+    /// Returns the arguments of the first call to `Mock::call`, or `None` if
+    /// there have been no calls.
     ///
     /// # Examples
     ///
     /// ```
     /// use double::Mock;
     ///
-    /// let mock = Mock::<i64, i64>::default();
-    /// assert_eq!(mock.call(10), 0);
-    ///
-    /// let mock = Mock::<(), String>::default();
-    /// assert_eq!(&mock.call(()), "");
+    /// let mock = Mock::<&str, &str>::default();
+    /// assert_eq!(mock.first_call(), None);
     ///
-    /// let mock = Mock::<(i64, &str), Option<bool>>::default();
-    /// assert_eq!(mock.call((10, "test")), None);
+    /// mock.call("first");
+    /// mock.call("second");
+    /// assert_eq!(mock.first_call(), Some("first"));
     /// ```
-    fn default() -> Self {
-        Self::new(R::default())
+    pub fn first_call(&self) -> Option<C> {
+        self.nth_call(0)
     }
-}
 
-impl<C, R> Mock<C, R>
-    where C: Clone + Debug + Eq + Hash,
-          R: Clone
-{
-    // ========================================================================
-    // * Exact Argument Checks
-    // ========================================================================
-
-    /// Returns true if the specified argument has been used for `Mock::call`.
+    /// Returns the arguments of the most recent call to `Mock::call`, or
+    /// `None` if there have been no calls.
     ///
     /// # Examples
     ///
     /// ```
     /// use double::Mock;
     ///
-    /// let mock = Mock::<&str, ()>::new(());
-    /// mock.call("foo");
-    /// mock.call("bar");
+    /// let mock = Mock::<&str, &str>::default();
+    /// assert_eq!(mock.last_call(), None);
     ///
-    /// assert!(mock.called_with("foo"));
-    /// assert!(mock.called_with("bar"));
-    /// assert!(!mock.called_with("baz"));
+    /// mock.call("first");
+    /// mock.call("second");
+    /// assert_eq!(mock.last_call(), Some("second"));
     /// ```
-    pub fn called_with<T: Into<C>>(&self, args: T) -> bool {
-        let expected_calls: Vec<T> = vec!(args);
-        self.get_match_info(expected_calls).expectations_matched()
+    pub fn last_call(&self) -> Option<C> {
+        self.calls.last().map(CallRecord::into_args)
     }
 
-    /// Returns true if `Mock::call` has been called with all of the specified
-    /// `calls`. The calls can be made in any order.  They don't have to be in
-    /// the order specified by `calls`.
+    /// Returns the `CallRecord` of every call to `Mock::call`, in order from
+    /// first to last.
     ///
     /// # Examples
     ///
     /// ```
     /// use double::Mock;
     ///
-    /// let mock = Mock::<&str, ()>::new(());
-    /// mock.call("foo");
-    /// mock.call("bar");
+    /// let mock = Mock::<&str, &str>::default();
+    /// mock.call("first");
+    /// mock.return_value("returned");
+    /// mock.call("second");
     ///
-    /// let expected_calls1 = vec!("foo", "bar");
-    /// assert!(mock.has_calls(expected_calls1));
-    /// let expected_calls2 = vec!("bar", "foo");
-    /// assert!(mock.has_calls(expected_calls2));
-    /// let expected_calls3 = vec!("foo");
-    /// assert!(mock.has_calls(expected_calls3));
-    /// let expected_calls4 = vec!("not_in_calls");
-    /// assert!(!mock.has_calls(expected_calls4));
-    /// let expected_calls5 = vec!("foo", "not_in_calls");
-    /// assert!(!mock.has_calls(expected_calls5));
+    /// let record = mock.records().last().unwrap();
+    /// assert_eq!(record.index(), 1);
+    /// assert_eq!(*record.args(), "second");
+    /// assert_eq!(record.return_value(), Some(&"returned"));
+    /// assert_eq!(record.to_string(), r#"called with "second", returned "returned""#);
+    ///
+    /// assert_eq!(mock.records().return_values(), vec!("", "returned"));
     /// ```
-    pub fn has_calls<T: Into<C>>(&self, calls: Vec<T>) -> bool {
-        self.get_match_info(calls).expectations_matched()
+    pub fn records(&self) -> CallRecords<C, R> {
+        CallRecords::new(self.calls.records())
     }
 
-    /// Returns true if `Mock::call` has been called with all of the specified
-    /// `calls`. The `calls` must be made in the order they are specified in
-    /// the vector.
+    /// Returns the `CallRecord` of every call whose arguments match
+    /// `pattern`, in order from first to last.
     ///
     /// # Examples
     ///
     /// ```
+    /// # #[macro_use] extern crate double;
     /// use double::Mock;
-    ///
-    /// let mock = Mock::<(i32, i32), ()>::new(());
-    /// mock.call((42, 0));
-    /// mock.call((42, 1));
-    /// mock.call((42, 0));  // called with same args as first call!
-    ///
-    /// assert!(mock.has_calls_in_order(vec!( (42, 0) )));
-    /// assert!(mock.has_calls_in_order(vec!( (42, 1) )));
-    /// assert!(mock.has_calls_in_order(vec!( (42, 0), (42, 1) )));
-    /// assert!(mock.has_calls_in_order(vec!( (42, 1), (42, 0) )));
-    /// assert!(mock.has_calls_in_order(vec!( (42, 0), (42, 1), (42, 0) )));
-    /// assert!(!mock.has_calls_in_order(vec!( (42, 0), (42, 0), (42, 1) )));
-    /// assert!(!mock.has_calls_in_order(vec!( (84, 0) )));
-    /// assert!(!mock.has_calls_in_order(vec!( (42, 0), (84, 0) )));
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<(i32, i32), ()>::default();
+    /// mock.call((1, 2));
+    /// mock.call((3, 4));
+    /// mock.call((5, 6));
+    ///
+    /// let matching = mock.calls_matching(matcher!(p!(gt, 2), p!(any)));
+    /// assert_eq!(matching.len(), 2);
+    /// assert_eq!(matching.args(), vec!((3, 4), (5, 6)));
+    /// # }
     /// ```
-    pub fn has_calls_in_order<T: Into<C>>(&self, calls: Vec<T>) -> bool {
-        self.get_match_info(calls).expectations_matched_in_order()
+    pub fn calls_matching(&self, pattern: &dyn Fn(&C) -> bool) -> CallRecords<C, R> {
+        CallRecords::new(
+            self.calls.records().into_iter().filter(|record| pattern(record.args())).collect())
     }
 
-    /// Returns true if `Mock::call` has been called with all of the specified
-    /// `calls` and it has not been called any other times. The calls can be
-    /// made in any order. They don't have to be in the order specified by
-    /// `calls`.
+    /// Returns the number of calls whose arguments match `pattern`.
     ///
     /// # Examples
     ///
     /// ```
+    /// # #[macro_use] extern crate double;
     /// use double::Mock;
+    /// use double::matcher::*;
     ///
-    /// let mock = Mock::<(i32, i32), ()>::new(());
-    /// mock.call((42, 0));
-    /// mock.call((42, 1));
-    /// mock.call((42, 0));
+    /// # fn main() {
+    /// let mock = Mock::<Result<i32, String>, ()>::default();
+    /// mock.call(Err("timeout".to_owned()));
+    /// mock.call(Err("timeout".to_owned()));
+    /// mock.call(Ok(200));
     ///
-    /// assert!(!mock.has_calls_exactly(vec!( (42, 0) )));
-    /// assert!(!mock.has_calls_exactly(vec!( (42, 1) )));
-    /// assert!(!mock.has_calls_exactly(vec!( (84, 0) )));
-    /// assert!(!mock.has_calls_exactly(vec!( (42, 0), (42, 1) )));
-    /// assert!(!mock.has_calls_exactly(vec!( (42, 1), (42, 0) )));
-    /// assert!(mock.has_calls_exactly(vec!( (42, 0), (42, 0), (42, 1) )));
-    /// assert!(mock.has_calls_exactly(vec!( (42, 0), (42, 1), (42, 0) )));
-    /// assert!(!mock.has_calls_exactly(vec!( (42, 0), (42, 1), (84, 0) )));
+    /// assert_eq!(mock.num_calls_with(p!(is_err, p!(any))), 2);
+    /// assert_eq!(mock.num_calls_with(p!(is_ok, p!(eq, 404))), 0);
+    /// # }
     /// ```
-    pub fn has_calls_exactly<T: Into<C>>(&self, calls: Vec<T>) -> bool {
-        self.get_match_info(calls).expectations_matched_exactly()
+    pub fn num_calls_with(&self, pattern: &dyn Fn(&C) -> bool) -> usize {
+        self.calls.indexed_args().iter().filter(|&(_, args)| pattern(args)).count()
     }
 
-    /// Returns true if `Mock::call` has been called with all of the specified
-    /// `calls` and it has not been called any other times. The calls must be
-    /// made in the order they are specified in `calls`.
+    /// Returns the arguments of every call whose arguments match `pattern`,
+    /// in order from first to last.
+    ///
+    /// This allows complex arguments to be checked using regular assertions.
     ///
     /// # Examples
     ///
     /// ```
+    /// # #[macro_use] extern crate double;
     /// use double::Mock;
+    /// use double::matcher::*;
     ///
-    /// let mock = Mock::<&str, ()>::new(());
-    /// mock.call("foo");
+    /// # fn main() {
+    /// let mock = Mock::<(String, Vec<u8>), ()>::default();
+    /// mock.call(("header".to_owned(), vec!(1, 2)));
+    /// mock.call(("body".to_owned(), vec!(3, 4, 5)));
+    ///
+    /// let bodies = mock.capture_args(matcher!(p!(eq, "body".to_owned()), p!(any)));
+    /// assert_eq!(bodies.len(), 1);
+    /// assert_eq!(bodies[0].1.len(), 3);
+    /// # }
+    /// ```
+    pub fn capture_args(&self, pattern: &dyn Fn(&C) -> bool) -> Vec<C> {
+        self.calls_matching(pattern).args()
+    }
+
+    /// Block until a call whose arguments match `pattern` has been recorded,
+    /// or `timeout` has elapsed. Returns true if a matching call was
+    /// recorded, including calls recorded before `wait_for_call` was called.
+    ///
+    /// Use this to synchronise with code under test that calls the mock
+    /// from another thread (which requires the `sync` feature), before
+    /// verifying the mock. Requires the `std` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use std::time::Duration;
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<i32, ()>::default();
+    /// mock.call(1);
+    ///
+    /// assert!(mock.wait_for_call(p!(eq, 1), Duration::from_secs(1)));
+    /// assert!(!mock.wait_for_call(p!(eq, 2), Duration::from_millis(10)));
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn wait_for_call(&self, pattern: &dyn Fn(&C) -> bool, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.num_calls_with(pattern) > 0 {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    /// Returns a future that resolves to the args of the next call whose
+    /// arguments match `pattern`. Unlike `Mock::wait_for_call`, calls made
+    /// before `await_call` was called don't count.
+    ///
+    /// Use this to wait for the code under test to call the mock from async
+    /// tests, e.g. by joining the future with the code under test, instead
+    /// of sleeping and checking the recorded calls. Requires the `std`
+    /// feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use std::future::Future;
+    /// use std::pin::Pin;
+    /// use std::task::{Context, Poll, Waker};
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<i32, ()>::default();
+    /// mock.call(2);
+    ///
+    /// let mut call = mock.await_call(p!(gt, 1));
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// assert_eq!(Poll::Pending, Pin::new(&mut call).poll(&mut cx));
+    ///
+    /// mock.call(1);
+    /// mock.call(3);
+    /// assert_eq!(Poll::Ready(3), Pin::new(&mut call).poll(&mut cx));
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn await_call(&self, pattern: StaticPattern<C>) -> CallFuture<C, R> {
+        let wakers = self.call_wakers.clone();
+        CallFuture::new(self.calls.clone(), wakers, Box::new(move |args| pattern(args)))
+    }
+
+    /// Make subsequent calls block until the returned gate is opened, or
+    /// lets them through one at a time (see `Gate::release_one`). The calls
+    /// are recorded before they block, so the test can inspect their args
+    /// using `Mock::in_flight_calls` before letting them through. This makes
+    /// it possible to test specific interleavings of code under test that
+    /// calls the mock from several threads (which requires the `sync`
+    /// feature).
+    ///
+    /// For async mocks, whose calls mustn't block, use
+    /// `Mock::returns_when_opened` or `AsyncMock::hold_until_opened` instead.
+    /// Gates require the `std` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i32, i32>::new(1);
+    /// let gate = mock.gate();
+    /// gate.open();
+    /// assert_eq!(mock.call(7), 1);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn gate(&self) -> Gate {
+        let gate = Gate::new();
+        self.hold_until_opened(&gate);
+        gate
+    }
+
+    /// Make subsequent calls block until `gate` is opened. Like `Mock::gate`,
+    /// but the gate can be shared with other mocks.
+    #[cfg(feature = "std")]
+    pub fn hold_until_opened(&self, gate: &Gate) {
+        *self.gate.borrow_mut() = Some(gate.clone());
+    }
+
+    /// Returns the args of the calls that haven't returned yet, e.g. because
+    /// they're blocked by the mock's gate (see `Mock::gate`), in the order
+    /// they were made.
+    pub fn in_flight_calls(&self) -> Vec<C> {
+        self.calls
+            .records()
+            .into_iter()
+            .filter(|record| record.return_value().is_none())
+            .map(CallRecord::into_args)
+            .collect()
+    }
+
+    /// Reset the call history for the `Mock`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<&str, &str>::default();
+    ///
+    /// mock.call("first");
+    /// mock.call("second");
+    ///
+    /// assert!(mock.called());
+    /// assert_eq!(mock.num_calls(), 2);
+    /// assert!(mock.called_with("first"));
+    /// assert!(mock.called_with("second"));
+    ///
+    /// mock.reset_calls();
+    ///
+    /// assert!(!mock.called());
+    /// assert_eq!(mock.num_calls(), 0);
+    /// assert!(!mock.called_with("first"));
+    /// assert!(!mock.called_with("second"));
+    /// ```
+    pub fn reset_calls(&self) {
+        self.calls.clear();
+        for (_, counter) in self.counters.borrow().iter() {
+            counter.reset();
+        }
+        self.verified_calls.borrow_mut().clear()
+    }
+
+    /// Reset the `Mock` to the state it was in when it was constructed.
+    ///
+    /// This clears the recorded calls (like `Mock::reset_calls`), all
+    /// configured return values, return value sequences, functions and
+    /// closures, the mock's gate, and all expectations. The mock goes back to
+    /// returning the default return value it was constructed with. The mock's
+    /// policy, call log capacity and counters (see `Mock::count_calls_with`)
+    /// are kept, as is whether it only counts calls.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<&str, &str>::new("original");
+    /// mock.return_value("new");
+    /// mock.return_value_for("banana", "tasty");
+    /// mock.expect(p!(eq, "apple"));
+    /// mock.call("banana");
+    ///
+    /// mock.reset();
+    ///
+    /// assert!(!mock.called());
+    /// assert!(mock.verify());
+    /// assert_eq!(mock.call("something"), "original");
+    /// assert_eq!(mock.call("banana"), "original");
+    /// # }
+    /// ```
+    pub fn reset(&self) {
+        *self.default_return_value.borrow_mut() = self.initial_return_value.borrow().clone();
+        self.return_value_sequence.borrow_mut().clear();
+        *self.default_fn.borrow_mut() = None;
+        *self.default_closure.borrow_mut() = None;
+        self.return_values.borrow_mut().clear();
+        self.fns.borrow_mut().clear();
+        self.closures.borrow_mut().clear();
+        #[cfg(feature = "std")]
+        {
+            *self.gate.borrow_mut() = None;
+        }
+        self.clear_expectations();
+        *self.default_configured.borrow_mut() = false;
+        *self.default_stub_used.borrow_mut() = None;
+        self.reset_calls();
+    }
+
+    /// Only keep the `capacity` most recent calls in the call log. Older calls
+    /// are dropped, but still counted by `Mock::num_calls`. `None` (the
+    /// default) keeps every call.
+    ///
+    /// This caps the memory used by mocks that receive a very large number of
+    /// calls. Checks on the args of calls, such as `called_with` and
+    /// `has_calls`, only consider the calls that are still in the log.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i32, ()>::default();
+    /// mock.set_call_log_capacity(Some(2));
+    /// for i in 0..1000 {
+    ///     mock.call(i);
+    /// }
+    ///
+    /// assert_eq!(mock.num_calls(), 1000);
+    /// assert_eq!(mock.num_dropped_calls(), 998);
+    /// assert_eq!(mock.calls(), vec!(998, 999));
+    /// assert_eq!(mock.nth_call(999), Some(999));
+    /// assert_eq!(mock.nth_call(0), None);
+    /// ```
+    pub fn set_call_log_capacity(&self, capacity: Option<usize>) {
+        let dropped = self.calls.set_capacity(capacity);
+        self.forget_verified(dropped);
+    }
+
+    /// Only count the mock's calls, instead of recording them in the call
+    /// log, if `counting_only` is true. Use this in performance-sensitive
+    /// tests, where cloning and storing the args and return value of every
+    /// call would perturb the code under test.
+    ///
+    /// Calls that are only counted are still handled by the mock's
+    /// behaviours and expectations, and counted by `Mock::num_calls` and
+    /// the counters registered using `Mock::count_calls_with`. Checks on the
+    /// args of calls, such as `called_with` and `has_calls`, don't see them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i32, ()>::default();
+    /// mock.set_counting_only(true);
+    /// let evens = mock.count_calls_with(&|&x| x % 2 == 0);
+    /// for i in 0..1000 {
+    ///     mock.call(i);
+    /// }
+    ///
+    /// assert_eq!(mock.num_calls(), 1000);
+    /// assert_eq!(evens.count(), 500);
+    /// assert!(mock.calls().is_empty());
+    /// ```
+    pub fn set_counting_only(&self, counting_only: bool) {
+        self.calls.set_recording(!counting_only);
+    }
+
+    /// Record how long the mock's closures and functions (see
+    /// `Mock::use_closure` and `Mock::use_fn`) run for, if
+    /// `recording_durations` is true. Only the closure or function is timed,
+    /// not the time a call spends waiting for the mock's gate. Use this with
+    /// closures that simulate slow dependencies, to check whether the code
+    /// under test overlapped its calls or made them one after the other. See
+    /// `CallRecord::duration` and `CallRecord::overlaps`. Requires the `std`
+    /// feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use double::Mock;
+    ///
+    /// let fetch = Mock::<i32, ()>::default();
+    /// fetch.set_recording_durations(true);
+    /// fetch.use_closure(Box::new(|_| thread::sleep(Duration::from_millis(10))));
+    /// fetch.call(1);
+    /// fetch.call(2);
+    ///
+    /// let records: Vec<_> = fetch.records().collect();
+    /// assert!(!records[0].overlaps(&records[1]));  // fetched one at a time
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn set_recording_durations(&self, recording_durations: bool) {
+        self.calls.set_recording_durations(recording_durations);
+    }
+
+    /// Count the calls whose args match `pattern` from now on, whether or
+    /// not the mock records its calls. Returns the counter, which counts
+    /// the calls without keeping their args, so it's cheap to use with
+    /// `Mock::set_counting_only`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<&str, ()>::default();
+    /// let misses = mock.count_calls_with(p!(eq, "miss"));
+    /// mock.call("hit");
+    /// mock.call("miss");
+    ///
+    /// assert_eq!(misses.count(), 1);
+    /// # }
+    /// ```
+    pub fn count_calls_with(&self, pattern: StaticPattern<C>) -> CallCounter {
+        let counter = CallCounter::new();
+        let mut counters = self.counters.borrow_mut();
+        let mut updated = (**counters).clone();
+        updated.push((SharedPtr::new(Box::new(move |args| pattern(args))), counter.clone()));
+        *counters = SharedPtr::new(updated);
+        counter
+    }
+
+    /// Returns the number of calls dropped from the call log because it was
+    /// full (see `Mock::set_call_log_capacity`).
+    pub fn num_dropped_calls(&self) -> usize {
+        self.calls.num_dropped()
+    }
+
+    /// Returns true if `self` and `other` are clones of the same mock, and so
+    /// share their configured behaviour and recorded calls.
+    ///
+    /// Cloning a mock is cheap, which allows the same mock to be injected
+    /// into several components and verified once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// struct Cache { store: Mock<&'static str, ()> }
+    /// struct Logger { store: Mock<&'static str, ()> }
+    ///
+    /// let store = Mock::<&str, ()>::default();
+    /// let cache = Cache { store: store.clone() };
+    /// let logger = Logger { store: store.clone() };
+    ///
+    /// cache.store.call("cached");
+    /// logger.store.call("logged");
+    ///
+    /// assert!(store.shares_state_with(&cache.store));
+    /// assert!(!store.shares_state_with(&Mock::default()));
+    /// assert!(store.has_calls_exactly_in_order(vec!("cached", "logged")));
+    /// ```
+    pub fn shares_state_with(&self, other: &Mock<C, R>) -> bool {
+        SharedPtr::ptr_eq(&self.calls, &other.calls)
+    }
+
+    /// Returns the policy used to handle unexpected calls.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::{Mock, MockPolicy};
+    ///
+    /// let mock = Mock::<i32, i32>::default();
+    /// assert_eq!(mock.policy(), MockPolicy::Nice);
+    /// ```
+    pub fn policy(&self) -> MockPolicy {
+        *self.policy.borrow()
+    }
+
+    /// Override the policy used to handle unexpected calls, regardless of the
+    /// policy that was active when the `Mock` was constructed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::{Mock, MockPolicy};
+    ///
+    /// let mock = Mock::<i32, i32>::default();
+    /// mock.set_policy(MockPolicy::Strict);
+    /// mock.return_value_for(1, 10);
+    ///
+    /// assert_eq!(mock.call(1), 10);
+    /// ```
+    ///
+    /// ```should_panic
+    /// use double::{Mock, MockPolicy};
+    ///
+    /// let mock = Mock::<i32, i32>::default();
+    /// mock.set_policy(MockPolicy::Strict);
+    /// mock.return_value_for(1, 10);
+    ///
+    /// mock.call(2);  // panics, no behaviour configured for `2`
+    /// ```
+    pub fn set_policy(&self, policy: MockPolicy) {
+        *self.policy.borrow_mut() = policy;
+    }
+
+    /// Format args in failure messages and reports using `formatter`, rather
+    /// than their `Debug` representation. This is useful for args whose
+    /// `Debug` output is too noisy to read.
+    ///
+    /// A formatter also lets failure messages that don't require args to
+    /// implement `Debug`, such as those printed by `Mock::verify` and those
+    /// for unexpected calls, include the args.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<(u32, i64), ()>::default();
+    /// mock.format_args_with(Box::new(|&(account, amount)| {
+    ///     format!("account {} by {}", account, amount)
+    /// }));
+    ///
+    /// mock.call((7, -20));
+    /// // prints "Closest call was #0 from src/main.rs:7:6 with args account
+    /// // 7 by -20: arg 1 should have been 20"
+    /// assert!(!mock.called_with((7, 20)));
+    /// ```
+    pub fn format_args_with(&self, formatter: ArgsFormatter<C>) {
+        *self.args_formatter.borrow_mut() = Some(SharedPtr::new(formatter));
+    }
+
+    /// Format args in failure messages and reports using their `Display`
+    /// representation, rather than their `Debug` representation. See
+    /// `Mock::format_args_with`.
+    ///
+    /// Tuples don't implement `Display`, so mocks with multiple args must use
+    /// `Mock::format_args_with` instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::fmt;
+    /// use double::Mock;
+    ///
+    /// #[derive(Clone, Eq, Hash, PartialEq)]
+    /// struct Url(String);
+    ///
+    /// impl fmt::Display for Url {
+    ///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    ///         write!(f, "<{}>", self.0)
+    ///     }
+    /// }
+    ///
+    /// let fetch = Mock::<Url, ()>::default();
+    /// fetch.format_args_with_display();
+    /// fetch.expect(&|url| url.0.starts_with("https"));
+    ///
+    /// fetch.call(Url("http://example.com".to_owned()));
+    /// // prints "Most recent call was #0 from src/main.rs:18:7 with args
+    /// // <http://example.com>"
+    /// assert!(!fetch.verify());
+    /// ```
+    pub fn format_args_with_display(&self)
+        where C: fmt::Display + 'static
+    {
+        self.format_args_with(Box::new(|args| args.to_string()));
+    }
+
+    // Formats `args` using the formatter set by `Mock::format_args_with`, if
+    // there is one.
+    fn format_args(&self, args: &C) -> Option<String> {
+        let formatter = self.args_formatter.borrow().clone();
+        formatter.map(|formatter| formatter(args))
+    }
+
+    fn assert_called(&self, times: Times) {
+        let num_calls = self.num_calls();
+        assert_call_count(times, num_calls, "", "");
+        let indices = self.calls.indexed_args().into_iter().map(|(index, _)| index);
+        self.verified_calls.borrow_mut().extend(indices);
+    }
+
+    // Records the call in the call log, unless the mock only counts calls, and
+    // counts it using the counters whose patterns match its args. Returns the
+    // call's index.
+    fn record_call(&self, args: &C, location: &'static Location<'static>) -> usize {
+        let call_index = if self.calls.is_recording() {
+            let (call_index, dropped) = self.calls.push(args.clone(), location);
+            self.forget_verified(dropped);
+            #[cfg(feature = "std")]
+            self.wake_awaited_calls();
+            call_index
+        } else {
+            self.calls.count()
+        };
+        let counters = self.counters.borrow().clone();
+        for (pattern, counter) in counters.iter() {
+            if pattern(args) {
+                counter.increment();
+            }
+        }
+        call_index
+    }
+
+    #[cfg(feature = "std")]
+    fn wait_for_gate(&self) {
+        let gate = self.gate.borrow().clone();
+        if let Some(gate) = gate {
+            gate.wait();
+        }
+    }
+
+    // Wakes the futures returned by `await_call`, so they check whether the
+    // call that was just recorded is the one they're waiting for
+    #[cfg(feature = "std")]
+    fn wake_awaited_calls(&self) {
+        let wakers = mem::take(&mut *self.call_wakers.borrow_mut());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    fn forget_verified(&self, dropped_calls: Vec<usize>) {
+        if dropped_calls.is_empty() {
+            return;
+        }
+        let mut verified_calls = self.verified_calls.borrow_mut();
+        for index in dropped_calls {
+            verified_calls.remove(&index);
+        }
+    }
+
+    // ========================================================================
+    // * Expectations
+    // ========================================================================
+
+    /// Expect the `Mock` to be called with arguments that match `pattern`.
+    ///
+    /// By default, the expectation must be matched by exactly one call. The
+    /// returned `Expectation` can be used to change this and to specify what
+    /// matching calls return. All expectations are checked at once using
+    /// `Mock::verify`.
+    ///
+    /// If a call matches multiple expectations, the first expectation (in
+    /// the order they were created) that has not yet been matched the
+    /// maximum number of times it allows is used. If all matching
+    /// expectations are saturated, the call is counted against the last
+    /// matching one, which makes verification fail.
+    ///
+    /// `pattern` must be `'static`. Matchers built from literals using
+    /// `matcher!` and `p!` satisfy this. Use `Mock::expect_closure` for
+    /// patterns that capture local variables.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<(i32, i32), i32>::default();
+    /// mock.expect(matcher!(p!(eq, 3), p!(any))).times(2).returning(7);
+    /// mock.expect(matcher!(p!(gt, 10), p!(lt, 0)));
+    ///
+    /// assert_eq!(mock.call((3, 0)), 7);
+    /// assert_eq!(mock.call((3, 100)), 7);
+    /// assert!(!mock.verify());  // second expectation not matched yet
+    ///
+    /// mock.call((11, -1));
+    /// assert!(mock.verify());
+    /// # }
+    /// ```
+    pub fn expect(&self, pattern: StaticPattern<C>) -> Expectation<C, R> {
+        self.expect_closure(Box::new(move |args| pattern(args)))
+    }
+
+    /// Expect the `Mock` to be called with arguments that match the closure
+    /// `pattern`. Behaves like `Mock::expect`, but `pattern` can capture
+    /// local state.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let threshold = 10;
+    /// let mock = Mock::<i32, i32>::default();
+    /// mock.expect_closure(Box::new(move |&x| x > threshold)).returning(1);
+    ///
+    /// assert_eq!(mock.call(5), 0);
+    /// assert_eq!(mock.call(15), 1);
+    /// assert!(mock.verify());
+    /// ```
+    pub fn expect_closure(&self, pattern: Pattern<C>) -> Expectation<C, R> {
+        let expectation = Expectation::new(pattern);
+        self.expectations.borrow_mut().push(expectation.clone());
+        self.expectation_statuses.borrow_mut().push(expectation.status());
+        expectation
+    }
+
+    /// Return a value from calls whose entire argument tuple satisfies
+    /// `predicate`. Useful for constraints across several arguments, which
+    /// matchers for individual arguments can't express.
+    ///
+    /// This sets an expectation (see `Mock::expect_closure`) that may be
+    /// matched any number of times. Use `returning` on the returned
+    /// `Expectation` to set the return value, and `times` to also verify
+    /// how often the predicate was satisfied.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<(String, usize), bool>::default();
+    /// mock.when_args(|(text, max_len)| text.len() > *max_len).returning(true);
+    ///
+    /// assert!(mock.call(("too long".to_owned(), 3)));
+    /// assert!(!mock.call(("short".to_owned(), 10)));
+    /// assert!(mock.verify());
+    /// ```
+    pub fn when_args<F: PatternFn<C>>(&self, predicate: F) -> Expectation<C, R> {
+        self.expect_closure(Box::new(predicate)).times(Times::any())
+    }
+
+    /// Returns true if all expectations set using `Mock::expect` are met.
+    /// Prints a description of each unmet expectation. For expectations that
+    /// were matched too few times, the recorded call that came closest to
+    /// matching is also printed, along with the args (and, for patterns built
+    /// using `matcher!`, the matchers) that rejected it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i32, ()>::default();
+    /// assert!(mock.verify());  // nothing expected
+    ///
+    /// mock.expect(&|&x| x == 42).times(1);
+    /// assert!(!mock.verify());
+    ///
+    /// mock.call(42);
+    /// assert!(mock.verify());
+    ///
+    /// mock.call(42);  // one call too many
+    /// assert!(!mock.verify());
+    /// ```
+    pub fn verify(&self) -> bool {
+        let mut satisfied = true;
+        let expectations = self.expectations.borrow().clone();
+        for (index, expectation) in expectations.iter().enumerate() {
+            if !expectation.is_verified() {
+                println!(
+                    "Expectation with index {} {}",
+                    index,
+                    expectation.describe_failure());
+                if expectation.needs_more_matches() {
+                    let pattern = |args: &C| expectation.matches(args);
+                    self.print_closest_call(
+                        &|args| diagnostics::rejected_by_pattern(&pattern, args),
+                        &|args| self.format_args(args));
+                }
+                satisfied = false;
+            }
+        }
+        let unused_stubs = *self.unused_stubs.borrow();
+        if unused_stubs != UnusedStubs::Ignore {
+            for description in self.describe_unused_stubs() {
+                if unused_stubs == UnusedStubs::Warn {
+                    eprintln!("WARNING: {}", description);
+                } else {
+                    println!("{}", description);
+                    satisfied = false;
+                }
+            }
+        }
+        satisfied
+    }
+
+    /// Verify all expectations, then clear both the expectations and the
+    /// call history. Returns the result of `Mock::verify`.
+    ///
+    /// This allows long scenario tests to be verified in phases, without the
+    /// calls or expectations of one phase interfering with the next.
+    /// Configured return values and stubs are kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<&str, ()>::default();
+    ///
+    /// // phase 1
+    /// mock.expect(&|&x| x == "open");
+    /// mock.call("open");
+    /// assert!(mock.checkpoint());
+    ///
+    /// // phase 2
+    /// mock.expect(&|&x| x == "close");
+    /// mock.call("close");
+    /// assert!(!mock.called_with("open"));
+    /// assert!(mock.checkpoint());
+    ///
+    /// assert_eq!(mock.num_calls(), 0);
+    /// ```
+    pub fn checkpoint(&self) -> bool {
+        let verified = self.verify();
+        self.clear_expectations();
+        self.reset_calls();
+        verified
+    }
+
+    // Runs the closure or function that handles the call with the given
+    // index, timing it if the mock records durations.
+    #[cfg(feature = "std")]
+    fn run_stub<F: FnOnce() -> R>(&self, call_index: usize, stub: F) -> R {
+        if !self.calls.is_recording_durations() {
+            return stub();
+        }
+        let started = Instant::now();
+        let return_value = stub();
+        self.calls.record_stub_run(call_index, started, started.elapsed());
+        return_value
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn run_stub<F: FnOnce() -> R>(&self, _call_index: usize, stub: F) -> R {
+        stub()
+    }
+
+    fn respond(&self, call_index: usize, args: C, location: &'static Location<'static>) -> R {
+        let expected = match self.match_expectation(&args) {
+            Some(expectation) => {
+                self.verified_calls.borrow_mut().insert(call_index);
+                match expectation.record_match() {
+                    Some(return_value) => {
+                        self.resolve(call_index, Behaviour::Expectation);
+                        return return_value;
+                    },
+                    None => true,
+                }
+            },
+            None => false,
+        };
+
+        // Each behaviour is taken out of the mock before it's run, since it
+        // may call or reconfigure the mock itself. Its borrow of the mock
+        // would last until the end of an `if let` that looked it up.
+        let closure = self.closures.borrow().get(&args).map(Stub::use_for_call);
+        if let Some(closure) = closure {
+            self.resolve(call_index, Behaviour::ClosureForArgs);
+            return self.run_stub(call_index, move || closure(args));
+        }
+        let function = self.fns.borrow().get(&args).map(Stub::use_for_call);
+        if let Some(function) = function {
+            self.resolve(call_index, Behaviour::FnForArgs);
+            return self.run_stub(call_index, move || function(args));
+        }
+        let return_value = self.return_values.borrow().get(&args).map(Stub::use_for_call);
+        if let Some(return_value) = return_value {
+            self.resolve(call_index, Behaviour::ReturnValueForArgs);
+            return return_value;
+        }
+        let default_fn = *self.default_fn.borrow();
+        if let Some(default_fn) = default_fn {
+            self.resolve(call_index, Behaviour::DefaultFn);
+            return self.run_stub(call_index, move || default_fn(args));
+        }
+        let default_closure = self.default_closure.borrow().clone();
+        if let Some(default_closure) = default_closure {
+            self.resolve(call_index, Behaviour::DefaultClosure);
+            return self.run_stub(call_index, move || default_closure(args));
+        }
+
+        if !expected && !*self.default_configured.borrow() {
+            self.handle_unexpected_call(call_index, &args, location);
+        }
+        // If there are no return values in the value sequence left, fall
+        // back to the configured default value.
+        let next_in_sequence = self.return_value_sequence.borrow_mut().pop();
+        match next_in_sequence {
+            Some(return_value) => {
+                self.resolve(call_index, Behaviour::ReturnValueSequence);
+                return_value
+            },
+            None => {
+                self.resolve(call_index, Behaviour::DefaultReturnValue);
+                let default_return_value = self.default_return_value.borrow().clone();
+                match default_return_value {
+                    Some(return_value) => return_value,
+                    None => panic!(
+                        "`{}` records the calls of a spy, so it has no return value",
+                        self.describe()),
+                }
+            },
+        }
+    }
+
+    // Records that `behaviour` handled a call, so the stubs that were never
+    // used can be reported (see `Mock::set_unused_stubs`). The stubs for
+    // specific args are marked as used when they're looked up.
+    fn resolve(&self, call_index: usize, behaviour: Behaviour) {
+        match behaviour {
+            Behaviour::DefaultFn | Behaviour::DefaultClosure | Behaviour::DefaultReturnValue => {
+                if let Some(ref used) = *self.default_stub_used.borrow() {
+                    used.store(true, Ordering::SeqCst);
+                }
+            },
+            _ => (),
+        }
+        self.trace_resolution(call_index, behaviour);
+    }
+
+    // Formats `args` using the mock's args formatter or, if the args are
+    // known to implement `Debug`, their `Debug` representation.
+    fn printable_args(&self, args: &C) -> Option<String> {
+        let debug_args = *self.debug_args.borrow();
+        self.format_args(args).or_else(|| debug_args.map(|debug| debug(args)))
+    }
+
+    // Emits a `tracing` event for a call, before it is handled. The args are
+    // included if they can be formatted (see `Mock::set_name`).
+    #[cfg(feature = "tracing")]
+    fn trace_call(&self, call_index: usize, args: &C, location: &Location) {
+        let args = self.printable_args(args);
+        trace::call(&self.describe(), call_index, args.as_deref(), location);
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    fn trace_call(&self, _call_index: usize, _args: &C, _location: &Location) {}
+
+    // Emits a `tracing` event naming the behaviour that handled a call.
+    #[cfg(feature = "tracing")]
+    fn trace_resolution(&self, call_index: usize, behaviour: Behaviour) {
+        trace::resolution(&self.describe(), call_index, behaviour.name());
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    fn trace_resolution(&self, _call_index: usize, _behaviour: Behaviour) {}
+
+    // Describes each stub and expectation that was configured but never used.
+    fn describe_unused_stubs(&self) -> Vec<String> {
+        let mut unused = Vec::new();
+        let stubs_for_args: Vec<(Behaviour, C)> = unused_args(&self.closures.borrow())
+            .map(|args| (Behaviour::ClosureForArgs, args))
+            .chain(unused_args(&self.fns.borrow()).map(|args| (Behaviour::FnForArgs, args)))
+            .chain(unused_args(&self.return_values.borrow())
+                .map(|args| (Behaviour::ReturnValueForArgs, args)))
+            .collect();
+        let mut unused_for_args: Vec<String> = stubs_for_args
+            .into_iter()
+            .map(|(behaviour, args)| format!(
+                "{}: {} stub for args {} was never used",
+                self.describe(),
+                behaviour.stub_method(),
+                self.printable_args(&args).unwrap_or_else(|| "?".to_owned())))
+            .collect();
+        // Stubs are stored in hash maps, so they are sorted to report them in
+        // the same order every time.
+        unused_for_args.sort();
+        unused.extend(unused_for_args);
+
+        let default_stub_unused = self.default_stub_used
+            .borrow()
+            .as_ref()
+            .is_some_and(|used| !used.load(Ordering::SeqCst));
+        if default_stub_unused {
+            let stub_method = if self.default_fn.borrow().is_some() {
+                "use_fn"
+            } else if self.default_closure.borrow().is_some() {
+                "use_closure"
+            } else {
+                "return_value"
+            };
+            unused.push(format!("{}: {} stub was never used", self.describe(), stub_method));
+        }
+        let num_unreturned = self.return_value_sequence.borrow().len();
+        if num_unreturned > 0 {
+            unused.push(format!(
+                "{}: {} of the values configured with return_values {} never returned",
+                self.describe(),
+                num_unreturned,
+                if num_unreturned == 1 { "was" } else { "were" }));
+        }
+        for (index, expectation) in self.expectations.borrow().iter().enumerate() {
+            // Unmet expectations are already reported by `Mock::verify`.
+            if expectation.num_matches() == 0
+                && !expectation.is_saturated()
+                && expectation.is_verified()
+            {
+                unused.push(format!(
+                    "{}: expectation with index {} was never matched", self.describe(), index));
+            }
+        }
+        unused
+    }
+
+    fn match_expectation(&self, args: &C) -> Option<Expectation<C, R>> {
+        // Cloned, so the patterns can use the mock
+        let expectations = self.expectations.borrow().clone();
+        let matching: Vec<Expectation<C, R>> = expectations
+            .into_iter()
+            .filter(|expectation| expectation.matches(args))
+            .collect();
+        let unsaturated = matching.iter().position(|expectation| !expectation.is_saturated());
+        match unsaturated {
+            Some(index) => matching.into_iter().nth(index),
+            None => matching.into_iter().last(),
+        }
+    }
+
+    fn clear_expectations(&self) {
+        self.expectations.borrow_mut().clear();
+        self.expectation_statuses.borrow_mut().clear();
+    }
+
+    // Prints the call that came closest to being accepted by `rejected`, and
+    // returns its args. See `diagnostics::closest_call`. The args are printed
+    // too if `debug_args` formats them.
+    fn print_closest_call(
+        &self,
+        rejected: &dyn Fn(&C) -> Option<Vec<Rejection>>,
+        debug_args: &dyn Fn(&C) -> Option<String>) -> Option<C>
+    {
+        let records = self.calls.records();
+        diagnostics::closest_call(&records, rejected).map(|(record, rejections)| {
+            let args = debug_args(record.args());
+            println!(
+                "{}",
+                diagnostics::describe_closest_call(
+                    record, args.as_deref(), &rejections, Palette::detect()));
+            record.args().clone()
+        })
+    }
+
+    fn describe(&self) -> String {
+        match *self.name.borrow() {
+            Some(ref name) => name.clone(),
+            None => format!(
+                "Mock<{}, {}>", type_name::<C>(), type_name::<R>()),
+        }
+    }
+
+    fn handle_unexpected_call(&self, call_index: usize, args: &C, location: &Location) {
+        let with_args = match self.format_args(args) {
+            Some(args) => format!(" with args {}", args),
+            None => String::new(),
+        };
+        let message = format!(
+            "Unexpected call with index {} to {} from {}{}: no behaviour configured for its args",
+            call_index,
+            self.describe(),
+            location,
+            with_args);
+        match *self.policy.borrow() {
+            MockPolicy::Nice => (),
+            MockPolicy::Warn => eprintln!("WARNING: {}", message),
+            MockPolicy::Strict => panic!("{}", message),
+        }
+    }
+}
+
+impl<C, R> Default for Mock<C, R>
+    where C: Clone + Eq + Hash,
+          R: Clone + Default
+{
+    /// Use `R::default()` as the initial return value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<i64, i64>::default();
+    /// assert_eq!(mock.call(10), 0);
+    ///
+    /// let mock = Mock::<(), String>::default();
+    /// assert_eq!(&mock.call(()), "");
+    ///
+    /// let mock = Mock::<(i64, &str), Option<bool>>::default();
+    /// assert_eq!(mock.call((10, "test")), None);
+    /// ```
+    fn default() -> Self {
+        Self::new(R::default())
+    }
+}
+
+impl<C, R> Mock<C, R>
+    where C: Clone + Debug + Eq + Hash,
+          R: Clone
+{
+    // ========================================================================
+    // * Exact Argument Checks
+    // ========================================================================
+
+    /// Returns true if the specified argument has been used for `Mock::call`.
+    /// If not, prints the recorded call that came closest, and which of its
+    /// args differ. Large args are shown as a line-by-line diff of their
+    /// pretty-printed `Debug` representations, e.g.
+    ///
+    /// ```text
+    /// Closest call was #0 from src/engine.rs:88:9: arg 0 differs
+    /// Diff of expected (-) and actual (+) args:
+    ///   Order {
+    ///       id: 7,
+    /// -     quantity: 3,
+    /// +     quantity: 2,
+    ///       ...
+    ///   }
+    /// ```
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<&str, ()>::new(());
+    /// mock.call("foo");
+    /// mock.call("bar");
+    ///
+    /// assert!(mock.called_with("foo"));
+    /// assert!(mock.called_with("bar"));
+    /// assert!(!mock.called_with("baz"));
+    /// ```
+    pub fn called_with<T: Into<C>>(&self, args: T) -> bool {
+        let args = args.into();
+        let match_info = self.get_match_info(vec!(args.clone()));
+        let matched = self.mark_verified(match_info, MatchInfo::expectations_matched);
+        if !matched {
+            let formatted = self.args_formatter.borrow().is_some();
+            let closest = self.print_closest_call(
+                &|call_args| diagnostics::rejected_by_args(&args, call_args),
+                &|call_args| match self.format_args(call_args) {
+                    Some(call_args) => Some(call_args),
+                    None => diagnostics::inline_debug(call_args),
+                });
+            let diff = closest
+                .filter(|_| !formatted)
+                .and_then(|closest| diagnostics::diff(&args, &closest, Palette::detect()));
+            if let Some(diff) = diff {
+                println!("{}", diff);
+            }
+        }
+        matched
+    }
+
+    /// Returns true if `Mock::call` has been called with all of the specified
+    /// `calls`. The calls can be made in any order.  They don't have to be in
+    /// the order specified by `calls`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<&str, ()>::new(());
+    /// mock.call("foo");
+    /// mock.call("bar");
+    ///
+    /// let expected_calls1 = vec!("foo", "bar");
+    /// assert!(mock.has_calls(expected_calls1));
+    /// let expected_calls2 = vec!("bar", "foo");
+    /// assert!(mock.has_calls(expected_calls2));
+    /// let expected_calls3 = vec!("foo");
+    /// assert!(mock.has_calls(expected_calls3));
+    /// let expected_calls4 = vec!("not_in_calls");
+    /// assert!(!mock.has_calls(expected_calls4));
+    /// let expected_calls5 = vec!("foo", "not_in_calls");
+    /// assert!(!mock.has_calls(expected_calls5));
+    /// ```
+    pub fn has_calls<T: Into<C>>(&self, calls: Vec<T>) -> bool {
+        let match_info = self.get_match_info(calls);
+        self.mark_verified(match_info, MatchInfo::expectations_matched)
+    }
+
+    /// Returns true if `Mock::call` has been called with all of the specified
+    /// `calls`. The `calls` must be made in the order they are specified in
+    /// the vector, but other calls may be made in between them. Use
+    /// `Mock::has_calls_consecutive` to disallow that.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<(i32, i32), ()>::new(());
+    /// mock.call((42, 0));
+    /// mock.call((42, 1));
+    /// mock.call((42, 0));  // called with same args as first call!
+    ///
+    /// assert!(mock.has_calls_in_order(vec!( (42, 0) )));
+    /// assert!(mock.has_calls_in_order(vec!( (42, 1) )));
+    /// assert!(mock.has_calls_in_order(vec!( (42, 0), (42, 1) )));
+    /// assert!(mock.has_calls_in_order(vec!( (42, 1), (42, 0) )));
+    /// assert!(mock.has_calls_in_order(vec!( (42, 0), (42, 1), (42, 0) )));
+    /// assert!(!mock.has_calls_in_order(vec!( (42, 0), (42, 0), (42, 1) )));
+    /// assert!(!mock.has_calls_in_order(vec!( (84, 0) )));
+    /// assert!(!mock.has_calls_in_order(vec!( (42, 0), (84, 0) )));
+    /// ```
+    pub fn has_calls_in_order<T: Into<C>>(&self, calls: Vec<T>) -> bool {
+        let match_info = self.get_match_info(calls);
+        self.mark_verified(match_info, MatchInfo::expectations_matched_in_order)
+    }
+
+    /// Returns true if `Mock::call` has been called with all of the specified
+    /// `calls` and it has not been called any other times. The calls can be
+    /// made in any order. They don't have to be in the order specified by
+    /// `calls`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<(i32, i32), ()>::new(());
+    /// mock.call((42, 0));
+    /// mock.call((42, 1));
+    /// mock.call((42, 0));
+    ///
+    /// assert!(!mock.has_calls_exactly(vec!( (42, 0) )));
+    /// assert!(!mock.has_calls_exactly(vec!( (42, 1) )));
+    /// assert!(!mock.has_calls_exactly(vec!( (84, 0) )));
+    /// assert!(!mock.has_calls_exactly(vec!( (42, 0), (42, 1) )));
+    /// assert!(!mock.has_calls_exactly(vec!( (42, 1), (42, 0) )));
+    /// assert!(mock.has_calls_exactly(vec!( (42, 0), (42, 0), (42, 1) )));
+    /// assert!(mock.has_calls_exactly(vec!( (42, 0), (42, 1), (42, 0) )));
+    /// assert!(!mock.has_calls_exactly(vec!( (42, 0), (42, 1), (84, 0) )));
+    /// ```
+    pub fn has_calls_exactly<T: Into<C>>(&self, calls: Vec<T>) -> bool {
+        let match_info = self.get_match_info(calls);
+        self.mark_verified(match_info, MatchInfo::expectations_matched_exactly)
+    }
+
+    /// Returns true if `Mock::call` has been called with all of the specified
+    /// `calls` and it has not been called any other times. The calls must be
+    /// made in the order they are specified in `calls`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<&str, ()>::new(());
+    /// mock.call("foo");
     /// mock.call("bar");
     ///
     /// let expected_calls1 = vec!("foo", "bar");
@@ -585,7 +2115,71 @@ impl<C, R> Mock<C, R>
     /// let expected_calls4 = vec!("bar");
     /// assert!(!mock.has_calls_exactly_in_order(expected_calls4));
     pub fn has_calls_exactly_in_order<T: Into<C>>(&self, calls: Vec<T>) -> bool {
-        self.get_match_info(calls).expectations_matched_in_order_exactly()
+        let match_info = self.get_match_info(calls);
+        self.mark_verified(match_info, MatchInfo::expectations_matched_in_order_exactly)
+    }
+
+    /// Returns true if `Mock::call` has been called with all of the specified
+    /// `calls`, one after another, in the order they are specified in the
+    /// vector. Unlike `Mock::has_calls_in_order`, no other calls may be made
+    /// in between them. Other calls may be made before or after them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<&str, ()>::new(());
+    /// mock.call("open");
+    /// mock.call("write");
+    /// mock.call("flush");
+    /// mock.call("close");
+    ///
+    /// assert!(mock.has_calls_consecutive(vec!("write", "flush")));
+    /// assert!(mock.has_calls_consecutive(vec!("open", "write", "flush", "close")));
+    /// assert!(mock.has_calls_in_order(vec!("open", "close")));
+    /// assert!(!mock.has_calls_consecutive(vec!("open", "close")));
+    /// assert!(!mock.has_calls_consecutive(vec!("flush", "write")));
+    /// ```
+    pub fn has_calls_consecutive<T: Into<C>>(&self, calls: Vec<T>) -> bool {
+        let expected_calls: Vec<C> = calls.into_iter().map(|args| args.into()).collect();
+        self.find_consecutive(expected_calls.len(), &|position, args| {
+            *args == expected_calls[position]
+        })
+    }
+
+    /// Assert that `Mock::call` has never been called with `args`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a message containing the index and args of the first
+    /// offending call if any call was made with `args`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<&str, ()>::default();
+    /// mock.call("foo");
+    ///
+    /// mock.assert_not_called_with("bar");
+    /// ```
+    ///
+    /// ```should_panic
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<&str, ()>::default();
+    /// mock.call("foo");
+    ///
+    /// // panics: "expected Mock not to be called with args "foo", but call
+    /// // with index 0 from src/main.rs:6:6 had args "foo"
+    /// mock.assert_not_called_with("foo");
+    /// ```
+    pub fn assert_not_called_with<T: Into<C>>(&self, args: T) {
+        let args = args.into();
+        let description = format!("args {}", self.describe_args(&args));
+        self.assert_no_call_matching(&|call_args| *call_args == args, &description);
     }
 
     // ========================================================================
@@ -611,6 +2205,12 @@ impl<C, R> Mock<C, R>
     /// [double repository's README.md](https://github.com/DonaldWhyte/double)
     /// for more information on this.
     ///
+    /// If no call matches, prints the recorded call that came closest. For
+    /// patterns built using `matcher!`, this is the call with the fewest
+    /// rejected args, and the matchers that rejected them are printed too,
+    /// e.g. `Closest call was #1 from src/engine.rs:88:9 with args (42, 1):
+    /// arg 1 was rejected by p!(eq, 0)`.
+    ///
     /// # Examples
     ///
     /// ```
@@ -630,7 +2230,14 @@ impl<C, R> Mock<C, R>
     /// ```
     pub fn called_with_pattern(&self, pattern: &dyn Fn(&C) -> bool) -> bool {
         let patterns: Vec<&dyn Fn(&C) -> bool> = vec!(pattern);
-        self.get_match_info_pattern(patterns).expectations_matched()
+        let match_info = self.get_match_info_pattern(patterns);
+        let matched = self.mark_verified(match_info, MatchInfo::expectations_matched);
+        if !matched {
+            self.print_closest_call(
+                &|call_args| diagnostics::rejected_by_pattern(pattern, call_args),
+                &|call_args| Some(self.describe_args(call_args)));
+        }
+        matched
     }
 
     /// Returns true if `Mock::call` has been called with all of the specified
@@ -658,12 +2265,82 @@ impl<C, R> Mock<C, R>
     /// assert!(!mock.has_patterns(vec!(&pattern1, &pattern3)));
     /// ```
     pub fn has_patterns(&self, patterns: Vec<&dyn Fn(&C) -> bool>) -> bool {
-        self.get_match_info_pattern(patterns).expectations_matched()
+        let match_info = self.get_match_info_pattern(patterns);
+        self.mark_verified(match_info, MatchInfo::expectations_matched)
     }
 
     /// Returns true if `Mock::call` has been called with all of the specified
     /// `patterns`. The `patterns` must be made in the order they are specified
-    /// in the input vector.
+    /// in the input vector, but other calls may be made in between them. Use
+    /// `Mock::has_patterns_consecutive` to disallow that.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<(i32, i32), ()>::new(());
+    /// mock.call((42, 0));
+    /// mock.call((42, 1));
+    /// mock.call((42, 0));  // called with same args as first call!
+    ///
+    /// let pattern1 = |args: &(i32, i32)| args.0 == 42 && args.1 != 0;
+    /// let pattern2 = |args: &(i32, i32)| args.0 == 42 && args.1 == 0;
+    /// let pattern3 = |args: &(i32, i32)| args.0 == 84;
+    ///
+    /// assert!(mock.has_patterns_in_order(vec!(&pattern1)));
+    /// assert!(mock.has_patterns_in_order(vec!(&pattern2)));
+    /// assert!(mock.has_patterns_in_order(vec!(&pattern1, &pattern2)));
+    /// assert!(mock.has_patterns_in_order(vec!(&pattern2, &pattern1)));
+    /// assert!(mock.has_patterns_in_order(vec!(&pattern2, &pattern1, &pattern2)));
+    /// assert!(!mock.has_patterns_in_order(vec!(&pattern1, &pattern2, &pattern1)));
+    /// assert!(!mock.has_patterns_in_order(vec!(&pattern1, &pattern1, &pattern2)));
+    /// assert!(!mock.has_patterns_in_order(vec!(&pattern2, &pattern2, &pattern1)));
+    /// assert!(!mock.has_patterns_in_order(vec!(&pattern3)));
+    /// assert!(!mock.has_patterns_in_order(vec!(&pattern1, &pattern3)));
+    /// ```
+    pub fn has_patterns_in_order(&self, patterns: Vec<&dyn Fn(&C) -> bool>) -> bool {
+        let match_info = self.get_match_info_pattern(patterns);
+        self.mark_verified(match_info, MatchInfo::expectations_matched_in_order)
+    }
+
+    /// Returns true if `Mock::call` has been called with all of the specified
+    /// `patterns` and it has not been called any other times. The calls can be
+    /// made in any order. They don't have to be in the order specified by
+    /// `patterns`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<(i32, i32), ()>::new(());
+    /// mock.call((42, 0));
+    /// mock.call((42, 1));
+    /// mock.call((42, 0));
+    ///
+    /// let pattern1 = |args: &(i32, i32)| args.0 == 42 && args.1 != 0;
+    /// let pattern2 = |args: &(i32, i32)| args.0 == 42 && args.1 == 0;
+    /// let pattern3 = |args: &(i32, i32)| args.0 == 84;
+    ///
+    /// assert!(!mock.has_patterns_exactly(vec!(&pattern1)));
+    /// assert!(!mock.has_patterns_exactly(vec!(&pattern2)));
+    /// assert!(!mock.has_patterns_exactly(vec!(&pattern3)));
+    /// assert!(!mock.has_patterns_exactly(vec!(&pattern1, &pattern2)));
+    /// assert!(!mock.has_patterns_exactly(vec!(&pattern2, &pattern1)));
+    /// assert!(mock.has_patterns_exactly(vec!(&pattern1, &pattern1, &pattern2)));
+    /// assert!(mock.has_patterns_exactly(vec!(&pattern1, &pattern2, &pattern1)));
+    /// assert!(!mock.has_patterns_exactly(vec!(&pattern1, &pattern2, &pattern3)));
+    /// ```
+    pub fn has_patterns_exactly(&self, patterns: Vec<&dyn Fn(&C) -> bool>) -> bool {
+        let match_info = self.get_match_info_pattern(patterns);
+        self.mark_verified(match_info, MatchInfo::expectations_matched_exactly)
+    }
+
+    /// Returns true if `Mock::call` has been called with all of the specified
+    /// `patterns` and it has not been called any other times. The calls must
+    /// be made match the patterns in the same order as specified in the
+    /// `patterns` vector.
     ///
     /// # Examples
     ///
@@ -679,90 +2356,519 @@ impl<C, R> Mock<C, R>
     /// let pattern2 = |args: &(i32, i32)| args.0 == 42 && args.1 == 0;
     /// let pattern3 = |args: &(i32, i32)| args.0 == 84;
     ///
-    /// assert!(mock.has_patterns_in_order(vec!(&pattern1)));
-    /// assert!(mock.has_patterns_in_order(vec!(&pattern2)));
-    /// assert!(mock.has_patterns_in_order(vec!(&pattern1, &pattern2)));
-    /// assert!(mock.has_patterns_in_order(vec!(&pattern2, &pattern1)));
-    /// assert!(mock.has_patterns_in_order(vec!(&pattern2, &pattern1, &pattern2)));
-    /// assert!(!mock.has_patterns_in_order(vec!(&pattern1, &pattern2, &pattern1)));
-    /// assert!(!mock.has_patterns_in_order(vec!(&pattern1, &pattern1, &pattern2)));
-    /// assert!(!mock.has_patterns_in_order(vec!(&pattern2, &pattern2, &pattern1)));
-    /// assert!(!mock.has_patterns_in_order(vec!(&pattern3)));
-    /// assert!(!mock.has_patterns_in_order(vec!(&pattern1, &pattern3)));
+    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern1)));
+    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern2)));
+    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern1, &pattern2)));
+    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern2, &pattern1)));
+    /// assert!(mock.has_patterns_exactly_in_order(vec!(&pattern2, &pattern1, &pattern2)));
+    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern1, &pattern2, &pattern1)));
+    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern1, &pattern1, &pattern2)));
+    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern2, &pattern2, &pattern1)));
+    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern3)));
+    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern1, &pattern3)));
+    /// ```
+    pub fn has_patterns_exactly_in_order(&self, patterns: Vec<&dyn Fn(&C) -> bool>) -> bool {
+        let match_info = self.get_match_info_pattern(patterns);
+        self.mark_verified(match_info, MatchInfo::expectations_matched_in_order_exactly)
+    }
+
+    /// Returns true if `Mock::call` has been called with args that match each
+    /// of the specified `patterns`, one after another, in the order the
+    /// patterns are specified in the vector. Unlike
+    /// `Mock::has_patterns_in_order`, no other calls may be made in between
+    /// them. Other calls may be made before or after them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<(i32, i32), ()>::new(());
+    /// mock.call((42, 0));
+    /// mock.call((84, 1));
+    /// mock.call((42, 1));
+    ///
+    /// let pattern1 = |args: &(i32, i32)| args.0 == 42;
+    /// let pattern2 = |args: &(i32, i32)| args.1 == 1;
+    ///
+    /// assert!(mock.has_patterns_consecutive(vec!(&pattern1, &pattern2)));
+    /// assert!(mock.has_patterns_consecutive(vec!(&pattern2, &pattern1)));
+    /// assert!(mock.has_patterns_in_order(vec!(&pattern1, &pattern1)));
+    /// assert!(!mock.has_patterns_consecutive(vec!(&pattern1, &pattern1)));
+    /// ```
+    pub fn has_patterns_consecutive(&self, patterns: Vec<&dyn Fn(&C) -> bool>) -> bool {
+        self.find_consecutive(patterns.len(), &|position, args| patterns[position](args))
+    }
+
+    // ========================================================================
+    // * Pattern Matching Call Count Checks
+    // ========================================================================
+
+    /// Assert that exactly `n` calls have arguments that match `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a message containing the actual number of matching calls
+    /// and all recorded calls if the number of matching calls is not `n`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<(i32, i32), ()>::default();
+    /// mock.call((42, 0));
+    /// mock.call((42, 1));
+    /// mock.call((84, 1));
+    ///
+    /// mock.called_with_pattern_exactly(matcher!(p!(eq, 42), p!(any)), 2);
+    /// mock.called_with_pattern_exactly(matcher!(p!(any), p!(eq, 1)), 2);
+    /// mock.called_with_pattern_exactly(matcher!(p!(eq, 84), p!(eq, 1)), 1);
+    /// # }
+    /// ```
+    pub fn called_with_pattern_exactly(&self, pattern: &dyn Fn(&C) -> bool, n: usize) {
+        self.assert_called_with_pattern(pattern, Times::exactly(n))
+    }
+
+    /// Assert that `n` or more calls have arguments that match `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a message containing the actual number of matching calls
+    /// and all recorded calls if fewer than `n` calls match.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<i32, ()>::default();
+    /// mock.call(1);
+    /// mock.call(2);
+    /// mock.call(3);
+    ///
+    /// mock.called_with_pattern_at_least(p!(gt, 1), 2);
+    /// # }
+    /// ```
+    pub fn called_with_pattern_at_least(&self, pattern: &dyn Fn(&C) -> bool, n: usize) {
+        self.assert_called_with_pattern(pattern, Times::at_least(n))
+    }
+
+    /// Assert that `n` or fewer calls have arguments that match `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a message containing the actual number of matching calls
+    /// and all recorded calls if more than `n` calls match.
+    ///
+    /// # Examples
+    ///
+    /// ```should_panic
+    /// # #[macro_use] extern crate double;
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<i32, ()>::default();
+    /// mock.call(1);
+    /// mock.call(2);
+    /// mock.call(3);
+    ///
+    /// // panics: "expected Mock to be called at most 1 time with args
+    /// // matching the pattern, but it was called 2 times"
+    /// mock.called_with_pattern_at_most(p!(gt, 1), 1);
+    /// # }
+    /// ```
+    pub fn called_with_pattern_at_most(&self, pattern: &dyn Fn(&C) -> bool, n: usize) {
+        self.assert_called_with_pattern(pattern, Times::at_most(n))
+    }
+
+    /// Assert that the number of calls with arguments that match `pattern` is
+    /// within `times`. `times` can be a `usize`, any range of `usize` or a
+    /// `Times`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a message containing the actual number of matching calls
+    /// and all recorded calls if the number of matching calls is outside
+    /// `times`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<i32, ()>::default();
+    /// mock.call(1);
+    /// mock.call(2);
+    /// mock.call(3);
+    ///
+    /// mock.called_with_pattern_times(p!(gt, 1), 2..=5);
+    /// mock.called_with_pattern_times(p!(gt, 2), ..2);
+    /// # }
+    /// ```
+    pub fn called_with_pattern_times<T: Into<Times>>(
+        &self,
+        pattern: &dyn Fn(&C) -> bool,
+        times: T)
+    {
+        self.assert_called_with_pattern(pattern, times.into())
+    }
+
+    // Used by `expect_called!`, which passes the mock and the matchers as they
+    // were written, so the panic message can name them. Without matchers,
+    // `pattern` matches any args.
+    #[doc(hidden)]
+    #[track_caller]
+    pub fn __private_expect_called(
+        &self,
+        mock: &str,
+        matchers: Option<&str>,
+        pattern: &dyn Fn(&C) -> bool,
+        times: Times)
+    {
+        let (num_calls, matching_indices, description) = match matchers {
+            Some(matchers) => {
+                let indices = self.calls_matching(pattern).indices();
+                (indices.len(), indices, format!(" with args matching {}", matchers))
+            },
+            None => (self.num_calls(), self.records().indices(), String::new()),
+        };
+        if !times.contains(num_calls) {
+            let calls: Vec<String> = self.calls
+                .records()
+                .iter()
+                .map(|record| self.describe_args(record.args()))
+                .collect();
+            panic!(
+                "expected `{}` to be called {}{}, but it was called {} {}\nRecorded calls: [{}]",
+                mock,
+                times,
+                description,
+                num_calls,
+                if num_calls == 1 { "time" } else { "times" },
+                calls.join(", "));
+        }
+        self.verified_calls.borrow_mut().extend(matching_indices);
+    }
+
+    /// Assert that no calls have arguments that match `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a message containing the actual number of matching calls
+    /// and all recorded calls if any call matches.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<i32, ()>::default();
+    /// mock.call(1);
+    ///
+    /// mock.never_called_with_pattern(p!(gt, 1));
+    /// # }
+    /// ```
+    pub fn never_called_with_pattern(&self, pattern: &dyn Fn(&C) -> bool) {
+        self.assert_called_with_pattern(pattern, Times::never())
+    }
+
+    /// Assert that no calls have arguments that match `pattern`.
+    ///
+    /// Unlike `never_called_with_pattern`, the panic message identifies the
+    /// first offending call.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a message containing the index and args of the first
+    /// offending call if any call matches `pattern`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<(i32, String), ()>::default();
+    /// mock.call((1, "foo".to_owned()));
+    ///
+    /// mock.assert_not_called_with_pattern(matcher!(p!(lt, 0), p!(any)));
+    /// # }
+    /// ```
+    ///
+    /// ```should_panic
+    /// # #[macro_use] extern crate double;
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<(i32, String), ()>::default();
+    /// mock.call((1, "foo".to_owned()));
+    /// mock.call((-1, "bar".to_owned()));
+    ///
+    /// // panics: "expected Mock not to be called with args matching the
+    /// // pattern, but call with index 1 from src/main.rs:9:6 had args
+    /// // (-1, "bar")"
+    /// mock.assert_not_called_with_pattern(matcher!(p!(lt, 0), p!(any)));
+    /// # }
+    /// ```
+    pub fn assert_not_called_with_pattern(&self, pattern: &dyn Fn(&C) -> bool) {
+        self.assert_no_call_matching(pattern, "args matching the pattern");
+    }
+
+    /// Assert that no call with arguments that match `pattern` is made within
+    /// `duration`. Blocks until `duration` has elapsed, or until a matching
+    /// call is made. Calls made before `assert_no_call_for` was called are
+    /// ignored.
+    ///
+    /// This is useful for testing debounce and throttle logic, where the
+    /// absence of a call is the behaviour under test. Requires the `std`
+    /// feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics with a message containing the index, location and args of the
+    /// offending call if a matching call is made within `duration`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate double;
+    /// use std::time::Duration;
+    /// use double::Mock;
+    /// use double::matcher::*;
+    ///
+    /// # fn main() {
+    /// let mock = Mock::<i32, ()>::default();
+    /// mock.call(1);
+    ///
+    /// mock.assert_no_call_for(p!(any), Duration::from_millis(10));
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn assert_no_call_for(&self, pattern: &dyn Fn(&C) -> bool, duration: Duration) {
+        let start = Instant::now();
+        let deadline = start + duration;
+        let first_index = self.num_calls();
+        loop {
+            let offending = self.calls_matching(pattern)
+                .find(|record| record.index() >= first_index);
+            if let Some(record) = offending {
+                panic!(
+                    "expected Mock not to be called with args matching the pattern for {:?}, \
+                     but call with index {} from {} had args {} after {:?}",
+                    duration,
+                    record.index(),
+                    record.location(),
+                    self.describe_args(record.args()),
+                    record.timestamp().saturating_duration_since(start));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return;
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    // ========================================================================
+    // * Interaction Checks
+    // ========================================================================
+
+    /// Returns true if every call made to the `Mock` has been matched by a
+    /// successful verification, such as `called_with`, `has_calls` or
+    /// `called_with_pattern`, or by an expectation (see `Mock::expect`).
+    /// Prints the index and args of each call that was not matched.
+    ///
+    /// This catches unintended extra calls to collaborators.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::Mock;
+    ///
+    /// let mock = Mock::<&str, ()>::default();
+    /// mock.call("foo");
+    /// mock.call("bar");
+    ///
+    /// assert!(mock.called_with("foo"));
+    /// assert!(!mock.verify_no_more_interactions());  // "bar" not verified
+    ///
+    /// assert!(mock.called_with("bar"));
+    /// assert!(mock.verify_no_more_interactions());
     /// ```
-    pub fn has_patterns_in_order(&self, patterns: Vec<&dyn Fn(&C) -> bool>) -> bool {
-        self.get_match_info_pattern(patterns).expectations_matched_in_order()
+    pub fn verify_no_more_interactions(&self) -> bool {
+        let verified_calls = self.verified_calls.borrow().clone();
+        let mut verified = true;
+        for record in self.calls.records() {
+            if !verified_calls.contains(&record.index()) {
+                println!(
+                    "Call with index {} from {} and args {} was not verified",
+                    record.index(),
+                    record.location(),
+                    self.describe_args(record.args()));
+                verified = false;
+            }
+        }
+        verified
     }
 
-    /// Returns true if `Mock::call` has been called with all of the specified
-    /// `patterns` and it has not been called any other times. The calls can be
-    /// made in any order. They don't have to be in the order specified by
-    /// `patterns`.
+    // ========================================================================
+    // * Naming and Reporting
+    // ========================================================================
+
+    /// Name the `Mock`, e.g. after the method it mocks. The name is used
+    /// instead of the mock's type in failure messages and, with the `tracing`
+    /// feature, in the events emitted for its calls. Mocks generated by
+    /// `mock_trait!` are named after the mock and the method, e.g.
+    /// `MockBalanceSheet::profit`.
     ///
     /// # Examples
     ///
     /// ```
     /// use double::Mock;
     ///
-    /// let mock = Mock::<(i32, i32), ()>::new(());
-    /// mock.call((42, 0));
-    /// mock.call((42, 1));
-    /// mock.call((42, 0));
-    ///
-    /// let pattern1 = |args: &(i32, i32)| args.0 == 42 && args.1 != 0;
-    /// let pattern2 = |args: &(i32, i32)| args.0 == 42 && args.1 == 0;
-    /// let pattern3 = |args: &(i32, i32)| args.0 == 84;
+    /// let mock = Mock::<i32, ()>::default();
+    /// assert_eq!(mock.name(), None);
     ///
-    /// assert!(!mock.has_patterns_exactly(vec!(&pattern1)));
-    /// assert!(!mock.has_patterns_exactly(vec!(&pattern2)));
-    /// assert!(!mock.has_patterns_exactly(vec!(&pattern3)));
-    /// assert!(!mock.has_patterns_exactly(vec!(&pattern1, &pattern2)));
-    /// assert!(!mock.has_patterns_exactly(vec!(&pattern2, &pattern1)));
-    /// assert!(mock.has_patterns_exactly(vec!(&pattern1, &pattern1, &pattern2)));
-    /// assert!(mock.has_patterns_exactly(vec!(&pattern1, &pattern2, &pattern1)));
-    /// assert!(!mock.has_patterns_exactly(vec!(&pattern1, &pattern2, &pattern3)));
+    /// mock.set_name("sink");
+    /// assert_eq!(mock.name(), Some("sink".to_owned()));
     /// ```
-    pub fn has_patterns_exactly(&self, patterns: Vec<&dyn Fn(&C) -> bool>) -> bool {
-        self.get_match_info_pattern(patterns).expectations_matched_exactly()
+    pub fn set_name(&self, name: &str) {
+        *self.name.borrow_mut() = Some(name.to_owned());
+        *self.debug_args.borrow_mut() = Some(|args| format!("{:?}", args));
     }
 
-    /// Returns true if `Mock::call` has been called with all of the specified
-    /// `patterns` and it has not been called any other times. The calls must
-    /// be made match the patterns in the same order as specified in the
-    /// `patterns` vector.
+    /// Returns the name set by `Mock::set_name`, if there is one.
+    pub fn name(&self) -> Option<String> {
+        self.name.borrow().clone()
+    }
+
+    /// Determine how `Mock::verify` reacts to stubs and expectations that
+    /// were configured but never used. This catches dead test setup, which
+    /// otherwise silently rots as the code under test changes.
+    ///
+    /// With `UnusedStubs::Warn` or `UnusedStubs::Fail`, `verify` prints each
+    /// stub configured with `return_value_for`, `use_fn_for` or
+    /// `use_closure_for` that never handled a call, a default behaviour
+    /// configured with `return_value`, `use_fn` or `use_closure` that never
+    /// handled a call, values configured with `return_values` that were never
+    /// returned, and expectations that were never matched (unless they were
+    /// expected to be called `Times::never`). With `UnusedStubs::Fail`,
+    /// `verify` also fails.
     ///
     /// # Examples
     ///
     /// ```
-    /// use double::Mock;
+    /// use double::{Mock, UnusedStubs};
     ///
-    /// let mock = Mock::<(i32, i32), ()>::new(());
-    /// mock.call((42, 0));
-    /// mock.call((42, 1));
-    /// mock.call((42, 0));  // called with same args as first call!
+    /// let mock = Mock::<&str, i32>::default();
+    /// mock.set_unused_stubs(UnusedStubs::Fail);
+    /// mock.return_value_for("one", 1);
+    /// mock.return_value_for("two", 2);
     ///
-    /// let pattern1 = |args: &(i32, i32)| args.0 == 42 && args.1 != 0;
-    /// let pattern2 = |args: &(i32, i32)| args.0 == 42 && args.1 == 0;
-    /// let pattern3 = |args: &(i32, i32)| args.0 == 84;
+    /// mock.call("one");
+    /// // prints "Mock<&str, i32>: return_value_for stub for args "two" was never used"
+    /// assert!(!mock.verify());
     ///
-    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern1)));
-    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern2)));
-    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern1, &pattern2)));
-    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern2, &pattern1)));
-    /// assert!(mock.has_patterns_exactly_in_order(vec!(&pattern2, &pattern1, &pattern2)));
-    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern1, &pattern2, &pattern1)));
-    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern1, &pattern1, &pattern2)));
-    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern2, &pattern2, &pattern1)));
-    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern3)));
-    /// assert!(!mock.has_patterns_exactly_in_order(vec!(&pattern1, &pattern3)));
+    /// mock.call("two");
+    /// assert!(mock.verify());
     /// ```
-    pub fn has_patterns_exactly_in_order(&self, patterns: Vec<&dyn Fn(&C) -> bool>) -> bool {
-        self.get_match_info_pattern(patterns).expectations_matched_in_order_exactly()
+    pub fn set_unused_stubs(&self, unused_stubs: UnusedStubs) {
+        *self.unused_stubs.borrow_mut() = unused_stubs;
+        *self.debug_args.borrow_mut() = Some(|args| format!("{:?}", args));
     }
 
     // ========================================================================
     // * Private Helpers
     // ========================================================================
+    fn mark_verified(&self, match_info: MatchInfo, check: fn(&MatchInfo) -> bool) -> bool {
+        let matched = check(&match_info);
+        if matched {
+            self.verified_calls.borrow_mut().extend(
+                match_info.pattern_index_to_match_indices
+                    .values()
+                    .flat_map(|indices| indices.iter().copied()));
+        }
+        matched
+    }
+
+    // Returns true if there are `num_expected` consecutive calls for which
+    // `matches(position, args)` returns true, where `position` is the
+    // position of the call in the run. The first such run is verified.
+    fn find_consecutive(
+        &self,
+        num_expected: usize,
+        matches: &dyn Fn(usize, &C) -> bool) -> bool
+    {
+        if num_expected == 0 {
+            return true;
+        }
+        let records = self.calls.records();
+        let run = records.windows(num_expected).find(|window| {
+            window
+                .iter()
+                .enumerate()
+                .all(|(position, record)| matches(position, record.args()))
+        });
+        match run {
+            Some(run) => {
+                self.verified_calls
+                    .borrow_mut()
+                    .extend(run.iter().map(|record| record.index()));
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn assert_called_with_pattern(&self, pattern: &dyn Fn(&C) -> bool, times: Times) {
+        let matching_indices = self.calls_matching(pattern).indices();
+        let calls: Vec<String> = self.calls
+            .records()
+            .iter()
+            .map(|record| self.describe_args(record.args()))
+            .collect();
+        let calls = format!("\nRecorded calls: [{}]", calls.join(", "));
+        assert_call_count(
+            times,
+            matching_indices.len(),
+            " with args matching the pattern",
+            &calls);
+        self.verified_calls.borrow_mut().extend(matching_indices);
+    }
+
+    fn assert_no_call_matching(&self, pattern: &dyn Fn(&C) -> bool, description: &str) {
+        if let Some(record) = self.calls_matching(pattern).next() {
+            panic!(
+                "expected Mock not to be called with {}, but call with index {} from {} had args {}",
+                description,
+                record.index(),
+                record.location(),
+                self.describe_args(record.args()));
+        }
+    }
+
+    // Formats `args` for failure messages. See `Mock::format_args_with`.
+    pub(crate) fn describe_args(&self, args: &C) -> String {
+        self.format_args(args).unwrap_or_else(|| format!("{:?}", args))
+    }
+
     fn get_match_info<T: Into<C>>(&self, expected_calls: Vec<T>) -> MatchInfo {
         let expected_calls_c: Vec<C> = expected_calls
             .into_iter()
@@ -773,21 +2879,21 @@ impl<C, R> Mock<C, R>
         // actual calls made to the mock whose args match that tuple exactly.
         let mut pattern_index_to_match_indices: HashMap<usize, Vec<usize>> =
             HashMap::new();
-        for (call_index, call_args) in self.calls.borrow().iter().enumerate() {
+        for record in self.calls.records() {
             for (expected_index, expected_args) in expected_calls_c.iter().enumerate() {
-                if call_args == expected_args {
+                if record.args() == expected_args {
                     pattern_index_to_match_indices
                         .entry(expected_index)
                         .or_insert(vec!())
-                        .push(call_index);
+                        .push(record.index());
                 }
             }
         }
 
         MatchInfo {
             num_expectations: expected_calls_c.len(),
-            num_actual_calls: self.calls.borrow().len(),
-            pattern_index_to_match_indices: pattern_index_to_match_indices,
+            num_actual_calls: self.num_calls(),
+            pattern_index_to_match_indices,
         }
     }
 
@@ -796,21 +2902,21 @@ impl<C, R> Mock<C, R>
         // calls made to the mock whose args match that pattern.
         let mut pattern_index_to_match_indices: HashMap<usize, Vec<usize>> =
             HashMap::new();
-        for (call_index, call_args) in self.calls.borrow().iter().enumerate() {
+        for record in self.calls.records() {
             for (expected_index, pattern_fn) in patterns.iter().enumerate() {
-                if pattern_fn(call_args) {
+                if pattern_fn(record.args()) {
                     pattern_index_to_match_indices
                         .entry(expected_index)
                         .or_insert(vec!())
-                        .push(call_index);
+                        .push(record.index());
                 }
             }
         }
 
         MatchInfo {
             num_expectations: patterns.len(),
-            num_actual_calls: self.calls.borrow().len(),
-            pattern_index_to_match_indices: pattern_index_to_match_indices,
+            num_actual_calls: self.num_calls(),
+            pattern_index_to_match_indices,
         }
     }
 }
@@ -890,20 +2996,267 @@ impl<C, O, E> Mock<C, Result<O, E>>
     }
 }
 
+#[cfg(feature = "std")]
+impl<C, T> Mock<C, MockFuture<T>>
+    where C: Clone + Eq + Hash,
+          T: Clone
+{
+    /// Return a future from `Mock::call` that resolves to `value` the first
+    /// time it is polled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::future::Future;
+    /// use std::pin::Pin;
+    /// use std::task::{Context, Poll, Waker};
+    /// use double::{Mock, MockFuture};
+    ///
+    /// let mock = Mock::<i32, MockFuture<String>>::default();
+    /// mock.returns_ready("fetched");
+    ///
+    /// let mut future = mock.call(1);
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Ready("fetched".to_owned()));
+    /// ```
+    pub fn returns_ready<V: Into<T>>(&self, value: V) {
+        self.return_value(MockFuture::ready(value.into()))
+    }
+
+    /// Return a future from `Mock::call` that is pending for the first
+    /// `num_polls` polls, then resolves to `value`. Useful for testing code
+    /// that must cope with futures that aren't immediately ready.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::future::Future;
+    /// use std::pin::Pin;
+    /// use std::task::{Context, Poll, Waker};
+    /// use double::{Mock, MockFuture};
+    ///
+    /// let mock = Mock::<(), MockFuture<u8>>::default();
+    /// mock.returns_pending_then(3, 1);
+    ///
+    /// let mut future = mock.call(());
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Pending);
+    /// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(3));
+    /// ```
+    pub fn returns_pending_then<V: Into<T>>(&self, value: V, num_polls: usize) {
+        self.return_value(MockFuture::pending_then(value.into(), num_polls))
+    }
+
+    /// Return a future from `Mock::call` that is pending until `gate` is
+    /// opened, then resolves to `value`. The futures returned by all calls
+    /// wait for the same gate. Useful for testing cancellation and
+    /// `select!` loops deterministically. See `Gate`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::future::Future;
+    /// use std::pin::Pin;
+    /// use std::task::{Context, Poll, Waker};
+    /// use double::{Gate, Mock, MockFuture};
+    ///
+    /// let gate = Gate::new();
+    /// let mock = Mock::<(), MockFuture<u8>>::default();
+    /// mock.returns_when_opened(3, &gate);
+    ///
+    /// let mut future = mock.call(());
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Pending);
+    /// gate.open();
+    /// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(3));
+    /// ```
+    pub fn returns_when_opened<V: Into<T>>(&self, value: V, gate: &Gate) {
+        self.return_value(MockFuture::until_opened(value.into(), gate))
+    }
+
+    /// Return a future from `Mock::call` that never resolves. Useful for
+    /// testing timeouts.
+    pub fn returns_never(&self) {
+        self.return_value(MockFuture::never())
+    }
+
+    /// Use the future returned by `closure` as the future returned by each
+    /// call, like `Mock::use_closure`. `closure` can be an `async fn` or a
+    /// closure that returns an `async` block, so stubs can await channels,
+    /// timers or `Gate`s to coordinate with the test. The mocked async
+    /// method awaits the future.
+    ///
+    /// # Examples
+    ///
+    /// ```edition2018
+    /// use std::future::Future;
+    /// use std::pin::Pin;
+    /// use std::task::{Context, Poll, Waker};
+    /// use double::{Gate, Mock, MockFuture};
+    ///
+    /// let gate = Gate::new();
+    /// let opened = gate.clone();
+    /// let mock = Mock::<u32, MockFuture<u32>>::default();
+    /// mock.use_async_closure(Box::new(move |n| {
+    ///     let wait = MockFuture::until_opened((), &opened);
+    ///     async move {
+    ///         wait.await;
+    ///         n * 2
+    ///     }
+    /// }));
+    ///
+    /// let mut future = mock.call(21);
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Pending);
+    /// gate.open();
+    /// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(42));
+    /// ```
+    pub fn use_async_closure<F>(&self, closure: Closure<C, F>)
+        where C: 'static,
+              F: Future<Output = T> + Send + 'static
+    {
+        self.use_closure(Box::new(move |args| MockFuture::from_future(closure(args))))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<C, O, E> Mock<C, MockFuture<Result<O, E>>>
+    where C: Clone + Eq + Hash,
+          O: Clone,
+          E: Clone
+{
+    /// Return a future from `Mock::call` that resolves to `Err(err)` once
+    /// `delay` has passed since it was first polled. Useful for simulating
+    /// timeouts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::future::Future;
+    /// use std::pin::Pin;
+    /// use std::task::{Context, Poll, Waker};
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use double::{Mock, MockFuture};
+    ///
+    /// let mock = Mock::<(), MockFuture<Result<u8, &str>>>::new(MockFuture::ready(Ok(1)));
+    /// mock.returns_err_after("timed out", Duration::from_millis(10));
+    ///
+    /// let mut future = mock.call(());
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Pending);
+    /// thread::sleep(Duration::from_millis(10));
+    /// assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(Err("timed out")));
+    /// ```
+    pub fn returns_err_after<V: Into<E>>(&self, err: V, delay: Duration) {
+        self.return_value(MockFuture::after(Err(err.into()), delay))
+    }
+}
+
 impl<C, R> Debug for Mock<C, R>
     where C: Clone + Debug + Eq + Hash,
           R: Clone + Debug
 {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Functions and closures can't be printed, so only the args they are
+        // configured for are.
+        let fn_args: Vec<C> = self.fns.borrow().keys().cloned().collect();
+        let closure_args: Vec<C> = self.closures.borrow().keys().cloned().collect();
         f.debug_struct("Mock")
-            .field("default_return_value", &self.default_return_value)
+            .field("default_return_value", &*self.default_return_value.borrow())
             .field("return_value_sequence", &self.return_value_sequence)
+            .field("has_default_fn", &self.default_fn.borrow().is_some())
+            .field("has_default_closure", &self.default_closure.borrow().is_some())
             .field("return_values", &self.return_values)
+            .field("fns_for", &fn_args)
+            .field("closures_for", &closure_args)
+            .field("expectations", &self.expectations)
+            .field("policy", &self.policy)
+            .field("has_args_formatter", &self.args_formatter.borrow().is_some())
+            .field("name", &self.name)
+            .field("unused_stubs", &self.unused_stubs)
+            .field("num_calls", &self.num_calls())
+            .field("num_dropped_calls", &self.num_dropped_calls())
             .field("calls", &self.calls)
             .finish()
     }
 }
 
+// The behaviour that handled a call.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Behaviour {
+    Expectation,
+    ClosureForArgs,
+    FnForArgs,
+    ReturnValueForArgs,
+    DefaultFn,
+    DefaultClosure,
+    ReturnValueSequence,
+    DefaultReturnValue,
+}
+
+impl Behaviour {
+    // The name used in `tracing` events.
+    #[cfg(feature = "tracing")]
+    fn name(self) -> &'static str {
+        match self {
+            Behaviour::Expectation => "expectation",
+            Behaviour::ClosureForArgs => "closure_for_args",
+            Behaviour::FnForArgs => "fn_for_args",
+            Behaviour::ReturnValueForArgs => "return_value_for_args",
+            Behaviour::DefaultFn => "default_fn",
+            Behaviour::DefaultClosure => "default_closure",
+            Behaviour::ReturnValueSequence => "return_value_sequence",
+            Behaviour::DefaultReturnValue => "default_return_value",
+        }
+    }
+
+    // The method that configures a stub for specific args.
+    fn stub_method(self) -> &'static str {
+        match self {
+            Behaviour::ClosureForArgs => "use_closure_for",
+            Behaviour::FnForArgs => "use_fn_for",
+            _ => "return_value_for",
+        }
+    }
+}
+
+// A stub configured for specific args. Whether it has handled a call is set
+// while the mock's stubs are only borrowed for reading, so calls handled by
+// stubs don't wait for each other.
+struct Stub<T> {
+    behaviour: T,
+    used: AtomicBool,
+}
+
+impl<T: Clone> Stub<T> {
+    fn new(behaviour: T) -> Self {
+        Stub { behaviour, used: AtomicBool::new(false) }
+    }
+
+    // Marks the stub as used and returns its behaviour, to handle a call.
+    fn use_for_call(&self) -> T {
+        self.used.store(true, Ordering::SeqCst);
+        self.behaviour.clone()
+    }
+}
+
+impl<T: Debug> Debug for Stub<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.behaviour.fmt(f)
+    }
+}
+
+// Returns the args of the stubs in `stubs` that never handled a call.
+fn unused_args<'a, C, T>(stubs: &'a HashMap<C, Stub<T>>) -> impl Iterator<Item = C> + 'a
+    where C: Clone
+{
+    stubs
+        .iter()
+        .filter(|&(_, stub)| !stub.used.load(Ordering::SeqCst))
+        .map(|(args, _)| args.clone())
+}
+
 struct MatchInfo {
     num_expectations: usize,
     num_actual_calls: usize,
@@ -918,18 +3271,18 @@ impl MatchInfo {
         let expected_indices_matched = HashSet::from_iter(
             self.pattern_index_to_match_indices
             .keys()
-            .map(|k| k.clone()));
+            .copied());
         let unmatched_expectation_indices: HashSet<usize> = HashSet::from_iter(
             expected_indices
             .difference(&expected_indices_matched)
-            .map(|i| i.clone()));
+            .copied());
 
         for index in unmatched_expectation_indices.iter() {
             println!(
                 "No match found for expected call/pattern with index {}",
                 index);
         }
-        unmatched_expectation_indices.len() == 0
+        unmatched_expectation_indices.is_empty()
     }
 
     pub fn expectations_matched_in_order(&self) -> bool {
@@ -987,9 +3340,11 @@ impl MatchInfo {
         // This algorithm will only be revised if a legitmate performance issue
         // is found.
         if self.expectations_matched() {
-            let permutation_constraints = self.pattern_index_to_match_indices
-                .iter()
-                .sorted_by(|a, b| a.0.cmp(&b.0))
+            let mut pattern_matches: Vec<_> =
+                self.pattern_index_to_match_indices.iter().collect();
+            pattern_matches.sort_by_key(|&(pattern_index, _)| pattern_index);
+            let permutation_constraints = pattern_matches
+                .into_iter()
                 .map(
                     |(_, matching_call_indices)| matching_call_indices.clone())
                 .collect();
@@ -1017,11 +3372,22 @@ impl MatchInfo {
     }
 }
 
+fn assert_call_count(times: Times, num_calls: usize, description: &str, details: &str) {
+    if !times.contains(num_calls) {
+        panic!(
+            "expected Mock to be called {}{}, but it was called {} {}{}",
+            times,
+            description,
+            num_calls,
+            if num_calls == 1 { "time" } else { "times" },
+            details);
+    }
+}
+
 fn generate_permutations(constraints: &Vec<Vec<usize>>) -> Vec<Vec<usize>> {
     let mut output: Vec<Vec<usize>> = vec!();
     if !constraints.is_empty() {
-        let mut permutation_buffer: Vec<usize> = vec!();
-        permutation_buffer.resize(constraints.len(), 0);
+        let mut permutation_buffer: Vec<usize> = vec![0; constraints.len()];
 
         generate_permutations_impl(
             &mut output, &mut permutation_buffer, constraints, 0);
@@ -1037,7 +3403,7 @@ fn generate_permutations_impl(
 {
     if current_index < permutation_buffer.len() {
         for val in &constraints[current_index] {
-            permutation_buffer[current_index] = val.clone();
+            permutation_buffer[current_index] = *val;
             generate_permutations_impl(
                 output_permutations,
                 permutation_buffer,
@@ -1062,6 +3428,349 @@ fn is_strictly_increasing(sequence: &[usize]) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::panic;
+
+    fn panic_message<F: FnOnce() + panic::UnwindSafe>(f: F) -> String {
+        let payload = panic::catch_unwind(f).unwrap_err();
+        payload.downcast_ref::<String>().cloned().unwrap_or_default()
+    }
+
+    #[test]
+    fn call_count_panic_message_includes_actual_count() {
+        let message = panic_message(|| {
+            let mock = Mock::<i32, ()>::default();
+            mock.call(1);
+            mock.call(2);
+            mock.call(3);
+            mock.called_at_most(2);
+        });
+        assert_eq!(
+            "expected Mock to be called at most 2 times, but it was called 3 times",
+            message);
+    }
+
+    #[test]
+    fn expect_called_panic_message_names_the_mock_and_lists_calls() {
+        let mock = Mock::<(i32, i32), ()>::default();
+        mock.call((3, 4));
+        mock.call((5, 6));
+        let message = panic_message(panic::AssertUnwindSafe(|| {
+            let pattern: &dyn Fn(&(i32, i32)) -> bool = &|args| args.0 == 3;
+            mock.__private_expect_called(
+                "queue.push", Some("(eq(3), any)"), pattern, Times::exactly(2));
+        }));
+        assert_eq!(
+            "expected `queue.push` to be called exactly 2 times with args matching \
+             (eq(3), any), but it was called 1 time\nRecorded calls: [(3, 4), (5, 6)]",
+            message);
+
+        mock.__private_expect_called("queue.push", None, &|_| true, Times::exactly(2));
+        assert!(mock.verify_no_more_interactions());
+    }
+
+    // The pattern calls the mock each time it matches, while the mock is
+    // running it
+    #[test]
+    fn patterns_can_call_the_mock_they_are_matched_by() {
+        let mock = Mock::<i32, ()>::default();
+        let inner = mock.clone();
+        let calls_back = move |&x: &i32| x > 0 && { inner.call(0); true };
+        mock.call(1);
+        mock.call(2);
+
+        assert_eq!(2, mock.num_calls_with(&calls_back));
+        assert_eq!(vec!(0, 1), mock.calls_matching(&calls_back).indices());
+        assert_eq!(vec!(1, 2), mock.capture_args(&calls_back));
+        assert_eq!(8, mock.num_calls());
+
+        mock.expect_closure(Box::new(calls_back));
+        assert!(!mock.verify());
+        mock.call(3);
+        assert!(mock.verify());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn durations_are_recorded_for_stubs_once_enabled() {
+        let mock = Mock::<i32, i32>::new(0);
+        mock.use_fn_for(1, |x| x);
+        mock.call(1);
+        mock.set_recording_durations(true);
+        mock.call(1);
+        mock.call(2);
+        let timed: Vec<bool> = mock.records().map(|record| record.duration().is_some()).collect();
+        assert_eq!(vec!(false, true, false), timed);
+    }
+
+    #[test]
+    fn spied_calls_return_the_delegates_value_and_match_expectations() {
+        let mock = Mock::<i32, i32>::new(100);
+        mock.expect(&|&x| x == 2).times(1).returning(200);
+
+        assert_eq!(4, mock.__private_spy(2, || 4));
+        assert_eq!(6, mock.__private_spy(3, || 6));
+        assert!(mock.has_calls_exactly_in_order(vec!(2, 3)));
+        assert_eq!(vec!(4, 6), mock.records().return_values());
+        assert!(mock.verify());
+    }
+
+    #[test]
+    fn not_called_with_panic_message_includes_offending_call() {
+        let mock = Mock::<(i32, i32), ()>::default();
+        mock.call((1, 2));
+        mock.call((3, 4));
+        let location = mock.records().next_back().unwrap().location();
+        assert_eq!(file!(), location.file());
+
+        let message = panic_message(panic::AssertUnwindSafe(|| {
+            mock.assert_not_called_with((3, 4));
+        }));
+        assert_eq!(
+            format!(
+                "expected Mock not to be called with args (3, 4), but call with index 1 \
+                 from {} had args (3, 4)",
+                location),
+            message);
+    }
+
+    #[test]
+    fn unexpected_call_panic_message_includes_call_site() {
+        let mock = Mock::<i32, i32>::new(0);
+        mock.set_policy(MockPolicy::Strict);
+        let message = panic_message(panic::AssertUnwindSafe(|| { mock.call(1); }));
+        let location = mock.records().next_back().unwrap().location();
+        assert_eq!(line!() - 2, location.line());
+        assert!(message.contains(&format!(" from {}: ", location)));
+    }
+
+    #[test]
+    fn pattern_call_count_panic_message_includes_calls() {
+        let message = panic_message(|| {
+            let mock = Mock::<i32, ()>::default();
+            mock.call(1);
+            mock.call(2);
+            mock.called_with_pattern_exactly(&|&x| x > 1, 2);
+        });
+        assert_eq!(
+            "expected Mock to be called exactly 2 times with args matching \
+             the pattern, but it was called 1 time\nRecorded calls: [1, 2]",
+            message);
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn mock_can_be_called_from_another_thread() {
+        use std::time::Duration;
+
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let mock = Mock::<i32, i32>::default();
+        assert_send_sync(&mock);
+        mock.use_closure(Box::new(|x| x * 2));
+
+        let handle = {
+            let mock = mock.clone();
+            thread::spawn(move || mock.call(21))
+        };
+        assert!(mock.wait_for_call(&|&x| x == 21, Duration::from_secs(10)));
+        assert_eq!(42, handle.join().unwrap());
+        assert!(mock.called_with(21));
+    }
+
+    #[test]
+    fn debug_describes_stubs_expectations_and_calls() {
+        let mock = Mock::<i32, i32>::default();
+        mock.return_value_for(1, 10);
+        mock.use_closure_for(2, Box::new(|x| x * 3));
+        mock.expect(&|&x| x == 1);
+        mock.call(1);
+
+        let debug = format!("{:?}", mock);
+        assert!(debug.contains("return_values: {1: 10}"), "{}", debug);
+        assert!(debug.contains("closures_for: [2]"), "{}", debug);
+        assert!(debug.contains("num_matches: 1, satisfied: true"), "{}", debug);
+        assert!(debug.contains("num_calls: 1"), "{}", debug);
+        assert!(debug.contains("calls: [#0 called with 1, returned 10]"), "{}", debug);
+    }
+
+    #[test]
+    fn unexpected_calls_are_numbered_by_their_index() {
+        let mock = Mock::<i32, i32>::new(0);
+        mock.set_policy(MockPolicy::Strict);
+        mock.return_value_for(1, 10);
+        mock.call(1);
+
+        let message = panic_message(panic::AssertUnwindSafe(|| { mock.call(2); }));
+        assert!(
+            message.starts_with("Unexpected call with index 1 to Mock<i32, i32> from "),
+            "{}",
+            message);
+        assert_eq!(Some(2), mock.nth_call(1));
+    }
+
+    #[test]
+    fn args_formatter_is_used_in_failure_messages() {
+        // Doesn't implement `Debug`
+        #[derive(Clone, Eq, Hash, PartialEq)]
+        struct Key(u32);
+
+        let mock = Mock::<Key, i32>::new(0);
+        mock.format_args_with(Box::new(|key| format!("key-{}", key.0)));
+        mock.set_policy(MockPolicy::Strict);
+        let message = panic_message(panic::AssertUnwindSafe(|| { mock.call(Key(7)); }));
+        assert!(message.contains(" with args key-7: no behaviour configured"), "{}", message);
+
+        let mock = Mock::<(i32, i32), ()>::default();
+        mock.format_args_with(Box::new(|&(x, y)| format!("<{}, {}>", x, y)));
+        mock.call((1, 2));
+        let message = panic_message(panic::AssertUnwindSafe(|| {
+            mock.assert_not_called_with((1, 2));
+        }));
+        assert!(
+            message.starts_with("expected Mock not to be called with args <1, 2>"),
+            "{}",
+            message);
+        assert!(message.ends_with("had args <1, 2>"), "{}", message);
+    }
+
+    #[test]
+    fn unused_stubs_are_reported() {
+        let mock = Mock::<i32, i32>::default();
+        mock.set_name("MockStore::get");
+        mock.set_unused_stubs(UnusedStubs::Warn);
+        mock.return_value_for(1, 10);
+        mock.use_fn_for(2, |x| x);
+        mock.use_closure_for(2, Box::new(|x| x * 2));
+        mock.return_value(5);
+        mock.return_values(vec!(7, 8, 9));
+        mock.expect(&|&x| x == 3).times(0..);
+        mock.expect(&|&x| x == 4).times(Times::never());
+
+        mock.call(2);
+        mock.call(5);
+        assert_eq!(mock.describe_unused_stubs(), vec!(
+            "MockStore::get: return_value_for stub for args 1 was never used",
+            "MockStore::get: use_fn_for stub for args 2 was never used",
+            "MockStore::get: return_value stub was never used",
+            "MockStore::get: 2 of the values configured with return_values were never returned",
+            "MockStore::get: expectation with index 0 was never matched",
+        ));
+        assert!(mock.verify());
+
+        mock.set_unused_stubs(UnusedStubs::Fail);
+        assert!(!mock.verify());
+        mock.reset();
+        assert!(mock.describe_unused_stubs().is_empty());
+        assert!(mock.verify());
+    }
+
+    #[test]
+    fn stubs_are_marked_as_used_without_cloning_args_or_borrowing_them_for_writing() {
+        use std::sync::atomic::{self, AtomicUsize};
+
+        static NUM_CLONES: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Debug, Eq, Hash, PartialEq)]
+        struct Key(i32);
+
+        impl Clone for Key {
+            fn clone(&self) -> Self {
+                NUM_CLONES.fetch_add(1, atomic::Ordering::SeqCst);
+                Key(self.0)
+            }
+        }
+
+        let mock = Mock::<Key, i32>::default();
+        mock.set_counting_only(true);
+        mock.return_value_for(Key(1), 10);
+        mock.use_fn_for(Key(2), |Key(x)| x);
+        mock.return_value(5);
+        let num_clones = NUM_CLONES.load(atomic::Ordering::SeqCst);
+        {
+            // Calls would panic (or deadlock with `sync`) if they borrowed
+            // the stubs for writing while they're borrowed here
+            let _return_values = mock.return_values.borrow();
+            let _fns = mock.fns.borrow();
+            let _default_stub_used = mock.default_stub_used.borrow();
+            assert_eq!(10, mock.call(Key(1)));
+            assert_eq!(2, mock.call(Key(2)));
+            assert_eq!(5, mock.call(Key(3)));
+        }
+        assert_eq!(num_clones, NUM_CLONES.load(atomic::Ordering::SeqCst));
+        assert!(mock.describe_unused_stubs().is_empty());
+    }
+
+    #[test]
+    fn when_args_times_verifies_predicate_matches() {
+        let mock = Mock::<(i32, i32), i32>::default();
+        mock.when_args(|&(low, high)| low > high).times(1).returning(-1);
+
+        assert_eq!(-1, mock.call((2, 1)));
+        assert_eq!(0, mock.call((1, 2)));
+        assert!(mock.verify());
+        mock.call((3, 1));
+        assert!(!mock.verify());
+    }
+
+    #[test]
+    fn values_returned_by_reference_outlive_later_calls() {
+        let mock = Mock::<u32, String>::default();
+        mock.use_closure(Box::new(|count| "a".repeat(count as usize)));
+        let first = mock.call_ref(1);
+        let others: Vec<&String> = (2..100).map(|count| mock.call_ref(count)).collect();
+        assert_eq!("a", first);
+        assert_eq!("aa", others[0]);
+        assert_eq!(99, others[97].len());
+    }
+
+    #[test]
+    fn clones_share_behaviour_and_calls() {
+        let mock = Mock::<i32, i32>::default();
+        let clone = mock.clone();
+        clone.return_value_for(1, 10);
+        mock.expect(&|&x| x == 2);
+
+        assert_eq!(10, mock.call(1));
+        clone.call(2);
+        assert!(mock.verify());
+        assert_eq!(vec!(1, 2), clone.calls());
+
+        clone.reset();
+        assert!(!mock.called());
+        assert_eq!(0, mock.call(1));
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn calls_from_many_threads_are_recorded_in_order() {
+        let mock = Mock::<(usize, usize), ()>::default();
+        let handles: Vec<_> = (0..16)
+            .map(|thread_index| {
+                let mock = mock.clone();
+                thread::spawn(move || {
+                    for call in 0..100 {
+                        mock.call((thread_index, call));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(1600, mock.num_calls());
+        let records: Vec<_> = mock.records().collect();
+        assert_eq!((0..1600).collect::<Vec<usize>>(), mock.records().indices());
+        for thread_index in 0..16 {
+            let calls: Vec<usize> = records
+                .iter()
+                .filter(|record| record.args().0 == thread_index)
+                .map(|record| record.args().1)
+                .collect();
+            assert_eq!((0..100).collect::<Vec<usize>>(), calls);
+        }
+        assert!(records.iter().all(|record| record.return_value().is_some()));
+    }
 
     #[test]
     fn generate_permutations_no_constraints() {
@@ -1103,6 +3812,122 @@ mod tests {
             vec!(0, 1, 0, 4)));
     }
 
+    #[test]
+    fn closures_and_patterns_can_call_and_reconfigure_their_mock() {
+        let mock = Mock::<u32, u32>::new(1u32);
+        let inner = mock.clone();
+        mock.use_closure(Box::new(move |n| match n {
+            0 => {
+                inner.use_fn(|_| 100);
+                1
+            },
+            n => n * inner.call(n - 1),
+        }));
+        let inner = mock.clone();
+        mock.expect_closure(Box::new(move |&n| {
+            if n == 3 {
+                inner.expect(&|&n| n == 0);
+            }
+            n == 3
+        }));
+
+        assert_eq!(6, mock.call(3));
+        assert_eq!(100, mock.call(10));
+        assert!(mock.verify());
+        assert_eq!(vec!(3, 2, 1, 0, 10), mock.calls());
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn gated_calls_block_until_they_are_let_through() {
+        use std::thread;
+
+        let mock = Mock::<i32, i32>::new(1);
+        let gate = mock.gate();
+        let callers: Vec<_> = (0..2)
+            .map(|i| {
+                let mock = mock.clone();
+                thread::spawn(move || mock.call(i))
+            })
+            .collect();
+
+        assert!(gate.wait_for_waiting(2, Duration::from_secs(5)));
+        let mut in_flight = mock.in_flight_calls();
+        in_flight.sort();
+        assert_eq!(vec!(0, 1), in_flight);
+
+        gate.open();
+        for caller in callers {
+            assert_eq!(1, caller.join().unwrap());
+        }
+        assert!(mock.in_flight_calls().is_empty());
+    }
+
+    #[test]
+    fn return_values_of_call_ref_are_kept_after_the_mock_is_reset() {
+        let mock = Mock::<i32, String>::new("value".to_owned());
+        let first = mock.call_ref(1);
+        for i in 0..10 {
+            mock.call_ref(i);
+        }
+        mock.reset_calls();
+        mock.reset();
+        assert_eq!("value", first);
+        assert_eq!(11, mock.kept_return_values.borrow().len());
+    }
+
+    #[test]
+    fn calls_that_are_only_counted_do_not_clone_their_return_value_for_the_log() {
+        use std::sync::atomic::{self, AtomicUsize};
+
+        static NUM_CLONES: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Debug)]
+        struct Value;
+
+        impl Clone for Value {
+            fn clone(&self) -> Self {
+                NUM_CLONES.fetch_add(1, atomic::Ordering::SeqCst);
+                Value
+            }
+        }
+
+        let num_clones_of_call = |mock: &Mock<i32, Value>| {
+            let before = NUM_CLONES.load(atomic::Ordering::SeqCst);
+            mock.call(1);
+            NUM_CLONES.load(atomic::Ordering::SeqCst) - before
+        };
+        let mock = Mock::<i32, Value>::new(Value);
+        let recorded = num_clones_of_call(&mock);
+        mock.set_counting_only(true);
+        assert_eq!(recorded - 1, num_clones_of_call(&mock));
+    }
+
+    #[test]
+    fn calls_that_are_only_counted_are_still_handled() {
+        let mock = Mock::<i32, &str>::new("default");
+        mock.return_value_for(1, "one");
+        mock.expect(&|&x| x > 1).times(2);
+        let ones = mock.count_calls_with(&|&x| x == 1);
+        mock.set_counting_only(true);
+
+        assert_eq!("one", mock.call(1));
+        assert_eq!("default", mock.call(2));
+        mock.call(3);
+        assert!(mock.verify());
+        assert_eq!(3, mock.num_calls());
+        assert!(mock.calls().is_empty());
+
+        mock.set_counting_only(false);
+        mock.call(1);
+        assert_eq!(vec!(1), mock.calls());
+        assert_eq!(Some(3), mock.records().next().map(|record| record.index()));
+        assert_eq!(2, ones.count());
+
+        mock.reset_calls();
+        assert_eq!(0, ones.count());
+    }
+
     #[test]
     fn is_strictly_increasing_empty_sequence() {
         let sequence: Vec<usize> = vec!();
@@ -1132,4 +3957,30 @@ mod tests {
         let sequence: Vec<usize> = vec!(42, 43, 44, 1, 80, 15000);
         assert!(!is_strictly_increasing(sequence.as_slice()));
     }
+
+    #[cfg(loom)]
+    #[test]
+    fn calls_from_the_threads_of_a_loom_model_are_all_recorded() {
+        extern crate loom;
+
+        // Each call borrows many parts of the mock, so the interleavings are
+        // only explored up to a few preemptions
+        let mut model = loom::model::Builder::new();
+        model.preemption_bound = Some(2);
+        model.check(|| {
+            let mock = Mock::<i32, i32>::new(7);
+            let threads: Vec<_> = (0..2)
+                .map(|i| {
+                    let mock = mock.clone();
+                    loom::thread::spawn(move || mock.call(i))
+                })
+                .collect();
+            for thread in threads {
+                assert_eq!(7, thread.join().unwrap());
+            }
+
+            assert_eq!(2, mock.num_calls());
+            assert!(mock.has_calls(vec!(0, 1)));
+        });
+    }
 }
@@ -0,0 +1,191 @@
+use std::fmt;
+use std::thread;
+
+use crate::shared::Shared;
+
+/// A double for `FnOnce` callbacks, such as completion handlers.
+///
+/// Unlike `Mock`, the return value does not need to implement `Clone`. It is
+/// moved out of the double when the callback is invoked. Hand the callback
+/// returned by `MockFnOnce::as_fn_once` to the code under test and keep the
+/// `MockFnOnce` itself to inspect the call.
+///
+/// When the `MockFnOnce` is dropped, it panics if the callback was not
+/// called the expected number of times: exactly once for doubles constructed
+/// using `MockFnOnce::new`, and never for doubles constructed using
+/// `MockFnOnce::never`. Calling the callback of a double constructed using
+/// `never`, or calling callbacks of the same double more than once, panics
+/// straight away.
+///
+/// # Examples
+///
+/// ```
+/// use double::MockFnOnce;
+///
+/// struct Connection;
+///
+/// fn connect<F: FnOnce(&'static str) -> Connection>(address: &'static str, on_connect: F) {
+///     let _connection = on_connect(address);
+/// }
+///
+/// let callback = MockFnOnce::<&str, Connection>::new(Connection);
+/// connect("localhost", callback.as_fn_once());
+///
+/// assert!(callback.called());
+/// assert_eq!(callback.call_args(), Some("localhost"));
+/// ```
+///
+/// ```should_panic
+/// use double::MockFnOnce;
+///
+/// let callback = MockFnOnce::<i32, ()>::new(());
+/// let _unused = callback.as_fn_once();
+/// // panics: callback was never called
+/// ```
+pub struct MockFnOnce<C, R> {
+    state: Shared<MockFnOnceState<C, R>>,
+}
+
+struct MockFnOnceState<C, R> {
+    return_value: Option<R>,
+    args: Option<C>,
+    expect_call: bool,
+    num_calls: usize,
+}
+
+impl<C, R> MockFnOnce<C, R> {
+    /// Creates a double that must be called exactly once, and returns
+    /// `return_value` when it is.
+    pub fn new<T: Into<R>>(return_value: T) -> Self {
+        MockFnOnce::with_state(Some(return_value.into()), true)
+    }
+
+    /// Creates a double that must never be called.
+    pub fn never() -> Self {
+        MockFnOnce::with_state(None, false)
+    }
+
+    fn with_state(return_value: Option<R>, expect_call: bool) -> Self {
+        MockFnOnce {
+            state: Shared::new(MockFnOnceState {
+                return_value,
+                args: None,
+                expect_call,
+                num_calls: 0,
+            }),
+        }
+    }
+
+    /// Returns the callback to hand to the code under test.
+    pub fn as_fn_once(&self) -> impl FnOnce(C) -> R {
+        let state = self.state.clone();
+        move |args| {
+            let mut state = state.borrow_mut();
+            state.num_calls += 1;
+            if !state.expect_call {
+                panic!("MockFnOnce was called, but was expected never to be called");
+            }
+            match state.return_value.take() {
+                Some(return_value) => {
+                    state.args = Some(args);
+                    return_value
+                },
+                None => panic!(
+                    "MockFnOnce was called {} times, but was expected to be called once",
+                    state.num_calls),
+            }
+        }
+    }
+
+    /// Returns true if the callback has been called.
+    pub fn called(&self) -> bool {
+        self.state.borrow().num_calls > 0
+    }
+
+    /// Returns true if the callback has been called the expected number of
+    /// times so far. Prints a description of the failure if not.
+    pub fn verify(&self) -> bool {
+        let state = self.state.borrow();
+        let expected_calls = if state.expect_call { 1 } else { 0 };
+        if state.num_calls != expected_calls {
+            println!(
+                "MockFnOnce was called {} times, but was expected to be called {}",
+                state.num_calls,
+                if state.expect_call { "once" } else { "never" });
+            return false;
+        }
+        true
+    }
+}
+
+impl<C: Clone, R> MockFnOnce<C, R> {
+    /// Returns the arguments the callback was called with, or `None` if it
+    /// has not been called.
+    pub fn call_args(&self) -> Option<C> {
+        self.state.borrow().args.clone()
+    }
+}
+
+impl<C, R> Drop for MockFnOnce<C, R> {
+    fn drop(&mut self) {
+        // Don't turn an existing panic (e.g. a failed assertion) into an abort
+        if !thread::panicking() && !self.verify() {
+            panic!("MockFnOnce was not called the expected number of times");
+        }
+    }
+}
+
+impl<C: fmt::Debug, R> fmt::Debug for MockFnOnce<C, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.borrow();
+        f.debug_struct("MockFnOnce")
+            .field("expect_call", &state.expect_call)
+            .field("num_calls", &state.num_calls)
+            .field("args", &state.args)
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic;
+
+    // A return type that can't be cloned
+    #[derive(Debug, PartialEq)]
+    struct Handle(i32);
+
+    #[test]
+    fn moves_return_value_out() {
+        let double = MockFnOnce::<i32, Handle>::new(Handle(7));
+        let callback = double.as_fn_once();
+        assert_eq!(Handle(7), callback(3));
+        assert!(double.verify());
+        assert_eq!(Some(3), double.call_args());
+    }
+
+    #[test]
+    fn calling_twice_panics() {
+        let double = MockFnOnce::<i32, Handle>::new(Handle(7));
+        double.as_fn_once()(1);
+        let second = double.as_fn_once();
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| second(2))).is_err());
+        assert!(!double.verify());
+        // Called twice, so dropping `double` would panic
+        std::mem::forget(double);
+    }
+
+    #[test]
+    fn drop_panics_unless_expectation_met() {
+        let never_called = panic::catch_unwind(|| {
+            let double = MockFnOnce::<i32, ()>::new(());
+            let _callback = double.as_fn_once();
+        });
+        assert!(never_called.is_err());
+
+        let never = MockFnOnce::<i32, ()>::never();
+        let _callback = never.as_fn_once();
+        assert!(never.verify());
+    }
+}
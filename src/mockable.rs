@@ -0,0 +1,131 @@
+use std::cell::RefCell;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::thread::LocalKey;
+use crate::mock::Mock;
+
+/// The thread-local slot that a function defined using `mockable_fn!` (or a
+/// shim generated by `mock_extern_fn!`) routes its calls through. It holds
+/// the mock that handles the function's calls on the current thread, if
+/// there is one.
+///
+/// Slots are declared by those macros, so they are rarely used directly.
+pub struct MockSlot<C, R>
+    where C: Clone + Eq + Hash,
+          R: Clone
+{
+    mock: RefCell<Option<Mock<C, R>>>,
+}
+
+impl<C, R> MockSlot<C, R>
+    where C: Clone + Eq + Hash,
+          R: Clone
+{
+    /// Creates an empty slot, which routes calls to the function's real
+    /// implementation.
+    pub const fn new() -> Self {
+        MockSlot { mock: RefCell::new(None) }
+    }
+
+    /// Returns the mock that handles calls on the current thread, if any.
+    pub fn mock(&self) -> Option<Mock<C, R>> {
+        self.mock.borrow().clone()
+    }
+
+    fn replace(&self, mock: Option<Mock<C, R>>) -> Option<Mock<C, R>> {
+        self.mock.replace(mock)
+    }
+}
+
+impl<C, R> Default for MockSlot<C, R>
+    where C: Clone + Eq + Hash,
+          R: Clone
+{
+    fn default() -> Self {
+        MockSlot::new()
+    }
+}
+
+/// RAII guard returned by the `mock_` functions generated by `mockable_fn!`
+/// and `mock_extern_fn!`. While it's alive, calls to the function on the
+/// current thread are handled by the mock. Dropping it restores the mock that
+/// handled them before it was created (or the real implementation), so
+/// guards can be nested.
+#[must_use = "the function is restored as soon as the guard is dropped"]
+pub struct ScopedMock<C, R>
+    where C: Clone + Eq + Hash + 'static,
+          R: Clone + 'static
+{
+    slot: &'static LocalKey<MockSlot<C, R>>,
+    previous: Option<Mock<C, R>>,
+    // The slot is thread-local, so the guard must be dropped on the thread
+    // that created it
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<C, R> ScopedMock<C, R>
+    where C: Clone + Eq + Hash + 'static,
+          R: Clone + 'static
+{
+    /// Routes the calls made through `slot` on the current thread to `mock`
+    /// until the returned guard is dropped. Calls are recorded by `mock` and
+    /// its clones.
+    pub fn new(slot: &'static LocalKey<MockSlot<C, R>>, mock: &Mock<C, R>) -> Self {
+        let previous = slot.with(|slot| slot.replace(Some(mock.clone())));
+        ScopedMock { slot, previous, _not_send: PhantomData }
+    }
+}
+
+impl<C, R> Drop for ScopedMock<C, R>
+    where C: Clone + Eq + Hash + 'static,
+          R: Clone + 'static
+{
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        // The slot is gone if the thread is exiting, and so are its calls
+        let _ = self.slot.try_with(|slot| slot.replace(previous));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    thread_local! {
+        static SLOT: MockSlot<u32, u32> = const { MockSlot::new() };
+    }
+
+    fn double(value: u32) -> u32 {
+        match SLOT.with(MockSlot::mock) {
+            Some(mock) => mock.call(value),
+            None => value * 2,
+        }
+    }
+
+    #[test]
+    fn guards_route_calls_to_mocks_until_dropped() {
+        let outer = Mock::new(1u32);
+        let inner = Mock::new(2u32);
+        {
+            let _outer = ScopedMock::new(&SLOT, &outer);
+            assert_eq!(1, double(5));
+            {
+                let _inner = ScopedMock::new(&SLOT, &inner);
+                assert_eq!(2, double(6));
+            }
+            assert_eq!(1, double(7));
+        }
+        assert_eq!(16, double(8));
+        assert!(outer.has_calls_exactly_in_order(vec!(5u32, 7u32)));
+        assert!(inner.has_calls_exactly(vec!(6u32)));
+    }
+
+    #[test]
+    fn other_threads_call_the_real_implementation() {
+        let mock = Mock::new(1u32);
+        let _guard = ScopedMock::new(&SLOT, &mock);
+        assert_eq!(6, ::std::thread::spawn(|| double(3)).join().unwrap());
+        assert!(!mock.called());
+    }
+}
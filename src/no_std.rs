@@ -0,0 +1,46 @@
+// Stands in for `std` when `double` is built without its `std` feature, so the
+// modules that mocks are built from can keep importing from `std::` (on the
+// 2015 edition, `use` paths start at the crate root). Only the parts of `std`
+// that `core`, `alloc` and `hashbrown` provide are here, so a module that uses
+// anything else doesn't build without `std`.
+
+#![allow(unused_imports)]
+
+extern crate hashbrown;
+
+pub use core::{any, cell, cmp, convert, fmt, future, hash, iter, marker, mem, ops, option, panic,
+               pin, ptr, result, slice, str, task, time};
+pub use alloc::{borrow, boxed, format, rc, string, vec};
+
+pub mod sync {
+    pub use core::sync::atomic;
+}
+
+pub mod collections {
+    pub use alloc::collections::*;
+    pub use super::hashbrown::{HashMap, HashSet};
+}
+
+// The prelude of `std`, which modules that build without it import explicitly
+pub mod prelude {
+    pub use core::prelude::rust_2015::*;
+    pub use alloc::borrow::ToOwned;
+    pub use alloc::boxed::Box;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec::Vec;
+}
+
+// Failures are printed to stdout and warnings to stderr, and without `std`
+// there's neither to print them to. Mocks still fail verification, and their
+// `assert_*` methods still panic with the description of each failure.
+macro_rules! println {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+macro_rules! eprintln {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
@@ -0,0 +1,130 @@
+#[cfg(feature = "std")]
+use std::cell::Cell;
+
+/// Determines how a `Mock` reacts to *unexpected* calls.
+///
+/// A call is unexpected if no behaviour was configured for it. That is, the
+/// call's arguments match no expectation set using `Mock::expect`, no
+/// behaviour configured using `return_value_for`, `use_fn_for` or
+/// `use_closure_for`, and no default behaviour was configured using
+/// `return_value`, `return_values`, `use_fn` or `use_closure` after the mock
+/// was constructed. Unexpected calls always return the mock's
+/// construction-time default return value.
+///
+/// Every `Mock` takes its policy from the policy that is active on the
+/// current thread when the mock is constructed. The active policy is `Nice`
+/// unless overridden using `double::set_policy`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum MockPolicy {
+    /// Unexpected calls are silently allowed.
+    #[default]
+    Nice,
+    /// Unexpected calls are allowed, but a warning is printed to stderr for
+    /// each one.
+    Warn,
+    /// Unexpected calls cause a panic.
+    Strict,
+}
+
+/// Determines how `Mock::verify` reacts to stubs and expectations that were
+/// configured but never used. See `Mock::set_unused_stubs`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum UnusedStubs {
+    /// Unused stubs are not reported (the default).
+    #[default]
+    Ignore,
+    /// A warning is printed to stderr for each unused stub.
+    Warn,
+    /// Each unused stub is printed, and verification fails.
+    Fail,
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    static ACTIVE_POLICY: Cell<MockPolicy> = const { Cell::new(MockPolicy::Nice) };
+}
+
+/// Returns the policy that will be used by mocks constructed on the current
+/// thread.
+///
+/// Without the `std` feature there are no thread locals to hold the active
+/// policy, so this is always `MockPolicy::Nice`. Use `Mock::set_policy` or
+/// `MockBuilder::policy` to give mocks a different policy.
+pub fn active_policy() -> MockPolicy {
+    #[cfg(feature = "std")]
+    {
+        ACTIVE_POLICY.with(|policy| policy.get())
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        MockPolicy::Nice
+    }
+}
+
+/// Make `policy` the active policy on the current thread until the returned
+/// guard is dropped.
+///
+/// All mocks constructed while the guard is alive use `policy`. Mocks keep
+/// their policy after the guard is dropped. Dropping the guard restores the
+/// policy that was active before it was created, so guards can be nested.
+///
+/// # Examples
+///
+/// ```
+/// use double::{Mock, MockPolicy};
+///
+/// let nice = Mock::<i32, i32>::default();
+/// {
+///     let _guard = double::set_policy(MockPolicy::Strict);
+///     let strict = Mock::<i32, i32>::default();
+///     assert_eq!(strict.policy(), MockPolicy::Strict);
+/// }
+/// assert_eq!(nice.policy(), MockPolicy::Nice);
+/// assert_eq!(double::active_policy(), MockPolicy::Nice);
+/// ```
+#[cfg(feature = "std")]
+pub fn set_policy(policy: MockPolicy) -> PolicyGuard {
+    let previous = ACTIVE_POLICY.with(|active| active.replace(policy));
+    PolicyGuard { previous }
+}
+
+/// RAII guard returned by `double::set_policy`. Restores the previously
+/// active policy when dropped.
+#[cfg(feature = "std")]
+#[must_use = "the policy is reset as soon as the guard is dropped"]
+#[derive(Debug)]
+pub struct PolicyGuard {
+    previous: MockPolicy,
+}
+
+#[cfg(feature = "std")]
+impl Drop for PolicyGuard {
+    fn drop(&mut self) {
+        ACTIVE_POLICY.with(|active| active.set(self.previous));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_policy_is_nice() {
+        assert_eq!(MockPolicy::Nice, active_policy());
+    }
+
+    #[test]
+    fn guard_restores_previous_policy() {
+        {
+            let _strict = set_policy(MockPolicy::Strict);
+            assert_eq!(MockPolicy::Strict, active_policy());
+            {
+                let _warn = set_policy(MockPolicy::Warn);
+                assert_eq!(MockPolicy::Warn, active_policy());
+            }
+            assert_eq!(MockPolicy::Strict, active_policy());
+        }
+        assert_eq!(MockPolicy::Nice, active_policy());
+    }
+}
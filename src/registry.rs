@@ -0,0 +1,317 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::expectation::StatusRef;
+use crate::shared::{AtomicUsize, Ordering, Shared, SharedPtr};
+
+type Registry = Rc<RefCell<Vec<RegisteredMock>>>;
+
+// The parts of a mock's state that don't depend on its argument and return
+// types, shared with the registry.
+pub(crate) struct RegisteredMock {
+    // Describes the mock's type, for mocks without a name
+    pub(crate) description: String,
+    pub(crate) name: Shared<Option<String>>,
+    pub(crate) expectations: Shared<Vec<StatusRef>>,
+    pub(crate) num_calls: SharedPtr<AtomicUsize>,
+    pub(crate) verified_calls: Shared<HashSet<usize>>,
+}
+
+impl RegisteredMock {
+    fn describe(&self) -> String {
+        self.name.borrow().clone().unwrap_or_else(|| self.description.clone())
+    }
+
+    // Summarises the mock's calls and expectations on one line, e.g.
+    // `3 calls (2 matched, 1 unmatched), 1 expectation (1 unmet)`.
+    fn summarise(&self) -> String {
+        let num_calls = self.num_calls.load(Ordering::SeqCst);
+        let num_matched = self.verified_calls.borrow().len();
+        let calls = if num_calls == 0 {
+            "no calls".to_owned()
+        } else {
+            format!(
+                "{} ({} matched, {} unmatched)",
+                count(num_calls, "call"),
+                num_matched,
+                num_calls.saturating_sub(num_matched))
+        };
+        let expectations = self.expectations.borrow();
+        let num_unmet = expectations
+            .iter()
+            .filter(|status| !status.borrow().is_verified())
+            .count();
+        let expectations = if expectations.is_empty() {
+            "no expectations".to_owned()
+        } else {
+            format!("{} ({} unmet)", count(expectations.len(), "expectation"), num_unmet)
+        };
+        format!("{}, {}", calls, expectations)
+    }
+
+    // Describes each unmet expectation of the mock.
+    fn unmet_expectations(&self) -> Vec<String> {
+        self.expectations
+            .borrow()
+            .iter()
+            .enumerate()
+            .filter(|&(_, status)| !status.borrow().is_verified())
+            .map(|(index, status)| format!(
+                "Expectation with index {} {}", index, status.borrow().describe_failure()))
+            .collect()
+    }
+}
+
+thread_local! {
+    static ACTIVE_REGISTRY: RefCell<Option<Registry>> = const { RefCell::new(None) };
+}
+
+/// Start registering mocks on the current thread until the returned guard is
+/// dropped.
+///
+/// All mocks constructed while the guard is alive (including the method mocks
+/// of `struct`s generated by `mock_trait!`) register themselves with it. Use
+/// `double::verify_all` to verify the expectations of every registered mock
+/// at once. Dropping the guard restores the registry that was active before
+/// it was created, so guards can be nested.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use double::Mock;
+/// use double::matcher::*;
+///
+/// # fn main() {
+/// let _registry = double::register_mocks();
+/// let reader = Mock::<i32, ()>::default();
+/// let writer = Mock::<&str, ()>::default();
+/// reader.expect(p!(eq, 1));
+/// writer.expect(p!(eq, "data"));
+///
+/// reader.call(1);
+/// assert!(!double::verify_all());  // `writer` expectation unmet
+///
+/// writer.call("data");
+/// assert!(double::verify_all());
+/// # }
+/// ```
+pub fn register_mocks() -> RegistryGuard {
+    let previous = ACTIVE_REGISTRY.with(|active| {
+        active.replace(Some(Rc::new(RefCell::new(vec!()))))
+    });
+    RegistryGuard { previous }
+}
+
+/// Returns true if the expectations of every mock registered with the active
+/// registry (see `double::register_mocks`) are met. Prints a description of
+/// each unmet expectation of every mock, so all failures are reported
+/// together.
+///
+/// Returns true if no registry is active.
+pub fn verify_all() -> bool {
+    let registry = match ACTIVE_REGISTRY.with(|active| active.borrow().clone()) {
+        Some(registry) => registry,
+        None => return true,
+    };
+    let mut verified = true;
+    for (mock_index, mock) in registry.borrow().iter().enumerate() {
+        for (index, status) in mock.expectations.borrow().iter().enumerate() {
+            let status = status.borrow();
+            if !status.is_verified() {
+                println!(
+                    "Expectation with index {} of mock with index {} ({}) {}",
+                    index,
+                    mock_index,
+                    mock.describe(),
+                    status.describe_failure());
+                verified = false;
+            }
+        }
+    }
+    if !verified {
+        print!("{}", describe_interactions(&registry.borrow()));
+    }
+    verified
+}
+
+/// Prints a summary of the interactions with every mock registered with the
+/// active registry (see `double::register_mocks`): how many calls each mock
+/// received, how many of them were matched by a successful verification or
+/// an expectation, and its unmet expectations. `double::verify_all` prints
+/// the summary automatically if verification fails.
+///
+/// The method mocks of `struct`s generated by `mock_trait!` are listed under
+/// the name of their mock, e.g.
+///
+/// ```text
+/// Interactions with 3 registered mocks:
+/// MockBalanceSheet
+///   profit: 2 calls (1 matched, 1 unmatched), 1 expectation (1 unmet)
+///     Expectation with index 0 expected to be called exactly 2 times, but was called 1 time
+///   close: no calls, no expectations
+/// Mock<i32, ()>: 1 call (1 matched, 0 unmatched), no expectations
+/// ```
+///
+/// Prints nothing if no registry is active.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// mock_trait!(
+///     MockBalanceSheet,
+///     profit(u32, u32) -> i32,
+///     close(()) -> ());
+///
+/// # fn main() {
+/// let _registry = double::register_mocks();
+/// let sheet = MockBalanceSheet::default();
+/// sheet.profit.call((500, 250));
+///
+/// double::report();
+/// # }
+/// ```
+pub fn report() {
+    if let Some(registry) = ACTIVE_REGISTRY.with(|active| active.borrow().clone()) {
+        print!("{}", describe_interactions(&registry.borrow()));
+    }
+}
+
+// Register a mock with the active registry, if there is one.
+pub(crate) fn register(mock: RegisteredMock) {
+    ACTIVE_REGISTRY.with(|active| {
+        if let Some(ref registry) = *active.borrow() {
+            registry.borrow_mut().push(mock);
+        }
+    });
+}
+
+// Renders the report printed by `double::report`. Consecutive mocks named
+// `<mock>::<method>` are grouped under `<mock>`.
+fn describe_interactions(mocks: &[RegisteredMock]) -> String {
+    let mut report = format!(
+        "Interactions with {} registered {}:\n",
+        mocks.len(),
+        if mocks.len() == 1 { "mock" } else { "mocks" });
+    let mut current_group: Option<String> = None;
+    for mock in mocks {
+        let name = mock.describe();
+        let split_name = mock.name.borrow().as_ref().and_then(|name| {
+            name.rsplit_once("::").map(|(group, method)| (group.to_owned(), method.to_owned()))
+        });
+        let (group, method) = match split_name {
+            Some((group, method)) => (Some(group), method),
+            None => (None, name),
+        };
+        let indent = if group.is_some() { "  " } else { "" };
+        if let Some(ref group) = group {
+            if current_group.as_ref() != Some(group) {
+                report.push_str(&format!("{}\n", group));
+            }
+        }
+        current_group = group;
+        report.push_str(&format!("{}{}: {}\n", indent, method, mock.summarise()));
+        for expectation in mock.unmet_expectations() {
+            report.push_str(&format!("{}  {}\n", indent, expectation));
+        }
+    }
+    report
+}
+
+// e.g. `1 call` or `3 calls`
+fn count(n: usize, noun: &str) -> String {
+    format!("{} {}{}", n, noun, if n == 1 { "" } else { "s" })
+}
+
+/// RAII guard returned by `double::register_mocks`. Restores the previously
+/// active registry when dropped.
+#[must_use = "mocks stop being registered as soon as the guard is dropped"]
+pub struct RegistryGuard {
+    previous: Option<Registry>,
+}
+
+impl Drop for RegistryGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        ACTIVE_REGISTRY.with(|active| *active.borrow_mut() = previous);
+    }
+}
+
+impl fmt::Debug for RegistryGuard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RegistryGuard").finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::Mock;
+
+    #[test]
+    fn verify_all_without_registry_is_true() {
+        let mock = Mock::<i32, ()>::default();
+        mock.expect(&|&x| x == 1);
+        assert!(verify_all());
+    }
+
+    #[test]
+    fn guard_restores_previous_registry() {
+        let _outer = register_mocks();
+        let outer_mock = Mock::<i32, ()>::default();
+        outer_mock.expect(&|&x| x == 1);
+        {
+            let _inner = register_mocks();
+            let inner_mock = Mock::<i32, ()>::default();
+            inner_mock.expect(&|&x| x == 2);
+            inner_mock.call(2);
+            assert!(verify_all());
+        }
+        assert!(!verify_all());
+    }
+
+    #[test]
+    fn report_groups_method_mocks_under_their_mock() {
+        let _registry = register_mocks();
+        let profit = Mock::<(u32, u32), i32>::default();
+        profit.set_name("MockBalanceSheet::profit");
+        profit.expect(&|_| true).times(2);
+        let close = Mock::<(), ()>::default();
+        close.set_name("MockBalanceSheet::close");
+        let log = Mock::<i32, ()>::default();
+
+        profit.call((500, 250));
+        profit.call((1, 2));
+        profit.call((3, 4));
+        log.call(1);
+        assert!(log.called_with(1));
+
+        let report = ACTIVE_REGISTRY.with(|active| {
+            describe_interactions(&active.borrow().as_ref().unwrap().borrow())
+        });
+        assert_eq!(report, [
+            "Interactions with 3 registered mocks:",
+            "MockBalanceSheet",
+            "  profit: 3 calls (3 matched, 0 unmatched), 1 expectation (1 unmet)",
+            "    Expectation with index 0 expected to be called exactly 2 times, but was \
+             called 3 times",
+            "  close: no calls, no expectations",
+            "Mock<i32, ()>: 1 call (1 matched, 0 unmatched), no expectations",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn cleared_expectations_are_not_verified() {
+        let _registry = register_mocks();
+        let mock = Mock::<i32, ()>::default();
+        mock.expect(&|&x| x == 1);
+        assert!(!verify_all());
+        mock.reset();
+        assert!(verify_all());
+    }
+}
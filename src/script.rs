@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use crate::future::Timer;
+
+// The script of a scripted async double, e.g. `MockStream`, whose steps are
+// played out as it's polled. Delay and pending steps hold back the steps of
+// type `T` that follow them, which are what the double yields, reads, etc.
+#[derive(Debug)]
+pub(crate) struct Script<T> {
+    steps: VecDeque<Step<T>>,
+    // When the delay at the front of the script was first polled, and the
+    // timer that wakes the double once it has passed
+    delay_started: Option<Instant>,
+    delay_timer: Option<Timer>,
+    pending_forever: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Step<T> {
+    Next(T),
+    Delay(Duration),
+    Pending(usize),
+}
+
+impl<T> Script<T> {
+    pub(crate) fn push(&mut self, next: T) {
+        self.steps.push_back(Step::Next(next));
+    }
+
+    // Used by `MockAsyncRead` to put back what's left of a chunk that didn't
+    // fit in a read
+    #[cfg(feature = "tokio")]
+    pub(crate) fn push_front(&mut self, next: T) {
+        self.steps.push_front(Step::Next(next));
+    }
+
+    pub(crate) fn push_delay(&mut self, delay: Duration) {
+        self.steps.push_back(Step::Delay(delay));
+    }
+
+    pub(crate) fn push_pending(&mut self, num_polls: usize) {
+        self.steps.push_back(Step::Pending(num_polls));
+    }
+
+    // Makes the script pend forever once it's exhausted
+    pub(crate) fn set_pending_forever(&mut self) {
+        self.pending_forever = true;
+    }
+
+    // Used by `MockStream` for its size hint, like `remaining`
+    #[cfg(feature = "futures")]
+    pub(crate) fn is_pending_forever(&self) -> bool {
+        self.pending_forever
+    }
+
+    // The steps of type `T` that haven't been played out yet
+    #[cfg(feature = "futures")]
+    pub(crate) fn remaining(&self) -> impl Iterator<Item = &T> {
+        self.steps.iter().filter_map(|step| match *step {
+            Step::Next(ref next) => Some(next),
+            _ => None,
+        })
+    }
+
+    // Plays out the script up to its next step of type `T`, which is popped,
+    // or returns `None` once it's exhausted. A background thread wakes the
+    // waker once a delay has passed, so this works with any executor.
+    pub(crate) fn poll_next(&mut self, cx: &mut Context) -> Poll<Option<T>> {
+        loop {
+            match self.steps.pop_front() {
+                Some(Step::Next(next)) => return Poll::Ready(Some(next)),
+                Some(Step::Delay(delay)) => {
+                    let now = Instant::now();
+                    let deadline = *self.delay_started.get_or_insert(now) + delay;
+                    if now >= deadline {
+                        self.delay_started = None;
+                        self.delay_timer = None;
+                        continue;
+                    }
+                    self.steps.push_front(Step::Delay(delay));
+                    match self.delay_timer {
+                        Some(ref timer) => timer.update(cx.waker()),
+                        None => self.delay_timer = Some(Timer::start(deadline, cx.waker())),
+                    }
+                    return Poll::Pending;
+                },
+                Some(Step::Pending(0)) => continue,
+                Some(Step::Pending(num_polls)) => {
+                    self.steps.push_front(Step::Pending(num_polls - 1));
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                },
+                None if self.pending_forever => return Poll::Pending,
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+// Clones of a double that's part way through a delay start their own timer
+impl<T: Clone> Clone for Script<T> {
+    fn clone(&self) -> Self {
+        Script {
+            steps: self.steps.clone(),
+            delay_started: self.delay_started,
+            delay_timer: None,
+            pending_forever: self.pending_forever,
+        }
+    }
+}
+
+impl<T> Default for Script<T> {
+    fn default() -> Self {
+        Script {
+            steps: VecDeque::new(),
+            delay_started: None,
+            delay_timer: None,
+            pending_forever: false,
+        }
+    }
+}
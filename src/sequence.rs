@@ -0,0 +1,192 @@
+use std::fmt;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+use crate::shared::Shared;
+
+/// An ordering constraint across expectations, possibly on different mocks.
+///
+/// Expectations join a sequence using `Expectation::in_sequence`, in the
+/// order they are expected to be matched. A call that matches an expectation
+/// in a sequence is out of order if any earlier expectation in the sequence
+/// is not yet satisfied, or if a later expectation has already been matched.
+/// Out of order calls cause both `Mock::verify` (of the mock the expectation
+/// was set on) and `Sequence::verify` to fail.
+///
+/// `Sequence` is a handle. Clones of it refer to the same sequence.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use double::{Mock, Sequence};
+/// use double::matcher::*;
+///
+/// # fn main() {
+/// let open = Mock::<&str, ()>::default();
+/// let write = Mock::<(i32, String), ()>::default();
+/// let close = Mock::<i32, ()>::default();
+///
+/// let seq = Sequence::new();
+/// open.expect(p!(eq, "data.txt")).in_sequence(&seq);
+/// write.expect(matcher!(p!(eq, 3), p!(any))).in_sequence(&seq);
+/// close.expect(p!(eq, 3)).in_sequence(&seq);
+///
+/// open.call("data.txt");
+/// write.call((3, "hello".to_owned()));
+/// close.call(3);
+///
+/// assert!(seq.verify());
+/// assert!(open.verify() && write.verify() && close.verify());
+/// # }
+/// ```
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use double::{Mock, Sequence};
+/// use double::matcher::*;
+///
+/// # fn main() {
+/// let write = Mock::<i32, ()>::default();
+/// let close = Mock::<i32, ()>::default();
+///
+/// let seq = Sequence::new();
+/// write.expect(p!(eq, 3)).in_sequence(&seq);
+/// close.expect(p!(eq, 3)).in_sequence(&seq);
+///
+/// close.call(3);  // closed before writing
+/// write.call(3);
+///
+/// assert!(!seq.verify());
+/// assert!(!close.verify());
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct Sequence {
+    state: Shared<SequenceState>,
+}
+
+#[cfg(not(feature = "sync"))]
+pub(crate) type Step = Box<dyn Fn() -> bool>;
+#[cfg(feature = "sync")]
+pub(crate) type Step = Box<dyn Fn() -> bool + Send + Sync>;
+
+#[derive(Default)]
+struct SequenceState {
+    // Returns true if the step's expectation is satisfied
+    steps: Vec<Step>,
+    furthest_matched: Option<usize>,
+    violations: Vec<String>,
+}
+
+impl Sequence {
+    /// Creates a new, empty sequence.
+    pub fn new() -> Self {
+        Sequence::default()
+    }
+
+    /// Returns true if no call matched an expectation in the sequence out of
+    /// order. Prints a description of each out of order call.
+    ///
+    /// This does not check that the expectations themselves are satisfied.
+    /// Use `Mock::verify` for that.
+    pub fn verify(&self) -> bool {
+        let state = self.state.borrow();
+        for violation in &state.violations {
+            println!("{}", violation);
+        }
+        state.violations.is_empty()
+    }
+
+    /// Returns the number of expectations in the sequence.
+    pub fn len(&self) -> usize {
+        self.state.borrow().steps.len()
+    }
+
+    /// Returns true if no expectations have joined the sequence.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn add_step(&self, is_satisfied: Step) -> usize {
+        let mut state = self.state.borrow_mut();
+        state.steps.push(is_satisfied);
+        state.steps.len() - 1
+    }
+
+    // Record that the expectation at `position` was matched. Returns false
+    // if the match was out of order.
+    pub(crate) fn record_match(&self, position: usize) -> bool {
+        let unsatisfied = {
+            let state = self.state.borrow();
+            state.steps[..position]
+                .iter()
+                .position(|is_satisfied| !is_satisfied())
+        };
+        let mut state = self.state.borrow_mut();
+        let violation = match (unsatisfied, state.furthest_matched) {
+            (Some(earlier), _) => Some(format!(
+                "Expectation {} in sequence was matched before expectation {} was satisfied",
+                position,
+                earlier)),
+            (None, Some(furthest)) if furthest > position => Some(format!(
+                "Expectation {} in sequence was matched after expectation {}",
+                position,
+                furthest)),
+            _ => None,
+        };
+        state.furthest_matched = state.furthest_matched.max(Some(position));
+        match violation {
+            Some(violation) => {
+                state.violations.push(violation);
+                false
+            },
+            None => true,
+        }
+    }
+}
+
+impl fmt::Debug for Sequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.borrow();
+        f.debug_struct("Sequence")
+            .field("num_steps", &state.steps.len())
+            .field("violations", &state.violations)
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn matching_later_step_before_earlier_is_satisfied_is_violation() {
+        let seq = Sequence::new();
+        let first_satisfied = Arc::new(AtomicBool::new(false));
+        let flag = first_satisfied.clone();
+        assert_eq!(0, seq.add_step(Box::new(move || flag.load(Ordering::SeqCst))));
+        assert_eq!(1, seq.add_step(Box::new(|| true)));
+
+        assert!(!seq.record_match(1));
+        first_satisfied.store(true, Ordering::SeqCst);
+        assert!(seq.record_match(1));
+        assert!(!seq.verify());
+    }
+
+    #[test]
+    fn matching_earlier_step_after_later_is_violation() {
+        let seq = Sequence::new();
+        seq.add_step(Box::new(|| true));
+        seq.add_step(Box::new(|| true));
+
+        assert!(seq.record_match(0));
+        assert!(seq.record_match(1));
+        assert!(seq.verify());
+        assert!(!seq.record_match(0));
+        assert!(!seq.verify());
+    }
+}
@@ -0,0 +1,161 @@
+// Shared, interior-mutable state used by mocks and the objects attached to
+// them (expectations, sequences).
+//
+// By default this is `Rc<RefCell<T>>`, which is cheap but confines mocks to a
+// single thread. With the `sync` feature it is `Arc<RwLock<T>>` instead, and
+// all stored closures must be `Send + Sync`, which makes mocks `Send + Sync`
+// as long as their argument and return types are.
+//
+// With `--cfg loom`, the `Arc`, `RwLock` and the atomics of the call log come
+// from `loom`, so the model explores the interleavings of calls to mocks
+// shared by its threads. Mocks must then be created inside `loom::model`. The
+// counters that are shared by all mocks are statics, which `loom` can't
+// model, so they stay atomics of `std`.
+
+#[cfg(loom)]
+extern crate loom;
+
+use std::fmt;
+
+#[cfg(not(feature = "sync"))]
+use std::cell::{Ref, RefCell, RefMut};
+#[cfg(not(feature = "sync"))]
+use std::rc::Rc;
+#[cfg(feature = "sync")]
+use std::sync::PoisonError;
+#[cfg(all(feature = "sync", not(loom)))]
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(all(feature = "sync", loom))]
+use self::loom::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+#[cfg(not(loom))]
+pub(crate) use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(loom)]
+pub(crate) use self::loom::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+#[cfg(not(feature = "sync"))]
+pub(crate) type ReadGuard<'a, T> = Ref<'a, T>;
+#[cfg(not(feature = "sync"))]
+pub(crate) type WriteGuard<'a, T> = RefMut<'a, T>;
+#[cfg(feature = "sync")]
+pub(crate) type ReadGuard<'a, T> = RwLockReadGuard<'a, T>;
+#[cfg(feature = "sync")]
+pub(crate) type WriteGuard<'a, T> = RwLockWriteGuard<'a, T>;
+
+/// A boxed closure that can be stored in a mock. With the `sync` feature, it
+/// must be `Send + Sync`.
+#[cfg(not(feature = "sync"))]
+pub type Closure<A, R> = Box<dyn Fn(A) -> R>;
+/// A boxed closure that can be stored in a mock. With the `sync` feature, it
+/// must be `Send + Sync`.
+#[cfg(feature = "sync")]
+pub type Closure<A, R> = Box<dyn Fn(A) -> R + Send + Sync>;
+
+/// A boxed argument pattern that can be stored in a mock. With the `sync`
+/// feature, it must be `Send + Sync`.
+#[cfg(not(feature = "sync"))]
+pub type Pattern<C> = Box<dyn Fn(&C) -> bool>;
+/// A boxed argument pattern that can be stored in a mock. With the `sync`
+/// feature, it must be `Send + Sync`.
+#[cfg(feature = "sync")]
+pub type Pattern<C> = Box<dyn Fn(&C) -> bool + Send + Sync>;
+
+/// A borrowed argument pattern that can be stored in a mock, such as the
+/// patterns created using `matcher!`. With the `sync` feature, it must be
+/// `Sync`.
+#[cfg(not(feature = "sync"))]
+pub type StaticPattern<C> = &'static dyn Fn(&C) -> bool;
+/// A borrowed argument pattern that can be stored in a mock, such as the
+/// patterns created using `matcher!`. With the `sync` feature, it must be
+/// `Sync`.
+#[cfg(feature = "sync")]
+pub type StaticPattern<C> = &'static (dyn Fn(&C) -> bool + Sync);
+
+/// A boxed closure that formats a mock's args for failure messages and
+/// reports. With the `sync` feature, it must be `Send + Sync`.
+#[cfg(not(feature = "sync"))]
+pub type ArgsFormatter<C> = Box<dyn Fn(&C) -> String>;
+/// A boxed closure that formats a mock's args for failure messages and
+/// reports. With the `sync` feature, it must be `Send + Sync`.
+#[cfg(feature = "sync")]
+pub type ArgsFormatter<C> = Box<dyn Fn(&C) -> String + Send + Sync>;
+
+// A shared pointer to state that manages its own interior mutability.
+#[cfg(not(feature = "sync"))]
+pub(crate) type SharedPtr<T> = Rc<T>;
+#[cfg(feature = "sync")]
+pub(crate) type SharedPtr<T> = Arc<T>;
+
+/// A closure over a mock's entire argument tuple that can be boxed into a
+/// `Pattern`. With the `sync` feature, it must be `Send + Sync`.
+#[cfg(not(feature = "sync"))]
+pub trait PatternFn<C>: Fn(&C) -> bool + 'static {}
+#[cfg(not(feature = "sync"))]
+impl<C, F: Fn(&C) -> bool + 'static> PatternFn<C> for F {}
+/// A closure over a mock's entire argument tuple that can be boxed into a
+/// `Pattern`. With the `sync` feature, it must be `Send + Sync`.
+#[cfg(feature = "sync")]
+pub trait PatternFn<C>: Fn(&C) -> bool + Send + Sync + 'static {}
+#[cfg(feature = "sync")]
+impl<C, F: Fn(&C) -> bool + Send + Sync + 'static> PatternFn<C> for F {}
+
+pub(crate) struct Shared<T> {
+    #[cfg(not(feature = "sync"))]
+    inner: Rc<RefCell<T>>,
+    #[cfg(feature = "sync")]
+    inner: Arc<RwLock<T>>,
+}
+
+impl<T> Shared<T> {
+    #[cfg(not(feature = "sync"))]
+    pub(crate) fn new(value: T) -> Self {
+        Shared { inner: Rc::new(RefCell::new(value)) }
+    }
+
+    #[cfg(feature = "sync")]
+    pub(crate) fn new(value: T) -> Self {
+        Shared { inner: Arc::new(RwLock::new(value)) }
+    }
+
+    #[cfg(not(feature = "sync"))]
+    pub(crate) fn borrow(&self) -> ReadGuard<'_, T> {
+        self.inner.borrow()
+    }
+
+    // A panic while the lock is held (e.g. a failed assertion inside a mocked
+    // closure) must not make the mock unusable, so poisoning is ignored.
+    #[cfg(feature = "sync")]
+    pub(crate) fn borrow(&self) -> ReadGuard<'_, T> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[cfg(not(feature = "sync"))]
+    pub(crate) fn borrow_mut(&self) -> WriteGuard<'_, T> {
+        self.inner.borrow_mut()
+    }
+
+    #[cfg(feature = "sync")]
+    pub(crate) fn borrow_mut(&self) -> WriteGuard<'_, T> {
+        self.inner.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Shared { inner: self.inner.clone() }
+    }
+}
+
+impl<T: Default> Default for Shared<T> {
+    fn default() -> Self {
+        Shared::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.borrow().fmt(f)
+    }
+}
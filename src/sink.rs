@@ -0,0 +1,277 @@
+extern crate futures_sink;
+
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use self::futures_sink::Sink;
+use crate::script::Script;
+use crate::shared::Shared;
+
+/// A recording `Sink`, for testing producers that send to streaming
+/// collaborators, e.g. the write half of a connection.
+///
+/// The items sent to the sink are recorded, in order. The sink is ready for
+/// an item straight away, unless backpressure is applied, using either a
+/// script or `apply_backpressure`. The script is built by chaining `delay`,
+/// `pending` and `error` steps, each of which is played out by the polls
+/// for the sink's readiness before the next item is sent. Once the script
+/// is exhausted, the sink is always ready (or never is again, if the script
+/// ends with `pending_forever`). `apply_backpressure` holds the sink back
+/// until `release_backpressure` is called, for tests that check what a
+/// producer does while it waits.
+///
+/// `MockSink` is a handle. Clones of it share its script and the items sent
+/// to it, so keep a clone to verify the items after handing the sink to the
+/// code under test.
+///
+/// Requires the `futures` feature.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate double;
+/// # extern crate futures_sink;
+/// use std::pin::Pin;
+/// use std::task::{Context, Poll, Waker};
+/// use futures_sink::Sink;
+/// use double::MockSink;
+///
+/// # fn main() {
+/// let sink = MockSink::<u32, &str>::new().pending(1).error("disconnected");
+/// let mut cx = Context::from_waker(Waker::noop());
+/// let mut handle = sink.clone();
+/// let mut sink_ref = Pin::new(&mut handle);
+///
+/// assert_eq!(Poll::Pending, sink_ref.as_mut().poll_ready(&mut cx));
+/// assert_eq!(Poll::Ready(Err("disconnected")), sink_ref.as_mut().poll_ready(&mut cx));
+/// assert_eq!(Poll::Ready(Ok(())), sink_ref.as_mut().poll_ready(&mut cx));
+/// sink_ref.as_mut().start_send(7).unwrap();
+///
+/// sink.apply_backpressure();
+/// assert_eq!(Poll::Pending, sink_ref.as_mut().poll_ready(&mut cx));
+/// sink.release_backpressure();
+/// assert_eq!(Poll::Ready(Ok(())), sink_ref.as_mut().poll_ready(&mut cx));
+/// sink_ref.as_mut().start_send(8).unwrap();
+/// assert_eq!(Poll::Ready(Ok(())), sink_ref.as_mut().poll_close(&mut cx));
+///
+/// assert_eq!(vec!(7, 8), sink.items());
+/// assert!(sink.is_closed());
+/// # }
+/// ```
+pub struct MockSink<T, E = ()> {
+    state: Shared<SinkState<T, E>>,
+}
+
+struct SinkState<T, E> {
+    // The script of the polls for the sink's readiness, whose errors are the
+    // only steps that aren't delays or pending polls
+    script: Script<E>,
+    items: Vec<T>,
+    backpressure: bool,
+    // The waker of the poll held back by `apply_backpressure`, if any
+    waker: Option<Waker>,
+    num_flushes: usize,
+    closed: bool,
+}
+
+impl<T, E> MockSink<T, E> {
+    /// Creates a sink with an empty script, which is always ready for the
+    /// next item.
+    pub fn new() -> Self {
+        MockSink {
+            state: Shared::new(SinkState {
+                script: Script::default(),
+                items: Vec::new(),
+                backpressure: false,
+                waker: None,
+                num_flushes: 0,
+                closed: false,
+            }),
+        }
+    }
+
+    /// Fail the next poll for the sink's readiness with `err`.
+    pub fn error(self, err: E) -> Self {
+        self.state.borrow_mut().script.push(err);
+        self
+    }
+
+    /// Wait until `delay` has passed before the sink is ready for the next
+    /// item. A background thread wakes the waker when the delay has passed,
+    /// so this works with any executor.
+    pub fn delay(self, delay: Duration) -> Self {
+        self.state.borrow_mut().script.push_delay(delay);
+        self
+    }
+
+    /// Return `Poll::Pending` for `num_polls` polls for the sink's readiness
+    /// before it's ready for the next item. The waker is woken after each
+    /// pending poll.
+    pub fn pending(self, num_polls: usize) -> Self {
+        self.state.borrow_mut().script.push_pending(num_polls);
+        self
+    }
+
+    /// Never be ready for another item once the script is exhausted, to
+    /// simulate a consumer that stops receiving.
+    pub fn pending_forever(self) -> Self {
+        self.state.borrow_mut().script.set_pending_forever();
+        self
+    }
+
+    /// Hold the sink back from being ready for the next item, until
+    /// `release_backpressure` is called.
+    pub fn apply_backpressure(&self) {
+        self.state.borrow_mut().backpressure = true;
+    }
+
+    /// Release the backpressure applied by `apply_backpressure`, waking the
+    /// task that's waiting for the sink to be ready, if any.
+    pub fn release_backpressure(&self) {
+        let waker = {
+            let mut state = self.state.borrow_mut();
+            state.backpressure = false;
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Returns the number of items that have been sent to the sink.
+    pub fn num_items(&self) -> usize {
+        self.state.borrow().items.len()
+    }
+
+    /// Returns the number of times the sink has been flushed.
+    pub fn num_flushes(&self) -> usize {
+        self.state.borrow().num_flushes
+    }
+
+    /// Returns true if the sink has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.state.borrow().closed
+    }
+}
+
+impl<T: Clone, E> MockSink<T, E> {
+    /// Returns the items that have been sent to the sink, in order.
+    pub fn items(&self) -> Vec<T> {
+        self.state.borrow().items.clone()
+    }
+}
+
+impl<T, E> Clone for MockSink<T, E> {
+    fn clone(&self) -> Self {
+        MockSink { state: self.state.clone() }
+    }
+}
+
+impl<T, E> Default for MockSink<T, E> {
+    fn default() -> Self {
+        MockSink::new()
+    }
+}
+
+impl<T: fmt::Debug, E> fmt::Debug for MockSink<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.borrow();
+        f.debug_struct("MockSink")
+            .field("items", &state.items)
+            .field("backpressure", &state.backpressure)
+            .field("closed", &state.closed)
+            .finish()
+    }
+}
+
+impl<T, E> Sink<T> for MockSink<T, E> {
+    type Error = E;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), E>> {
+        let mut state = self.state.borrow_mut();
+        if state.backpressure {
+            state.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        match state.script.poll_next(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Some(err)) => Poll::Ready(Err(err)),
+            Poll::Ready(None) => Poll::Ready(Ok(())),
+        }
+    }
+
+    #[track_caller]
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), E> {
+        let mut state = self.state.borrow_mut();
+        assert!(!state.closed, "item sent to a `MockSink` after it was closed");
+        state.items.push(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), E>> {
+        self.state.borrow_mut().num_flushes += 1;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), E>> {
+        self.state.borrow_mut().closed = true;
+        Poll::Ready(Ok(()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::Wake;
+    use std::thread;
+
+    fn poll_ready<T>(sink: &mut MockSink<T, &'static str>) -> Poll<Result<(), &'static str>> {
+        Pin::new(sink).poll_ready(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn delay_holds_back_readiness() {
+        let mut sink = MockSink::new().delay(Duration::from_millis(20));
+        assert_eq!(Poll::Pending, poll_ready(&mut sink));
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(Poll::Ready(Ok(())), poll_ready(&mut sink));
+        Pin::new(&mut sink).start_send("sent").unwrap();
+        assert_eq!(vec!("sent"), sink.items());
+
+        let mut sink = MockSink::<u32, &str>::new().pending_forever();
+        assert_eq!(Poll::Pending, poll_ready(&mut sink));
+        assert_eq!(Poll::Pending, poll_ready(&mut sink));
+    }
+
+    #[test]
+    fn releasing_backpressure_wakes_the_waiting_task() {
+        struct Woken(AtomicBool);
+
+        impl Wake for Woken {
+            fn wake(self: Arc<Self>) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let flag = Arc::new(Woken(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut sink = MockSink::<u32>::new();
+        sink.apply_backpressure();
+        assert!(Pin::new(&mut sink).poll_ready(&mut Context::from_waker(&waker)).is_pending());
+        assert!(!flag.0.load(Ordering::SeqCst));
+        sink.release_backpressure();
+        assert!(flag.0.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    #[should_panic(expected = "item sent to a `MockSink` after it was closed")]
+    fn items_cant_be_sent_after_closing() {
+        let mut sink = MockSink::<u32>::new();
+        let _ = Pin::new(&mut sink).poll_close(&mut Context::from_waker(Waker::noop()));
+        let _ = Pin::new(&mut sink).start_send(1);
+    }
+}
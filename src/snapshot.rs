@@ -0,0 +1,360 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+#[cfg(feature = "std")]
+use crate::async_mock::AsyncMock;
+use crate::call_record::CallRecord;
+use crate::mock::Mock;
+
+// Number of decimal places floats are rounded to in snapshots.
+const FLOAT_PRECISION: usize = 6;
+
+/// Implemented by objects whose recorded calls can be rendered as a stable
+/// snapshot.
+///
+/// This is implemented by `Mock`, `AsyncMock` and the mock `struct`s
+/// generated by `mock_trait!` and `mock_trait_no_default!`, so they can be
+/// rendered together using `double::snapshot`. The calls of a generated mock
+/// are named after the mock and the method that was called, e.g.
+/// `sheet.profit(500, 250) -> 250`.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// mock_trait!(
+///     MockBalanceSheet,
+///     profit(u32, u32) -> i32,
+///     close(()) -> ());
+///
+/// # fn main() {
+/// let sheet = MockBalanceSheet::default();
+/// sheet.profit.return_value(250);
+/// sheet.profit.call((500, 250));
+/// sheet.close.call(());
+///
+/// assert_eq!(
+///     double::snapshot(&[("sheet", &sheet)]),
+///     "#0 sheet.profit(500, 250) -> 250\n\
+///      #1 sheet.close() -> ()\n");
+/// # }
+/// ```
+pub trait Snapshot {
+    /// Returns the sequence number (see `CallRecord::sequence_number`) and
+    /// the stable rendering of each recorded call, prefixing each call with
+    /// `name`.
+    fn rendered_calls(&self, name: &str) -> Vec<(usize, String)>;
+}
+
+impl<C, R> Snapshot for Mock<C, R>
+    where C: Clone + Debug + Eq + Hash,
+          R: Clone + Debug
+{
+    fn rendered_calls(&self, name: &str) -> Vec<(usize, String)> {
+        self.records()
+            .map(|record| (record.sequence_number(), self.render_call(name, &record)))
+            .collect()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<C, R> Snapshot for AsyncMock<C, R>
+    where C: Clone + Debug + Eq + Hash,
+          R: Clone + Debug
+{
+    fn rendered_calls(&self, name: &str) -> Vec<(usize, String)> {
+        self.mock().rendered_calls(name)
+    }
+}
+
+impl<C, R> Mock<C, R>
+    where C: Clone + Debug + Eq + Hash,
+          R: Clone + Debug
+{
+    /// Returns a deterministic rendering of the mock's call history, with
+    /// one line per call, for use with snapshot (golden file) testing.
+    ///
+    /// Each line holds the call's index, its args and its return value. Only
+    /// details that are stable from run to run are included, so timestamps,
+    /// durations and call sites are left out. The `Debug` representations of
+    /// args and return values are normalised so they are stable too:
+    ///
+    /// * the entries of maps and sets (e.g. `HashMap` and `HashSet`) are
+    ///   sorted by their rendering
+    /// * floats are rounded to 6 decimal places, with trailing zeros removed
+    ///
+    /// Args are formatted using the mock's args formatter, if it has one (see
+    /// `Mock::format_args_with`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashSet;
+    /// use double::Mock;
+    ///
+    /// let tags: HashSet<&str> = vec!("urgent", "billing", "new").into_iter().collect();
+    /// let mock = Mock::<&str, (HashSet<&str>, f64)>::new((tags, 0.1 + 0.2));
+    /// mock.call("invoice");
+    ///
+    /// assert_eq!(
+    ///     mock.snapshot(),
+    ///     "#0 (\"invoice\") -> ({\"billing\", \"new\", \"urgent\"}, 0.3)\n");
+    /// ```
+    pub fn snapshot(&self) -> String {
+        self.records()
+            .map(|record| format!("#{} {}\n", record.index(), self.render_call("", &record)))
+            .collect()
+    }
+
+    fn render_call(&self, name: &str, record: &CallRecord<C, R>) -> String {
+        let args = normalise_debug(&self.describe_args(record.args()));
+        let args = if args.starts_with('(') && args.ends_with(')') {
+            args
+        } else {
+            format!("({})", args)
+        };
+        match record.return_value() {
+            Some(return_value) => format!(
+                "{}{} -> {}", name, args, normalise_debug(&format!("{:?}", return_value))),
+            None => format!("{}{} did not return", name, args),
+        }
+    }
+}
+
+/// Returns a deterministic rendering of the interleaved call histories of the
+/// given named `mocks`, with one line per call, for use with snapshot (golden
+/// file) testing. See `Mock::snapshot`.
+///
+/// Calls are ordered by when they were made, and numbered from zero. Unlike
+/// the calls' sequence numbers, these numbers only depend on the calls made
+/// to `mocks`, so they are stable even if other tests run at the same time.
+///
+/// # Examples
+///
+/// ```
+/// use double::Mock;
+///
+/// let reader = Mock::<i32, String>::new("data");
+/// let writer = Mock::<(String, bool), ()>::default();
+/// reader.call(1);
+/// writer.call(("data".to_owned(), true));
+/// reader.call(2);
+///
+/// assert_eq!(
+///     double::snapshot(&[("reader", &reader), ("writer", &writer)]),
+///     "#0 reader(1) -> \"data\"\n\
+///      #1 writer(\"data\", true) -> ()\n\
+///      #2 reader(2) -> \"data\"\n");
+/// ```
+pub fn snapshot(mocks: &[(&str, &dyn Snapshot)]) -> String {
+    let mut calls: Vec<(usize, String)> = mocks
+        .iter()
+        .flat_map(|&(name, mock)| mock.rendered_calls(name))
+        .collect();
+    calls.sort_by_key(|&(sequence_number, _)| sequence_number);
+    calls
+        .into_iter()
+        .enumerate()
+        .map(|(position, (_, call))| format!("#{} {}\n", position, call))
+        .collect()
+}
+
+// Normalises a `Debug` representation so that it is the same from run to run.
+// The entries of brace-delimited groups that aren't structs (i.e. maps and
+// sets) are sorted, and floats are rounded.
+fn normalise_debug(debug: &str) -> String {
+    let chars: Vec<char> = debug.chars().collect();
+    let mut pos = 0;
+    let mut normalised = String::new();
+    while pos < chars.len() {
+        let entries = normalise_entries(&chars, &mut pos, None);
+        normalised.push_str(&entries.join(", "));
+    }
+    normalised
+}
+
+// Normalises the comma-separated entries of a group, up to and including
+// `closer`.
+fn normalise_entries(chars: &[char], pos: &mut usize, closer: Option<char>) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut entry = String::new();
+    while *pos < chars.len() {
+        let c = chars[*pos];
+        match c {
+            '"' | '\'' => entry.push_str(&literal(chars, pos, c)),
+            '(' | '[' | '{' => {
+                *pos += 1;
+                let group_closer = match c {
+                    '(' => ')',
+                    '[' => ']',
+                    _ => '}',
+                };
+                let is_struct = entry.trim_end().ends_with(is_identifier_char);
+                let mut group = normalise_entries(chars, pos, Some(group_closer));
+                if c == '{' && !is_struct {
+                    group.sort();
+                }
+                entry.push(c);
+                if !group.is_empty() {
+                    if is_struct {
+                        entry.push_str(&format!(" {} ", group.join(", ")));
+                    } else {
+                        entry.push_str(&group.join(", "));
+                    }
+                }
+                entry.push(group_closer);
+            },
+            ',' => {
+                *pos += 1;
+                push_entry(&mut entries, &entry);
+                entry.clear();
+            },
+            _ if Some(c) == closer => {
+                *pos += 1;
+                push_entry(&mut entries, &entry);
+                return entries;
+            },
+            _ if c.is_ascii_digit() && !entry.ends_with(is_identifier_char) => {
+                entry.push_str(&number(chars, pos));
+            },
+            _ => {
+                *pos += 1;
+                entry.push(c);
+            },
+        }
+    }
+    push_entry(&mut entries, &entry);
+    entries
+}
+
+fn push_entry(entries: &mut Vec<String>, entry: &str) {
+    let entry = entry.trim();
+    if !entry.is_empty() {
+        entries.push(entry.to_owned());
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Consumes a string or char literal starting with `quote`, including any
+// escaped quotes.
+fn literal(chars: &[char], pos: &mut usize, quote: char) -> String {
+    let mut literal = String::new();
+    literal.push(quote);
+    *pos += 1;
+    while *pos < chars.len() {
+        let c = chars[*pos];
+        *pos += 1;
+        literal.push(c);
+        if c == '\\' && *pos < chars.len() {
+            literal.push(chars[*pos]);
+            *pos += 1;
+        } else if c == quote {
+            break;
+        }
+    }
+    literal
+}
+
+// Consumes a number, rounding it if it's a float.
+fn number(chars: &[char], pos: &mut usize) -> String {
+    let start = *pos;
+    let digits = |pos: &mut usize| {
+        while *pos < chars.len() && chars[*pos].is_ascii_digit() {
+            *pos += 1;
+        }
+    };
+    let is_digit_at = |index: usize| index < chars.len() && chars[index].is_ascii_digit();
+
+    digits(pos);
+    let mut is_float = false;
+    if *pos < chars.len() && chars[*pos] == '.' && is_digit_at(*pos + 1) {
+        is_float = true;
+        *pos += 1;
+        digits(pos);
+    }
+    if *pos < chars.len() && chars[*pos] == 'e' {
+        let exponent_start = if *pos + 1 < chars.len() && chars[*pos + 1] == '-' {
+            *pos + 2
+        } else {
+            *pos + 1
+        };
+        if is_digit_at(exponent_start) {
+            is_float = true;
+            *pos = exponent_start;
+            digits(pos);
+        }
+    }
+
+    let number: String = chars[start..*pos].iter().collect();
+    match number.parse::<f64>() {
+        Ok(float) if is_float => round_float(float),
+        _ => number,
+    }
+}
+
+fn round_float(float: f64) -> String {
+    let rounded = format!("{:.*}", FLOAT_PRECISION, float);
+    let trimmed = rounded.trim_end_matches('0');
+    if trimmed.ends_with('.') {
+        format!("{}0", trimmed)
+    } else {
+        trimmed.to_owned()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    #[test]
+    fn normalise_debug_sorts_maps_and_sets() {
+        let map: HashMap<&str, HashSet<i32>> = vec!(
+            ("b", vec!(3, 1, 2).into_iter().collect()),
+            ("a", HashSet::new()),
+        ).into_iter().collect();
+        assert_eq!(
+            r#"{"a": {}, "b": {1, 2, 3}}"#,
+            normalise_debug(&format!("{:?}", map)));
+    }
+
+    #[test]
+    fn normalise_debug_keeps_struct_fields_in_order() {
+        #[derive(Debug)]
+        #[allow(dead_code)]
+        struct Point {
+            y: f32,
+            x: f32,
+        }
+        assert_eq!(
+            "[Point { y: 0.3, x: 1.0 }]",
+            normalise_debug(&format!("{:?}", vec!(Point { y: 0.1 + 0.2, x: 1.0 }))));
+    }
+
+    #[test]
+    fn normalise_debug_rounds_floats() {
+        assert_eq!("(0.3, -2.5, 0.0, 1.0, 10)", normalise_debug(&format!(
+            "{:?}", (0.1f64 + 0.2, -2.5f64, 1e-9f64, 1e0f64, 10))));
+        assert_eq!("(20000000000000000.0, 1..2)", normalise_debug("(2e16, 1..2)"));
+    }
+
+    #[test]
+    fn normalise_debug_leaves_literals_alone() {
+        let debug = format!("{:?}", ("{b, a} 0.30000000000000004", '\'', "x\"}"));
+        assert_eq!(debug, normalise_debug(&debug));
+    }
+
+    #[test]
+    fn snapshot_reports_calls_that_did_not_return() {
+        let mock = Mock::<(), i32>::new(5);
+        mock.use_closure(Box::new(|_| panic!("boom")));
+        let _ = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| mock.call(())));
+        assert_eq!("#0 () did not return\n", mock.snapshot());
+    }
+}
@@ -0,0 +1,420 @@
+//! Ready-made mocks of the traits of the standard library that code under
+//! test most often takes, so they don't need to be defined in every project.
+//!
+//! Each mock has a `Mock` field for each of the trait's required methods,
+//! like the mocks generated by `mock_trait!`, so they are stubbed and
+//! verified in the same way. Their `Default` implementations behave like an
+//! empty source or a sink that accepts everything. Since `io::Error` doesn't
+//! implement `Clone`, the mocks of `io` traits return an `io::ErrorKind`
+//! instead, which is converted to an `io::Error` of that kind.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::io::{self, BufRead, Write};
+//! use double::std_mocks::{MockBufRead, MockWrite};
+//!
+//! fn shout(input: &mut impl BufRead, output: &mut impl Write) -> io::Result<()> {
+//!     for line in input.lines() {
+//!         writeln!(output, "{}", line?.to_uppercase())?;
+//!     }
+//!     output.flush()
+//! }
+//!
+//! let mut input = MockBufRead::default();
+//! input.fill_buf.return_values(vec!(Ok(b"hello\nwor".to_vec()), Ok(b"ld\n".to_vec())));
+//! let mut output = MockWrite::default();
+//!
+//! shout(&mut input, &mut output).unwrap();
+//! assert_eq!(b"HELLO\nWORLD\n".to_vec(), output.written());
+//! assert!(output.flush.called());
+//!
+//! output.write.return_value(Err(io::ErrorKind::BrokenPipe));
+//! let mut input = MockBufRead::default();
+//! input.fill_buf.return_value(Ok(b"again\n".to_vec()));
+//! let error = shout(&mut input, &mut output).unwrap_err();
+//! assert_eq!(io::ErrorKind::BrokenPipe, error.kind());
+//! ```
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, SeekFrom};
+use crate::call_record::CallRecord;
+use crate::mock::Mock;
+use crate::snapshot::Snapshot;
+use crate::verify::Verify;
+
+// Generates a mock `struct` with a `Mock` field for each of the methods, and
+// its `new()`, `total_calls()`, `was_used()` and implementations of `Verify`
+// and `Snapshot`, like `mock_trait_no_default!` does. Private fields that
+// hold the mock's state follow the methods, with their initial values.
+macro_rules! std_mock {
+    ($(#[$attr:meta])* $mock_name:ident [$($param:ident),*]
+        { $($method:ident: $args:ty => $retval:ty),* }
+        { $($field:ident: $field_type:ty = $init:expr),* }) => (
+        $(#[$attr])*
+        #[derive(Clone, Debug)]
+        pub struct $mock_name<$($param: Clone),*> {
+            $( pub $method: Mock<$args, $retval>, )*
+            $( $field: $field_type, )*
+        }
+
+        impl<$($param: Clone + fmt::Debug),*> $mock_name<$($param),*> {
+            /// Creates a mock whose methods return the given values.
+            pub fn new($($method: $retval),*) -> Self {
+                let mock = $mock_name {
+                    $( $method: Mock::new($method), )*
+                    $( $field: $init, )*
+                };
+                $( mock.$method.set_name(concat!(stringify!($mock_name), "::", stringify!($method))); )*
+                mock
+            }
+
+            /// Returns the total number of calls made to all of the mock's
+            /// methods.
+            pub fn total_calls(&self) -> usize {
+                0 $( + self.$method.num_calls() )*
+            }
+
+            /// Returns true if any of the mock's methods have been called.
+            pub fn was_used(&self) -> bool {
+                self.total_calls() > 0
+            }
+        }
+
+        impl<$($param: Clone + fmt::Debug),*> Verify for $mock_name<$($param),*> {
+            fn verify(&self) -> bool {
+                let results = [$( self.$method.verify() ),*];
+                results.iter().all(|verified| *verified)
+            }
+
+            fn checkpoint(&self) -> bool {
+                let results = [$( self.$method.checkpoint() ),*];
+                results.iter().all(|verified| *verified)
+            }
+
+            fn verify_no_more_interactions(&self) -> bool {
+                let results = [$( self.$method.verify_no_more_interactions() ),*];
+                results.iter().all(|verified| *verified)
+            }
+        }
+
+        impl<$($param: Clone + fmt::Debug),*> Snapshot for $mock_name<$($param),*> {
+            fn rendered_calls(&self, name: &str) -> Vec<(usize, String)> {
+                let mut calls = Vec::new();
+                $(
+                    calls.extend(self.$method.rendered_calls(
+                        &format!("{}.{}", name, stringify!($method))));
+                )*
+                calls
+            }
+        }
+    );
+}
+
+std_mock!(
+    /// Mock of `io::Read`.
+    ///
+    /// `read` is called with the length of the buffer to read into, and
+    /// returns the bytes that are read into it, which must fit. By default,
+    /// it returns no bytes, as at the end of the input.
+    MockRead [] { read: usize => Result<Vec<u8>, io::ErrorKind> } {});
+
+impl Default for MockRead {
+    fn default() -> Self {
+        MockRead::new(Ok(Vec::new()))
+    }
+}
+
+impl io::Read for MockRead {
+    #[track_caller]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.read.call(buf.len()).map_err(io::Error::from)?;
+        copy_into(&data, buf);
+        Ok(data.len())
+    }
+}
+
+std_mock!(
+    /// Mock of `io::Write`.
+    ///
+    /// `write` is called with the bytes to write, and returns how many of
+    /// them are written, up to their number. By default, it returns
+    /// `usize::MAX`, so all of them are. `flush` returns `Ok(())` by default.
+    MockWrite [] {
+        write: Vec<u8> => Result<usize, io::ErrorKind>,
+        flush: () => Result<(), io::ErrorKind>
+    } {});
+
+impl MockWrite {
+    /// Returns the bytes that have been written, in order.
+    pub fn written(&self) -> Vec<u8> {
+        let mut written = Vec::new();
+        for record in self.write.records() {
+            if let Some(&Ok(len)) = record.return_value() {
+                let buf = record.args();
+                written.extend_from_slice(&buf[..len.min(buf.len())]);
+            }
+        }
+        written
+    }
+}
+
+impl Default for MockWrite {
+    fn default() -> Self {
+        MockWrite::new(Ok(usize::MAX), Ok(()))
+    }
+}
+
+impl io::Write for MockWrite {
+    #[track_caller]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.write.call(buf.to_vec()).map_err(io::Error::from)?;
+        Ok(len.min(buf.len()))
+    }
+
+    #[track_caller]
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush.call(()).map_err(io::Error::from)
+    }
+}
+
+/// The position that `MockSeek::seek` is called with. It wraps an
+/// `io::SeekFrom`, which doesn't implement `Hash`, and converts from one, so
+/// calls can be verified with a `SeekFrom`, e.g.
+/// `mock.seek.called_with(SeekFrom::Start(8))`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SeekArg(pub SeekFrom);
+
+impl From<SeekFrom> for SeekArg {
+    fn from(position: SeekFrom) -> Self {
+        SeekArg(position)
+    }
+}
+
+impl Hash for SeekArg {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self.0 {
+            SeekFrom::Start(offset) => (0u8, offset).hash(state),
+            SeekFrom::End(offset) => (1u8, offset).hash(state),
+            SeekFrom::Current(offset) => (2u8, offset).hash(state),
+        }
+    }
+}
+
+std_mock!(
+    /// Mock of `io::Seek`.
+    ///
+    /// `seek` returns the new position, which is 0 by default.
+    MockSeek [] { seek: SeekArg => Result<u64, io::ErrorKind> } {});
+
+impl Default for MockSeek {
+    fn default() -> Self {
+        MockSeek::new(Ok(0))
+    }
+}
+
+impl io::Seek for MockSeek {
+    #[track_caller]
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.seek.call(SeekArg(pos)).map_err(io::Error::from)
+    }
+}
+
+std_mock!(
+    /// Mock of `io::BufRead`, and of the `io::Read` it requires.
+    ///
+    /// `fill_buf` is called when the bytes it last returned have been
+    /// consumed, and returns the next bytes of the input. Until they are
+    /// consumed, the mock's `fill_buf` method returns what's left of them
+    /// without calling it again, as a `BufRead` would. By default, it returns
+    /// no bytes, as at the end of the input. `consume` records the number of
+    /// bytes consumed, and reading from the mock fills and consumes its
+    /// buffer in the same way.
+    MockBufRead [] {
+        fill_buf: () => Result<Vec<u8>, io::ErrorKind>,
+        consume: usize => ()
+    } {
+        // The bytes returned by `fill_buf` that haven't been consumed yet
+        buffer: Vec<u8> = Vec::new()
+    });
+
+impl Default for MockBufRead {
+    fn default() -> Self {
+        MockBufRead::new(Ok(Vec::new()), ())
+    }
+}
+
+impl io::Read for MockBufRead {
+    #[track_caller]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = {
+            let available = io::BufRead::fill_buf(self)?;
+            let len = available.len().min(buf.len());
+            buf[..len].copy_from_slice(&available[..len]);
+            len
+        };
+        io::BufRead::consume(self, len);
+        Ok(len)
+    }
+}
+
+impl io::BufRead for MockBufRead {
+    #[track_caller]
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.buffer.is_empty() {
+            self.buffer = self.fill_buf.call(()).map_err(io::Error::from)?;
+        }
+        Ok(&self.buffer)
+    }
+
+    #[track_caller]
+    fn consume(&mut self, amt: usize) {
+        self.consume.call(amt);
+        let amt = amt.min(self.buffer.len());
+        self.buffer.drain(..amt);
+    }
+}
+
+std_mock!(
+    /// Mock of `Iterator`, whose `next` returns `None` by default. Stub it
+    /// with `return_values` to return a sequence of items, followed by
+    /// `None`.
+    MockIterator [T] { next: () => Option<T> } {});
+
+impl<T: Clone + fmt::Debug> Default for MockIterator<T> {
+    fn default() -> Self {
+        MockIterator::new(None)
+    }
+}
+
+impl<T: Clone + fmt::Debug> Iterator for MockIterator<T> {
+    type Item = T;
+
+    #[track_caller]
+    fn next(&mut self) -> Option<T> {
+        self.next.call(())
+    }
+}
+
+std_mock!(
+    /// Mock of `fmt::Write`.
+    ///
+    /// `write_str` returns `Ok(())` by default.
+    MockFmtWrite [] { write_str: String => fmt::Result } {});
+
+impl MockFmtWrite {
+    /// Returns the strings that have been written, concatenated in order.
+    pub fn written(&self) -> String {
+        self.write_str.records()
+            .filter(|record| record.return_value().is_some_and(Result::is_ok))
+            .map(CallRecord::into_args)
+            .collect()
+    }
+}
+
+impl Default for MockFmtWrite {
+    fn default() -> Self {
+        MockFmtWrite::new(Ok(()))
+    }
+}
+
+impl fmt::Write for MockFmtWrite {
+    #[track_caller]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_str.call(s.to_owned())
+    }
+}
+
+// Copies the bytes returned by a mock into the buffer given to `read`
+#[track_caller]
+fn copy_into(data: &[u8], buf: &mut [u8]) {
+    assert!(
+        data.len() <= buf.len(),
+        "mock returned {} bytes to read into a buffer of {}",
+        data.len(),
+        buf.len());
+    buf[..data.len()].copy_from_slice(data);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write as FmtWrite;
+    use std::io::{BufRead, Read, Seek, Write};
+    use super::*;
+
+    #[test]
+    fn reads_return_the_stubbed_bytes() {
+        let mut mock = MockRead::default();
+        mock.read.return_values(vec!(Ok(b"abc".to_vec()), Err(io::ErrorKind::Interrupted)));
+        let mut buf = [0u8; 8];
+        assert_eq!(3, mock.read(&mut buf).unwrap());
+        assert_eq!(b"abc", &buf[..3]);
+        assert_eq!(io::ErrorKind::Interrupted, mock.read(&mut buf).unwrap_err().kind());
+        assert_eq!(0, mock.read(&mut buf).unwrap());
+        assert!(mock.read.has_calls_exactly(vec!(8usize, 8, 8)));
+    }
+
+    #[test]
+    #[should_panic(expected = "mock returned 4 bytes to read into a buffer of 2")]
+    fn reads_must_fit_the_buffer() {
+        let mut mock = MockRead::new(Ok(b"abcd".to_vec()));
+        let _ = mock.read(&mut [0u8; 2]);
+    }
+
+    #[test]
+    fn writes_accept_up_to_the_stubbed_number_of_bytes() {
+        let mut mock = MockWrite::default();
+        mock.write.return_values(vec!(Ok(2), Err(io::ErrorKind::WriteZero)));
+        assert_eq!(2, mock.write(b"hello").unwrap());
+        assert!(mock.write(b"llo").is_err());
+        mock.write_all(b"llo").unwrap();
+        assert_eq!(b"hello".to_vec(), mock.written());
+        assert!(mock.write.has_calls_exactly_in_order(
+            vec!(b"hello".to_vec(), b"llo".to_vec(), b"llo".to_vec())));
+    }
+
+    #[test]
+    fn seeks_are_recorded_with_their_positions() {
+        let mut mock = MockSeek::default();
+        mock.seek.return_value_for(SeekFrom::End(-4), Ok(12));
+        assert_eq!(12, mock.seek(SeekFrom::End(-4)).unwrap());
+        assert_eq!(0, mock.seek(SeekFrom::Start(0)).unwrap());
+        assert!(mock.seek.called_with(SeekFrom::Start(0)));
+        assert!(!mock.seek.called_with(SeekFrom::Current(0)));
+    }
+
+    #[test]
+    fn buffered_bytes_are_kept_until_consumed() {
+        let mut mock = MockBufRead::default();
+        mock.fill_buf.return_values(vec!(Ok(b"one\ntw".to_vec()), Ok(b"o\n".to_vec())));
+        let lines: Vec<String> = (&mut mock).lines().map(Result::unwrap).collect();
+        assert_eq!(vec!("one".to_owned(), "two".to_owned()), lines);
+        assert_eq!(3, mock.fill_buf.num_calls());
+        assert!(mock.verify());
+
+        mock.fill_buf.return_value(Ok(b"xyz".to_vec()));
+        let mut buf = [0u8; 2];
+        assert_eq!(2, mock.read(&mut buf).unwrap());
+        assert_eq!(b"z", mock.fill_buf().unwrap());
+    }
+
+    #[test]
+    fn iterators_yield_the_stubbed_items() {
+        let mock = MockIterator::default();
+        mock.next.return_values(vec!(Some(1), Some(2)));
+        assert_eq!(vec!(1, 2), mock.clone().collect::<Vec<i32>>());
+        mock.next.called_exactly(3);
+    }
+
+    #[test]
+    fn formatted_writes_are_recorded() {
+        let mut mock = MockFmtWrite::default();
+        write!(mock, "{}-{}", 1, 2).unwrap();
+        assert_eq!("1-2", mock.written());
+        mock.write_str.return_value(Err(fmt::Error));
+        assert!(mock.write_char('!').is_err());
+        assert_eq!("1-2", mock.written());
+    }
+}
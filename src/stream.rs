@@ -0,0 +1,188 @@
+extern crate futures_core;
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use self::futures_core::Stream;
+use crate::script::Script;
+
+/// A scripted `Stream`, for testing consumers of streaming collaborators.
+///
+/// The script is built by chaining `item`, `delay` and `pending` steps. The
+/// stream yields the scripted items in order, pausing for each delay, and
+/// ends once the script is exhausted (or never does, if the script ends with
+/// `pending_forever`).
+///
+/// `MockStream` implements `Clone`, so it can be used as the return type of
+/// a `Mock`. Every call to the mock returns a fresh copy of the script.
+/// Mocked methods that must return a boxed stream can use `MockStream::boxed`.
+///
+/// Requires the `futures` feature.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate double;
+/// # extern crate futures_core;
+/// use std::pin::Pin;
+/// use std::task::{Context, Poll, Waker};
+/// use futures_core::Stream;
+/// use double::MockStream;
+///
+/// # fn main() {
+/// let mut stream = MockStream::new()
+///     .item(Ok(1))
+///     .pending(1)
+///     .item(Ok(2))
+///     .error("connection reset");
+///
+/// let mut cx = Context::from_waker(Waker::noop());
+/// let mut next = || Pin::new(&mut stream).poll_next(&mut cx);
+/// assert_eq!(next(), Poll::Ready(Some(Ok(1))));
+/// assert_eq!(next(), Poll::Pending);
+/// assert_eq!(next(), Poll::Ready(Some(Ok(2))));
+/// assert_eq!(next(), Poll::Ready(Some(Err("connection reset"))));
+/// assert_eq!(next(), Poll::Ready(None));
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct MockStream<T> {
+    script: Script<T>,
+}
+
+impl<T> MockStream<T> {
+    /// Creates a stream with an empty script, which ends straight away.
+    pub fn new() -> Self {
+        MockStream { script: Script::default() }
+    }
+
+    /// Yield `item`.
+    pub fn item(mut self, item: T) -> Self {
+        self.script.push(item);
+        self
+    }
+
+    /// Yield each of `items`, in order.
+    pub fn items<I: IntoIterator<Item = T>>(self, items: I) -> Self {
+        items.into_iter().fold(self, MockStream::item)
+    }
+
+    /// Wait until `delay` has passed before moving on to the next step. A
+    /// background thread wakes the waker when the delay has passed, so this
+    /// works with any executor.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.script.push_delay(delay);
+        self
+    }
+
+    /// Return `Poll::Pending` for `num_polls` polls before moving on to the
+    /// next step. The waker is woken after each pending poll.
+    pub fn pending(mut self, num_polls: usize) -> Self {
+        self.script.push_pending(num_polls);
+        self
+    }
+
+    /// Never end once the script is exhausted, to simulate a stream that
+    /// hangs.
+    pub fn pending_forever(mut self) -> Self {
+        self.script.set_pending_forever();
+        self
+    }
+}
+
+impl<O, E> MockStream<Result<O, E>> {
+    /// Yield `Err(err)`. Typically the last step of the script.
+    pub fn error(self, err: E) -> Self {
+        self.item(Err(err))
+    }
+}
+
+impl<T: Send + 'static> MockStream<T> {
+    /// Box the stream, for mocked methods that return
+    /// `Pin<Box<dyn Stream<Item = T> + Send>>` (e.g. `BoxStream`).
+    pub fn boxed(self) -> Pin<Box<dyn Stream<Item = T> + Send>> {
+        Box::pin(self)
+    }
+}
+
+impl<T> Default for MockStream<T> {
+    fn default() -> Self {
+        MockStream::new()
+    }
+}
+
+// Items are never pinned, so the stream can be moved even if they aren't
+impl<T> Unpin for MockStream<T> {}
+
+impl<T> Stream for MockStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        self.get_mut().script.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let num_items = self.script.remaining().count();
+        (num_items, if self.script.is_pending_forever() { None } else { Some(num_items) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Wake, Waker};
+    use std::thread;
+
+    fn poll_next<T>(stream: &mut MockStream<T>) -> Poll<Option<T>> {
+        Pin::new(stream).poll_next(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn delay_holds_back_next_item() {
+        let mut stream = MockStream::new()
+            .delay(Duration::from_millis(20))
+            .items(vec!(1, 2));
+        assert_eq!((2, Some(2)), stream.size_hint());
+
+        assert_eq!(Poll::Pending, poll_next(&mut stream));
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(Poll::Ready(Some(1)), poll_next(&mut stream));
+        assert_eq!(Poll::Ready(Some(2)), poll_next(&mut stream));
+        assert_eq!(Poll::Ready(None), poll_next(&mut stream));
+    }
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn delay_wakes_the_latest_waker_once_however_often_it_is_polled() {
+        let mut stream = MockStream::new().delay(Duration::from_millis(20)).item(1);
+        let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(wakes.clone());
+        for _ in 0..100 {
+            let poll = Pin::new(&mut stream).poll_next(&mut Context::from_waker(&waker));
+            assert_eq!(Poll::Pending, poll);
+        }
+        while wakes.0.load(Ordering::SeqCst) == 0 {
+            thread::yield_now();
+        }
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(1, wakes.0.load(Ordering::SeqCst));
+        assert_eq!(Poll::Ready(Some(1)), poll_next(&mut stream));
+    }
+
+    #[test]
+    fn pending_forever_never_ends() {
+        let mut stream = MockStream::new().item(1).pending_forever();
+        assert_eq!(Poll::Ready(Some(1)), poll_next(&mut stream));
+        assert_eq!(Poll::Pending, poll_next(&mut stream));
+        assert_eq!(Poll::Pending, poll_next(&mut stream));
+    }
+}
@@ -0,0 +1,362 @@
+//! Ready-made mocks of tokio's `AsyncRead` and `AsyncWrite`, for testing
+//! protocol codecs and other code that reads from and writes to sockets,
+//! without a socket.
+//!
+//! Like `MockStream`, each mock follows a script that's built by chaining
+//! steps, which is played out as the mock is polled. `MockAsyncRead` reads
+//! the scripted chunks of bytes, and `MockAsyncWrite` records the bytes that
+//! are written to it. Since `io::Error` doesn't implement `Clone`, errors are
+//! scripted as an `io::ErrorKind`, which is converted to an `io::Error` of
+//! that kind.
+//!
+//! Requires the `tokio` feature.
+//!
+//! # Examples
+//!
+//! ```edition2018
+//! # extern crate double;
+//! # extern crate tokio;
+//! use std::io;
+//! use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+//! use double::tokio_mocks::{MockAsyncRead, MockAsyncWrite};
+//!
+//! // Echoes each line that's read, in upper case
+//! async fn shout(
+//!     input: &mut (impl AsyncRead + Unpin),
+//!     output: &mut (impl AsyncWrite + Unpin)) -> io::Result<()>
+//! {
+//!     let mut text = String::new();
+//!     input.read_to_string(&mut text).await?;
+//!     for line in text.lines() {
+//!         output.write_all(format!("{}\n", line.to_uppercase()).as_bytes()).await?;
+//!     }
+//!     output.shutdown().await
+//! }
+//!
+//! # fn main() {
+//! let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+//!
+//! let mut input = MockAsyncRead::new().chunk("hello\nwor").pending(1).chunk("ld\n");
+//! let output = MockAsyncWrite::new().accept(3);
+//! runtime.block_on(shout(&mut input, &mut output.clone())).unwrap();
+//! assert_eq!(b"HELLO\nWORLD\n".to_vec(), output.written());
+//! assert_eq!(vec!(b"HEL".to_vec(), b"LO\n".to_vec(), b"WORLD\n".to_vec()), output.writes());
+//! assert!(output.is_shut_down());
+//!
+//! let mut input = MockAsyncRead::new().chunk("hello").error(io::ErrorKind::ConnectionReset);
+//! let error = runtime.block_on(shout(&mut input, &mut MockAsyncWrite::new())).unwrap_err();
+//! assert_eq!(io::ErrorKind::ConnectionReset, error.kind());
+//! # }
+//! ```
+
+extern crate tokio;
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use self::tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use crate::script::Script;
+use crate::shared::Shared;
+
+/// A scripted `AsyncRead`.
+///
+/// The script is built by chaining `chunk`, `delay`, `pending`, `error` and
+/// `eof` steps. Each read reads as much of the next chunk as fits in the
+/// buffer, leaving the rest of it for the next read, so codecs can be tested
+/// with frames that are split across reads. Reads reach the end of the input
+/// once the script is exhausted (or never do, if the script ends with
+/// `pending_forever`).
+///
+/// `MockAsyncRead` implements `Clone`, so it can be used as the return type of
+/// a `Mock`. Every call to the mock returns a fresh copy of the script.
+#[derive(Clone, Debug, Default)]
+pub struct MockAsyncRead {
+    script: Script<ReadStep>,
+}
+
+#[derive(Clone, Debug)]
+enum ReadStep {
+    Chunk(Vec<u8>),
+    Error(io::ErrorKind),
+    Eof,
+}
+
+impl MockAsyncRead {
+    /// Creates a reader with an empty script, which is at the end of its
+    /// input straight away.
+    pub fn new() -> Self {
+        MockAsyncRead::default()
+    }
+
+    /// Read `bytes`, over as many reads as it takes.
+    pub fn chunk<B: Into<Vec<u8>>>(mut self, bytes: B) -> Self {
+        self.script.push(ReadStep::Chunk(bytes.into()));
+        self
+    }
+
+    /// Read each of `chunks`, in order.
+    pub fn chunks<I>(self, chunks: I) -> Self
+        where I: IntoIterator,
+              I::Item: Into<Vec<u8>>
+    {
+        chunks.into_iter().fold(self, MockAsyncRead::chunk)
+    }
+
+    /// Fail the next read with an error of kind `kind`.
+    pub fn error(mut self, kind: io::ErrorKind) -> Self {
+        self.script.push(ReadStep::Error(kind));
+        self
+    }
+
+    /// Read no bytes, as at the end of the input, before moving on to the
+    /// next step.
+    pub fn eof(mut self) -> Self {
+        self.script.push(ReadStep::Eof);
+        self
+    }
+
+    /// Wait until `delay` has passed before moving on to the next step. A
+    /// background thread wakes the waker when the delay has passed, so this
+    /// works with any executor.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.script.push_delay(delay);
+        self
+    }
+
+    /// Return `Poll::Pending` for `num_polls` polls before moving on to the
+    /// next step. The waker is woken after each pending poll.
+    pub fn pending(mut self, num_polls: usize) -> Self {
+        self.script.push_pending(num_polls);
+        self
+    }
+
+    /// Never reach the end of the input once the script is exhausted, to
+    /// simulate a peer that stops sending.
+    pub fn pending_forever(mut self) -> Self {
+        self.script.set_pending_forever();
+        self
+    }
+}
+
+impl AsyncRead for MockAsyncRead {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf) -> Poll<io::Result<()>>
+    {
+        let script = &mut self.get_mut().script;
+        match script.poll_next(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Some(ReadStep::Chunk(mut chunk))) => {
+                let len = chunk.len().min(buf.remaining());
+                buf.put_slice(&chunk[..len]);
+                if len < chunk.len() {
+                    script.push_front(ReadStep::Chunk(chunk.split_off(len)));
+                }
+                Poll::Ready(Ok(()))
+            },
+            Poll::Ready(Some(ReadStep::Error(kind))) => Poll::Ready(Err(kind.into())),
+            Poll::Ready(Some(ReadStep::Eof)) | Poll::Ready(None) => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+/// A scripted `AsyncWrite`, which records the bytes written to it.
+///
+/// The script is built by chaining `accept`, `delay`, `pending` and `error`
+/// steps, each of which handles the next write. Once the script is exhausted,
+/// writes accept all of their bytes (or never complete, if the script ends
+/// with `pending_forever`). Writes after the writer has been shut down fail
+/// with `io::ErrorKind::BrokenPipe`.
+///
+/// `MockAsyncWrite` is a handle. Clones of it share its script and the bytes
+/// written to it, so keep a clone to verify the bytes after handing the
+/// writer to the code under test.
+#[derive(Clone, Debug, Default)]
+pub struct MockAsyncWrite {
+    state: Shared<WriteState>,
+}
+
+#[derive(Debug, Default)]
+struct WriteState {
+    script: Script<WriteStep>,
+    // The bytes accepted by each write, in order
+    writes: Vec<Vec<u8>>,
+    num_flushes: usize,
+    shut_down: bool,
+}
+
+#[derive(Clone, Debug)]
+enum WriteStep {
+    Accept(usize),
+    Error(io::ErrorKind),
+}
+
+impl MockAsyncWrite {
+    /// Creates a writer with an empty script, which accepts all of the bytes
+    /// of every write.
+    pub fn new() -> Self {
+        MockAsyncWrite::default()
+    }
+
+    /// Accept at most `max_len` of the bytes of the next write. Accepting 0
+    /// bytes signals that the writer can't accept any more.
+    pub fn accept(self, max_len: usize) -> Self {
+        self.state.borrow_mut().script.push(WriteStep::Accept(max_len));
+        self
+    }
+
+    /// Fail the next write with an error of kind `kind`.
+    pub fn error(self, kind: io::ErrorKind) -> Self {
+        self.state.borrow_mut().script.push(WriteStep::Error(kind));
+        self
+    }
+
+    /// Wait until `delay` has passed before moving on to the next step. A
+    /// background thread wakes the waker when the delay has passed, so this
+    /// works with any executor.
+    pub fn delay(self, delay: Duration) -> Self {
+        self.state.borrow_mut().script.push_delay(delay);
+        self
+    }
+
+    /// Return `Poll::Pending` for `num_polls` polls before moving on to the
+    /// next step. The waker is woken after each pending poll.
+    pub fn pending(self, num_polls: usize) -> Self {
+        self.state.borrow_mut().script.push_pending(num_polls);
+        self
+    }
+
+    /// Never complete writes once the script is exhausted, to simulate a
+    /// peer that stops receiving.
+    pub fn pending_forever(self) -> Self {
+        self.state.borrow_mut().script.set_pending_forever();
+        self
+    }
+
+    /// Returns the bytes that have been written, in order.
+    pub fn written(&self) -> Vec<u8> {
+        self.state.borrow().writes.concat()
+    }
+
+    /// Returns the bytes accepted by each write, in order.
+    pub fn writes(&self) -> Vec<Vec<u8>> {
+        self.state.borrow().writes.clone()
+    }
+
+    /// Returns the number of times the writer has been flushed.
+    pub fn num_flushes(&self) -> usize {
+        self.state.borrow().num_flushes
+    }
+
+    /// Returns true if the writer has been shut down.
+    pub fn is_shut_down(&self) -> bool {
+        self.state.borrow().shut_down
+    }
+}
+
+impl AsyncWrite for MockAsyncWrite {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8]) -> Poll<io::Result<usize>>
+    {
+        let mut state = self.state.borrow_mut();
+        if state.shut_down {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        let len = match state.script.poll_next(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Some(WriteStep::Accept(max_len))) => max_len.min(buf.len()),
+            Poll::Ready(Some(WriteStep::Error(kind))) => return Poll::Ready(Err(kind.into())),
+            Poll::Ready(None) => buf.len(),
+        };
+        state.writes.push(buf[..len].to_vec());
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        self.state.borrow_mut().num_flushes += 1;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        self.state.borrow_mut().shut_down = true;
+        Poll::Ready(Ok(()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Waker;
+    use std::thread;
+
+    fn poll_read(reader: &mut MockAsyncRead, len: usize) -> Poll<Result<Vec<u8>, io::ErrorKind>> {
+        let mut bytes = vec!(0u8; len);
+        let mut buf = ReadBuf::new(&mut bytes);
+        let mut cx = Context::from_waker(Waker::noop());
+        Pin::new(reader)
+            .poll_read(&mut cx, &mut buf)
+            .map(|result| result.map(|()| buf.filled().to_vec()).map_err(|error| error.kind()))
+    }
+
+    fn poll_write(writer: &mut MockAsyncWrite, bytes: &[u8]) -> Poll<Result<usize, io::ErrorKind>> {
+        Pin::new(writer)
+            .poll_write(&mut Context::from_waker(Waker::noop()), bytes)
+            .map_err(|error| error.kind())
+    }
+
+    #[test]
+    fn chunks_are_split_across_reads_that_they_dont_fit() {
+        let mut reader = MockAsyncRead::new()
+            .chunks(vec!("hello", "!"))
+            .eof()
+            .pending(1)
+            .error(io::ErrorKind::ConnectionReset);
+        assert_eq!(Poll::Ready(Ok(b"hel".to_vec())), poll_read(&mut reader, 3));
+        assert_eq!(Poll::Ready(Ok(b"lo".to_vec())), poll_read(&mut reader, 3));
+        assert_eq!(Poll::Ready(Ok(b"!".to_vec())), poll_read(&mut reader, 3));
+        assert_eq!(Poll::Ready(Ok(Vec::new())), poll_read(&mut reader, 3));
+        assert_eq!(Poll::Pending, poll_read(&mut reader, 3));
+        assert_eq!(Poll::Ready(Err(io::ErrorKind::ConnectionReset)), poll_read(&mut reader, 3));
+        assert_eq!(Poll::Ready(Ok(Vec::new())), poll_read(&mut reader, 3));
+    }
+
+    #[test]
+    fn delays_hold_back_reads() {
+        let mut reader = MockAsyncRead::new().delay(Duration::from_millis(20)).chunk("a");
+        assert_eq!(Poll::Pending, poll_read(&mut reader, 1));
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(Poll::Ready(Ok(b"a".to_vec())), poll_read(&mut reader, 1));
+
+        let mut reader = MockAsyncRead::new().pending_forever();
+        assert_eq!(Poll::Pending, poll_read(&mut reader, 1));
+    }
+
+    #[test]
+    fn writes_record_the_bytes_that_the_script_accepts() {
+        let writer = MockAsyncWrite::new().accept(2).error(io::ErrorKind::WriteZero).pending(1);
+        let mut clone = writer.clone();
+        assert_eq!(Poll::Ready(Ok(2)), poll_write(&mut clone, b"abc"));
+        assert_eq!(Poll::Ready(Err(io::ErrorKind::WriteZero)), poll_write(&mut clone, b"c"));
+        assert_eq!(Poll::Pending, poll_write(&mut clone, b"c"));
+        assert_eq!(Poll::Ready(Ok(1)), poll_write(&mut clone, b"c"));
+        assert_eq!(b"abc".to_vec(), writer.written());
+        assert_eq!(vec!(b"ab".to_vec(), b"c".to_vec()), writer.writes());
+    }
+
+    #[test]
+    fn writes_fail_once_shut_down() {
+        let mut writer = MockAsyncWrite::new();
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(Pin::new(&mut writer).poll_flush(&mut cx).is_ready());
+        assert!(Pin::new(&mut writer).poll_shutdown(&mut cx).is_ready());
+        assert_eq!(Poll::Ready(Err(io::ErrorKind::BrokenPipe)), poll_write(&mut writer, b"late"));
+        assert_eq!(1, writer.num_flushes());
+        assert!(writer.is_shut_down());
+        assert!(writer.written().is_empty());
+    }
+}
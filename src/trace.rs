@@ -0,0 +1,133 @@
+// `tracing` events emitted for mock calls, with the `tracing` feature.
+//
+// Events are emitted at `DEBUG` level with the `double` target, so they can
+// be enabled separately from the events of the code under test (e.g. with
+// `RUST_LOG=double=debug`). Enable the `log` feature of `tracing` to receive
+// them as `log` records instead.
+
+extern crate tracing;
+
+use std::panic::Location;
+
+// Emitted when a mock is called, before the call is handled.
+pub(crate) fn call(mock: &str, call_index: usize, args: Option<&str>, location: &Location) {
+    tracing::debug!(
+        target: "double",
+        mock,
+        call_index,
+        args,
+        location = %location,
+        "mock called");
+}
+
+// Emitted once the behaviour that handles a call has been chosen, e.g.
+// `return_value_for_args` or `default_return_value`.
+pub(crate) fn resolution(mock: &str, call_index: usize, behaviour: &str) {
+    tracing::debug!(
+        target: "double",
+        mock,
+        call_index,
+        behaviour,
+        "mock call resolved");
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use super::tracing::field::{Field, Visit};
+    use super::tracing::span::{Attributes, Id, Record};
+    use super::tracing::{subscriber, Event, Metadata, Subscriber};
+    use crate::mock::Mock;
+
+    // Records the fields of every event as `name=value` pairs.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    struct FieldWriter(Vec<String>);
+
+    impl Visit for FieldWriter {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.push(format!("{}={:?}", field.name(), value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push(format!("{}={}", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.target() == "double"
+        }
+
+        fn new_span(&self, _: &Attributes) -> Id {
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, _: &Record) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event) {
+            let mut writer = FieldWriter(Vec::new());
+            event.record(&mut writer);
+            self.events.lock().unwrap().push(writer.0.join(" "));
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn calls_and_resolutions_are_traced() {
+        let recorder = Recorder::default();
+        let mock = Mock::<(i32, &str), i32>::new(0);
+        mock.set_name("MockStore::put");
+        mock.return_value_for((1, "one"), 10);
+
+        subscriber::with_default(recorder.clone(), || {
+            mock.call((1, "one"));
+            mock.call((2, "two"));
+        });
+
+        let locations: Vec<String> = mock.records()
+            .map(|record| record.location().to_string())
+            .collect();
+        assert_eq!(*recorder.events.lock().unwrap(), vec!(
+            format!(
+                "message=mock called mock=MockStore::put call_index=0 args=(1, \"one\") \
+                 location={}",
+                locations[0]),
+            "message=mock call resolved mock=MockStore::put call_index=0 \
+             behaviour=return_value_for_args".to_owned(),
+            format!(
+                "message=mock called mock=MockStore::put call_index=1 args=(2, \"two\") \
+                 location={}",
+                locations[1]),
+            "message=mock call resolved mock=MockStore::put call_index=1 \
+             behaviour=default_return_value".to_owned(),
+        ));
+    }
+
+    #[test]
+    fn args_of_unnamed_mocks_are_only_traced_if_formatted() {
+        let recorder = Recorder::default();
+        let mock = Mock::<i32, ()>::default();
+
+        subscriber::with_default(recorder.clone(), || {
+            mock.call(1);
+            mock.format_args_with(Box::new(|args| format!("#{}", args)));
+            mock.call(2);
+        });
+
+        let events = recorder.events.lock().unwrap();
+        assert!(!events[0].contains("args="));
+        assert!(events[0].contains("mock=Mock<i32, ()>"));
+        assert!(events[2].contains("args=#2"));
+    }
+}
@@ -0,0 +1,364 @@
+#[cfg(feature = "std")]
+use std::fmt;
+use std::fmt::Debug;
+use std::hash::Hash;
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(not(feature = "std"))]
+use std::prelude::*;
+
+#[cfg(feature = "std")]
+use crate::async_mock::AsyncMock;
+use crate::mock::Mock;
+
+/// Implemented by objects whose expectations can be verified.
+///
+/// This is implemented by `Mock` and by the mock `struct`s generated by
+/// `mock_trait!` and `mock_trait_no_default!`. A generated mock is verified
+/// if all of its method mocks are verified.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use double::Verify;
+/// use double::matcher::*;
+///
+/// mock_trait!(
+///     MockTaskManager,
+///     max_threads(()) -> u32,
+///     set_max_threads(u32) -> ());
+///
+/// # fn main() {
+/// let mock = MockTaskManager::default();
+/// mock.set_max_threads.expect(p!(eq, 4));
+/// assert!(!mock.verify());
+///
+/// mock.set_max_threads.call(4);
+/// assert!(mock.verify());
+///
+/// assert!(mock.checkpoint());
+/// assert!(!mock.set_max_threads.called());
+/// # }
+/// ```
+pub trait Verify {
+    /// Returns true if all expectations are met. Prints a description of each
+    /// unmet expectation.
+    fn verify(&self) -> bool;
+
+    /// Verify all expectations, then clear all expectations and recorded
+    /// calls. Returns the result of verification.
+    fn checkpoint(&self) -> bool;
+
+    /// Returns true if every recorded call has been matched by a successful
+    /// verification or an expectation. Prints each call that was not.
+    fn verify_no_more_interactions(&self) -> bool;
+}
+
+impl<C, R> Verify for Mock<C, R>
+    where C: Clone + Debug + Eq + Hash,
+          R: Clone
+{
+    fn verify(&self) -> bool {
+        Mock::verify(self)
+    }
+
+    fn checkpoint(&self) -> bool {
+        Mock::checkpoint(self)
+    }
+
+    fn verify_no_more_interactions(&self) -> bool {
+        Mock::verify_no_more_interactions(self)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<C, R> Verify for AsyncMock<C, R>
+    where C: Clone + Debug + Eq + Hash,
+          R: Clone
+{
+    fn verify(&self) -> bool {
+        self.mock().verify()
+    }
+
+    fn checkpoint(&self) -> bool {
+        self.mock().checkpoint()
+    }
+
+    fn verify_no_more_interactions(&self) -> bool {
+        self.mock().verify_no_more_interactions()
+    }
+}
+
+/// Returns true if none of the given `mocks` have calls that were not matched
+/// by a successful verification or an expectation. All mocks are checked,
+/// even if an earlier one fails, so every unverified call is printed.
+///
+/// # Examples
+///
+/// ```
+/// use double::Mock;
+///
+/// let reader = Mock::<i32, ()>::default();
+/// let writer = Mock::<String, ()>::default();
+/// reader.call(1);
+/// writer.call("data".to_owned());
+///
+/// assert!(reader.called_with(1));
+/// assert!(!double::verify_no_more_interactions(&[&reader, &writer]));
+///
+/// assert!(writer.called_with("data".to_owned()));
+/// assert!(double::verify_no_more_interactions(&[&reader, &writer]));
+/// ```
+pub fn verify_no_more_interactions(mocks: &[&dyn Verify]) -> bool {
+    let results: Vec<bool> = mocks
+        .iter()
+        .map(|mock| mock.verify_no_more_interactions())
+        .collect();
+    results.iter().all(|verified| *verified)
+}
+
+/// Assert that `first` was called with args that match `first_pattern`
+/// before `second` was called with args that match `second_pattern`.
+///
+/// The first matching call of each mock is compared, using the calls'
+/// sequence numbers (see `CallRecord::sequence_number`). The mocks can have
+/// different argument and return types.
+///
+/// # Panics
+///
+/// Panics if either mock has no matching call, or if the first matching call
+/// to `second` happened before the first matching call to `first`.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use double::Mock;
+/// use double::matcher::*;
+///
+/// # fn main() {
+/// let open = Mock::<&str, ()>::default();
+/// let write = Mock::<(i32, String), ()>::default();
+/// open.call("data.txt");
+/// write.call((3, "hello".to_owned()));
+///
+/// double::assert_called_before(
+///     &open, p!(eq, "data.txt"),
+///     &write, matcher!(p!(eq, 3), p!(any)));
+/// # }
+/// ```
+///
+/// ```should_panic
+/// # #[macro_use] extern crate double;
+/// use double::Mock;
+/// use double::matcher::*;
+///
+/// # fn main() {
+/// let open = Mock::<&str, ()>::default();
+/// let write = Mock::<i32, ()>::default();
+/// write.call(3);
+/// open.call("data.txt");
+///
+/// // panics, `write` was called first
+/// double::assert_called_before(&open, p!(any), &write, p!(any));
+/// # }
+/// ```
+pub fn assert_called_before<C1, R1, C2, R2>(
+    first: &Mock<C1, R1>,
+    first_pattern: &dyn Fn(&C1) -> bool,
+    second: &Mock<C2, R2>,
+    second_pattern: &dyn Fn(&C2) -> bool)
+    where C1: Clone + Debug + Eq + Hash,
+          R1: Clone,
+          C2: Clone + Debug + Eq + Hash,
+          R2: Clone
+{
+    let first_call = match first.calls_matching(first_pattern).next() {
+        Some(record) => record,
+        None => panic!(
+            "expected first Mock to be called with args matching the pattern, but it was \
+             not\nRecorded calls: {:?}",
+            first.calls()),
+    };
+    let second_call = match second.calls_matching(second_pattern).next() {
+        Some(record) => record,
+        None => panic!(
+            "expected second Mock to be called with args matching the pattern, but it was \
+             not\nRecorded calls: {:?}",
+            second.calls()),
+    };
+    if second_call.sequence_number() < first_call.sequence_number() {
+        panic!(
+            "expected call with args {:?} to happen before call with args {:?}, but it \
+             happened after it",
+            first_call.args(),
+            second_call.args());
+    }
+}
+
+/// Verifies the expectations of one or more mocks when dropped.
+///
+/// This means forgetting the final `assert!(mock.verify())` cannot silently
+/// cause a test to pass. If any of the mocks' expectations are not met when
+/// the guard is dropped, the guard panics. If the thread is already panicking
+/// (e.g. because an assertion earlier in the test failed), verification is
+/// skipped so the original panic is reported.
+///
+/// The guard stores clones of the mocks it verifies. Clones of a mock share
+/// state with the original, so the guard sees all calls made to the mock.
+/// Requires the `std` feature, which it needs to tell whether the thread is
+/// panicking.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate double;
+/// use double::{Mock, VerifyGuard};
+/// use double::matcher::*;
+///
+/// # fn main() {
+/// let mock = Mock::<i32, i32>::default();
+/// let _guard = VerifyGuard::new(&mock);
+/// mock.expect(p!(eq, 42)).returning(1);
+///
+/// assert_eq!(mock.call(42), 1);
+/// // expectation is verified when `_guard` goes out of scope
+/// # }
+/// ```
+///
+/// ```should_panic
+/// # #[macro_use] extern crate double;
+/// use double::{Mock, VerifyGuard};
+/// use double::matcher::*;
+///
+/// # fn main() {
+/// let mock = Mock::<i32, i32>::default();
+/// let _guard = VerifyGuard::new(&mock);
+/// mock.expect(p!(eq, 42));
+/// // panics when `_guard` is dropped, mock never called with 42
+/// # }
+/// ```
+#[cfg(feature = "std")]
+#[must_use = "expectations are verified as soon as the guard is dropped"]
+pub struct VerifyGuard {
+    mocks: Vec<Box<dyn Verify>>,
+}
+
+#[cfg(feature = "std")]
+impl VerifyGuard {
+    /// Create a guard that verifies `mock` when dropped.
+    pub fn new<V: Verify + Clone + 'static>(mock: &V) -> Self {
+        VerifyGuard { mocks: vec!(Box::new(mock.clone())) }
+    }
+
+    /// Also verify `mock` when the guard is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use double::{Mock, VerifyGuard};
+    ///
+    /// let first = Mock::<i32, ()>::default();
+    /// let second = Mock::<String, ()>::default();
+    /// let _guard = VerifyGuard::new(&first).with(&second);
+    /// ```
+    pub fn with<V: Verify + Clone + 'static>(mut self, mock: &V) -> Self {
+        self.mocks.push(Box::new(mock.clone()));
+        self
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for VerifyGuard {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            return;
+        }
+        let num_failed = self.mocks
+            .iter()
+            .filter(|mock| !mock.verify())
+            .count();
+        if num_failed > 0 {
+            panic!(
+                "{} of {} mock(s) guarded by VerifyGuard have unmet expectations",
+                num_failed,
+                self.mocks.len());
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Debug for VerifyGuard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VerifyGuard")
+            .field("num_mocks", &self.mocks.len())
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic;
+
+    #[test]
+    fn called_before_compares_first_matching_calls() {
+        let first = Mock::<i32, ()>::default();
+        let second = Mock::<&str, ()>::default();
+        first.call(1);
+        second.call("a");
+        first.call(2);
+        second.call("b");
+
+        assert_called_before(&first, &|&x| x == 1, &second, &|&x| x == "a");
+        assert_called_before(&first, &|&x| x == 2, &second, &|&x| x == "b");
+        assert_called_before(&second, &|&x| x == "a", &first, &|&x| x == 2);
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            assert_called_before(&second, &|&x| x == "b", &first, &|_| true);
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn guard_panics_on_drop_if_expectations_unmet() {
+        let result = panic::catch_unwind(|| {
+            let mock = Mock::<i32, ()>::default();
+            mock.expect(&|&x| x == 1);
+            let _guard = VerifyGuard::new(&mock);
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn guard_does_not_panic_while_already_unwinding() {
+        let result = panic::catch_unwind(|| {
+            let mock = Mock::<i32, ()>::default();
+            mock.expect(&|&x| x == 1);
+            let _guard = VerifyGuard::new(&mock);
+            panic!("original failure");
+        });
+        // A second panic during unwinding would abort the process, so
+        // reaching this point means the guard skipped verification.
+        let payload = result.unwrap_err();
+        assert_eq!(Some(&"original failure"), payload.downcast_ref::<&str>());
+    }
+
+    #[test]
+    fn guard_verifies_all_mocks() {
+        let first = Mock::<i32, ()>::default();
+        let second = Mock::<i32, ()>::default();
+        first.expect(&|&x| x == 1);
+        second.expect(&|&x| x == 2);
+        first.call(1);
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let _guard = VerifyGuard::new(&first).with(&second);
+        }));
+        assert!(result.is_err());
+
+        second.call(2);
+        let _guard = VerifyGuard::new(&first).with(&second);
+    }
+}